@@ -13,12 +13,108 @@ const STRUCTS: &str = stringify! {
         Command,
         Get,
         Set,
+        Lpush,
+        Rpush,
+        Lrange,
+        Llen,
+        Unlink,
+        Lpop,
+        Rpop,
+        Getset,
+        Shutdown,
+        Hset,
+        Hsetnx,
+        Hget,
+        Hmget,
+        Hgetall,
+        Hkeys,
+        Hvals,
+        Hdel,
+        Hexists,
+        Hincrby,
+        Hincrbyfloat,
+        Hlen,
+        Hstrlen,
+        Hrandfield,
+        Hscan,
+        Lpushx,
+        Rpushx,
+        Append,
+        Strlen,
+        Getrange,
+        Setrange,
+        Getbit,
+        Setbit,
+        Bitcount,
+        Sadd,
+        Srem,
+        Smembers,
+        Sismember,
+        Smismember,
+        Scard,
+        Object,
+        Sinter,
+        Sunion,
+        Sdiff,
+        Sinterstore,
+        Sunionstore,
+        Sdiffstore,
+        Linsert,
+        Incr,
+        Zadd,
+        Zscore,
+        Zrange,
+        Zcard,
+        Zrangebyscore,
+        Zcount,
+        Debug,
+        Lindex,
+        Lrem,
+        Lset,
+        Lmove,
+        Rpoplpush,
+        Info,
+        Blpop,
+        Brpop,
+        Subscribe,
+        Unsubscribe,
+        Psubscribe,
+        Punsubscribe,
+        Publish,
+        Blmove,
+        Brpoplpush,
+        Ltrim,
+        Lmpop,
+        Zmpop,
+        Lpos,
+        Save,
+        Bgsave,
+        Select,
+        Swapdb,
+        Client,
+        Keys,
+        Scan,
+        Auth,
+        Hello,
+        Del,
+        Flushdb,
+        Config,
     }
 
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub(crate) enum SetParams {
         EX,
         PX,
+        KeepTtl,
+        NX,
+        XX,
+        Get,
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    pub(crate) enum ObjectParams {
+        Refcount,
+        Encoding,
     }
 };
 
@@ -37,6 +133,92 @@ fn main() {
             .entry("command".into(), "CommandKeywords::Command")
             .entry("get".into(), "CommandKeywords::Get")
             .entry("set".into(), "CommandKeywords::Set")
+            .entry("lpush".into(), "CommandKeywords::Lpush")
+            .entry("rpush".into(), "CommandKeywords::Rpush")
+            .entry("lrange".into(), "CommandKeywords::Lrange")
+            .entry("llen".into(), "CommandKeywords::Llen")
+            .entry("unlink".into(), "CommandKeywords::Unlink")
+            .entry("lpop".into(), "CommandKeywords::Lpop")
+            .entry("rpop".into(), "CommandKeywords::Rpop")
+            .entry("getset".into(), "CommandKeywords::Getset")
+            .entry("shutdown".into(), "CommandKeywords::Shutdown")
+            .entry("hset".into(), "CommandKeywords::Hset")
+            .entry("hsetnx".into(), "CommandKeywords::Hsetnx")
+            .entry("hget".into(), "CommandKeywords::Hget")
+            .entry("hmget".into(), "CommandKeywords::Hmget")
+            .entry("hgetall".into(), "CommandKeywords::Hgetall")
+            .entry("hkeys".into(), "CommandKeywords::Hkeys")
+            .entry("hvals".into(), "CommandKeywords::Hvals")
+            .entry("hdel".into(), "CommandKeywords::Hdel")
+            .entry("hexists".into(), "CommandKeywords::Hexists")
+            .entry("hincrby".into(), "CommandKeywords::Hincrby")
+            .entry("hincrbyfloat".into(), "CommandKeywords::Hincrbyfloat")
+            .entry("hlen".into(), "CommandKeywords::Hlen")
+            .entry("hstrlen".into(), "CommandKeywords::Hstrlen")
+            .entry("hrandfield".into(), "CommandKeywords::Hrandfield")
+            .entry("hscan".into(), "CommandKeywords::Hscan")
+            .entry("lpushx".into(), "CommandKeywords::Lpushx")
+            .entry("rpushx".into(), "CommandKeywords::Rpushx")
+            .entry("append".into(), "CommandKeywords::Append")
+            .entry("strlen".into(), "CommandKeywords::Strlen")
+            .entry("getrange".into(), "CommandKeywords::Getrange")
+            .entry("setrange".into(), "CommandKeywords::Setrange")
+            .entry("getbit".into(), "CommandKeywords::Getbit")
+            .entry("setbit".into(), "CommandKeywords::Setbit")
+            .entry("bitcount".into(), "CommandKeywords::Bitcount")
+            .entry("sadd".into(), "CommandKeywords::Sadd")
+            .entry("srem".into(), "CommandKeywords::Srem")
+            .entry("smembers".into(), "CommandKeywords::Smembers")
+            .entry("sismember".into(), "CommandKeywords::Sismember")
+            .entry("smismember".into(), "CommandKeywords::Smismember")
+            .entry("scard".into(), "CommandKeywords::Scard")
+            .entry("object".into(), "CommandKeywords::Object")
+            .entry("sinter".into(), "CommandKeywords::Sinter")
+            .entry("sunion".into(), "CommandKeywords::Sunion")
+            .entry("sdiff".into(), "CommandKeywords::Sdiff")
+            .entry("sinterstore".into(), "CommandKeywords::Sinterstore")
+            .entry("sunionstore".into(), "CommandKeywords::Sunionstore")
+            .entry("sdiffstore".into(), "CommandKeywords::Sdiffstore")
+            .entry("linsert".into(), "CommandKeywords::Linsert")
+            .entry("incr".into(), "CommandKeywords::Incr")
+            .entry("zadd".into(), "CommandKeywords::Zadd")
+            .entry("zscore".into(), "CommandKeywords::Zscore")
+            .entry("zrange".into(), "CommandKeywords::Zrange")
+            .entry("zcard".into(), "CommandKeywords::Zcard")
+            .entry("zrangebyscore".into(), "CommandKeywords::Zrangebyscore")
+            .entry("zcount".into(), "CommandKeywords::Zcount")
+            .entry("debug".into(), "CommandKeywords::Debug")
+            .entry("lindex".into(), "CommandKeywords::Lindex")
+            .entry("lrem".into(), "CommandKeywords::Lrem")
+            .entry("lset".into(), "CommandKeywords::Lset")
+            .entry("lmove".into(), "CommandKeywords::Lmove")
+            .entry("rpoplpush".into(), "CommandKeywords::Rpoplpush")
+            .entry("info".into(), "CommandKeywords::Info")
+            .entry("blpop".into(), "CommandKeywords::Blpop")
+            .entry("brpop".into(), "CommandKeywords::Brpop")
+            .entry("subscribe".into(), "CommandKeywords::Subscribe")
+            .entry("unsubscribe".into(), "CommandKeywords::Unsubscribe")
+            .entry("psubscribe".into(), "CommandKeywords::Psubscribe")
+            .entry("punsubscribe".into(), "CommandKeywords::Punsubscribe")
+            .entry("publish".into(), "CommandKeywords::Publish")
+            .entry("blmove".into(), "CommandKeywords::Blmove")
+            .entry("brpoplpush".into(), "CommandKeywords::Brpoplpush")
+            .entry("ltrim".into(), "CommandKeywords::Ltrim")
+            .entry("lmpop".into(), "CommandKeywords::Lmpop")
+            .entry("zmpop".into(), "CommandKeywords::Zmpop")
+            .entry("lpos".into(), "CommandKeywords::Lpos")
+            .entry("save".into(), "CommandKeywords::Save")
+            .entry("bgsave".into(), "CommandKeywords::Bgsave")
+            .entry("select".into(), "CommandKeywords::Select")
+            .entry("swapdb".into(), "CommandKeywords::Swapdb")
+            .entry("client".into(), "CommandKeywords::Client")
+            .entry("keys".into(), "CommandKeywords::Keys")
+            .entry("scan".into(), "CommandKeywords::Scan")
+            .entry("auth".into(), "CommandKeywords::Auth")
+            .entry("hello".into(), "CommandKeywords::Hello")
+            .entry("del".into(), "CommandKeywords::Del")
+            .entry("flushdb".into(), "CommandKeywords::Flushdb")
+            .entry("config".into(), "CommandKeywords::Config")
             .build()
     )
         .expect("Failed to write COMMAND_KEYWORDS to file");
@@ -48,8 +230,39 @@ fn main() {
         phf_codegen::Map::<&uncased::UncasedStr>::new()
             .entry("ex".into(), "SetParams::EX")
             .entry("px".into(), "SetParams::PX")
+            .entry("keepttl".into(), "SetParams::KeepTtl")
+            .entry("nx".into(), "SetParams::NX")
+            .entry("xx".into(), "SetParams::XX")
+            .entry("get".into(), "SetParams::Get")
             .build()
     )
     .expect("Failed to write SET_PARAMS to file");
+    writeln!(&mut file, ";\n\n").expect("Failed to write new line to file");
+
+    writeln!(
+        &mut file,
+        "pub(crate) static OBJECT_PARAMS: phf::Map<&'static uncased::UncasedStr, ObjectParams> = \n{}",
+        phf_codegen::Map::<&uncased::UncasedStr>::new()
+            .entry("refcount".into(), "ObjectParams::Refcount")
+            .entry("encoding".into(), "ObjectParams::Encoding")
+            .build()
+    )
+    .expect("Failed to write OBJECT_PARAMS to file");
+    writeln!(&mut file, ";\n\n").expect("Failed to write new line to file");
+
+    // DEBUG subcommands the Redis test suite calls that are safe to answer
+    // with a plain OK. Anything else, SEGFAULT and PANIC included, is refused.
+    writeln!(
+        &mut file,
+        "pub(crate) static DEBUG_NOOPS: phf::Set<&'static uncased::UncasedStr> = \n{}",
+        phf_codegen::Set::<&uncased::UncasedStr>::new()
+            .entry("jmap".into())
+            .entry("malloc-stats".into())
+            .entry("quicklist-packed-threshold".into())
+            .entry("stringmatch-len".into())
+            .entry("change-repl-id".into())
+            .build()
+    )
+    .expect("Failed to write DEBUG_NOOPS to file");
     writeln!(&mut file, ";").expect("Failed to write new line to file");
 }
@@ -1,10 +1,25 @@
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
 
 use bytes::BytesMut;
-use sharded_slab::Clear;
 
 const CAPACITY: usize = 64 * 1024;
 
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The pool shard the current thread takes values from and returns them
+    /// to, so worker threads rarely share a lock.
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Resets a value for reuse while keeping its allocation.
+pub(crate) trait Clear {
+    fn clear(&mut self);
+}
+
 #[derive(Debug)]
 pub(crate) struct Buffer(pub(crate) BytesMut);
 
@@ -14,6 +29,12 @@ impl Clear for Buffer {
     }
 }
 
+impl Clear for Vec<u8> {
+    fn clear(&mut self) {
+        Vec::clear(self);
+    }
+}
+
 impl Default for Buffer {
     fn default() -> Self {
         Self(BytesMut::with_capacity(CAPACITY))
@@ -33,3 +54,222 @@ impl Deref for Buffer {
         &self.0
     }
 }
+
+/// Values handed out to connections and taken back once they are done, so
+/// their allocations are reused. Unlike a slab, the pool can shrink again:
+/// idle values beyond what it should keep are freed by [`Pool::reclaim`].
+///
+/// Idle values are split into a shard per CPU, and each thread uses the
+/// shard of its own, so replies on different worker threads do not
+/// contend for one lock.
+#[derive(Debug)]
+pub(crate) struct Pool<T> {
+    shards: Box<[Mutex<Vec<T>>]>,
+    /// Whether a value was handed out since the pool was last reclaimed.
+    busy: AtomicBool,
+}
+
+impl<T> Default for Pool<T> {
+    fn default() -> Self {
+        let shards = std::thread::available_parallelism().map_or(1, usize::from);
+
+        Self {
+            shards: (0..shards).map(|_| Mutex::default()).collect(),
+            busy: AtomicBool::default(),
+        }
+    }
+}
+
+impl<T> Pool<T> {
+    /// The shard of the current thread.
+    fn shard(&self) -> &Mutex<Vec<T>> {
+        &self.shards[SHARD.with(|shard| *shard) % self.shards.len()]
+    }
+}
+
+impl<T: Clear + Default> Pool<T> {
+    /// An idle value, or a new one when the shard of the current thread has
+    /// none.
+    pub(crate) fn acquire(self: &Arc<Self>) -> Pooled<T> {
+        self.busy.store(true, Ordering::Relaxed);
+        let value = self.shard().lock().unwrap().pop().unwrap_or_default();
+
+        Pooled {
+            value: Some(value),
+            pool: Arc::clone(self),
+        }
+    }
+
+    /// Frees the idle values beyond `keep`, returning how many were freed.
+    /// Nothing is freed if a value was handed out since the last call, so
+    /// the pool only shrinks after a whole period without new demand rather
+    /// than in the middle of a burst.
+    pub(crate) fn reclaim(&self, keep: usize) -> usize {
+        if self.busy.swap(false, Ordering::Relaxed) {
+            return 0;
+        }
+
+        let mut keep = keep;
+        let mut freed = 0;
+
+        for shard in self.shards.iter() {
+            let mut idle = shard.lock().unwrap();
+            let kept = idle.len().min(keep);
+
+            freed += idle.len() - kept;
+            idle.truncate(kept);
+            idle.shrink_to(kept);
+            keep -= kept;
+        }
+
+        freed
+    }
+}
+
+/// Reclaims `pool` every `period`, keeping up to `keep` idle values, until
+/// the pool is dropped.
+pub(crate) async fn reclaim_every<T: Clear + Default>(
+    pool: Weak<Pool<T>>,
+    keep: usize,
+    period: Duration,
+) {
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        let Some(pool) = pool.upgrade() else {
+            return;
+        };
+
+        pool.reclaim(keep);
+    }
+}
+
+/// A value on loan from a [`Pool`], cleared and returned to it on drop.
+#[derive(Debug)]
+pub(crate) struct Pooled<T: Clear> {
+    value: Option<T>,
+    pool: Arc<Pool<T>>,
+}
+
+impl<T: Clear> Deref for Pooled<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().expect("value is only taken on drop")
+    }
+}
+
+impl<T: Clear> DerefMut for Pooled<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("value is only taken on drop")
+    }
+}
+
+impl<T: Clear> Drop for Pooled<T> {
+    fn drop(&mut self) {
+        if let Some(mut value) = self.value.take() {
+            value.clear();
+            self.pool.shard().lock().unwrap().push(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idle<T>(pool: &Pool<T>) -> usize {
+        pool.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+
+    #[test]
+    fn test_values_are_reused_cleared() {
+        let pool = Arc::new(Pool::<Vec<u8>>::default());
+
+        let mut value = pool.acquire();
+        value.reserve(1024);
+        value.extend_from_slice(b"data");
+        drop(value);
+        assert_eq!(1, idle(&pool));
+
+        let value = pool.acquire();
+        assert!(value.is_empty());
+        assert!(value.capacity() >= 1024);
+        assert_eq!(0, idle(&pool));
+    }
+
+    #[test]
+    fn test_reclaim_after_burst() {
+        let pool = Arc::new(Pool::<Buffer>::default());
+
+        let burst: Vec<_> = (0..100).map(|_| pool.acquire()).collect();
+        drop(burst);
+        assert_eq!(100, idle(&pool));
+
+        // Values were handed out during the last period, so it is left be.
+        assert_eq!(0, pool.reclaim(10));
+        assert_eq!(100, idle(&pool));
+
+        assert_eq!(90, pool.reclaim(10));
+        assert_eq!(10, idle(&pool));
+
+        let kept = pool.acquire();
+        assert_eq!(9, idle(&pool));
+        drop(kept);
+        assert_eq!(0, pool.reclaim(10));
+    }
+
+    #[test]
+    fn test_threads_reuse_values_across_shards() {
+        let pool = Arc::new(Pool::<Vec<u8>>::default());
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let pool = Arc::clone(&pool);
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        let mut value = pool.acquire();
+                        assert!(value.is_empty());
+                        value.extend_from_slice(b"data");
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // Each thread kept reusing the one value of its shard.
+        let idle_values = idle(&pool);
+        assert!((1..=8).contains(&idle_values));
+
+        assert_eq!(0, pool.reclaim(0));
+        assert_eq!(idle_values, pool.reclaim(0));
+        assert_eq!(0, idle(&pool));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reclaim_every_period() {
+        let pool = Arc::new(Pool::<Vec<u8>>::default());
+        drop((0..50).map(|_| pool.acquire()).collect::<Vec<_>>());
+
+        let task = tokio::spawn(reclaim_every(
+            Arc::downgrade(&pool),
+            5,
+            Duration::from_secs(1),
+        ));
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(5, idle(&pool));
+
+        drop(pool);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(task.is_finished());
+    }
+}
@@ -0,0 +1,546 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+use tokio::time::{timeout_at, Instant};
+
+use super::collection::collection_mut;
+use super::list::{List, ListSide};
+use super::{Database, Keyspace, TypeError};
+
+/// A key and the element popped from it.
+pub type Popped = (Box<[u8]>, Box<[u8]>);
+
+/// How a blocked client is served once its list has elements.
+#[derive(Debug)]
+enum Waiter {
+    /// BLPOP/BRPOP: handed an element popped from the given end.
+    Pop(ListSide, oneshot::Sender<Popped>),
+    /// BLMOVE: woken to retry the move itself, as it also has to push.
+    Move(oneshot::Sender<()>),
+}
+
+/// Clients parked in a blocking list command, queued per key in arrival
+/// order.
+#[derive(Debug, Default)]
+pub(super) struct Blocked {
+    next_id: u64,
+    queues: HashMap<Box<[u8]>, VecDeque<u64>>,
+    waiters: HashMap<u64, Waiter>,
+}
+
+impl Blocked {
+    fn register(&mut self, keys: &[impl AsRef<[u8]>], waiter: Waiter) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.waiters.insert(id, waiter);
+
+        for key in keys {
+            self.queues
+                .entry(Box::from(key.as_ref()))
+                .or_default()
+                .push_back(id);
+        }
+
+        id
+    }
+
+    fn deregister(&mut self, id: u64, keys: &[Box<[u8]>]) {
+        self.waiters.remove(&id);
+
+        for key in keys {
+            if let Some(queue) = self.queues.get_mut(key) {
+                queue.retain(|&waiter| waiter != id);
+
+                if queue.is_empty() {
+                    self.queues.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Takes the longest waiting client blocked on `key`. A client blocked on
+    /// several keys is served at most once.
+    fn next_waiter(&mut self, key: &[u8]) -> Option<Waiter> {
+        let queue = self.queues.get_mut(key)?;
+
+        let waiter =
+            std::iter::from_fn(|| queue.pop_front()).find_map(|id| self.waiters.remove(&id));
+
+        if queue.is_empty() {
+            self.queues.remove(key);
+        }
+
+        waiter
+    }
+}
+
+/// Removes a client from the blocked queues once it stops waiting, whether
+/// it was served, timed out or its connection went away.
+struct Registration<'a> {
+    database: &'a Database,
+    id: u64,
+    keys: Vec<Box<[u8]>>,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.database
+            .blocked
+            .lock()
+            .unwrap()
+            .deregister(self.id, &self.keys);
+    }
+}
+
+impl Database {
+    /// Pops from the `side` end of the first non-empty list among `keys`. If
+    /// all are empty, waits until an element is pushed to one of them or the
+    /// `timeout` elapses, in which case `None` is returned. Without a
+    /// timeout it waits forever.
+    ///
+    /// Waiting clients are served in the order they blocked, and a pushed
+    /// element is handed to exactly one of them under the same write lock as
+    /// the push.
+    pub async fn blocking_pop(
+        &self,
+        keys: &[impl AsRef<[u8]>],
+        side: ListSide,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Popped>, TypeError> {
+        let (sender, mut receiver) = oneshot::channel();
+
+        let id = {
+            let mut guard = self.map.write().await;
+
+            let popped = self.tracked(&mut guard, keys, |map| {
+                for key in keys {
+                    let key = key.as_ref();
+
+                    let Some(list) = collection_mut::<List>(map, key, false)? else {
+                        continue;
+                    };
+
+                    if let Some(value) = side.pop(list) {
+                        if list.is_empty() {
+                            map.remove(key);
+                        }
+
+                        return Ok(Some((Box::from(key), value)));
+                    }
+                }
+
+                Ok::<_, TypeError>(None)
+            })?;
+
+            if popped.is_some() {
+                return Ok(popped);
+            }
+
+            // Registered before the write lock is released, so no push can
+            // slip in between the check above and the wait below.
+            self.blocked
+                .lock()
+                .unwrap()
+                .register(keys, Waiter::Pop(side, sender))
+        };
+
+        let registration = Registration {
+            database: self,
+            id,
+            keys: keys.iter().map(|key| Box::from(key.as_ref())).collect(),
+        };
+
+        let popped = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, &mut receiver).await.ok(),
+            None => Some((&mut receiver).await),
+        };
+
+        if let Some(Ok(popped)) = popped {
+            return Ok(Some(popped));
+        }
+
+        // A push may have served this client just as the timeout fired.
+        drop(registration);
+        Ok(receiver.try_recv().ok())
+    }
+
+    /// Moves an element from the `from` end of `source` to the `to` end of
+    /// `destination` like [`Database::lmove`], first waiting for `source` to
+    /// have one. Gives up with `None` once the `timeout` elapses; without one
+    /// it waits forever.
+    ///
+    /// A woken client retries the move under the write lock. If another
+    /// client took the element first, it waits again for whatever is left of
+    /// the timeout.
+    pub async fn blocking_lmove(
+        &self,
+        source: &[u8],
+        destination: &[u8],
+        from: ListSide,
+        to: ListSide,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Box<[u8]>>, TypeError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            let (sender, receiver) = oneshot::channel();
+
+            let id = {
+                let mut guard = self.map.write().await;
+
+                if let Some(element) =
+                    self.move_element(&mut guard, source, destination, from, to)?
+                {
+                    return Ok(Some(element));
+                }
+
+                self.blocked
+                    .lock()
+                    .unwrap()
+                    .register(&[source], Waiter::Move(sender))
+            };
+
+            let _registration = Registration {
+                database: self,
+                id,
+                keys: vec![Box::from(source)],
+            };
+
+            let woken = match deadline {
+                Some(deadline) => timeout_at(deadline, receiver).await.is_ok(),
+                None => receiver.await.is_ok(),
+            };
+
+            if !woken {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Serves the clients blocked on the list just pushed to at `key`, in the
+    /// order they blocked. Popping clients are handed an element each; moving
+    /// clients are woken once per element left to retry their move. Must be
+    /// called under the same write lock as the push.
+    pub(super) fn serve_blocked(&self, key: &[u8], list: &mut List) {
+        let mut blocked = self.blocked.lock().unwrap();
+        let mut reserved = 0;
+
+        while list.len() > reserved {
+            let Some(waiter) = blocked.next_waiter(key) else {
+                break;
+            };
+
+            match waiter {
+                Waiter::Pop(side, sender) => {
+                    let Some(value) = side.pop(list) else {
+                        break;
+                    };
+
+                    // The client is gone; the element goes back where it came from.
+                    if let Err((_, value)) = sender.send((Box::from(key), value)) {
+                        side.push(list, value, self.list_limits);
+                    }
+                }
+                Waiter::Move(sender) => {
+                    if sender.send(()).is_ok() {
+                        reserved += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Serves the clients blocked on any list in `map`, for when a whole
+    /// keyspace is swapped in under them rather than pushed to key by key.
+    pub(super) fn serve_all_blocked(&self, map: &mut Keyspace) {
+        let keys: Vec<Box<[u8]>> = self
+            .blocked
+            .lock()
+            .unwrap()
+            .queues
+            .keys()
+            .cloned()
+            .collect();
+
+        self.tracked(map, &keys, |map| {
+            for key in &keys {
+                let Ok(Some(list)) = collection_mut::<List>(map, key, false) else {
+                    continue;
+                };
+
+                self.serve_blocked(key, list);
+
+                if list.is_empty() {
+                    map.remove(key);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::time::sleep;
+
+    use super::*;
+
+    fn popped(key: &str, value: &str) -> Option<Popped> {
+        Some((Box::from(key.as_bytes()), Box::from(value.as_bytes())))
+    }
+
+    #[tokio::test]
+    async fn test_blocking_pop_immediate() {
+        let database = Database::new();
+        database.rpush(b"second", &["a", "b"]).await.unwrap();
+
+        assert_eq!(
+            Ok(popped("second", "b")),
+            database
+                .blocking_pop(&["first", "second"], ListSide::Right, None)
+                .await
+        );
+        assert_eq!(
+            Ok(popped("second", "a")),
+            database
+                .blocking_pop(&["first", "second"], ListSide::Left, None)
+                .await
+        );
+        assert!(database.get(b"second").await.is_none());
+
+        database.insert(b"string", "value", None).await;
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database
+                .blocking_pop(&["string"], ListSide::Left, None)
+                .await
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blocking_pop_timeout() {
+        let database = Database::new();
+
+        let start = Instant::now();
+        assert_eq!(
+            Ok(None),
+            database
+                .blocking_pop(&["list"], ListSide::Left, Some(Duration::from_millis(500)))
+                .await
+        );
+        assert_eq!(Duration::from_millis(500), start.elapsed());
+        assert!(database.blocked.lock().unwrap().queues.is_empty());
+
+        database.rpush(b"list", &["a"]).await.unwrap();
+        assert_eq!(Ok(1), database.llen(b"list").await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blocking_pop_wakes_waiters_in_order() {
+        let database = Arc::new(Database::new());
+
+        let mut waiters = Vec::new();
+
+        for keys in [&["list"][..], &["other", "list"][..]] {
+            let database = Arc::clone(&database);
+            waiters.push(tokio::spawn(async move {
+                database.blocking_pop(keys, ListSide::Left, None).await
+            }));
+
+            // Lets the waiter block before the next one does.
+            sleep(Duration::from_millis(1)).await;
+        }
+
+        assert_eq!(Ok(2), database.rpush(b"list", &["a", "b"]).await);
+
+        let second = waiters.pop().unwrap();
+        let first = waiters.pop().unwrap();
+
+        assert_eq!(Ok(popped("list", "a")), first.await.unwrap());
+        assert_eq!(Ok(popped("list", "b")), second.await.unwrap());
+        assert!(database.get(b"list").await.is_none());
+        assert!(database.blocked.lock().unwrap().queues.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blocking_pop_mixed_sides_in_order() {
+        let database = Arc::new(Database::new());
+
+        let mut waiters = Vec::new();
+
+        for side in [ListSide::Right, ListSide::Left, ListSide::Right] {
+            let database = Arc::clone(&database);
+            waiters.push(tokio::spawn(async move {
+                database.blocking_pop(&["list"], side, None).await
+            }));
+
+            sleep(Duration::from_millis(1)).await;
+        }
+
+        // Only two elements: the waiter that blocked last keeps waiting.
+        database.rpush(b"list", &["a", "b"]).await.unwrap();
+        sleep(Duration::from_millis(1)).await;
+
+        let last = waiters.pop().unwrap();
+        assert!(!last.is_finished());
+
+        let mut results = Vec::new();
+        for waiter in waiters {
+            results.push(waiter.await.unwrap());
+        }
+
+        assert_eq!(
+            vec![Ok(popped("list", "b")), Ok(popped("list", "a"))],
+            results
+        );
+        assert!(database.get(b"list").await.is_none());
+
+        database.rpush(b"list", &["c"]).await.unwrap();
+        assert_eq!(Ok(popped("list", "c")), last.await.unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blocking_pop_woken_by_lmove() {
+        let database = Arc::new(Database::new());
+        database.rpush(b"source", &["a"]).await.unwrap();
+
+        let cl = Arc::clone(&database);
+        let waiter =
+            tokio::spawn(async move { cl.blocking_pop(&["list"], ListSide::Right, None).await });
+
+        sleep(Duration::from_millis(1)).await;
+
+        assert_eq!(
+            Ok(Some(Box::from(b"a" as &[u8]))),
+            database
+                .lmove(b"source", b"list", ListSide::Left, ListSide::Left)
+                .await
+        );
+        assert_eq!(Ok(popped("list", "a")), waiter.await.unwrap());
+        assert!(database.get(b"source").await.is_none());
+        assert!(database.get(b"list").await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blocking_lmove_waits_for_producer() {
+        let database = Arc::new(Database::new());
+
+        let cl = Arc::clone(&database);
+        let producer = tokio::spawn(async move {
+            sleep(Duration::from_millis(50)).await;
+            cl.lpush(b"source", &["a", "b"]).await
+        });
+
+        let start = Instant::now();
+        assert_eq!(
+            Ok(Some(Box::from(b"a" as &[u8]))),
+            database
+                .blocking_lmove(
+                    b"source",
+                    b"destination",
+                    ListSide::Right,
+                    ListSide::Left,
+                    None
+                )
+                .await
+        );
+        assert_eq!(Duration::from_millis(50), start.elapsed());
+        assert_eq!(Ok(2), producer.await.unwrap());
+
+        assert_eq!(
+            Ok(vec![Box::from(b"b" as &[u8])]),
+            database.lrange(b"source", 0, -1).await
+        );
+        assert_eq!(
+            Ok(vec![Box::from(b"a" as &[u8])]),
+            database.lrange(b"destination", 0, -1).await
+        );
+        assert!(database.blocked.lock().unwrap().queues.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blocking_lmove_waits_again_when_beaten() {
+        let database = Arc::new(Database::new());
+
+        let cl = Arc::clone(&database);
+        let mover = tokio::spawn(async move {
+            cl.blocking_lmove(
+                b"source",
+                b"destination",
+                ListSide::Left,
+                ListSide::Right,
+                Some(Duration::from_secs(10)),
+            )
+            .await
+        });
+
+        sleep(Duration::from_millis(1)).await;
+
+        // Another client takes the element before the woken mover runs.
+        database.rpush(b"source", &["a"]).await.unwrap();
+        database.lpop(b"source", None).await.unwrap();
+
+        sleep(Duration::from_secs(1)).await;
+        assert!(!mover.is_finished());
+
+        database.rpush(b"source", &["b"]).await.unwrap();
+
+        assert_eq!(Ok(Some(Box::from(b"b" as &[u8]))), mover.await.unwrap());
+        assert_eq!(
+            Ok(vec![Box::from(b"b" as &[u8])]),
+            database.lrange(b"destination", 0, -1).await
+        );
+        assert!(database.blocked.lock().unwrap().queues.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blocking_lmove_timeout() {
+        let database = Database::new();
+
+        let start = Instant::now();
+        assert_eq!(
+            Ok(None),
+            database
+                .blocking_lmove(
+                    b"source",
+                    b"destination",
+                    ListSide::Right,
+                    ListSide::Left,
+                    Some(Duration::from_millis(250)),
+                )
+                .await
+        );
+        assert_eq!(Duration::from_millis(250), start.elapsed());
+        assert!(database.get(b"destination").await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blocking_pop_serves_each_element_once() {
+        let database = Arc::new(Database::new());
+
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let database = Arc::clone(&database);
+                tokio::spawn(async move {
+                    database
+                        .blocking_pop(&["list"], ListSide::Left, Some(Duration::from_secs(1)))
+                        .await
+                })
+            })
+            .collect();
+
+        sleep(Duration::from_millis(1)).await;
+        database.rpush(b"list", &["a"]).await.unwrap();
+
+        let mut results = Vec::new();
+        for waiter in waiters {
+            results.push(waiter.await.unwrap().unwrap());
+        }
+
+        results.sort();
+        assert_eq!(vec![None, popped("list", "a")], results);
+    }
+}
@@ -0,0 +1,122 @@
+use tokio::sync::RwLockReadGuard;
+use tokio::time::Instant;
+
+use super::{evict_expired, live, Database, Entry, Keyspace, TypeError, Value};
+
+/// A container type stored inside [`Value`] that the typed accessors below
+/// can narrow a key to, answering `WRONGTYPE` for any other kind of value.
+pub(super) trait Collection: Default + Sized {
+    fn narrow(value: &Value) -> Option<&Self>;
+
+    fn narrow_mut(value: &mut Value) -> Option<&mut Self>;
+
+    fn into_value(self) -> Value;
+
+    fn is_empty(&self) -> bool;
+}
+
+/// Narrows the keyspace to the collection at `key`, creating an empty one
+/// first if `create` is set. Yields `Ok(None)` for a missing key.
+pub(super) fn collection_mut<'a, T: Collection>(
+    map: &'a mut Keyspace,
+    key: &[u8],
+    create: bool,
+) -> Result<Option<&'a mut T>, TypeError> {
+    evict_expired(map, key, Instant::now());
+
+    match map
+        .get(key)
+        .inspect(|entry| entry.touch())
+        .map(Entry::value)
+    {
+        Some(value) if T::narrow(value).is_some() => {}
+        Some(_) => return Err(TypeError::WrongType),
+        None if create => {
+            map.insert(key.into(), Entry::new(T::default().into_value(), None));
+        }
+        None => return Ok(None),
+    }
+
+    Ok(map
+        .get_mut(key)
+        .and_then(|entry| T::narrow_mut(entry.value_mut())))
+}
+
+impl Database {
+    /// Read-locks the keyspace and narrows the guard to the collection at
+    /// `key`. Yields `Ok(None)` for a missing key.
+    pub(super) async fn get_collection<T: Collection>(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<RwLockReadGuard<'_, T>>, TypeError> {
+        let now = Instant::now();
+        let guard = self.map.read().await;
+
+        let guard =
+            match RwLockReadGuard::try_map(guard, |map| live(map, key, now).and_then(T::narrow)) {
+                Ok(collection) => return Ok(Some(collection)),
+                Err(guard) => guard,
+            };
+
+        match live(&guard, key, now) {
+            Some(_) => Err(TypeError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Write-locks the keyspace and runs `f` over the collection at `key`,
+    /// see [`collection_mut`]. The key is removed if `f` leaves it empty.
+    pub(super) async fn with_collection_mut<T: Collection, R>(
+        &self,
+        key: &[u8],
+        create: bool,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Result<Option<R>, TypeError> {
+        let mut guard = self.map.write().await;
+
+        self.tracked(&mut guard, &[key], |map| {
+            let Some(collection) = collection_mut::<T>(map, key, create)? else {
+                return Ok(None);
+            };
+
+            let result = f(collection);
+
+            if collection.is_empty() {
+                map.remove(key);
+            }
+
+            Ok(Some(result))
+        })
+    }
+
+    /// Like [`Database::with_collection_mut`] over the first of `keys` that
+    /// exists, which for collections means the first non-empty one, returning
+    /// that key with the result. A key of the wrong type before it fails.
+    pub(super) async fn with_first_collection_mut<T: Collection, R>(
+        &self,
+        keys: &[impl AsRef<[u8]>],
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Result<Option<(Box<[u8]>, R)>, TypeError> {
+        let mut guard = self.map.write().await;
+
+        self.tracked(&mut guard, keys, |map| {
+            for key in keys {
+                let key = key.as_ref();
+
+                let Some(collection) = collection_mut::<T>(map, key, false)? else {
+                    continue;
+                };
+
+                let result = f(collection);
+
+                if collection.is_empty() {
+                    map.remove(key);
+                }
+
+                return Ok(Some((Box::from(key), result)));
+            }
+
+            Ok(None)
+        })
+    }
+}
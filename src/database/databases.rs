@@ -0,0 +1,212 @@
+use std::ops::Index;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::error::{DbIndexError, MemoryError};
+use super::Database;
+
+/// The numbered databases SELECT switches between. Each has a keyspace of
+/// its own, while the limits and `maxmemory` are shared by all of them.
+#[derive(Debug)]
+pub struct Databases {
+    databases: Box<[Arc<Database>]>,
+    pub(super) saving: Arc<AtomicBool>,
+    /// Microseconds a background save waits before writing, as set by
+    /// DEBUG SLEEP-AFTER-FORK-SECONDS.
+    save_delay: AtomicU64,
+}
+
+impl Databases {
+    /// Makes `first` database 0, followed by empty databases with its limits
+    /// up to `count`. There is always at least the first one.
+    pub fn new(first: Arc<Database>, count: usize) -> Self {
+        let siblings: Vec<_> = (1..count).map(|_| Arc::new(first.sibling())).collect();
+
+        Self {
+            databases: std::iter::once(first).chain(siblings).collect(),
+            saving: Arc::default(),
+            save_delay: AtomicU64::new(0),
+        }
+    }
+
+    /// Makes every later BGSAVE wait `delay` before writing the file, so
+    /// tests can observe a save in progress.
+    pub fn set_save_delay(&self, delay: Duration) {
+        let micros = u64::try_from(delay.as_micros()).unwrap_or(u64::MAX);
+        self.save_delay.store(micros, Ordering::Relaxed);
+    }
+
+    pub(super) fn save_delay(&self) -> Duration {
+        Duration::from_micros(self.save_delay.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.databases.len()
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = &Database> {
+        self.databases.iter().map(AsRef::as_ref)
+    }
+
+    /// Checks that `index`, as a client gave it, names one of the databases.
+    pub fn checked_index(&self, index: i64) -> Result<usize, DbIndexError> {
+        usize::try_from(index)
+            .ok()
+            .filter(|&index| index < self.len())
+            .ok_or(DbIndexError::OutOfRange)
+    }
+
+    /// Swaps the keyspaces of databases `a` and `b`, so clients using either
+    /// see the other's keys from then on. Clients blocked on a list that
+    /// exists after the swap are served right away.
+    pub async fn swap(&self, a: i64, b: i64) -> Result<(), DbIndexError> {
+        let (a, b) = (self.checked_index(a)?, self.checked_index(b)?);
+
+        if a == b {
+            return Ok(());
+        }
+
+        // Always locked lowest index first, so two swaps cannot deadlock.
+        let first = &self.databases[a.min(b)];
+        let second = &self.databases[a.max(b)];
+
+        let mut first_map = first.map.write().await;
+        let mut second_map = second.map.write().await;
+        std::mem::swap(&mut *first_map, &mut *second_map);
+
+        first.serve_all_blocked(&mut first_map);
+        second.serve_all_blocked(&mut second_map);
+
+        Ok(())
+    }
+
+    /// Like [`Database::reclaim_memory_with`], but evicts from the databases
+    /// in order, moving on to the next once one has no key left to evict.
+    /// `on_evict` is also given the index of the database evicted from.
+    pub async fn reclaim_memory(
+        &self,
+        mut on_evict: impl FnMut(usize, &[u8]),
+    ) -> Result<(), MemoryError> {
+        for (index, database) in self.iter().enumerate() {
+            let reclaimed = database
+                .reclaim_memory_with(|key| on_evict(index, key))
+                .await;
+
+            if reclaimed.is_ok() {
+                return Ok(());
+            }
+        }
+
+        Err(MemoryError::OutOfMemory)
+    }
+}
+
+impl Index<usize> for Databases {
+    type Output = Database;
+
+    fn index(&self, index: usize) -> &Database {
+        &self.databases[index]
+    }
+}
+
+impl From<Database> for Databases {
+    fn from(database: Database) -> Self {
+        Self::new(Arc::new(database), 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::{ListSide, MaxMemoryPolicy};
+    use super::*;
+
+    fn databases(count: usize) -> Databases {
+        Databases::new(Arc::new(Database::new()), count)
+    }
+
+    #[tokio::test]
+    async fn test_checked_index() {
+        let databases = databases(16);
+
+        assert_eq!(Ok(0), databases.checked_index(0));
+        assert_eq!(Ok(15), databases.checked_index(15));
+        assert_eq!(Err(DbIndexError::OutOfRange), databases.checked_index(16));
+        assert_eq!(Err(DbIndexError::OutOfRange), databases.checked_index(-1));
+
+        assert_eq!(1, Databases::new(Arc::new(Database::new()), 0).len());
+    }
+
+    #[tokio::test]
+    async fn test_swap_exchanges_keyspaces() {
+        let databases = databases(16);
+        databases[0].set(b"zero", "0", None).await.unwrap();
+        databases[1].set(b"one", "1", None).await.unwrap();
+
+        assert_eq!(Ok(()), databases.swap(0, 1).await);
+        assert!(databases[0].get(b"zero").await.is_none());
+        assert!(databases[0].get(b"one").await.is_some());
+        assert!(databases[1].get(b"zero").await.is_some());
+
+        assert_eq!(Ok(()), databases.swap(1, 1).await);
+        assert!(databases[1].get(b"zero").await.is_some());
+
+        assert_eq!(Err(DbIndexError::OutOfRange), databases.swap(0, 16).await);
+    }
+
+    #[tokio::test]
+    async fn test_swap_serves_blocked_clients() {
+        let databases = Arc::new(databases(2));
+        databases[1].rpush(b"list", &["a"]).await.unwrap();
+
+        let cl = Arc::clone(&databases);
+        let waiter =
+            tokio::spawn(async move { cl[0].blocking_pop(&["list"], ListSide::Left, None).await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        databases.swap(0, 1).await.unwrap();
+
+        let popped = waiter.await.unwrap().unwrap();
+        assert_eq!(
+            Some((Box::from(&b"list"[..]), Box::from(&b"a"[..]))),
+            popped
+        );
+        assert!(databases[0].get(b"list").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_siblings_share_maxmemory() {
+        let first = Database::new().with_max_memory(2000, MaxMemoryPolicy::AllKeysRandom);
+        let databases = Databases::new(Arc::new(first), 2);
+
+        for key in 0..20 {
+            databases[0]
+                .set(
+                    format!("key:{key}").as_bytes(),
+                    "x".repeat(100).as_str(),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        // Database 1 has nothing to evict, so room is made in database 0.
+        assert!(databases[1].reclaim_memory().await.is_err());
+
+        let mut evicted = Vec::new();
+        let reclaimed = databases
+            .reclaim_memory(|index, key| evicted.push((index, key.to_vec())))
+            .await;
+
+        assert_eq!(Ok(()), reclaimed);
+        assert!(!evicted.is_empty());
+        for (index, key) in evicted {
+            assert_eq!(0, index);
+            assert!(databases[0].get(key).await.is_none());
+        }
+        assert!(databases[0].used_memory() <= 2000);
+        assert_eq!(databases[0].used_memory(), databases[1].used_memory());
+    }
+}
@@ -0,0 +1,68 @@
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+pub enum TypeError {
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+pub enum IncrError {
+    #[error(transparent)]
+    Type(#[from] TypeError),
+
+    #[error("ERR value is not an integer or out of range")]
+    NotInteger,
+
+    #[error("ERR increment or decrement would overflow")]
+    Overflow,
+
+    #[error("ERR hash value is not an integer")]
+    HashNotInteger,
+
+    #[error("ERR hash value is not a float")]
+    HashNotFloat,
+
+    #[error("ERR increment would produce NaN or Infinity")]
+    NotFinite,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+pub enum StringError {
+    #[error(transparent)]
+    Type(#[from] TypeError),
+
+    #[error("ERR string exceeds maximum allowed size (proto-max-value-size)")]
+    TooLarge,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SaveError {
+    #[error("ERR Background save already in progress")]
+    InProgress,
+
+    #[error("ERR {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+pub enum DbIndexError {
+    #[error("ERR DB index is out of range")]
+    OutOfRange,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+pub enum MemoryError {
+    #[error("OOM command not allowed when used memory > 'maxmemory'.")]
+    OutOfMemory,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+pub enum LsetError {
+    #[error(transparent)]
+    Type(#[from] TypeError),
+
+    #[error("ERR no such key")]
+    KeyNotFound,
+
+    #[error("ERR index out of range")]
+    IndexOutOfRange,
+}
@@ -0,0 +1,964 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use rand::seq::IteratorRandom;
+use tokio::sync::RwLockReadGuard;
+
+use super::collection::Collection;
+use super::keys::{scan_page, scan_position, ScanOrder};
+use super::{parse_integer, Database, IncrError, TypeError, Value};
+
+/// Fields with their values, plus the fields in SCAN order so HSCAN only
+/// walks the page it returns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hash {
+    fields: HashMap<Box<[u8]>, Box<[u8]>>,
+    order: ScanOrder,
+}
+
+pub type FieldValue = (Box<[u8]>, Box<[u8]>);
+
+/// A field HRANDFIELD picked, with its value when asked for.
+pub type RandomField = (Box<[u8]>, Option<Box<[u8]>>);
+
+/// HRANDFIELD replies with a single field without a count and an array
+/// with one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HrandfieldResult {
+    /// No count was given: a field, `None` for a missing key.
+    Single(Option<Box<[u8]>>),
+    /// A positive count: distinct fields, at most the whole hash.
+    Distinct(Vec<RandomField>),
+    /// A negative count: `count` picks out of `choices`, which may repeat.
+    /// Picking is left to the caller, as there may be far more picks than
+    /// fit in memory.
+    Repeated {
+        choices: Vec<RandomField>,
+        count: u64,
+    },
+}
+
+impl Hash {
+    /// Sets `field` to `value`, returning the value it replaced.
+    pub fn insert(&mut self, field: &[u8], value: Box<[u8]>) -> Option<Box<[u8]>> {
+        if let Some(old) = self.fields.get_mut(field) {
+            return Some(std::mem::replace(old, value));
+        }
+
+        self.order.insert((scan_position(field), Box::from(field)));
+        self.fields.insert(Box::from(field), value);
+        None
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Box<[u8]>> {
+        let (field, value) = self.fields.remove_entry(field)?;
+        self.order.remove(&(scan_position(&field), field));
+        Some(value)
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&[u8]> {
+        self.fields.get(field).map(AsRef::as_ref)
+    }
+
+    pub fn contains_key(&self, field: &[u8]) -> bool {
+        self.fields.contains_key(field)
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.fields
+            .iter()
+            .map(|(field, value)| (field.as_ref(), value.as_ref()))
+    }
+
+    /// Estimated bytes the fields and values take, each field stored once
+    /// in the map and once more in the SCAN order.
+    pub fn memory_usage(&self) -> usize {
+        self.iter()
+            .map(|(field, value)| {
+                size_of::<(Box<[u8]>, Box<[u8]>)>()
+                    + size_of::<(u64, Box<[u8]>)>()
+                    + 2 * field.len()
+                    + value.len()
+            })
+            .sum()
+    }
+}
+
+/// Formats a float the way HINCRBYFLOAT stores and replies with it: in plain
+/// decimal notation, with the fewest of up to 17 significant digits that
+/// read back as the same number.
+pub fn format_float(value: f64) -> String {
+    // 17 significant digits always read back as the same number.
+    let scientific = (0..16)
+        .map(|precision| format!("{value:.precision$e}"))
+        .find(|formatted| formatted.parse() == Ok(value))
+        .unwrap_or_else(|| format!("{value:.16e}"));
+
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let digits = mantissa.replace('.', "");
+
+    // Where the decimal point goes, counted in digits from the left.
+    let point = exponent.parse::<isize>().unwrap_or(0) + 1;
+
+    match usize::try_from(point) {
+        Err(_) | Ok(0) => format!("{sign}0.{}{digits}", "0".repeat(point.unsigned_abs())),
+        Ok(point) if point >= digits.len() => {
+            format!("{sign}{digits}{}", "0".repeat(point - digits.len()))
+        }
+        Ok(point) => format!("{sign}{}.{}", &digits[..point], &digits[point..]),
+    }
+}
+
+impl Collection for Hash {
+    fn narrow(value: &Value) -> Option<&Self> {
+        match value {
+            Value::Hash(hash) => Some(hash),
+            _ => None,
+        }
+    }
+
+    fn narrow_mut(value: &mut Value) -> Option<&mut Self> {
+        match value {
+            Value::Hash(hash) => Some(hash),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Hash(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        Hash::is_empty(self)
+    }
+}
+
+impl Database {
+    /// Sets every field/value pair, returning how many fields were newly
+    /// created rather than overwritten.
+    pub async fn hset(
+        &self,
+        key: &[u8],
+        pairs: &[(impl AsRef<[u8]>, impl AsRef<[u8]>)],
+    ) -> Result<usize, TypeError> {
+        let created = self
+            .with_hash_mut(key, true, |hash| {
+                pairs
+                    .iter()
+                    .filter(|(field, value)| {
+                        hash.insert(field.as_ref(), Box::from(value.as_ref()))
+                            .is_none()
+                    })
+                    .count()
+            })
+            .await?;
+
+        Ok(created.unwrap_or(0))
+    }
+
+    /// Sets `field` only if the hash does not have it yet, returning whether
+    /// it was set.
+    pub async fn hsetnx(&self, key: &[u8], field: &[u8], value: &[u8]) -> Result<bool, TypeError> {
+        let set = self
+            .with_hash_mut(key, true, |hash| {
+                let vacant = !hash.contains_key(field);
+                if vacant {
+                    hash.insert(field, Box::from(value));
+                }
+
+                vacant
+            })
+            .await?;
+
+        Ok(set.unwrap_or(false))
+    }
+
+    pub async fn hget(&self, key: &[u8], field: &[u8]) -> Result<Option<Box<[u8]>>, TypeError> {
+        Ok(self
+            .get_hash(key)
+            .await?
+            .and_then(|hash| hash.get(field).map(Box::from)))
+    }
+
+    /// The values of `fields` in the order they were asked for, `None` for
+    /// each that is missing.
+    pub async fn hmget(
+        &self,
+        key: &[u8],
+        fields: &[impl AsRef<[u8]>],
+    ) -> Result<Vec<Option<Box<[u8]>>>, TypeError> {
+        let hash = self.get_hash(key).await?;
+
+        Ok(fields
+            .iter()
+            .map(|field| hash.as_ref()?.get(field.as_ref()).map(Box::from))
+            .collect())
+    }
+
+    pub async fn hgetall(&self, key: &[u8]) -> Result<Vec<FieldValue>, TypeError> {
+        Ok(self.get_hash(key).await?.map_or_else(Vec::new, |hash| {
+            hash.iter()
+                .map(|(field, value)| (field.into(), value.into()))
+                .collect()
+        }))
+    }
+
+    /// Every field of the hash, in the order HVALS and HGETALL list them as
+    /// long as the hash is not changed in between.
+    pub async fn hkeys(&self, key: &[u8]) -> Result<Vec<Box<[u8]>>, TypeError> {
+        Ok(self.get_hash(key).await?.map_or_else(Vec::new, |hash| {
+            hash.iter().map(|(field, _)| field.into()).collect()
+        }))
+    }
+
+    pub async fn hvals(&self, key: &[u8]) -> Result<Vec<Box<[u8]>>, TypeError> {
+        Ok(self.get_hash(key).await?.map_or_else(Vec::new, |hash| {
+            hash.iter().map(|(_, value)| value.into()).collect()
+        }))
+    }
+
+    pub async fn hexists(&self, key: &[u8], field: &[u8]) -> Result<bool, TypeError> {
+        Ok(self
+            .get_hash(key)
+            .await?
+            .is_some_and(|hash| hash.contains_key(field)))
+    }
+
+    /// Number of fields in the hash, 0 for a missing key.
+    pub async fn hlen(&self, key: &[u8]) -> Result<usize, TypeError> {
+        Ok(self.get_hash(key).await?.map_or(0, |hash| hash.len()))
+    }
+
+    /// Length of the value in `field`, 0 if the field or key is missing.
+    pub async fn hstrlen(&self, key: &[u8], field: &[u8]) -> Result<usize, TypeError> {
+        Ok(self
+            .get_hash(key)
+            .await?
+            .and_then(|hash| hash.get(field).map(|value| value.len()))
+            .unwrap_or(0))
+    }
+
+    /// Random fields of the hash at `key`, as HRANDFIELD picks them: one
+    /// without a `count`, distinct ones up to the whole hash for a positive
+    /// count, and `-count` that may repeat for a negative one. Values are
+    /// only included `with_values`.
+    pub async fn hrandfield(
+        &self,
+        key: &[u8],
+        count: Option<i64>,
+        with_values: bool,
+    ) -> Result<HrandfieldResult, TypeError> {
+        let hash = self.get_hash(key).await?;
+        let len = hash.as_ref().map_or(0, |hash| hash.len());
+        let pairs = hash.iter().flat_map(|hash| hash.iter());
+
+        let mut rng = rand::thread_rng();
+        let pick =
+            |(field, value): (&[u8], &[u8])| (field.into(), with_values.then(|| value.into()));
+
+        Ok(match count {
+            None => HrandfieldResult::Single(pairs.choose(&mut rng).map(|(field, _)| field.into())),
+            Some(count) if count >= 0 => {
+                // Clamped first, as the picks are allocated for up front.
+                let count = usize::try_from(count).map_or(len, |count| count.min(len));
+
+                HrandfieldResult::Distinct(
+                    pairs
+                        .choose_multiple(&mut rng, count)
+                        .into_iter()
+                        .map(pick)
+                        .collect(),
+                )
+            }
+            Some(count) => HrandfieldResult::Repeated {
+                choices: pairs.map(pick).collect(),
+                count: count.unsigned_abs(),
+            },
+        })
+    }
+
+    /// Like [`Database::scan`], over the fields of the hash at `key`, which
+    /// are returned with their values.
+    pub async fn hscan(
+        &self,
+        key: &[u8],
+        cursor: u64,
+        pattern: Option<&[u8]>,
+        count: usize,
+    ) -> Result<(u64, Vec<FieldValue>), TypeError> {
+        let Some(hash) = self.get_hash(key).await? else {
+            return Ok((0, Vec::new()));
+        };
+
+        let (next, fields) = scan_page(&hash.order, cursor, pattern, count);
+
+        Ok((
+            next,
+            fields
+                .into_iter()
+                .map(|field| (Box::from(field), hash.fields[field].clone()))
+                .collect(),
+        ))
+    }
+
+    /// Adds `delta` to the integer in `field`, starting from 0 for a missing
+    /// field or key, and returns the result.
+    pub async fn hincrby(&self, key: &[u8], field: &[u8], delta: i64) -> Result<i64, IncrError> {
+        let result = self
+            .with_hash_mut(key, true, |hash| {
+                let current = match hash.get(field) {
+                    Some(value) => parse_integer(value).ok_or(IncrError::HashNotInteger)?,
+                    None => 0,
+                };
+
+                let result = current.checked_add(delta).ok_or(IncrError::Overflow)?;
+                hash.insert(field, Box::from(result.to_string().as_bytes()));
+
+                Ok(result)
+            })
+            .await?;
+
+        result.unwrap_or(Ok(delta))
+    }
+
+    /// Adds `delta` to the number in `field` like [`Database::hincrby`]. The
+    /// result is stored the way [`format_float`] formats it.
+    pub async fn hincrbyfloat(
+        &self,
+        key: &[u8],
+        field: &[u8],
+        delta: f64,
+    ) -> Result<f64, IncrError> {
+        let result = self
+            .with_hash_mut(key, true, |hash| {
+                let current = match hash.get(field) {
+                    Some(value) => std::str::from_utf8(value)
+                        .ok()
+                        .and_then(|value| value.parse::<f64>().ok())
+                        .filter(|value| !value.is_nan())
+                        .ok_or(IncrError::HashNotFloat)?,
+                    None => 0.0,
+                };
+
+                let result = current + delta;
+                if !result.is_finite() {
+                    return Err(IncrError::NotFinite);
+                }
+
+                hash.insert(field, Box::from(format_float(result).as_bytes()));
+
+                Ok(result)
+            })
+            .await?;
+
+        result.unwrap_or(Ok(delta))
+    }
+
+    /// Removes `fields`, returning how many existed. The key is deleted once
+    /// its last field is gone.
+    pub async fn hdel(&self, key: &[u8], fields: &[impl AsRef<[u8]>]) -> Result<usize, TypeError> {
+        let removed = self
+            .with_hash_mut(key, false, |hash| {
+                fields
+                    .iter()
+                    .filter(|field| hash.remove(field.as_ref()).is_some())
+                    .count()
+            })
+            .await?;
+
+        Ok(removed.unwrap_or(0))
+    }
+
+    pub(super) async fn get_hash(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<RwLockReadGuard<'_, Hash>>, TypeError> {
+        self.get_collection(key).await
+    }
+
+    pub(super) async fn with_hash_mut<T>(
+        &self,
+        key: &[u8],
+        create: bool,
+        f: impl FnOnce(&mut Hash) -> T,
+    ) -> Result<Option<T>, TypeError> {
+        self.with_collection_mut(key, create, f).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::super::Entry;
+    use super::*;
+
+    fn bytes(value: &str) -> Box<[u8]> {
+        Box::from(value.as_bytes())
+    }
+
+    #[tokio::test]
+    async fn test_hset_counts_new_fields() {
+        let database = Database::new();
+
+        assert_eq!(
+            Ok(2),
+            database.hset(b"hash", &[("a", "1"), ("b", "2")]).await
+        );
+        assert_eq!(
+            Ok(1),
+            database.hset(b"hash", &[("a", "3"), ("c", "4")]).await
+        );
+
+        assert_eq!(Ok(Some(bytes("3"))), database.hget(b"hash", b"a").await);
+        assert_eq!(Ok(None), database.hget(b"hash", b"missing").await);
+        assert_eq!(Ok(None), database.hget(b"missing", b"a").await);
+    }
+
+    #[tokio::test]
+    async fn test_hgetall() {
+        let database = Database::new();
+        database
+            .hset(b"hash", &[("a", "1"), ("b", "2")])
+            .await
+            .unwrap();
+
+        let mut pairs = database.hgetall(b"hash").await.unwrap();
+        pairs.sort();
+
+        assert_eq!(
+            vec![(bytes("a"), bytes("1")), (bytes("b"), bytes("2"))],
+            pairs
+        );
+        assert_eq!(Ok(vec![]), database.hgetall(b"missing").await);
+    }
+
+    #[tokio::test]
+    async fn test_hrandfield() {
+        let database = Database::new();
+        let pairs: Vec<_> = (0..5).map(|i| (format!("f{i}"), format!("v{i}"))).collect();
+        database.hset(b"hash", &pairs).await.unwrap();
+
+        let distinct = |result| match result {
+            Ok(HrandfieldResult::Distinct(picked)) => picked,
+            result => panic!("expected distinct fields, got {result:?}"),
+        };
+
+        let picked = distinct(database.hrandfield(b"hash", Some(3), false).await);
+        let fields: HashSet<_> = picked.iter().map(|(field, _)| field.clone()).collect();
+        assert_eq!(3, fields.len());
+        assert!(picked.iter().all(|(_, value)| value.is_none()));
+
+        let picked = distinct(database.hrandfield(b"hash", Some(10), true).await);
+        let fields: HashSet<_> = picked.iter().map(|(field, _)| field.clone()).collect();
+        assert_eq!(5, picked.len());
+        assert_eq!(5, fields.len());
+
+        for (field, value) in picked {
+            assert_eq!(field[1..], value.unwrap()[1..]);
+        }
+
+        let picked = distinct(database.hrandfield(b"hash", Some(i64::MAX), false).await);
+        assert_eq!(5, picked.len());
+
+        // Negative counts leave the picking, which may repeat, to the caller.
+        let Ok(HrandfieldResult::Repeated { choices, count }) =
+            database.hrandfield(b"hash", Some(-20), true).await
+        else {
+            panic!("expected repeated fields");
+        };
+        assert_eq!(20, count);
+        assert_eq!(5, choices.len());
+
+        let Ok(HrandfieldResult::Single(Some(field))) =
+            database.hrandfield(b"hash", None, false).await
+        else {
+            panic!("expected a single field");
+        };
+        assert!(fields.contains(&field));
+
+        assert_eq!(
+            Ok(HrandfieldResult::Distinct(vec![])),
+            database.hrandfield(b"hash", Some(0), true).await
+        );
+        assert_eq!(
+            Ok(HrandfieldResult::Single(None)),
+            database.hrandfield(b"missing", None, false).await
+        );
+        assert_eq!(
+            Ok(HrandfieldResult::Distinct(vec![])),
+            database.hrandfield(b"missing", Some(1), false).await
+        );
+        assert_eq!(
+            Ok(HrandfieldResult::Repeated {
+                choices: vec![],
+                count: 1
+            }),
+            database.hrandfield(b"missing", Some(-1), false).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hsetnx() {
+        let database = Database::new();
+
+        assert_eq!(Ok(true), database.hsetnx(b"hash", b"field", b"first").await);
+        assert_eq!(
+            Ok(false),
+            database.hsetnx(b"hash", b"field", b"second").await
+        );
+        assert_eq!(Ok(true), database.hsetnx(b"hash", b"other", b"value").await);
+
+        assert_eq!(
+            Ok(Some(bytes("first"))),
+            database.hget(b"hash", b"field").await
+        );
+        assert_eq!(
+            Ok(Some(bytes("value"))),
+            database.hget(b"hash", b"other").await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hmget() {
+        let database = Database::new();
+        database
+            .hset(b"hash", &[("a", "1"), ("b", "2")])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Ok(vec![
+                Some(bytes("2")),
+                None,
+                Some(bytes("1")),
+                Some(bytes("1"))
+            ]),
+            database.hmget(b"hash", &["b", "missing", "a", "a"]).await
+        );
+        assert_eq!(
+            Ok(vec![None, None]),
+            database.hmget(b"missing", &["a", "b"]).await
+        );
+
+        let fields: &[&[u8]] = &[b"a", b"b"];
+        assert_eq!(
+            Ok(vec![Some(bytes("1")), Some(bytes("2"))]),
+            database.hmget(b"hash", fields).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hkeys_and_hvals() {
+        let database = Database::new();
+        database
+            .hset(b"hash", &[(&b"\0a\xff"[..], &b"1\r\n"[..]), (b"b", b"2")])
+            .await
+            .unwrap();
+
+        let mut fields = database.hkeys(b"hash").await.unwrap();
+        fields.sort();
+        assert_eq!(vec![Box::from(&b"\0a\xff"[..]), bytes("b")], fields);
+
+        let mut values = database.hvals(b"hash").await.unwrap();
+        values.sort();
+        assert_eq!(vec![Box::from(&b"1\r\n"[..]), bytes("2")], values);
+
+        assert_eq!(Ok(vec![]), database.hkeys(b"missing").await);
+        assert_eq!(Ok(vec![]), database.hvals(b"missing").await);
+    }
+
+    #[tokio::test]
+    async fn test_hkeys_and_hvals_line_up() {
+        let database = Database::new();
+        let pairs: Vec<_> = (0..100)
+            .map(|n| (format!("field:{n}"), format!("value:{n}")))
+            .collect();
+        database.hset(b"hash", &pairs).await.unwrap();
+
+        let fields = database.hkeys(b"hash").await.unwrap();
+        let values = database.hvals(b"hash").await.unwrap();
+        let together: Vec<_> = fields.into_iter().zip(values).collect();
+
+        assert_eq!(100, together.len());
+        assert_eq!(database.hgetall(b"hash").await.unwrap(), together);
+    }
+
+    #[tokio::test]
+    async fn test_hincrby() {
+        let database = Database::new();
+
+        assert_eq!(Ok(5), database.hincrby(b"hash", b"counter", 5).await);
+        assert_eq!(Ok(-2), database.hincrby(b"hash", b"counter", -7).await);
+        assert_eq!(
+            Ok(Some(bytes("-2"))),
+            database.hget(b"hash", b"counter").await
+        );
+
+        database
+            .hset(b"hash", &[("text", "abc"), ("max", "9223372036854775807")])
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(IncrError::HashNotInteger),
+            database.hincrby(b"hash", b"text", 1).await
+        );
+        assert_eq!(
+            Err(IncrError::Overflow),
+            database.hincrby(b"hash", b"max", 1).await
+        );
+        assert_eq!(
+            Ok(Some(bytes("9223372036854775807"))),
+            database.hget(b"hash", b"max").await
+        );
+
+        for value in ["+1", " 1", "1 ", "01", "-0", "", "-"] {
+            database.hset(b"hash", &[("loose", value)]).await.unwrap();
+            assert_eq!(
+                Err(IncrError::HashNotInteger),
+                database.hincrby(b"hash", b"loose", 1).await,
+                "{value:?}"
+            );
+        }
+        database.hset(b"hash", &[("zero", "0")]).await.unwrap();
+        assert_eq!(Ok(-1), database.hincrby(b"hash", b"zero", -1).await);
+        database.hdel(b"hash", &["loose", "zero"]).await.unwrap();
+
+        // A failed increment does not leave an empty hash behind.
+        assert_eq!(
+            Err(IncrError::HashNotInteger),
+            database.hincrby(b"hash", b"text", 1).await
+        );
+        database
+            .hdel(b"hash", &["counter", "text", "max"])
+            .await
+            .unwrap();
+        assert!(database.get(b"hash").await.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_hincr_by_concurrently() {
+        let database = Arc::new(Database::new());
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let database = Arc::clone(&database);
+                tokio::spawn(async move {
+                    for _ in 0..100 {
+                        database.hincrby(b"hash", b"counter", 1).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(
+            Ok(Some(bytes("800"))),
+            database.hget(b"hash", b"counter").await
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_hsetnx_concurrently() {
+        let database = Arc::new(Database::new());
+
+        let tasks: Vec<_> = (0..16)
+            .map(|task| {
+                let database = Arc::clone(&database);
+                tokio::spawn(async move {
+                    let value = task.to_string();
+                    let set = database
+                        .hsetnx(b"hash", b"field", value.as_bytes())
+                        .await
+                        .unwrap();
+
+                    set.then_some(value)
+                })
+            })
+            .collect();
+
+        let mut winners = Vec::new();
+        for task in tasks {
+            winners.extend(task.await.unwrap());
+        }
+
+        assert_eq!(1, winners.len());
+        assert_eq!(
+            Ok(Some(bytes(&winners[0]))),
+            database.hget(b"hash", b"field").await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hscan_covers_every_field() {
+        let database = Database::new();
+        let pairs: Vec<_> = (0..5000)
+            .map(|i| (format!("f{i}"), format!("v{i}")))
+            .collect();
+        database.hset(b"hash", &pairs).await.unwrap();
+
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+
+        loop {
+            let (next, pairs) = database.hscan(b"hash", cursor, None, 100).await.unwrap();
+            for (field, value) in pairs {
+                assert_eq!(field[1..], value[1..]);
+                assert!(seen.insert(field));
+            }
+
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        assert_eq!(5000, seen.len());
+        assert_eq!(
+            Ok((0, vec![])),
+            database.hscan(b"missing", 0, None, 10).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hscan_match() {
+        let database = Database::new();
+        let pairs: Vec<_> = (0..5000).map(|i| (format!("f{i}"), "x")).collect();
+        database.hset(b"hash", &pairs).await.unwrap();
+
+        let mut matched = Vec::new();
+        let mut cursor = 0;
+
+        loop {
+            let (next, pairs) = database
+                .hscan(b"hash", cursor, Some(b"f1*"), 500)
+                .await
+                .unwrap();
+            matched.extend(pairs.into_iter().map(|(field, _)| field));
+
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        // f1, f10 to f19, f100 to f199 and f1000 to f1999.
+        assert_eq!(1111, matched.len());
+        assert!(matched.iter().all(|field| field.starts_with(b"f1")));
+    }
+
+    #[tokio::test]
+    async fn test_hincrbyfloat() {
+        let database = Database::new();
+
+        assert_eq!(Ok(10.5), database.hincrbyfloat(b"hash", b"f", 10.5).await);
+        assert_eq!(Ok(10.6), database.hincrbyfloat(b"hash", b"f", 0.1).await);
+        assert_eq!(Ok(Some(bytes("10.6"))), database.hget(b"hash", b"f").await);
+
+        database
+            .hset(
+                b"hash",
+                &[("exp", "5.0e3"), ("text", "abc"), ("nan", "nan")],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            Ok(5200.0),
+            database.hincrbyfloat(b"hash", b"exp", 200.0).await
+        );
+        assert_eq!(
+            Ok(Some(bytes("5200"))),
+            database.hget(b"hash", b"exp").await
+        );
+
+        assert_eq!(
+            Err(IncrError::HashNotFloat),
+            database.hincrbyfloat(b"hash", b"text", 1.0).await
+        );
+        assert_eq!(
+            Err(IncrError::HashNotFloat),
+            database.hincrbyfloat(b"hash", b"nan", 1.0).await
+        );
+        assert_eq!(
+            Err(IncrError::NotFinite),
+            database.hincrbyfloat(b"hash", b"f", f64::INFINITY).await
+        );
+        assert_eq!(Ok(Some(bytes("10.6"))), database.hget(b"hash", b"f").await);
+    }
+
+    #[tokio::test]
+    async fn test_hincr_by_float_formatting() {
+        let database = Database::new();
+
+        for (delta, stored) in [
+            (0.1, "0.1"),
+            (0.2, "0.30000000000000004"),
+            (-0.30000000000000004, "0"),
+            (1e-7, "0.0000001"),
+            (-1e21, "-1000000000000000000000"),
+        ] {
+            database.hincrbyfloat(b"hash", b"f", delta).await.unwrap();
+            assert_eq!(Ok(Some(bytes(stored))), database.hget(b"hash", b"f").await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hdel_removes_key_with_last_field() {
+        let database = Database::new();
+        database
+            .hset(b"hash", &[("a", "1"), ("b", "2")])
+            .await
+            .unwrap();
+
+        assert_eq!(Ok(1), database.hdel(b"hash", &["a", "missing"]).await);
+        assert_eq!(Ok(1), database.hdel(b"hash", &["b"]).await);
+        assert!(database.map.read().await.get(b"hash" as &[u8]).is_none());
+        assert_eq!(Ok(0), database.hdel(b"hash", &["b"]).await);
+        assert_eq!(Ok(0), database.hlen(b"hash").await);
+
+        database.hset(b"hash", &[("a", "1")]).await.unwrap();
+        let fields: &[&[u8]] = &[b"a", b"a"];
+        assert_eq!(Ok(1), database.hdel(b"hash", fields).await);
+
+        // With its last field gone, the key is free to hold another type.
+        assert_eq!(Ok(1), database.rpush(b"hash", &[b"a"]).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_hexists() {
+        let database = Database::new();
+        database.hset(b"hash", &[("a", "1")]).await.unwrap();
+
+        assert_eq!(Ok(true), database.hexists(b"hash", b"a").await);
+        assert_eq!(Ok(false), database.hexists(b"hash", b"b").await);
+        assert_eq!(Ok(false), database.hexists(b"missing", b"a").await);
+
+        let mut hash = Hash::default();
+        hash.insert(b"a", bytes("1"));
+        database.map.write().await.insert(
+            bytes("expiring"),
+            Entry::new(Value::Hash(hash), Some(Duration::from_secs(1))),
+        );
+        assert_eq!(Ok(true), database.hexists(b"expiring", b"a").await);
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(Ok(false), database.hexists(b"expiring", b"a").await);
+        assert_eq!(Ok(0), database.hdel(b"expiring", &["a"]).await);
+    }
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!("3", format_float(3.0));
+        assert_eq!("10.6", format_float(10.5 + 0.1));
+        assert_eq!("-3.5", format_float(-3.5));
+        assert_eq!("0.30000000000000004", format_float(0.1 + 0.2));
+        assert_eq!("0.000001", format_float(1e-6));
+        assert_eq!("100000000000000000000", format_float(1e20));
+        assert_eq!("0", format_float(0.0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_hlen_and_hstrlen() {
+        let database = Database::new();
+        database
+            .hset(b"hash", &[("a", "1"), ("b", "hello")])
+            .await
+            .unwrap();
+
+        assert_eq!(Ok(2), database.hlen(b"hash").await);
+        assert_eq!(Ok(0), database.hlen(b"missing").await);
+
+        let mut hash = Hash::default();
+        for n in 0..3 {
+            hash.insert(n.to_string().as_bytes(), bytes("x"));
+        }
+        database.map.write().await.insert(
+            bytes("expiring"),
+            Entry::new(Value::Hash(hash), Some(Duration::from_secs(1))),
+        );
+        assert_eq!(Ok(3), database.hlen(b"expiring").await);
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(Ok(0), database.hlen(b"expiring").await);
+
+        assert_eq!(Ok(5), database.hstrlen(b"hash", b"b").await);
+        assert_eq!(Ok(1), database.hstrlen(b"hash", b"a").await);
+        assert_eq!(Ok(0), database.hstrlen(b"hash", b"missing").await);
+        assert_eq!(Ok(0), database.hstrlen(b"missing", b"a").await);
+    }
+
+    #[tokio::test]
+    async fn test_hash_wrong_type() {
+        let database = Database::new();
+        database.rpush(b"list", &[b"a"]).await.unwrap();
+
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.hset(b"list", &[("a", "1")]).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.hget(b"list", b"a").await
+        );
+        assert_eq!(Err(TypeError::WrongType), database.hgetall(b"list").await);
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.hsetnx(b"list", b"a", b"1").await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.hmget(b"list", &["a"]).await
+        );
+        assert_eq!(Err(TypeError::WrongType), database.hkeys(b"list").await);
+        assert_eq!(
+            Err(TypeError::WrongType.into()),
+            database.hincrby(b"list", b"a", 1).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType.into()),
+            database.hincrbyfloat(b"list", b"a", 1.0).await
+        );
+        assert_eq!(Err(TypeError::WrongType), database.hvals(b"list").await);
+        assert_eq!(Err(TypeError::WrongType), database.hlen(b"list").await);
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.hscan(b"list", 0, None, 10).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.hrandfield(b"list", Some(1), false).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.hstrlen(b"list", b"a").await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.hexists(b"list", b"a").await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.hdel(b"list", &["a"]).await
+        );
+
+        database.hset(b"hash", &[("a", "1")]).await.unwrap();
+        assert_eq!(Err(TypeError::WrongType), database.llen(b"hash").await);
+    }
+}
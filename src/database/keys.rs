@@ -0,0 +1,196 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+
+use tokio::time::Instant;
+
+use super::Database;
+use crate::glob::glob_match;
+
+/// Names kept in SCAN order beside the map they name, so a scan only walks
+/// the page it returns.
+pub(super) type ScanOrder = BTreeSet<(u64, Box<[u8]>)>;
+
+/// Where a key falls in SCAN order. The hasher is unseeded, so a key keeps
+/// its position for as long as the server runs.
+pub(super) fn scan_position(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Database {
+    /// Every live key matching the glob `pattern`, matched on the raw key
+    /// bytes so binary keys are found too.
+    pub async fn keys(&self, pattern: &[u8]) -> Vec<Box<[u8]>> {
+        let guard = self.map.read().await;
+        let now = Instant::now();
+
+        guard
+            .iter()
+            .filter(|(key, entry)| !entry.is_expired(now) && glob_match(pattern, key))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Visits about `count` keys from `cursor` on, returning the cursor to
+    /// continue from, 0 once every key was visited, and those of them that
+    /// match `pattern`. Keys are visited in an order that does not change as
+    /// others are added or removed, so a key that exists for the whole scan
+    /// is returned exactly once.
+    pub async fn scan(
+        &self,
+        cursor: u64,
+        pattern: Option<&[u8]>,
+        count: usize,
+    ) -> (u64, Vec<Box<[u8]>>) {
+        let guard = self.map.read().await;
+        let now = Instant::now();
+
+        let (next, keys) = scan_page(guard.scan_order(), cursor, pattern, count);
+        let keys = keys
+            .into_iter()
+            .filter(|key| guard.get(key).is_some_and(|entry| !entry.is_expired(now)))
+            .map(Box::from)
+            .collect();
+
+        (next, keys)
+    }
+}
+
+/// One page of a cursor scan over the names in `order`, as SCAN and HSCAN
+/// return them: about `count` names from `cursor` on, of which those
+/// matching `pattern` are kept, and the cursor to continue from.
+pub(super) fn scan_page<'a>(
+    order: &'a ScanOrder,
+    cursor: u64,
+    pattern: Option<&[u8]>,
+    count: usize,
+) -> (u64, Vec<&'a [u8]>) {
+    let mut last = None;
+    let mut found = Vec::new();
+
+    for (visited, (position, name)) in order.range((cursor, Box::default())..).enumerate() {
+        // Names sharing a position are visited together, as the cursor
+        // cannot point between them.
+        if visited >= count.max(1) && last != Some(position) {
+            return (*position, found);
+        }
+
+        last = Some(position);
+
+        if pattern.map_or(true, |pattern| glob_match(pattern, name)) {
+            found.push(name.as_ref());
+        }
+    }
+
+    (0, found)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    const BINARY: &[u8] = b"bin:\xff\x00\xfe";
+
+    #[tokio::test]
+    async fn test_keys_matches_binary_keys() {
+        let database = Database::new();
+        database.set(BINARY, "1", None).await.unwrap();
+        database.set(b"text", "2", None).await.unwrap();
+
+        let mut keys = database.keys(b"*").await;
+        keys.sort();
+        assert_eq!(vec![Box::from(BINARY), Box::from(&b"text"[..])], keys);
+
+        assert_eq!(
+            vec![Box::from(BINARY)],
+            database.keys(b"bin:[\xf0-\xff]*").await
+        );
+        assert_eq!(vec![Box::from(BINARY)], database.keys(b"bin:?\x00?").await);
+        assert!(database.keys(b"bin:[^\xff]*").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scan_visits_every_key_once() {
+        let database = Database::new();
+        for key in 0..100 {
+            database
+                .set(format!("key:{key}").as_bytes(), "x", None)
+                .await
+                .unwrap();
+        }
+        database.set(BINARY, "1", None).await.unwrap();
+
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+
+        loop {
+            let (next, keys) = database.scan(cursor, None, 7).await;
+            for key in keys {
+                assert!(seen.insert(key));
+            }
+
+            // Keys added mid-scan may or may not show up, but must not
+            // disturb the rest.
+            database
+                .set(format!("added:{cursor}").as_bytes(), "x", None)
+                .await
+                .unwrap();
+
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        for key in 0..100 {
+            assert!(seen.contains(format!("key:{key}").as_bytes()));
+        }
+        assert!(seen.contains(BINARY));
+    }
+
+    #[test]
+    fn test_scan_page_walks_only_the_page() {
+        let order: ScanOrder = (0..10u8)
+            .map(|n| (scan_position(&[n]), Box::from([n].as_slice())))
+            .collect();
+        let names: Vec<_> = order.iter().map(|(_, name)| name.as_ref()).collect();
+        let positions: Vec<_> = order.iter().map(|(position, _)| *position).collect();
+
+        assert_eq!(
+            (positions[3], names[..3].to_vec()),
+            scan_page(&order, 0, None, 3)
+        );
+        assert_eq!(
+            (positions[6], names[3..6].to_vec()),
+            scan_page(&order, positions[3], None, 3)
+        );
+        assert_eq!(
+            (0, names[7..].to_vec()),
+            scan_page(&order, positions[7], None, 3)
+        );
+        assert_eq!(
+            (positions[5], vec![names[4]]),
+            scan_page(&order, positions[4], Some(names[4]), 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scan_match_on_binary_keys() {
+        let database = Database::new();
+        database.set(BINARY, "1", None).await.unwrap();
+        database.set(b"text", "2", None).await.unwrap();
+
+        assert_eq!(
+            (0, vec![Box::from(BINARY)]),
+            database.scan(0, Some(b"*\xfe"), 10).await
+        );
+        assert_eq!(
+            (0, vec![Box::from(BINARY)]),
+            database.scan(0, Some(b"bin:[\xf0-\xff]*"), 10).await
+        );
+    }
+}
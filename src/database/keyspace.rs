@@ -0,0 +1,203 @@
+use indexmap::map::Entry as Slot;
+use indexmap::{IndexMap, IndexSet};
+use rand::seq::index;
+use rand::Rng;
+
+use super::keys::{scan_position, ScanOrder};
+use super::Entry;
+
+/// The keys of one database. Keys are kept in insertion slots so eviction
+/// can sample them in constant time, and those with a timeout are tracked
+/// apart so volatile eviction and the expiry sweep only look at them. Every
+/// key is also kept in SCAN order, so a scan only walks the page it returns.
+#[derive(Debug, Default)]
+pub(super) struct Keyspace {
+    entries: IndexMap<Box<[u8]>, Entry>,
+    volatile: IndexSet<Box<[u8]>>,
+    order: ScanOrder,
+}
+
+impl Keyspace {
+    pub(super) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: IndexMap::with_capacity(capacity),
+            volatile: IndexSet::new(),
+            order: ScanOrder::new(),
+        }
+    }
+
+    #[inline]
+    pub(super) fn get(&self, key: &[u8]) -> Option<&Entry> {
+        self.entries.get(key)
+    }
+
+    /// The entry at `key`, to change its value in place. Its timeout must
+    /// not change, as only [`Keyspace::insert`] tracks timeouts.
+    #[inline]
+    pub(super) fn get_mut(&mut self, key: &[u8]) -> Option<&mut Entry> {
+        self.entries.get_mut(key)
+    }
+
+    #[inline]
+    pub(super) fn contains_key(&self, key: &[u8]) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub(super) fn insert(&mut self, key: Box<[u8]>, entry: Entry) -> Option<Entry> {
+        if entry.expire.is_some() {
+            self.volatile.insert(key.clone());
+        } else {
+            self.volatile.swap_remove(key.as_ref());
+        }
+
+        match self.entries.entry(key) {
+            Slot::Occupied(mut slot) => Some(slot.insert(entry)),
+            Slot::Vacant(slot) => {
+                self.order
+                    .insert((scan_position(slot.key()), slot.key().clone()));
+                slot.insert(entry);
+                None
+            }
+        }
+    }
+
+    /// Removes the entry at `key`. The last key takes its slot, so the
+    /// order of keys changes.
+    pub(super) fn remove(&mut self, key: &[u8]) -> Option<Entry> {
+        let (_, key, entry) = self.entries.swap_remove_full(key)?;
+
+        if entry.expire.is_some() {
+            self.volatile.swap_remove(&key);
+        }
+
+        self.order.remove(&(scan_position(&key), key));
+
+        Some(entry)
+    }
+
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[cfg(test)]
+    pub(super) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = (&Box<[u8]>, &Entry)> {
+        self.entries.iter()
+    }
+
+    /// Every key in SCAN order, expired or not.
+    pub(super) fn scan_order(&self) -> &ScanOrder {
+        &self.order
+    }
+
+    /// The keys that have a timeout, expired or not.
+    pub(super) fn volatile_keys(&self) -> impl Iterator<Item = &Box<[u8]>> {
+        self.volatile.iter()
+    }
+
+    /// Up to `amount` distinct keys picked at random, with their entries,
+    /// in time that does not grow with the keyspace.
+    pub(super) fn sample<'a>(
+        &'a self,
+        rng: &mut impl Rng,
+        amount: usize,
+    ) -> impl Iterator<Item = (&'a Box<[u8]>, &'a Entry)> {
+        index::sample(rng, self.entries.len(), amount.min(self.entries.len()))
+            .into_iter()
+            .filter_map(|index| self.entries.get_index(index))
+    }
+
+    /// Like [`Keyspace::sample`], among the keys that have a timeout.
+    pub(super) fn sample_volatile<'a>(
+        &'a self,
+        rng: &mut impl Rng,
+        amount: usize,
+    ) -> impl Iterator<Item = (&'a Box<[u8]>, &'a Entry)> {
+        index::sample(rng, self.volatile.len(), amount.min(self.volatile.len()))
+            .into_iter()
+            .filter_map(|index| {
+                self.entries
+                    .get_key_value(self.volatile.get_index(index)?.as_ref())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::Value;
+    use super::*;
+
+    fn entry(timeout: Option<u64>) -> Entry {
+        Entry::new(Value::Integer(1), timeout.map(Duration::from_secs))
+    }
+
+    fn volatile(keyspace: &Keyspace) -> Vec<&[u8]> {
+        let mut keys: Vec<_> = keyspace.volatile_keys().map(AsRef::as_ref).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn test_tracks_keys_with_timeout() {
+        let mut keyspace = Keyspace::default();
+
+        keyspace.insert(b"a".as_slice().into(), entry(Some(10)));
+        keyspace.insert(b"b".as_slice().into(), entry(None));
+        keyspace.insert(b"c".as_slice().into(), entry(Some(10)));
+        assert_eq!(vec![b"a".as_slice(), b"c"], volatile(&keyspace));
+
+        // Overwriting without a timeout drops it, and removing forgets it.
+        keyspace.insert(b"a".as_slice().into(), entry(None));
+        keyspace.insert(b"b".as_slice().into(), entry(Some(10)));
+        keyspace.remove(b"c");
+        assert_eq!(vec![b"b".as_slice()], volatile(&keyspace));
+        assert_eq!(2, keyspace.len());
+    }
+
+    #[test]
+    fn test_keeps_keys_in_scan_order() {
+        let mut keyspace = Keyspace::default();
+
+        keyspace.insert(b"a".as_slice().into(), entry(None));
+        keyspace.insert(b"b".as_slice().into(), entry(Some(10)));
+        keyspace.insert(b"a".as_slice().into(), entry(Some(10)));
+        keyspace.remove(b"b");
+        keyspace.insert(b"c".as_slice().into(), entry(None));
+
+        let mut keys: Vec<_> = keyspace
+            .scan_order()
+            .iter()
+            .map(|(_, key)| key.as_ref())
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(vec![b"a".as_slice(), b"c"], keys);
+    }
+
+    #[test]
+    fn test_samples_distinct_keys() {
+        let mut keyspace = Keyspace::default();
+        let mut rng = rand::thread_rng();
+        assert_eq!(0, keyspace.sample(&mut rng, 5).count());
+
+        for key in 0..100u8 {
+            let timeout = (key % 10 == 0).then_some(10);
+            keyspace.insert([key].as_slice().into(), entry(timeout));
+        }
+
+        let mut sampled: Vec<_> = keyspace.sample(&mut rng, 5).map(|(key, _)| key).collect();
+        sampled.sort_unstable();
+        sampled.dedup();
+        assert_eq!(5, sampled.len());
+
+        assert_eq!(10, keyspace.sample_volatile(&mut rng, 50).count());
+        assert!(keyspace
+            .sample_volatile(&mut rng, 5)
+            .all(|(key, entry)| key[0] % 10 == 0 && entry.deadline().is_some()));
+    }
+}
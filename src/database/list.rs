@@ -0,0 +1,1460 @@
+use std::collections::{vec_deque, VecDeque};
+use std::iter::FusedIterator;
+use std::mem::size_of;
+
+use tokio::sync::RwLockReadGuard;
+
+use super::collection::{collection_mut, Collection};
+use super::listpack::{self, ListPack};
+use super::{Database, Keyspace, LsetError, TypeError, Value};
+
+const DEFAULT_MAX_PACKED_ENTRIES: usize = 128;
+const DEFAULT_MAX_PACKED_VALUE: usize = 64;
+
+/// How large a list may grow before it leaves the compact [`ListPack`]
+/// encoding, like Redis' `list-max-listpack-*` settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ListLimits {
+    /// Most elements a packed list holds.
+    pub max_entries: usize,
+
+    /// Largest element, in bytes, a packed list holds.
+    pub max_value: usize,
+}
+
+impl Default for ListLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_PACKED_ENTRIES,
+            max_value: DEFAULT_MAX_PACKED_VALUE,
+        }
+    }
+}
+
+/// A list value. Small lists are packed into a single buffer and switch to
+/// a deque of separately allocated elements once they exceed their
+/// [`ListLimits`], never switching back.
+#[derive(Debug, Clone)]
+pub enum List {
+    Packed(ListPack),
+    Linked(VecDeque<Box<[u8]>>),
+}
+
+impl Default for List {
+    fn default() -> Self {
+        List::Packed(ListPack::default())
+    }
+}
+
+/// Lists are equal when their elements are, whatever the encodings.
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl List {
+    pub fn len(&self) -> usize {
+        match self {
+            List::Packed(pack) => pack.len(),
+            List::Linked(deque) => deque.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The name OBJECT ENCODING reports for the list.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            List::Packed(_) => "listpack",
+            List::Linked(_) => "linkedlist",
+        }
+    }
+
+    /// Bytes the list takes, counting allocated capacity but not allocator
+    /// overhead.
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + match self {
+                List::Packed(pack) => pack.capacity(),
+                List::Linked(deque) => {
+                    deque.capacity() * size_of::<Box<[u8]>>()
+                        + deque.iter().map(|value| value.len()).sum::<usize>()
+                }
+            }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        match self {
+            List::Packed(pack) => Iter::Packed(pack.iter()),
+            List::Linked(deque) => Iter::Linked(deque.iter()),
+        }
+    }
+
+    /// The element at `index`, which a packed list has to walk to.
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        match self {
+            List::Packed(pack) => pack.get(index),
+            List::Linked(deque) => deque.get(index).map(AsRef::as_ref),
+        }
+    }
+
+    pub fn push_front(&mut self, value: Box<[u8]>, limits: ListLimits) {
+        self.fit(1, &value, limits);
+
+        match self {
+            List::Packed(pack) => pack.push_front(&value),
+            List::Linked(deque) => deque.push_front(value),
+        }
+    }
+
+    pub fn push_back(&mut self, value: Box<[u8]>, limits: ListLimits) {
+        self.fit(1, &value, limits);
+
+        match self {
+            List::Packed(pack) => pack.push_back(&value),
+            List::Linked(deque) => deque.push_back(value),
+        }
+    }
+
+    pub fn pop_front(&mut self) -> Option<Box<[u8]>> {
+        match self {
+            List::Packed(pack) => pack.pop_front(),
+            List::Linked(deque) => deque.pop_front(),
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<Box<[u8]>> {
+        match self {
+            List::Packed(pack) => pack.pop_back(),
+            List::Linked(deque) => deque.pop_back(),
+        }
+    }
+
+    /// Inserts `value` so that it ends up at `index`, which may be the
+    /// length to append.
+    pub fn insert(&mut self, index: usize, value: Box<[u8]>, limits: ListLimits) {
+        self.fit(1, &value, limits);
+
+        match self {
+            List::Packed(pack) => pack.insert(index, &value),
+            List::Linked(deque) => deque.insert(index, value),
+        }
+    }
+
+    /// Replaces the element at `index`, returning whether there was one.
+    pub fn replace(&mut self, index: usize, value: &[u8], limits: ListLimits) -> bool {
+        if index >= self.len() {
+            return false;
+        }
+
+        self.fit(0, value, limits);
+
+        match self {
+            List::Packed(pack) => pack.replace(index, value),
+            List::Linked(deque) => {
+                deque[index] = Box::from(value);
+                true
+            }
+        }
+    }
+
+    /// Keeps only the elements in `start..=stop`, both within bounds.
+    pub fn keep(&mut self, start: usize, stop: usize) {
+        match self {
+            List::Packed(pack) => pack.keep(start, stop),
+            List::Linked(deque) => {
+                deque.truncate(stop + 1);
+                deque.drain(..start);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match self {
+            List::Packed(pack) => pack.clear(),
+            List::Linked(deque) => deque.clear(),
+        }
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&[u8]) -> bool) {
+        match self {
+            List::Packed(pack) => pack.retain(f),
+            List::Linked(deque) => deque.retain(|value| f(value)),
+        }
+    }
+
+    /// Leaves the packed encoding if growing by `additional` elements or
+    /// storing `value` would exceed `limits`.
+    fn fit(&mut self, additional: usize, value: &[u8], limits: ListLimits) {
+        if let List::Packed(pack) = self {
+            if pack.len() + additional > limits.max_entries || value.len() > limits.max_value {
+                *self = List::Linked(pack.iter().map(Box::from).collect());
+            }
+        }
+    }
+}
+
+/// Walks the elements of a [`List`] in either encoding.
+#[derive(Debug, Clone)]
+pub enum Iter<'a> {
+    Packed(listpack::Iter<'a>),
+    Linked(vec_deque::Iter<'a, Box<[u8]>>),
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::Packed(iter) => iter.next(),
+            Iter::Linked(iter) => iter.next().map(AsRef::as_ref),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Iter::Packed(iter) => iter.size_hint(),
+            Iter::Linked(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::Packed(iter) => iter.next_back(),
+            Iter::Linked(iter) => iter.next_back().map(AsRef::as_ref),
+        }
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+impl FusedIterator for Iter<'_> {}
+
+/// A key and the elements LMPOP or ZMPOP popped from it.
+pub type MultiPopped<T> = (Box<[u8]>, Vec<T>);
+
+/// LPOP and RPOP reply with a single element without a count and an array
+/// with one. Either is `None` for a missing key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PopResult {
+    Single(Option<Box<[u8]>>),
+    Multi(Option<Vec<Box<[u8]>>>),
+}
+
+/// LPOS replies with a single index without COUNT and an array with it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LposResult {
+    Single(Option<usize>),
+    Multi(Vec<usize>),
+}
+
+/// Which side of the pivot LINSERT puts the new element on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListPosition {
+    Before,
+    After,
+}
+
+/// The end of a list LMOVE pops from or pushes to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListSide {
+    Left,
+    Right,
+}
+
+impl ListSide {
+    pub(super) fn pop(self, list: &mut List) -> Option<Box<[u8]>> {
+        match self {
+            ListSide::Left => list.pop_front(),
+            ListSide::Right => list.pop_back(),
+        }
+    }
+
+    pub(super) fn push(self, list: &mut List, value: Box<[u8]>, limits: ListLimits) {
+        match self {
+            ListSide::Left => list.push_front(value, limits),
+            ListSide::Right => list.push_back(value, limits),
+        }
+    }
+}
+
+/// Resolves a possibly negative `index` against a list of `len` elements.
+fn normalize_index(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+
+    usize::try_from(index).ok().filter(|&index| index < len)
+}
+
+/// Resolves an inclusive `start..=stop` range, where negative indices count
+/// from the tail, against a list of `len` elements. Returns `None` when the
+/// range selects nothing.
+pub(super) fn normalize_range(len: usize, start: i64, stop: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+
+    if start > stop || start >= len {
+        None
+    } else {
+        Some((start as usize, stop as usize))
+    }
+}
+
+impl Collection for List {
+    fn narrow(value: &Value) -> Option<&Self> {
+        match value {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    fn narrow_mut(value: &mut Value) -> Option<&mut Self> {
+        match value {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::List(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        List::is_empty(self)
+    }
+}
+
+impl Database {
+    pub async fn lpush(&self, key: &[u8], values: &[impl AsRef<[u8]>]) -> Result<usize, TypeError> {
+        self.push(key, values, true, ListSide::Left).await
+    }
+
+    pub async fn rpush(&self, key: &[u8], values: &[impl AsRef<[u8]>]) -> Result<usize, TypeError> {
+        self.push(key, values, true, ListSide::Right).await
+    }
+
+    /// Like [`Database::lpush`], but only when the key already holds a list.
+    /// Returns 0 without creating anything otherwise.
+    pub async fn lpushx(
+        &self,
+        key: &[u8],
+        values: &[impl AsRef<[u8]>],
+    ) -> Result<usize, TypeError> {
+        self.push(key, values, false, ListSide::Left).await
+    }
+
+    /// Like [`Database::rpush`], but only when the key already holds a list.
+    /// Returns 0 without creating anything otherwise.
+    pub async fn rpushx(
+        &self,
+        key: &[u8],
+        values: &[impl AsRef<[u8]>],
+    ) -> Result<usize, TypeError> {
+        self.push(key, values, false, ListSide::Right).await
+    }
+
+    /// Returns the inclusive `start..=stop` window of the list. Only the
+    /// selected elements are copied out while the read lock is held.
+    pub async fn lrange(
+        &self,
+        key: &[u8],
+        start: i64,
+        stop: i64,
+    ) -> Result<Vec<Box<[u8]>>, TypeError> {
+        Ok(match self.get_list(key).await? {
+            Some(list) => match normalize_range(list.len(), start, stop) {
+                Some((start, stop)) => list
+                    .iter()
+                    .skip(start)
+                    .take(stop - start + 1)
+                    .map(Box::from)
+                    .collect(),
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        })
+    }
+
+    /// Returns the element at `index`, where negative indices count from the
+    /// tail. Redis documents LINDEX as O(N); that holds for packed lists,
+    /// while the VecDeque of larger ones makes it O(1). LRANGE remains the
+    /// way to read more than one element under a single lock.
+    pub async fn lindex(&self, key: &[u8], index: i64) -> Result<Option<Box<[u8]>>, TypeError> {
+        Ok(self.get_list(key).await?.and_then(|list| {
+            normalize_index(list.len(), index).and_then(|index| list.get(index).map(Box::from))
+        }))
+    }
+
+    /// Replaces the element at `index`, where negative indices count from
+    /// the tail.
+    pub async fn lset(&self, key: &[u8], index: i64, value: &[u8]) -> Result<(), LsetError> {
+        self.with_list_mut(key, false, |list| {
+            let index = normalize_index(list.len(), index).ok_or(LsetError::IndexOutOfRange)?;
+
+            list.replace(index, value, self.list_limits);
+            Ok(())
+        })
+        .await?
+        .unwrap_or(Err(LsetError::KeyNotFound))
+    }
+
+    pub async fn llen(&self, key: &[u8]) -> Result<usize, TypeError> {
+        Ok(self.get_list(key).await?.map_or(0, |list| list.len()))
+    }
+
+    /// Returns the indices of elements equal to `element`, skipping the
+    /// first `|rank| - 1` matches and searching from the tail when `rank` is
+    /// negative. Without a `count` only the first index is returned, while
+    /// `Some(0)` returns all of them. A non-zero `maxlen` limits the search
+    /// to that many elements. `rank` must not be zero.
+    pub async fn lpos(
+        &self,
+        key: &[u8],
+        element: &[u8],
+        rank: i64,
+        count: Option<usize>,
+        maxlen: usize,
+    ) -> Result<LposResult, TypeError> {
+        let limit = match count {
+            Some(0) => usize::MAX,
+            Some(count) => count,
+            None => 1,
+        };
+
+        let found: Vec<usize> = match self.get_list(key).await? {
+            Some(list) => {
+                let scan = if maxlen == 0 { list.len() } else { maxlen };
+                let skip = rank.unsigned_abs().saturating_sub(1) as usize;
+
+                let hits = list.iter().map(|value| value == element);
+                let found_at = |(idx, hit): (usize, bool)| hit.then_some(idx);
+
+                if rank > 0 {
+                    let matches = hits.enumerate().take(scan).filter_map(found_at);
+                    matches.skip(skip).take(limit).collect()
+                } else {
+                    let matches = hits.enumerate().rev().take(scan).filter_map(found_at);
+                    matches.skip(skip).take(limit).collect()
+                }
+            }
+            None => Vec::new(),
+        };
+
+        Ok(match count {
+            Some(_) => LposResult::Multi(found),
+            None => LposResult::Single(found.first().copied()),
+        })
+    }
+
+    /// Keeps only the elements in the inclusive `start..=stop` range, with
+    /// the same index rules as [`Database::lrange`]. The key is deleted if
+    /// the range selects nothing.
+    pub async fn ltrim(&self, key: &[u8], start: i64, stop: i64) -> Result<(), TypeError> {
+        self.with_list_mut(key, false, |list| {
+            match normalize_range(list.len(), start, stop) {
+                Some((start, stop)) => list.keep(start, stop),
+                None => list.clear(),
+            }
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Inserts `element` next to the first element equal to `pivot` and
+    /// returns the new length, `-1` if there is no such element, or `0` if
+    /// the key does not exist. Both finding the pivot and shifting elements
+    /// to make room are O(N), as neither encoding inserts cheaply in the
+    /// middle.
+    pub async fn linsert(
+        &self,
+        key: &[u8],
+        position: ListPosition,
+        pivot: &[u8],
+        element: &[u8],
+    ) -> Result<i64, TypeError> {
+        let len = self
+            .with_list_mut(key, false, |list| {
+                let Some(idx) = list.iter().position(|value| value == pivot) else {
+                    return -1;
+                };
+
+                let idx = match position {
+                    ListPosition::Before => idx,
+                    ListPosition::After => idx + 1,
+                };
+
+                list.insert(idx, Box::from(element), self.list_limits);
+                list.len() as i64
+            })
+            .await?;
+
+        Ok(len.unwrap_or(0))
+    }
+
+    /// Removes elements equal to `element` and returns how many went: the
+    /// first `count` from the head when `count` is positive, the last
+    /// `-count` from the tail when negative, and all of them when zero. The
+    /// key is deleted if the list empties.
+    pub async fn lrem(&self, key: &[u8], count: i64, element: &[u8]) -> Result<usize, TypeError> {
+        let removed = self
+            .with_list_mut(key, false, |list| {
+                let limit = match count {
+                    0 => usize::MAX,
+                    count => count.unsigned_abs() as usize,
+                };
+
+                let matches = list.iter().filter(|&value| value == element).count();
+                let removed = matches.min(limit);
+
+                // Removing from the tail keeps the first matches instead.
+                let mut skip = if count < 0 { matches - removed } else { 0 };
+                let mut left = removed;
+
+                list.retain(|value| {
+                    if left == 0 || value != element {
+                        return true;
+                    }
+
+                    if skip > 0 {
+                        skip -= 1;
+                        return true;
+                    }
+
+                    left -= 1;
+                    false
+                });
+
+                removed
+            })
+            .await?;
+
+        Ok(removed.unwrap_or(0))
+    }
+
+    /// Pops an element from the `from` end of `source`, pushes it onto the
+    /// `to` end of `destination` and returns it. Both happen under a single
+    /// write lock, so the element is never missing from both lists, and the
+    /// same key may be given twice to rotate a list. A missing `source`
+    /// yields `None` and creates nothing.
+    pub async fn lmove(
+        &self,
+        source: &[u8],
+        destination: &[u8],
+        from: ListSide,
+        to: ListSide,
+    ) -> Result<Option<Box<[u8]>>, TypeError> {
+        let mut guard = self.map.write().await;
+        self.move_element(&mut guard, source, destination, from, to)
+    }
+
+    /// [`Database::lmove`] under a write lock the caller already holds.
+    pub(super) fn move_element(
+        &self,
+        guard: &mut Keyspace,
+        source: &[u8],
+        destination: &[u8],
+        from: ListSide,
+        to: ListSide,
+    ) -> Result<Option<Box<[u8]>>, TypeError> {
+        self.tracked(guard, &[source, destination], |guard| {
+            if collection_mut::<List>(guard, source, false)?.is_none() {
+                return Ok(None);
+            }
+
+            // Like Redis, a destination of the wrong type fails before popping.
+            collection_mut::<List>(guard, destination, false)?;
+
+            let Some((element, emptied)) = collection_mut::<List>(guard, source, false)?
+                .and_then(|list| Some((from.pop(list)?, list.is_empty())))
+            else {
+                return Ok(None);
+            };
+
+            if emptied {
+                guard.remove(source);
+            }
+
+            if let Some(list) = collection_mut::<List>(guard, destination, true)? {
+                to.push(list, element.clone(), self.list_limits);
+                self.serve_blocked(destination, list);
+
+                // A blocked client may have taken the element straight away.
+                if list.is_empty() {
+                    guard.remove(destination);
+                }
+            }
+
+            Ok(Some(element))
+        })
+    }
+
+    /// Pops up to `count` elements from the `side` end of the first
+    /// non-empty list among `keys`. `None` if they are all empty.
+    pub async fn lmpop(
+        &self,
+        keys: &[impl AsRef<[u8]>],
+        side: ListSide,
+        count: usize,
+    ) -> Result<Option<MultiPopped<Box<[u8]>>>, TypeError> {
+        self.with_first_collection_mut(keys, |list: &mut List| {
+            (0..count.min(list.len()))
+                .filter_map(|_| side.pop(list))
+                .collect()
+        })
+        .await
+    }
+
+    pub async fn lpop(&self, key: &[u8], count: Option<usize>) -> Result<PopResult, TypeError> {
+        self.pop(key, count, ListSide::Left).await
+    }
+
+    pub async fn rpop(&self, key: &[u8], count: Option<usize>) -> Result<PopResult, TypeError> {
+        self.pop(key, count, ListSide::Right).await
+    }
+
+    pub(super) async fn get_list(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<RwLockReadGuard<'_, List>>, TypeError> {
+        self.get_collection(key).await
+    }
+
+    pub(super) async fn with_list_mut<T>(
+        &self,
+        key: &[u8],
+        create: bool,
+        f: impl FnOnce(&mut List) -> T,
+    ) -> Result<Option<T>, TypeError> {
+        self.with_collection_mut(key, create, f).await
+    }
+
+    async fn push(
+        &self,
+        key: &[u8],
+        values: &[impl AsRef<[u8]>],
+        create: bool,
+        side: ListSide,
+    ) -> Result<usize, TypeError> {
+        let len = self
+            .with_list_mut(key, create, |list| {
+                for value in values {
+                    side.push(list, Box::from(value.as_ref()), self.list_limits);
+                }
+
+                let len = list.len();
+                self.serve_blocked(key, list);
+                len
+            })
+            .await?;
+
+        Ok(len.unwrap_or(0))
+    }
+
+    async fn pop(
+        &self,
+        key: &[u8],
+        count: Option<usize>,
+        side: ListSide,
+    ) -> Result<PopResult, TypeError> {
+        let result = self
+            .with_list_mut(key, false, |list| match count {
+                Some(count) => PopResult::Multi(Some(
+                    (0..count.min(list.len()))
+                        .filter_map(|_| side.pop(list))
+                        .collect(),
+                )),
+                None => PopResult::Single(side.pop(list)),
+            })
+            .await?;
+
+        Ok(result.unwrap_or(match count {
+            Some(_) => PopResult::Multi(None),
+            None => PopResult::Single(None),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn database_with_list() -> Database {
+        let database = Database::new();
+        database
+            .rpush(b"list", &[b"a", b"b", b"c", b"d", b"e"])
+            .await
+            .unwrap();
+
+        database
+    }
+
+    fn range(values: &[&str]) -> Vec<Box<[u8]>> {
+        values
+            .iter()
+            .map(|value| Box::from(value.as_bytes()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_lpush_prepends_in_order() {
+        let database = Database::new();
+
+        assert_eq!(Ok(3), database.lpush(b"list", &[b"a", b"b", b"c"]).await);
+        assert_eq!(Ok(4), database.rpush(b"list", &[b"d"]).await);
+
+        assert_eq!(
+            Ok(range(&["c", "b", "a", "d"])),
+            database.lrange(b"list", 0, -1).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pushx_requires_existing_list() {
+        let database = Database::new();
+
+        assert_eq!(Ok(0), database.lpushx(b"list", &[b"a"]).await);
+        assert_eq!(Ok(0), database.rpushx(b"list", &[b"a", b"b"]).await);
+        assert!(database.map.read().await.get(b"list" as &[u8]).is_none());
+
+        database.rpush(b"list", &[b"b"]).await.unwrap();
+        assert_eq!(Ok(2), database.lpushx(b"list", &[b"a"]).await);
+        assert_eq!(Ok(4), database.rpushx(b"list", &[b"c", b"d"]).await);
+        assert_eq!(
+            Ok(range(&["a", "b", "c", "d"])),
+            database.lrange(b"list", 0, -1).await
+        );
+
+        database.insert(b"key", "value", None).await;
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.lpushx(b"key", &[b"a"]).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.rpushx(b"key", &[b"a"]).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_linsert() {
+        let database = Database::new();
+        database.rpush(b"list", &["a", "c"]).await.unwrap();
+
+        assert_eq!(
+            Ok(3),
+            database
+                .linsert(b"list", ListPosition::Before, b"c", b"b")
+                .await
+        );
+        assert_eq!(
+            Ok(4),
+            database
+                .linsert(b"list", ListPosition::After, b"c", b"d")
+                .await
+        );
+        assert_eq!(
+            Ok(range(&["a", "b", "c", "d"])),
+            database.lrange(b"list", 0, -1).await
+        );
+
+        assert_eq!(
+            Ok(-1),
+            database
+                .linsert(b"list", ListPosition::Before, b"C", b"x")
+                .await
+        );
+        assert_eq!(
+            Ok(0),
+            database
+                .linsert(b"missing", ListPosition::After, b"a", b"x")
+                .await
+        );
+        assert!(database.map.read().await.get(b"missing" as &[u8]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_linsert_binary_pivot() {
+        let database = Database::new();
+        database
+            .rpush(b"list", &[b"\0\xff" as &[u8], b"\0"])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Ok(3),
+            database
+                .linsert(b"list", ListPosition::After, b"\0", b"\xff")
+                .await
+        );
+        assert_eq!(
+            Ok(vec![
+                Box::from(b"\0\xff" as &[u8]),
+                Box::from(b"\0" as &[u8]),
+                Box::from(b"\xff" as &[u8]),
+            ]),
+            database.lrange(b"list", 0, -1).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lrange_negative_indices() {
+        let database = database_with_list().await;
+
+        assert_eq!(
+            Ok(range(&["d", "e"])),
+            database.lrange(b"list", -2, -1).await
+        );
+        assert_eq!(
+            Ok(range(&["b", "c", "d"])),
+            database.lrange(b"list", 1, -2).await
+        );
+        assert_eq!(
+            Ok(range(&["a", "b"])),
+            database.lrange(b"list", -100, 1).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lrange_index_combinations() {
+        let database = database_with_list().await;
+
+        assert_eq!(
+            Ok(range(&["b", "c", "d"])),
+            database.lrange(b"list", 1, 3).await
+        );
+        assert_eq!(
+            Ok(range(&["c", "d"])),
+            database.lrange(b"list", -3, -2).await
+        );
+        assert_eq!(
+            Ok(range(&["c", "d"])),
+            database.lrange(b"list", -3, 3).await
+        );
+        assert_eq!(
+            Ok(range(&["b", "c", "d"])),
+            database.lrange(b"list", 1, -2).await
+        );
+        assert_eq!(Ok(vec![]), database.lrange(b"list", 7, -1).await);
+        assert_eq!(
+            Ok(range(&["a", "b", "c", "d", "e"])),
+            database.lrange(b"list", 0, 7).await
+        );
+        assert_eq!(Ok(vec![]), database.lrange(b"list", 4, 2).await);
+    }
+
+    #[tokio::test]
+    async fn test_lrange_out_of_range() {
+        let database = database_with_list().await;
+
+        assert_eq!(
+            Ok(range(&["d", "e"])),
+            database.lrange(b"list", 3, 100).await
+        );
+        assert_eq!(Ok(vec![]), database.lrange(b"list", 5, 10).await);
+        assert_eq!(Ok(vec![]), database.lrange(b"list", 3, 1).await);
+        assert_eq!(Ok(vec![]), database.lrange(b"list", -1, -2).await);
+        assert_eq!(Ok(vec![]), database.lrange(b"missing", 0, -1).await);
+    }
+
+    #[tokio::test]
+    async fn test_lindex() {
+        let database = database_with_list().await;
+
+        assert_eq!(
+            Ok(Some(Box::from(b"a" as &[u8]))),
+            database.lindex(b"list", 0).await
+        );
+        assert_eq!(
+            Ok(Some(Box::from(b"e" as &[u8]))),
+            database.lindex(b"list", -1).await
+        );
+        assert_eq!(
+            Ok(Some(Box::from(b"a" as &[u8]))),
+            database.lindex(b"list", -5).await
+        );
+        assert_eq!(Ok(None), database.lindex(b"list", 5).await);
+        assert_eq!(Ok(None), database.lindex(b"list", -6).await);
+        assert_eq!(Ok(None), database.lindex(b"missing", 0).await);
+    }
+
+    #[tokio::test]
+    async fn test_lset() {
+        let database = database_with_list().await;
+
+        assert_eq!(Ok(()), database.lset(b"list", 0, b"first").await);
+        assert_eq!(Ok(()), database.lset(b"list", -1, b"last").await);
+        assert_eq!(
+            Ok(range(&["first", "b", "c", "d", "last"])),
+            database.lrange(b"list", 0, -1).await
+        );
+
+        assert_eq!(
+            Err(LsetError::IndexOutOfRange),
+            database.lset(b"list", 5, b"x").await
+        );
+        assert_eq!(
+            Err(LsetError::IndexOutOfRange),
+            database.lset(b"list", -6, b"x").await
+        );
+        assert_eq!(
+            Err(LsetError::KeyNotFound),
+            database.lset(b"missing", 0, b"x").await
+        );
+        assert!(database.get(b"missing").await.is_none());
+
+        database.insert(b"string", "value", None).await;
+        assert_eq!(
+            Err(LsetError::Type(TypeError::WrongType)),
+            database.lset(b"string", 0, b"x").await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lmove_rotates_same_key() {
+        let database = database_with_list().await;
+
+        assert_eq!(
+            Ok(Some(Box::from(b"e" as &[u8]))),
+            database
+                .lmove(b"list", b"list", ListSide::Right, ListSide::Left)
+                .await
+        );
+        assert_eq!(
+            Ok(range(&["e", "a", "b", "c", "d"])),
+            database.lrange(b"list", 0, -1).await
+        );
+
+        database.rpush(b"single", &["a"]).await.unwrap();
+        assert_eq!(
+            Ok(Some(Box::from(b"a" as &[u8]))),
+            database
+                .lmove(b"single", b"single", ListSide::Left, ListSide::Right)
+                .await
+        );
+        assert_eq!(Ok(1), database.llen(b"single").await);
+    }
+
+    #[tokio::test]
+    async fn test_lmove_across_keys() {
+        let database = Database::new();
+        database.rpush(b"source", &["a", "b"]).await.unwrap();
+
+        assert_eq!(
+            Ok(Some(Box::from(b"a" as &[u8]))),
+            database
+                .lmove(b"source", b"destination", ListSide::Left, ListSide::Right)
+                .await
+        );
+        assert_eq!(
+            Ok(Some(Box::from(b"b" as &[u8]))),
+            database
+                .lmove(b"source", b"destination", ListSide::Left, ListSide::Left)
+                .await
+        );
+        assert!(database.get(b"source").await.is_none());
+        assert_eq!(
+            Ok(range(&["b", "a"])),
+            database.lrange(b"destination", 0, -1).await
+        );
+
+        assert_eq!(
+            Ok(None),
+            database
+                .lmove(b"missing", b"other", ListSide::Left, ListSide::Right)
+                .await
+        );
+        assert!(database.get(b"other").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lmove_wrong_type_destination() {
+        let database = Database::new();
+        database.rpush(b"source", &["a"]).await.unwrap();
+        database.insert(b"string", "value", None).await;
+
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database
+                .lmove(b"source", b"string", ListSide::Left, ListSide::Right)
+                .await
+        );
+        assert_eq!(Ok(1), database.llen(b"source").await);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_lmove_concurrent_never_loses_elements() {
+        let database = std::sync::Arc::new(Database::new());
+        database
+            .rpush(b"ping", &["a", "b", "c", "d"])
+            .await
+            .unwrap();
+
+        let movers = [(b"ping", b"pong"), (b"pong", b"ping")].map(|(source, destination)| {
+            let database = std::sync::Arc::clone(&database);
+
+            tokio::spawn(async move {
+                for _ in 0..500 {
+                    database
+                        .lmove(source, destination, ListSide::Left, ListSide::Right)
+                        .await
+                        .unwrap();
+                }
+            })
+        });
+
+        for mover in movers {
+            mover.await.unwrap();
+        }
+
+        let mut elements = database.lrange(b"ping", 0, -1).await.unwrap();
+        elements.extend(database.lrange(b"pong", 0, -1).await.unwrap());
+        elements.sort();
+
+        assert_eq!(range(&["a", "b", "c", "d"]), elements);
+    }
+
+    #[tokio::test]
+    async fn test_lpos() {
+        let database = Database::new();
+        database
+            .rpush(b"list", &["a", "b", "c", "1", "2", "3", "c", "c"])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Ok(LposResult::Single(Some(2))),
+            database.lpos(b"list", b"c", 1, None, 0).await
+        );
+        assert_eq!(
+            Ok(LposResult::Single(Some(6))),
+            database.lpos(b"list", b"c", 2, None, 0).await
+        );
+        assert_eq!(
+            Ok(LposResult::Single(Some(7))),
+            database.lpos(b"list", b"c", -1, None, 0).await
+        );
+        assert_eq!(
+            Ok(LposResult::Multi(vec![6, 2])),
+            database.lpos(b"list", b"c", -2, Some(0), 0).await
+        );
+        assert_eq!(
+            Ok(LposResult::Multi(vec![2, 6])),
+            database.lpos(b"list", b"c", 1, Some(2), 0).await
+        );
+        assert_eq!(
+            Ok(LposResult::Multi(vec![2])),
+            database.lpos(b"list", b"c", 1, Some(0), 6).await
+        );
+        assert_eq!(
+            Ok(LposResult::Single(None)),
+            database.lpos(b"list", b"c", 1, None, 2).await
+        );
+        assert_eq!(
+            Ok(LposResult::Single(None)),
+            database.lpos(b"list", b"c", 4, None, 0).await
+        );
+        assert_eq!(
+            Ok(LposResult::Multi(Vec::new())),
+            database.lpos(b"missing", b"c", 1, Some(0), 0).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lmpop() {
+        let database = Database::new();
+        database.rpush(b"second", &["a", "b", "c"]).await.unwrap();
+
+        assert_eq!(
+            Ok(Some((Box::from(b"second" as &[u8]), range(&["a", "b"])))),
+            database
+                .lmpop(&["first", "second"], ListSide::Left, 2)
+                .await
+        );
+        assert_eq!(
+            Ok(Some((Box::from(b"second" as &[u8]), range(&["c"])))),
+            database
+                .lmpop(&["first", "second"], ListSide::Right, 10)
+                .await
+        );
+        assert!(database.get(b"second").await.is_none());
+        assert_eq!(
+            Ok(None),
+            database
+                .lmpop(&["first", "second"], ListSide::Left, 1)
+                .await
+        );
+
+        database.insert(b"string", "value", None).await;
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database
+                .lmpop(&["first", "string"], ListSide::Left, 1)
+                .await
+        );
+    }
+
+    async fn database_with_duplicates() -> Database {
+        let database = Database::new();
+        database
+            .rpush(b"list", &["a", "b", "a", "c", "a", "b"])
+            .await
+            .unwrap();
+
+        database
+    }
+
+    #[tokio::test]
+    async fn test_lrem_from_head() {
+        let database = database_with_duplicates().await;
+
+        assert_eq!(Ok(2), database.lrem(b"list", 2, b"a").await);
+        assert_eq!(
+            Ok(range(&["b", "c", "a", "b"])),
+            database.lrange(b"list", 0, -1).await
+        );
+        assert_eq!(Ok(0), database.lrem(b"list", 1, b"missing").await);
+        assert_eq!(Ok(0), database.lrem(b"missing", 1, b"a").await);
+    }
+
+    #[tokio::test]
+    async fn test_lrem_from_tail() {
+        let database = database_with_duplicates().await;
+
+        assert_eq!(Ok(2), database.lrem(b"list", -2, b"a").await);
+        assert_eq!(
+            Ok(range(&["a", "b", "c", "b"])),
+            database.lrange(b"list", 0, -1).await
+        );
+        assert_eq!(Ok(2), database.lrem(b"list", -5, b"b").await);
+        assert_eq!(
+            Ok(range(&["a", "c"])),
+            database.lrange(b"list", 0, -1).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lrem_all() {
+        let database = database_with_duplicates().await;
+
+        assert_eq!(Ok(3), database.lrem(b"list", 0, b"a").await);
+        assert_eq!(
+            Ok(range(&["b", "c", "b"])),
+            database.lrange(b"list", 0, -1).await
+        );
+
+        database.lrem(b"list", 0, b"b").await.unwrap();
+        assert_eq!(Ok(1), database.lrem(b"list", 0, b"c").await);
+        assert!(database.map.read().await.get(b"list" as &[u8]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ltrim() {
+        let database = Database::new();
+        database
+            .rpush(b"list", &["a", "b", "c", "d", "e"])
+            .await
+            .unwrap();
+
+        assert_eq!(Ok(()), database.ltrim(b"list", 0, -1).await);
+        assert_eq!(
+            Ok(range(&["a", "b", "c", "d", "e"])),
+            database.lrange(b"list", 0, -1).await
+        );
+
+        assert_eq!(Ok(()), database.ltrim(b"list", 1, -2).await);
+        assert_eq!(
+            Ok(range(&["b", "c", "d"])),
+            database.lrange(b"list", 0, -1).await
+        );
+
+        assert_eq!(Ok(()), database.ltrim(b"list", 2, 1).await);
+        assert!(database.map.read().await.get(b"list" as &[u8]).is_none());
+
+        assert_eq!(Ok(()), database.ltrim(b"missing", 0, -1).await);
+    }
+
+    #[tokio::test]
+    async fn test_list_encoding_upgrade_boundary() {
+        let limits = ListLimits {
+            max_entries: 3,
+            max_value: 4,
+        };
+        let database = Database::new().with_list_limits(limits);
+
+        database.rpush(b"list", &["a", "b", "c"]).await.unwrap();
+        assert_eq!(Some("listpack"), database.encoding(b"list").await);
+
+        database.lset(b"list", 0, b"abcd").await.unwrap();
+        assert_eq!(Some("listpack"), database.encoding(b"list").await);
+
+        database.rpush(b"list", &["d"]).await.unwrap();
+        assert_eq!(Some("linkedlist"), database.encoding(b"list").await);
+        assert_eq!(
+            Ok(range(&["abcd", "b", "c", "d"])),
+            database.lrange(b"list", 0, -1).await
+        );
+
+        // Shrinking does not switch back.
+        database.ltrim(b"list", 0, 0).await.unwrap();
+        assert_eq!(Some("linkedlist"), database.encoding(b"list").await);
+
+        database.lpush(b"large", &["abcde"]).await.unwrap();
+        assert_eq!(Some("linkedlist"), database.encoding(b"large").await);
+
+        database.rpush(b"replaced", &["a"]).await.unwrap();
+        database.lset(b"replaced", 0, b"abcde").await.unwrap();
+        assert_eq!(Some("linkedlist"), database.encoding(b"replaced").await);
+        assert_eq!(
+            Ok(Some(Box::from(b"abcde" as &[u8]))),
+            database.lindex(b"replaced", 0).await
+        );
+
+        database.rpush(b"inserted", &["a", "b", "c"]).await.unwrap();
+        database
+            .linsert(b"inserted", ListPosition::After, b"a", b"x")
+            .await
+            .unwrap();
+        assert_eq!(Some("linkedlist"), database.encoding(b"inserted").await);
+        assert_eq!(
+            Ok(range(&["a", "x", "b", "c"])),
+            database.lrange(b"inserted", 0, -1).await
+        );
+    }
+
+    #[test]
+    fn test_packed_lists_use_less_memory() {
+        let elements = (0..100).map(|idx| Box::from(idx.to_string().as_bytes()));
+
+        let mut packed = List::default();
+        let mut linked = List::Linked(VecDeque::new());
+
+        for element in elements {
+            packed.push_back(Box::clone(&element), ListLimits::default());
+            linked.push_back(element, ListLimits::default());
+        }
+
+        assert_eq!("listpack", packed.encoding());
+        assert_eq!(packed, linked);
+        assert!(packed.memory_usage() < linked.memory_usage());
+    }
+
+    /// Runs the same commands against a database whose lists stay packed and
+    /// one whose lists are never packed, which must answer alike.
+    #[tokio::test]
+    async fn test_list_encodings_behave_alike() {
+        let linked = ListLimits {
+            max_entries: 0,
+            max_value: 0,
+        };
+
+        let mut replies = Vec::new();
+
+        for database in [Database::new(), Database::new().with_list_limits(linked)] {
+            let mut reply = Vec::new();
+
+            database
+                .rpush(b"list", &["a", "b", "c", "a"])
+                .await
+                .unwrap();
+            database.lpush(b"list", &["z", "y"]).await.unwrap();
+            reply.push(database.lrange(b"list", 0, -1).await.unwrap());
+            reply.push(database.lrange(b"list", -3, -2).await.unwrap());
+
+            database.lset(b"list", -1, b"last").await.unwrap();
+            database.lrem(b"list", 1, b"a").await.unwrap();
+            database
+                .linsert(b"list", ListPosition::Before, b"c", b"b2")
+                .await
+                .unwrap();
+            reply.push(database.lrange(b"list", 0, -1).await.unwrap());
+
+            let popped = database.rpop(b"list", Some(2)).await.unwrap();
+            let PopResult::Multi(Some(popped)) = popped else {
+                unreachable!()
+            };
+            reply.push(popped);
+            reply.extend(database.lindex(b"list", 1).await.unwrap().map(|v| vec![v]));
+
+            database
+                .lmove(b"list", b"other", ListSide::Left, ListSide::Right)
+                .await
+                .unwrap();
+            database.ltrim(b"list", 1, -1).await.unwrap();
+            reply.push(database.lrange(b"list", 0, -1).await.unwrap());
+            reply.push(database.lrange(b"other", 0, -1).await.unwrap());
+
+            replies.push(reply);
+        }
+
+        assert_eq!(replies[0], replies[1]);
+        assert_eq!(
+            vec![
+                range(&["y", "z", "a", "b", "c", "a"]),
+                range(&["b", "c"]),
+                range(&["y", "z", "b", "b2", "c", "last"]),
+                range(&["last", "c"]),
+                range(&["z"]),
+                range(&["b", "b2"]),
+                range(&["y"]),
+            ],
+            replies[0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_llen() {
+        let database = database_with_list().await;
+
+        assert_eq!(Ok(5), database.llen(b"list").await);
+        assert_eq!(Ok(0), database.llen(b"missing").await);
+
+        database.lpop(b"list", Some(5)).await.unwrap();
+        assert_eq!(Ok(0), database.llen(b"list").await);
+    }
+
+    #[tokio::test]
+    async fn test_get_list() {
+        let database = database_with_list().await;
+        database.insert(b"key", "value", None).await;
+        database
+            .insert(b"expired", 1i64, Some(std::time::Duration::ZERO))
+            .await;
+
+        assert_eq!(
+            Some(5),
+            database
+                .get_list(b"list")
+                .await
+                .unwrap()
+                .map(|list| list.len())
+        );
+        assert!(database.get_list(b"missing").await.unwrap().is_none());
+        assert!(database.get_list(b"expired").await.unwrap().is_none());
+        assert_eq!(
+            Some(TypeError::WrongType),
+            database.get_list(b"key").await.err()
+        );
+        assert_eq!(Ok(1), database.rpush(b"expired", &[b"a"]).await);
+    }
+
+    #[tokio::test]
+    async fn test_pop() {
+        let database = database_with_list().await;
+
+        assert_eq!(
+            Ok(PopResult::Single(Some(Box::from(b"a" as &[u8])))),
+            database.lpop(b"list", None).await
+        );
+        assert_eq!(
+            Ok(PopResult::Single(Some(Box::from(b"e" as &[u8])))),
+            database.rpop(b"list", None).await
+        );
+        assert_eq!(
+            Ok(PopResult::Multi(Some(range(&["d", "c"])))),
+            database.rpop(b"list", Some(2)).await
+        );
+        assert_eq!(
+            Ok(PopResult::Multi(Some(vec![]))),
+            database.lpop(b"list", Some(0)).await
+        );
+        assert_eq!(
+            Ok(PopResult::Single(None)),
+            database.lpop(b"missing", None).await
+        );
+        assert_eq!(
+            Ok(PopResult::Multi(None)),
+            database.lpop(b"missing", Some(2)).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pop_last_element_removes_key() {
+        let database = database_with_list().await;
+
+        assert_eq!(
+            Ok(PopResult::Multi(Some(range(&["a", "b", "c", "d", "e"])))),
+            database.lpop(b"list", Some(10)).await
+        );
+        assert!(database.map.read().await.get(b"list" as &[u8]).is_none());
+
+        database.rpush(b"list", &[b"a"]).await.unwrap();
+        assert_eq!(
+            Ok(PopResult::Single(Some(Box::from(b"a" as &[u8])))),
+            database.rpop(b"list", None).await
+        );
+        assert!(database.map.read().await.get(b"list" as &[u8]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_wrong_type() {
+        let database = Database::new();
+        database.insert(b"key", "value", None).await;
+
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.lpush(b"key", &[b"a"]).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.rpush(b"key", &[b"a"]).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.lrange(b"key", 0, -1).await
+        );
+        assert_eq!(Err(TypeError::WrongType), database.llen(b"key").await);
+        assert_eq!(Err(TypeError::WrongType), database.lindex(b"key", 0).await);
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.lrem(b"key", 0, b"a").await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database
+                .linsert(b"key", ListPosition::Before, b"a", b"b")
+                .await
+        );
+        assert_eq!(Err(TypeError::WrongType), database.lpop(b"key", None).await);
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.rpop(b"key", Some(1)).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.ltrim(b"key", 0, -1).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.lpos(b"key", b"a", 1, None, 0).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.lmpop(&["key"], ListSide::Left, 1).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database
+                .lmove(b"key", b"other", ListSide::Left, ListSide::Right)
+                .await
+        );
+        assert_eq!(
+            Err(LsetError::Type(TypeError::WrongType)),
+            database.lset(b"key", 0, b"a").await
+        );
+    }
+}
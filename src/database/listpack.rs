@@ -0,0 +1,305 @@
+use std::iter::FusedIterator;
+use std::mem::size_of;
+
+/// Bytes taken by the length stored on each side of an entry.
+const LEN_SIZE: usize = size_of::<u32>();
+
+/// A list packed into one contiguous buffer, akin to Redis' listpack. Every
+/// entry is its length, its bytes and its length again, so the buffer can be
+/// walked from either end without a separate index.
+///
+/// Ends are O(1) to read and pop, but anything else walks the entries and
+/// pushing to the front shifts the whole buffer, which is why it is only
+/// used for small lists.
+#[derive(Debug, Clone, Default)]
+pub struct ListPack {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+impl ListPack {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bytes held by the buffer, including unused capacity.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    pub fn push_front(&mut self, value: &[u8]) {
+        self.insert_at(0, value);
+    }
+
+    pub fn push_back(&mut self, value: &[u8]) {
+        self.insert_at(self.buf.len(), value);
+    }
+
+    pub fn pop_front(&mut self) -> Option<Box<[u8]>> {
+        self.remove(0)
+    }
+
+    pub fn pop_back(&mut self) -> Option<Box<[u8]>> {
+        self.remove(self.len.checked_sub(1)?)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        (index < self.len).then(|| self.value_at(self.offset(index)))
+    }
+
+    /// Inserts `value` so that it ends up at `index`, which may be the
+    /// length to append.
+    pub fn insert(&mut self, index: usize, value: &[u8]) {
+        assert!(index <= self.len, "listpack index out of bounds");
+        self.insert_at(self.offset(index), value);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Box<[u8]>> {
+        if index >= self.len {
+            return None;
+        }
+
+        let offset = self.offset(index);
+        let value: Box<[u8]> = Box::from(self.value_at(offset));
+
+        self.buf.drain(offset..offset + entry_size(value.len()));
+        self.len -= 1;
+
+        Some(value)
+    }
+
+    /// Replaces the entry at `index`, returning whether there was one.
+    pub fn replace(&mut self, index: usize, value: &[u8]) -> bool {
+        if index >= self.len {
+            return false;
+        }
+
+        let offset = self.offset(index);
+        let size = entry_size(self.value_at(offset).len());
+
+        self.buf.splice(offset..offset + size, encode(value));
+
+        true
+    }
+
+    /// Keeps only the entries in `start..=stop`, both within bounds.
+    pub fn keep(&mut self, start: usize, stop: usize) {
+        let (begin, end) = (self.offset(start), self.offset(stop + 1));
+
+        self.buf.truncate(end);
+        self.buf.drain(..begin);
+
+        self.len = stop + 1 - start;
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.len = 0;
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&[u8]) -> bool) {
+        let mut kept = Self::default();
+
+        for value in self.iter().filter(|value| f(value)) {
+            kept.push_back(value);
+        }
+
+        *self = kept;
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            buf: &self.buf,
+            front: 0,
+            back: self.buf.len(),
+            remaining: self.len,
+        }
+    }
+
+    fn insert_at(&mut self, offset: usize, value: &[u8]) {
+        self.buf.splice(offset..offset, encode(value));
+        self.len += 1;
+    }
+
+    /// The byte offset of the entry at `index`, walking from whichever end
+    /// is closer. The length itself maps to the end of the buffer.
+    fn offset(&self, index: usize) -> usize {
+        if index <= self.len / 2 {
+            (0..index).fold(0, |offset, _| {
+                offset + entry_size(read_len(&self.buf, offset))
+            })
+        } else {
+            (index..self.len).fold(self.buf.len(), |offset, _| {
+                offset - entry_size(read_len(&self.buf, offset - LEN_SIZE))
+            })
+        }
+    }
+
+    fn value_at(&self, offset: usize) -> &[u8] {
+        let len = read_len(&self.buf, offset);
+        &self.buf[offset + LEN_SIZE..offset + LEN_SIZE + len]
+    }
+}
+
+/// The bytes of an entry: its length, the value and its length again.
+fn encode(value: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    let len = u32::try_from(value.len())
+        .expect("listpack entries are far below 4GiB")
+        .to_le_bytes();
+
+    len.into_iter().chain(value.iter().copied()).chain(len)
+}
+
+fn entry_size(len: usize) -> usize {
+    LEN_SIZE + len + LEN_SIZE
+}
+
+fn read_len(buf: &[u8], offset: usize) -> usize {
+    let mut len = [0; LEN_SIZE];
+    len.copy_from_slice(&buf[offset..offset + LEN_SIZE]);
+    u32::from_le_bytes(len) as usize
+}
+
+/// Walks the entries of a [`ListPack`] from either end.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    buf: &'a [u8],
+    front: usize,
+    back: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let len = read_len(self.buf, self.front);
+        let value = &self.buf[self.front + LEN_SIZE..self.front + LEN_SIZE + len];
+
+        self.front += entry_size(len);
+        self.remaining -= 1;
+
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let len = read_len(self.buf, self.back - LEN_SIZE);
+        let value = &self.buf[self.back - LEN_SIZE - len..self.back - LEN_SIZE];
+
+        self.back -= entry_size(len);
+        self.remaining -= 1;
+
+        Some(value)
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+impl FusedIterator for Iter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(values: &[&str]) -> ListPack {
+        let mut pack = ListPack::default();
+
+        for value in values {
+            pack.push_back(value.as_bytes());
+        }
+
+        pack
+    }
+
+    fn values(pack: &ListPack) -> Vec<&[u8]> {
+        pack.iter().collect()
+    }
+
+    #[test]
+    fn test_push_and_pop_both_ends() {
+        let mut pack = pack(&["b", "cc"]);
+        pack.push_front(b"");
+        pack.push_front(b"a");
+
+        assert_eq!(4, pack.len());
+        assert_eq!(vec![b"a" as &[u8], b"", b"b", b"cc"], values(&pack));
+        assert_eq!(
+            vec![b"cc" as &[u8], b"b", b"", b"a"],
+            pack.iter().rev().collect::<Vec<_>>()
+        );
+
+        assert_eq!(Some(Box::from(b"cc" as &[u8])), pack.pop_back());
+        assert_eq!(Some(Box::from(b"a" as &[u8])), pack.pop_front());
+        assert_eq!(Some(Box::from(b"" as &[u8])), pack.pop_front());
+        assert_eq!(Some(Box::from(b"b" as &[u8])), pack.pop_back());
+        assert_eq!(None, pack.pop_back());
+        assert!(pack.is_empty());
+    }
+
+    #[test]
+    fn test_indexed_access() {
+        let mut pack = pack(&["a", "b", "c", "d", "e"]);
+
+        assert_eq!(Some(b"a" as &[u8]), pack.get(0));
+        assert_eq!(Some(b"d" as &[u8]), pack.get(3));
+        assert_eq!(None, pack.get(5));
+
+        pack.insert(2, b"inserted");
+        pack.insert(6, b"last");
+        assert!(pack.replace(0, b"longer than before"));
+        assert!(pack.replace(4, b""));
+        assert!(!pack.replace(7, b"missing"));
+
+        assert_eq!(
+            vec![
+                b"longer than before" as &[u8],
+                b"b",
+                b"inserted",
+                b"c",
+                b"",
+                b"e",
+                b"last"
+            ],
+            values(&pack)
+        );
+
+        assert_eq!(Some(Box::from(b"inserted" as &[u8])), pack.remove(2));
+        assert_eq!(None, pack.remove(6));
+        assert_eq!(6, pack.len());
+    }
+
+    #[test]
+    fn test_keep_and_retain() {
+        let mut pack = pack(&["a", "bb", "c", "dd", "e"]);
+
+        pack.keep(1, 3);
+        assert_eq!(vec![b"bb" as &[u8], b"c", b"dd"], values(&pack));
+        assert_eq!(Some(b"dd" as &[u8]), pack.iter().next_back());
+
+        pack.retain(|value| value.len() == 2);
+        assert_eq!(vec![b"bb" as &[u8], b"dd"], values(&pack));
+        assert_eq!(2, pack.len());
+
+        pack.clear();
+        assert!(pack.is_empty());
+        assert_eq!(0, pack.iter().len());
+    }
+}
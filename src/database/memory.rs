@@ -0,0 +1,439 @@
+use std::collections::BTreeSet;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use super::error::MemoryError;
+use super::keyspace::Keyspace;
+use super::{Database, Databases, Entry};
+
+/// How many keys are sampled to pick each one to evict, like Redis'
+/// `maxmemory-samples`.
+const EVICTION_SAMPLES: usize = 5;
+
+static ACCESS_CLOCK: AtomicU64 = AtomicU64::new(0);
+
+/// A reading of the access clock, which ticks on every key access so LRU
+/// eviction can order keys exactly without reading the time.
+pub(super) fn access_tick() -> u64 {
+    ACCESS_CLOCK.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Which keys make room once the keyspace grows past `maxmemory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MaxMemoryPolicy {
+    /// Nothing is evicted; commands that would use more memory fail instead.
+    #[default]
+    NoEviction,
+    /// The least recently used key of a random sample.
+    AllKeysLru,
+    /// Any key at random.
+    AllKeysRandom,
+    /// The key closest to expiring of a random sample of keys with a
+    /// timeout.
+    VolatileTtl,
+}
+
+impl MaxMemoryPolicy {
+    const ALL: [Self; 4] = [
+        Self::NoEviction,
+        Self::AllKeysLru,
+        Self::AllKeysRandom,
+        Self::VolatileTtl,
+    ];
+
+    /// The name `maxmemory-policy` takes for the policy.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::NoEviction => "noeviction",
+            Self::AllKeysLru => "allkeys-lru",
+            Self::AllKeysRandom => "allkeys-random",
+            Self::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    /// The policy `name` stands for, in any case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The names of every policy, separated by commas.
+    pub fn names() -> String {
+        Self::ALL.map(Self::name).join(", ")
+    }
+}
+
+/// The `maxmemory` limit a database and its siblings share, which can change
+/// while the server runs. A limit of zero bytes means there is none.
+#[derive(Debug, Default)]
+pub(super) struct MaxMemory {
+    bytes: AtomicUsize,
+    policy: AtomicU8,
+}
+
+impl MaxMemory {
+    fn load(&self) -> (usize, MaxMemoryPolicy) {
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let policy = MaxMemoryPolicy::ALL[usize::from(self.policy.load(Ordering::Relaxed))];
+
+        (bytes, policy)
+    }
+
+    fn set(&self, bytes: usize, policy: MaxMemoryPolicy) {
+        let index = MaxMemoryPolicy::ALL
+            .iter()
+            .position(|&known| known == policy)
+            .unwrap_or_default();
+
+        self.policy.store(index as u8, Ordering::Relaxed);
+        self.bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Whether there is a limit, and so memory is accounted for.
+    #[inline]
+    pub(super) fn is_set(&self) -> bool {
+        self.bytes.load(Ordering::Relaxed) != 0
+    }
+}
+
+/// Estimated bytes an entry takes in the keyspace, its key included.
+pub(super) fn entry_size(key_len: usize, entry: &Entry) -> usize {
+    key_len + size_of::<Entry>() + entry.value().memory_usage()
+}
+
+/// Moves the memory counter from `before` to `after` bytes. It never goes
+/// below zero, as the expiry sweep releases entries whether or not they
+/// were ever charged.
+pub(super) fn charge(used: &AtomicUsize, before: usize, after: usize) {
+    let _ = used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+        Some((used + after).saturating_sub(before))
+    });
+}
+
+/// Picks the key `policy` evicts next, if it has any candidate at all.
+fn eviction_candidate(map: &Keyspace, policy: MaxMemoryPolicy) -> Option<Box<[u8]>> {
+    let mut rng = rand::thread_rng();
+
+    let key = match policy {
+        MaxMemoryPolicy::NoEviction => None,
+        MaxMemoryPolicy::AllKeysRandom => map.sample(&mut rng, 1).next().map(|(key, _)| key),
+        MaxMemoryPolicy::AllKeysLru => map
+            .sample(&mut rng, EVICTION_SAMPLES)
+            .min_by_key(|(_, entry)| entry.accessed())
+            .map(|(key, _)| key),
+        MaxMemoryPolicy::VolatileTtl => map
+            .sample_volatile(&mut rng, EVICTION_SAMPLES)
+            .filter_map(|(key, entry)| Some((key, entry.deadline()?)))
+            .min_by_key(|(_, deadline)| *deadline)
+            .map(|(key, _)| key),
+    };
+
+    key.cloned()
+}
+
+impl Database {
+    /// Caps the estimated memory the keyspace may use at `bytes`, making
+    /// room by `policy` before commands that would use more. Memory is only
+    /// accounted for once a limit is set, so it has to be set before any key
+    /// is written; [`Databases::set_max_memory`] counts what is already
+    /// there. Zero bytes means no limit.
+    pub fn with_max_memory(self, bytes: usize, policy: MaxMemoryPolicy) -> Self {
+        self.max_memory.set(bytes, policy);
+        self
+    }
+
+    /// The limit in bytes, zero when there is none, and the policy that
+    /// makes room once it is reached.
+    pub fn max_memory(&self) -> (usize, MaxMemoryPolicy) {
+        self.max_memory.load()
+    }
+
+    /// Estimated bytes the keyspace takes. Always zero without a limit.
+    pub fn used_memory(&self) -> usize {
+        self.used_memory.load(Ordering::Relaxed)
+    }
+
+    /// Evicts keys by the configured policy until the memory in use is back
+    /// within the limit. Fails under `noeviction`, or once the policy has no
+    /// key left to evict.
+    pub async fn reclaim_memory(&self) -> Result<(), MemoryError> {
+        self.reclaim_memory_with(|_| {}).await
+    }
+
+    /// Like [`Database::reclaim_memory`], calling `on_evict` with each key
+    /// as it is evicted.
+    pub async fn reclaim_memory_with(
+        &self,
+        mut on_evict: impl FnMut(&[u8]),
+    ) -> Result<(), MemoryError> {
+        let (bytes, policy) = self.max_memory.load();
+
+        if bytes == 0 || self.used_memory() <= bytes {
+            return Ok(());
+        }
+
+        let mut guard = self.map.write().await;
+
+        while self.used_memory() > bytes {
+            let Some(key) = eviction_candidate(&guard, policy) else {
+                return Err(MemoryError::OutOfMemory);
+            };
+
+            self.remove_entry(&mut guard, &key);
+            on_evict(&key);
+        }
+
+        Ok(())
+    }
+
+    /// Inserts `entry` at `key`, charging it and releasing whatever entry it
+    /// replaced, which is returned.
+    pub(super) fn insert_entry(
+        &self,
+        map: &mut Keyspace,
+        key: Box<[u8]>,
+        entry: Entry,
+    ) -> Option<Entry> {
+        if !self.max_memory.is_set() {
+            return map.insert(key, entry);
+        }
+
+        let key_len = key.len();
+        let added = entry_size(key_len, &entry);
+        let old = map.insert(key, entry);
+        let removed = old.as_ref().map_or(0, |old| entry_size(key_len, old));
+        charge(&self.used_memory, removed, added);
+
+        old
+    }
+
+    /// Removes the entry at `key`, releasing what it was charged.
+    pub(super) fn remove_entry(&self, map: &mut Keyspace, key: &[u8]) -> Option<Entry> {
+        let old = map.remove(key)?;
+
+        if self.max_memory.is_set() {
+            charge(&self.used_memory, entry_size(key.len(), &old), 0);
+        }
+
+        Some(old)
+    }
+
+    /// Runs `f`, which may change the entries at `keys` in place, and
+    /// charges the difference in their size.
+    pub(super) fn tracked<R>(
+        &self,
+        map: &mut Keyspace,
+        keys: &[impl AsRef<[u8]>],
+        f: impl FnOnce(&mut Keyspace) -> R,
+    ) -> R {
+        if !self.max_memory.is_set() {
+            return f(map);
+        }
+
+        // The same key may be given twice, as when a list is rotated.
+        let keys: BTreeSet<&[u8]> = keys.iter().map(AsRef::as_ref).collect();
+        let size = |map: &Keyspace| -> usize {
+            keys.iter()
+                .filter_map(|key| Some(entry_size(key.len(), map.get(key)?)))
+                .sum()
+        };
+
+        let before = size(map);
+        let result = f(map);
+        charge(&self.used_memory, before, size(map));
+
+        result
+    }
+}
+
+impl Databases {
+    /// Changes the limit every database shares, as CONFIG SET `maxmemory`
+    /// and `maxmemory-policy` do. Memory is counted from scratch when a
+    /// limit is set where there was none, and no longer once it is removed.
+    pub async fn set_max_memory(&self, bytes: usize, policy: MaxMemoryPolicy) {
+        let limit = &self[0].max_memory;
+
+        if limit.is_set() == (bytes != 0) {
+            limit.set(bytes, policy);
+            return;
+        }
+
+        // Every database is locked, lowest index first, so no write lands
+        // between counting and switching accounting on or off.
+        let mut guards = Vec::with_capacity(self.len());
+        for database in self.iter() {
+            guards.push(database.map.write().await);
+        }
+
+        let used = match bytes {
+            0 => 0,
+            _ => guards
+                .iter()
+                .flat_map(|guard| guard.iter())
+                .map(|(key, entry)| entry_size(key.len(), entry))
+                .sum(),
+        };
+
+        self[0].used_memory.store(used, Ordering::Relaxed);
+        limit.set(bytes, policy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::ListSide;
+    use super::*;
+
+    async fn fill(database: &Database, keys: usize) {
+        for key in 0..keys {
+            database.reclaim_memory().await.unwrap();
+            database
+                .set(
+                    format!("key:{key}").as_bytes(),
+                    "x".repeat(100).as_str(),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_used_memory_follows_writes() {
+        let database = Database::new().with_max_memory(usize::MAX, MaxMemoryPolicy::NoEviction);
+        assert_eq!(0, database.used_memory());
+
+        database.set(b"key", "value", None).await.unwrap();
+        let string = database.used_memory();
+        assert!(string > 0);
+
+        database.rpush(b"list", &[b"a", b"b"]).await.unwrap();
+        let list = database.used_memory();
+        assert!(list > string);
+
+        database.rpush(b"list", &[[b'x'; 1000]]).await.unwrap();
+        assert!(database.used_memory() >= list + 1000);
+
+        database
+            .lmove(b"list", b"list", ListSide::Left, ListSide::Right)
+            .await
+            .unwrap();
+        database.append(b"key", b"more").await.unwrap();
+        database.incr_by(b"counter", 1).await.unwrap();
+
+        database.unlink(&["key", "list", "counter"]).await;
+        assert_eq!(0, database.used_memory());
+
+        assert_eq!(0, Database::new().used_memory());
+    }
+
+    #[tokio::test]
+    async fn test_noeviction_refuses_writes_past_limit() {
+        let database = Database::new().with_max_memory(2000, MaxMemoryPolicy::NoEviction);
+
+        let mut keys = 0;
+        while database.used_memory() <= 2000 {
+            assert_eq!(Ok(()), database.reclaim_memory().await);
+            database
+                .set(
+                    format!("key:{keys}").as_bytes(),
+                    "x".repeat(100).as_str(),
+                    None,
+                )
+                .await
+                .unwrap();
+            keys += 1;
+        }
+
+        assert_eq!(
+            Err(MemoryError::OutOfMemory),
+            database.reclaim_memory().await
+        );
+        assert_eq!(keys, database.map.read().await.len());
+
+        database.unlink(&["key:0", "key:1"]).await;
+        assert_eq!(Ok(()), database.reclaim_memory().await);
+    }
+
+    #[tokio::test]
+    async fn test_allkeys_random_stays_within_limit() {
+        let database = Database::new().with_max_memory(2000, MaxMemoryPolicy::AllKeysRandom);
+        fill(&database, 100).await;
+        database.reclaim_memory().await.unwrap();
+
+        assert!(database.used_memory() <= 2000);
+        assert!(database.map.read().await.len() < 100);
+    }
+
+    #[tokio::test]
+    async fn test_allkeys_lru_keeps_recently_used_keys() {
+        let database = Database::new().with_max_memory(4000, MaxMemoryPolicy::AllKeysLru);
+        fill(&database, 10).await;
+
+        // Sampling five keys at a time, the key read before every write is
+        // always the most recently used, so it is never the one evicted.
+        for key in 10..100 {
+            database.get(b"key:0").await;
+            database.reclaim_memory().await.unwrap();
+            database
+                .set(
+                    format!("key:{key}").as_bytes(),
+                    "x".repeat(100).as_str(),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        database.reclaim_memory().await.unwrap();
+        assert!(database.used_memory() <= 4000);
+        assert!(database.get(b"key:0").await.is_some());
+        assert!(database.map.read().await.len() < 100);
+    }
+
+    #[tokio::test]
+    async fn test_volatile_ttl_evicts_soonest_expiring() {
+        let database = Database::new().with_max_memory(1000, MaxMemoryPolicy::VolatileTtl);
+
+        database
+            .set(b"persistent", "x".repeat(10).as_str(), None)
+            .await
+            .unwrap();
+        for (key, secs) in [("soon", 10), ("later", 1000)] {
+            database
+                .set(
+                    key.as_bytes(),
+                    "x".repeat(10).as_str(),
+                    Some(Duration::from_secs(secs)),
+                )
+                .await
+                .unwrap();
+        }
+
+        database
+            .set(b"big", "x".repeat(200).as_str(), None)
+            .await
+            .unwrap();
+        database.reclaim_memory().await.unwrap();
+
+        assert!(database.get(b"soon").await.is_none());
+        assert!(database.get(b"persistent").await.is_some());
+        assert!(database.get(b"later").await.is_some());
+        assert!(database.get(b"big").await.is_some());
+
+        // Only keys with a timeout are candidates.
+        database
+            .set(b"bigger", "x".repeat(1000).as_str(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            Err(MemoryError::OutOfMemory),
+            database.reclaim_memory().await
+        );
+        assert!(database.get(b"later").await.is_none());
+        assert!(database.get(b"persistent").await.is_some());
+    }
+}
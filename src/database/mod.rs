@@ -1,13 +1,46 @@
+mod blocking;
+mod collection;
+mod databases;
+mod error;
+mod hash;
+mod keys;
+mod keyspace;
+mod list;
+mod listpack;
+mod memory;
+mod rdb;
+mod set;
+mod sorted_set;
+mod string;
 mod value;
 
 use std::borrow::Cow;
-use std::collections::HashMap;
 use std::mem::ManuallyDrop;
 use std::ops::Add;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-pub use crate::database::value::Value;
+use crate::database::blocking::Blocked;
+pub use crate::database::blocking::Popped;
+pub use crate::database::databases::Databases;
+pub use crate::database::error::{
+    DbIndexError, IncrError, LsetError, MemoryError, SaveError, StringError, TypeError,
+};
+pub(crate) use crate::database::hash::format_float;
+pub use crate::database::hash::{FieldValue, Hash, HrandfieldResult, RandomField};
+use crate::database::keyspace::Keyspace;
+pub use crate::database::list::{
+    List, ListLimits, ListPosition, ListSide, LposResult, MultiPopped, PopResult,
+};
+pub use crate::database::memory::MaxMemoryPolicy;
+use crate::database::memory::{access_tick, charge, entry_size, MaxMemory};
+pub use crate::database::set::SetOperation;
+pub(crate) use crate::database::sorted_set::format_score;
+pub use crate::database::sorted_set::{ScoreBound, ScoreEnd, ScoredMember, SortedSet};
+pub(crate) use crate::database::string::MAX_STRING_LENGTH;
+pub use crate::database::string::{SetCondition, SetOptions};
+pub use crate::database::value::{Lookup, Value};
 use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
 use tokio::time::{sleep, Instant};
@@ -16,18 +49,121 @@ use tokio::time::{sleep, Instant};
 pub struct Database {
     map: Map,
     handle: JoinHandle<()>,
+    max_value_size: Option<usize>,
+    list_limits: ListLimits,
+    blocked: Mutex<Blocked>,
+    max_memory: Arc<MaxMemory>,
+    used_memory: Arc<AtomicUsize>,
+    active_expire: Arc<AtomicBool>,
+    lazy_free: bool,
 }
 
-type Map = Arc<RwLock<HashMap<Box<[u8]>, Entry>>>;
+type Map = Arc<RwLock<Keyspace>>;
+
+/// Drops `value` on a blocking thread, so freeing a large value does not
+/// hold up the task that removed it.
+fn free_in_background<T: Send + 'static>(value: T) {
+    tokio::task::spawn_blocking(move || drop(value));
+}
+
+/// Reads `value` as an integer the way Redis writes one: plain decimal
+/// digits with an optional minus sign, without a plus sign, spaces or
+/// leading zeros. Only strings that read back unchanged count, the ones
+/// Redis would store as integers or agree to increment.
+fn parse_integer(value: &[u8]) -> Option<i64> {
+    let digits = value.strip_prefix(b"-").unwrap_or(value);
+
+    match digits {
+        [b'0'] if digits.len() == value.len() => Some(0),
+        [b'1'..=b'9', rest @ ..] if rest.iter().all(u8::is_ascii_digit) => {
+            std::str::from_utf8(value).ok()?.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+/// Returns the value stored at `key` unless it has already expired.
+#[inline]
+fn live<'a>(map: &'a Keyspace, key: &[u8], now: Instant) -> Option<&'a Value> {
+    map.get(key)
+        .filter(|entry| !entry.is_expired(now))
+        .inspect(|entry| entry.touch())
+        .map(Entry::value)
+}
+
+/// Drops the entry at `key` if it has expired, so writers never observe or
+/// extend a value the background sweep simply has not reached yet.
+#[inline]
+fn evict_expired(map: &mut Keyspace, key: &[u8], now: Instant) {
+    if map.get(key).is_some_and(|entry| entry.is_expired(now)) {
+        map.remove(key);
+    }
+}
 
 #[derive(Debug)]
-enum Entry {
-    Expire {
-        value: Value,
-        created: Instant,
-        duration: Duration,
-    },
-    NonExpire(Value),
+struct Entry {
+    value: Value,
+    expire: Option<Expire>,
+    /// The access clock reading from when the key was last read or written,
+    /// which LRU eviction compares.
+    accessed: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Expire {
+    created: Instant,
+    duration: Duration,
+}
+
+impl Entry {
+    #[inline]
+    fn new(value: Value, duration: Option<Duration>) -> Self {
+        Entry {
+            value,
+            expire: duration.map(|duration| Expire {
+                created: Instant::now(),
+                duration,
+            }),
+            accessed: AtomicU64::new(access_tick()),
+        }
+    }
+
+    #[inline]
+    fn value(&self) -> &Value {
+        &self.value
+    }
+
+    #[inline]
+    fn into_value(self) -> Value {
+        self.value
+    }
+
+    #[inline]
+    fn value_mut(&mut self) -> &mut Value {
+        &mut self.value
+    }
+
+    /// When the key expires, if it has a timeout.
+    #[inline]
+    fn deadline(&self) -> Option<Instant> {
+        self.expire
+            .map(|Expire { created, duration }| created.add(duration))
+    }
+
+    #[inline]
+    fn is_expired(&self, now: Instant) -> bool {
+        self.deadline().is_some_and(|deadline| deadline.le(&now))
+    }
+
+    #[inline]
+    fn touch(&self) {
+        self.accessed.store(access_tick(), Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn accessed(&self) -> u64 {
+        self.accessed.load(Ordering::Relaxed)
+    }
 }
 
 impl Default for Database {
@@ -38,17 +174,98 @@ impl Default for Database {
 
 impl Database {
     pub fn new() -> Self {
-        let map: Map = Arc::new(RwLock::new(HashMap::with_capacity(1024)));
+        Self::with_shared(
+            Arc::default(),
+            Arc::default(),
+            Arc::new(AtomicBool::new(true)),
+        )
+    }
+
+    /// An empty database with the same limits as this one, whose keys count
+    /// towards the same `maxmemory` and whose expired keys are swept as long
+    /// as this one's are.
+    fn sibling(&self) -> Self {
+        let mut database = Self::with_shared(
+            Arc::clone(&self.used_memory),
+            Arc::clone(&self.max_memory),
+            Arc::clone(&self.active_expire),
+        );
+        database.max_value_size = self.max_value_size;
+        database.list_limits = self.list_limits;
+        database.lazy_free = self.lazy_free;
+        database
+    }
+
+    fn with_shared(
+        used_memory: Arc<AtomicUsize>,
+        max_memory: Arc<MaxMemory>,
+        active_expire: Arc<AtomicBool>,
+    ) -> Self {
+        let map: Map = Arc::new(RwLock::new(Keyspace::with_capacity(1024)));
 
         let cl = Arc::clone(&map);
+        let used = Arc::clone(&used_memory);
+        let active = Arc::clone(&active_expire);
         let handle: JoinHandle<()> = tokio::spawn(async move {
             loop {
                 sleep(Duration::from_secs(10)).await;
-                Self::clean(&cl).await;
+
+                if active.load(Ordering::Relaxed) {
+                    Self::clean(&cl, &used).await;
+                }
             }
         });
 
-        Self { map, handle }
+        Self {
+            map,
+            handle,
+            max_value_size: None,
+            list_limits: ListLimits::default(),
+            blocked: Mutex::default(),
+            max_memory,
+            used_memory,
+            active_expire,
+            lazy_free: false,
+        }
+    }
+
+    /// Frees the values DEL, FLUSHDB and overwriting SETs remove in the
+    /// background, in the spirit of Redis' `lazyfree-lazy-user-del`, so
+    /// removing a large collection does not stall the command. UNLINK
+    /// always does.
+    pub fn with_lazy_free(mut self, enabled: bool) -> Self {
+        self.lazy_free = enabled;
+        self
+    }
+
+    /// Drops `value`, which was just removed from the keyspace, in the
+    /// background when lazy freeing is on.
+    fn free<T: Send + 'static>(&self, value: T) {
+        if self.lazy_free {
+            free_in_background(value);
+        }
+    }
+
+    /// Turns the background sweep of expired keys on or off, for this
+    /// database and its siblings alike, as DEBUG SET-ACTIVE-EXPIRE does.
+    /// Expired keys are never read either way, and reads still remove them.
+    pub fn set_active_expire(&self, enabled: bool) {
+        self.active_expire.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Rejects string writes that would leave a value longer than `limit`
+    /// bytes, in the spirit of Redis' `proto-max-value-size`. This is
+    /// separate from the cap on incoming bulk strings.
+    pub fn with_max_value_size(mut self, limit: usize) -> Self {
+        self.max_value_size = Some(limit);
+        self
+    }
+
+    /// Sets how large lists grow before leaving the compact encoding. Lists
+    /// already past the new limits keep their encoding.
+    pub fn with_list_limits(mut self, limits: ListLimits) -> Self {
+        self.list_limits = limits;
+        self
     }
 
     pub async fn insert<'a>(
@@ -60,19 +277,47 @@ impl Database {
         let key = key.into().clone().into();
         let mut lock = self.map.write().await;
 
-        lock.insert(
+        self.insert_entry(
+            &mut lock,
             key,
-            match duration {
-                Some(duration) => Entry::Expire {
-                    value: value.try_into().unwrap(),
-                    created: Instant::now(),
-                    duration,
-                },
-                None => Entry::NonExpire(value.try_into().unwrap()),
-            },
+            Entry::new(value.try_into().unwrap(), duration),
         );
     }
 
+    /// Stores `value` like [`Database::insert`], unless it is longer than
+    /// the configured maximum value size.
+    pub async fn set<'a>(
+        &self,
+        key: impl Into<Cow<'a, [u8]>>,
+        value: impl TryInto<Value, Error = &'static str>,
+        duration: Option<Duration>,
+    ) -> Result<(), StringError> {
+        let options = SetOptions {
+            expiration: duration,
+            ..SetOptions::default()
+        };
+
+        self.set_with(key, value, options).await.map(|_| ())
+    }
+
+    /// Stores `value` like [`Database::set`] and returns the string value
+    /// it replaced. Nothing is written when the key holds a non-string value.
+    pub async fn replace<'a>(
+        &self,
+        key: impl Into<Cow<'a, [u8]>>,
+        value: impl TryInto<Value, Error = &'static str>,
+        duration: Option<Duration>,
+    ) -> Result<Option<Value>, StringError> {
+        let options = SetOptions {
+            expiration: duration,
+            get: true,
+            ..SetOptions::default()
+        };
+
+        let (_, old) = self.set_with(key, value, options).await?;
+        Ok(old)
+    }
+
     pub async fn get_by_string(&self, key: impl AsRef<str>) -> Option<Value> {
         self.get(Cow::Borrowed(key.as_ref().as_bytes())).await
     }
@@ -94,57 +339,143 @@ impl Database {
         let now = Instant::now();
         let guard = self.map.read().await;
 
-        match guard.get(&key as &Box<[u8]>) {
-            Some(Entry::NonExpire(val)) => {
-                if should_drop {
-                    ManuallyDrop::into_inner(key);
-                }
-
-                Some(val.clone())
+        let value = match guard.get(&key as &Box<[u8]>) {
+            Some(entry) if !entry.is_expired(now) => {
+                entry.touch();
+                Some(entry.value.clone())
             }
-            Some(Entry::Expire {
-                value: val,
-                created,
-                duration,
-            }) if now.lt(&created.add(*duration)) => {
-                if should_drop {
-                    ManuallyDrop::into_inner(key);
-                }
+            // The first read to find a key expired removes it, rather than
+            // leaving it to the sweep.
+            Some(_) => {
+                drop(guard);
+
+                let mut guard = self.map.write().await;
+                self.tracked(&mut guard, &[&key[..]], |map| evict_expired(map, &key, now));
 
-                Some(val.clone())
+                None
             }
             None => None,
-            _ => None,
+        };
+
+        if should_drop {
+            ManuallyDrop::into_inner(key);
+        }
+
+        value
+    }
+
+    /// Narrows the value at `key` with `narrow`, which picks out the type the
+    /// caller works on, telling a missing key apart from one holding another
+    /// type. A key found expired is removed, as [`Database::get`] does.
+    pub async fn lookup<T>(
+        &self,
+        key: &[u8],
+        narrow: impl FnOnce(&Value) -> Option<T>,
+    ) -> Lookup<T> {
+        let now = Instant::now();
+        let guard = self.map.read().await;
+
+        match guard.get(key) {
+            Some(entry) if !entry.is_expired(now) => {
+                entry.touch();
+                narrow(entry.value()).map_or(Lookup::WrongType, Lookup::Found)
+            }
+            Some(_) => {
+                drop(guard);
+
+                let mut guard = self.map.write().await;
+                self.tracked(&mut guard, &[key], |map| evict_expired(map, key, now));
+
+                Lookup::Missing
+            }
+            None => Lookup::Missing,
+        }
+    }
+
+    /// The refcount OBJECT REFCOUNT reports for `key`, if it exists.
+    pub async fn refcount(&self, key: &[u8]) -> Option<usize> {
+        let guard = self.map.read().await;
+        live(&guard, key, Instant::now()).map(Value::refcount)
+    }
+
+    /// The encoding OBJECT ENCODING reports for `key`, if it exists.
+    pub async fn encoding(&self, key: &[u8]) -> Option<&'static str> {
+        let guard = self.map.read().await;
+        live(&guard, key, Instant::now()).map(Value::encoding)
+    }
+
+    /// Removes `keys` and returns how many of them were present. The removed
+    /// values are dropped on a background thread so freeing large values
+    /// does not stall the caller.
+    pub async fn unlink(&self, keys: &[impl AsRef<[u8]>]) -> usize {
+        let removed = self.remove_all(keys).await;
+        let count = removed.len();
+        free_in_background(removed);
+
+        count
+    }
+
+    /// Removes `keys` like [`Database::unlink`], but frees the removed values
+    /// before returning unless lazy freeing is on.
+    pub async fn del(&self, keys: &[impl AsRef<[u8]>]) -> usize {
+        let removed = self.remove_all(keys).await;
+        let count = removed.len();
+        self.free(removed);
+
+        count
+    }
+
+    /// Removes every key. They are freed in the background when `lazy` is
+    /// set, or when it is not given and lazy freeing is on.
+    pub async fn flush(&self, lazy: Option<bool>) {
+        let mut guard = self.map.write().await;
+        let removed = std::mem::take(&mut *guard);
+
+        if self.max_memory.is_set() {
+            let size = removed
+                .iter()
+                .map(|(key, entry)| entry_size(key.len(), entry))
+                .sum();
+            charge(&self.used_memory, size, 0);
         }
+
+        drop(guard);
+
+        if lazy.unwrap_or(self.lazy_free) {
+            free_in_background(removed);
+        }
+    }
+
+    /// Removes `keys`, returning the entries of those that had not expired.
+    async fn remove_all(&self, keys: &[impl AsRef<[u8]>]) -> Vec<Entry> {
+        let mut guard = self.map.write().await;
+        let now = Instant::now();
+
+        keys.iter()
+            .filter_map(|key| self.remove_entry(&mut guard, key.as_ref()))
+            .filter(|entry| !entry.is_expired(now))
+            .collect()
     }
 
-    async fn clean(map: &Map) {
+    async fn clean(map: &Map, used_memory: &AtomicUsize) {
         let guard = map.read().await;
         let now = Instant::now();
 
         let keys = guard
-            .iter()
-            .filter_map(|(key, entry)| match entry {
-                Entry::Expire {
-                    value: _,
-                    duration,
-                    created,
-                } if created.add(*duration).lt(&now) => Some(key.clone()),
-                _ => None,
-            })
+            .volatile_keys()
+            .filter(|key| guard.get(key).is_some_and(|entry| entry.is_expired(now)))
+            .cloned()
             .collect::<Vec<Box<[u8]>>>();
 
         drop(guard);
         let mut guard = map.write().await;
         let now = Instant::now();
         for key in keys {
-            match guard.get(&key) {
-                Some(Entry::Expire {
-                    value: _,
-                    created,
-                    duration,
-                }) if created.add(*duration).lt(&now) => {
-                    guard.remove(&key);
+            match guard.get(&key).and_then(Entry::deadline) {
+                Some(deadline) if deadline.lt(&now) => {
+                    if let Some(entry) = guard.remove(&key) {
+                        charge(used_memory, entry_size(key.len(), &entry), 0);
+                    }
                 }
                 _ => continue,
             };
@@ -160,8 +491,80 @@ impl Drop for Database {
 
 #[cfg(test)]
 mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+
     use super::*;
 
+    /// The heap buffer being watched, and the thread that freed it once it
+    /// has been, so tests can tell whether a value was freed in the
+    /// background.
+    static WATCHED: AtomicUsize = AtomicUsize::new(0);
+    static FREED_ON: AtomicUsize = AtomicUsize::new(0);
+
+    struct WatchingAllocator;
+
+    #[global_allocator]
+    static ALLOCATOR: WatchingAllocator = WatchingAllocator;
+
+    unsafe impl GlobalAlloc for WatchingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let watched =
+                WATCHED.compare_exchange(ptr as usize, 0, Ordering::Relaxed, Ordering::Relaxed);
+
+            if watched.is_ok() {
+                FREED_ON.store(current_thread(), Ordering::Relaxed);
+            }
+
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    thread_local! {
+        static THREAD: u8 = const { 0 };
+    }
+
+    /// Tells threads apart without allocating, by the address of a thread
+    /// local.
+    fn current_thread() -> usize {
+        THREAD
+            .try_with(|thread| thread as *const u8 as usize)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Watches the heap buffer of the string at `key` for being freed.
+    async fn watch(database: &Database, key: &[u8]) {
+        let guard = database.map.read().await;
+        let ptr = match guard.get(key).unwrap().value() {
+            Value::String(value) => value.as_ptr(),
+            Value::Bytes(value) => value.as_ptr(),
+            value => panic!("no heap buffer to watch in {value:?}"),
+        };
+
+        FREED_ON.store(0, Ordering::Relaxed);
+        WATCHED.store(ptr as usize, Ordering::Relaxed);
+    }
+
+    /// The thread that freed the watched buffer, if it was freed yet.
+    fn freed_on() -> Option<usize> {
+        Some(FREED_ON.load(Ordering::Relaxed)).filter(|thread| *thread != 0)
+    }
+
+    /// Waits for the watched buffer to be freed, returning the thread that
+    /// freed it.
+    async fn wait_freed_on() -> usize {
+        loop {
+            if let Some(thread) = freed_on() {
+                return thread;
+            }
+
+            sleep(Duration::from_millis(1)).await;
+        }
+    }
+
     #[tokio::test]
     async fn test_database_non_expire() {
         let database = Database::new();
@@ -205,6 +608,29 @@ mod tests {
         assert_eq!(None, val);
     }
 
+    #[tokio::test]
+    async fn test_database_lookup() {
+        let database = Database::new();
+        let integer = |value: &Value| match value {
+            Value::Integer(val) => Some(*val),
+            _ => None,
+        };
+
+        database.insert(b"int", 1i64, None).await;
+        database.insert(b"string", "value", None).await;
+        database
+            .insert(b"expiring", 2i64, Some(Duration::from_millis(10)))
+            .await;
+
+        assert_eq!(Lookup::Found(1), database.lookup(b"int", integer).await);
+        assert_eq!(Lookup::WrongType, database.lookup(b"string", integer).await);
+        assert_eq!(Lookup::Missing, database.lookup(b"missing", integer).await);
+
+        sleep(Duration::from_millis(20)).await;
+        assert_eq!(Lookup::Missing, database.lookup(b"expiring", integer).await);
+        assert!(!database.map.read().await.contains_key(b"expiring"));
+    }
+
     #[tokio::test]
     async fn test_database_expired_value_manual_drop() {
         let database = Database::new();
@@ -222,6 +648,137 @@ mod tests {
         assert_eq!(None, val);
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn test_database_active_expire_toggle() {
+        let database = Database::new();
+        database.set_active_expire(false);
+
+        database
+            .insert(b"key", 1i64, Some(Duration::from_millis(100)))
+            .await;
+        sleep(Duration::from_secs(30)).await;
+
+        // Left for the first read to find, which removes it.
+        assert!(database.map.read().await.contains_key(b"key" as &[u8]));
+        assert_eq!(None, database.get(b"key").await);
+        assert!(!database.map.read().await.contains_key(b"key" as &[u8]));
+
+        database.set_active_expire(true);
+        database
+            .insert(b"key", 1i64, Some(Duration::from_millis(100)))
+            .await;
+        sleep(Duration::from_secs(30)).await;
+        assert!(database.map.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_database_unlink() {
+        let database = Database::new();
+
+        database.insert(b"key1", 1i64, None).await;
+        database.insert(b"key2", "value", None).await;
+        database
+            .insert(b"key3", 1i64, Some(Duration::from_millis(10)))
+            .await;
+
+        sleep(Duration::from_millis(11)).await;
+
+        let count = database
+            .unlink(&["key1", "key2", "key3", "not_exists"])
+            .await;
+        assert_eq!(2, count);
+
+        assert_eq!(None, database.get(b"key1").await);
+        assert_eq!(None, database.get(b"key2").await);
+        assert!(database.map.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_database_lazy_free() {
+        let database = Database::new()
+            .with_lazy_free(true)
+            .with_max_memory(usize::MAX, MaxMemoryPolicy::NoEviction);
+
+        let here = current_thread();
+
+        database
+            .set(b"big", &*"x".repeat(100_000), None)
+            .await
+            .unwrap();
+        database.set(b"key", "value", None).await.unwrap();
+        watch(&database, b"big").await;
+
+        assert_eq!(2, database.del(&["big", "key", "missing"]).await);
+        assert!(database.map.read().await.is_empty());
+        assert_eq!(0, database.used_memory());
+        assert_ne!(here, wait_freed_on().await);
+
+        // The value an overwriting SET replaces is freed the same way.
+        database.set(b"key", "old", None).await.unwrap();
+        watch(&database, b"key").await;
+        database.set(b"key", "new", None).await.unwrap();
+        assert_ne!(here, wait_freed_on().await);
+
+        // Without lazy freeing, DEL and SET drop what they remove before
+        // returning, unless FLUSHDB is told ASYNC.
+        let database = Database::new();
+
+        database.set(b"key", "value", None).await.unwrap();
+        watch(&database, b"key").await;
+        database.del(&["key"]).await;
+        assert_eq!(Some(here), freed_on());
+
+        database.set(b"key", "old", None).await.unwrap();
+        watch(&database, b"key").await;
+        database.set(b"key", "new", None).await.unwrap();
+        assert_eq!(Some(here), freed_on());
+
+        watch(&database, b"key").await;
+        database.flush(Some(true)).await;
+        assert_ne!(here, wait_freed_on().await);
+
+        database.set(b"key", "value", None).await.unwrap();
+        watch(&database, b"key").await;
+        database.flush(None).await;
+        assert_eq!(Some(here), freed_on());
+    }
+
+    #[tokio::test]
+    async fn test_database_flush() {
+        let database = Database::new().with_max_memory(usize::MAX, MaxMemoryPolicy::NoEviction);
+
+        for lazy in [None, Some(true), Some(false)] {
+            database.set(b"key", "value", None).await.unwrap();
+            database.rpush(b"list", &["a", "b"]).await.unwrap();
+
+            database.flush(lazy).await;
+            assert!(database.map.read().await.is_empty());
+            assert_eq!(0, database.used_memory());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_database_shared_integer_refcount() {
+        let database = Database::new();
+
+        database.insert(b"a", 100i64, None).await;
+        database.insert(b"b", 100i64, None).await;
+        database.insert(b"large", 100_000i64, None).await;
+        database.insert(b"negative", -1i64, None).await;
+        database.insert(b"string", "100", None).await;
+
+        assert_eq!(database.refcount(b"a").await, database.refcount(b"b").await);
+        assert!(database.refcount(b"a").await.unwrap() > 1);
+        assert_eq!(Some(1), database.refcount(b"large").await);
+        assert_eq!(Some(1), database.refcount(b"negative").await);
+        assert_eq!(Some(1), database.refcount(b"string").await);
+        assert_eq!(None, database.refcount(b"missing").await);
+
+        assert_eq!(Some("int"), database.encoding(b"a").await);
+        assert_eq!(Some("int"), database.encoding(b"large").await);
+        assert_eq!(Some("embstr"), database.encoding(b"string").await);
+    }
+
     #[tokio::test]
     async fn test_database_clean() {
         let database = Database::new();
@@ -235,7 +792,7 @@ mod tests {
             .await;
 
         sleep(Duration::from_millis(11)).await;
-        Database::clean(&database.map).await;
+        Database::clean(&database.map, &database.used_memory).await;
 
         assert!(database
             .map
@@ -251,7 +808,7 @@ mod tests {
             .is_some());
 
         sleep(Duration::from_millis(100)).await;
-        Database::clean(&database.map).await;
+        Database::clean(&database.map, &database.used_memory).await;
         assert!(database
             .map
             .read()
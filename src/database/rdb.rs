@@ -0,0 +1,604 @@
+//! Snapshots of the keyspace, written by SAVE and BGSAVE and read back on
+//! startup.
+//!
+//! The format is loosely modelled on Redis' RDB but is not compatible with
+//! it. All integers are little-endian and every string is a `u32` length
+//! followed by its bytes.
+//!
+//! ```text
+//! "REDIS-RS" version:u8
+//! (
+//!     [0xFE db:u32]          the database the keys that follow belong to
+//!     [0xFC expires_at:u64]  absolute unix time in milliseconds
+//!     type:u8 key:string value
+//! )*
+//! 0xFF
+//! ```
+//!
+//! Keys before the first `0xFE` belong to database 0, which is where every
+//! key of a version 1 file goes.
+//!
+//! Values are encoded by type:
+//!
+//! | type | value                                     |
+//! |------|-------------------------------------------|
+//! | 0    | bytes: string                             |
+//! | 1    | UTF-8 string: string                      |
+//! | 2    | integer: i64                              |
+//! | 3    | list: count:u32, then count strings       |
+//! | 4    | hash: count:u32, then count field/values  |
+//! | 5    | set: count:u32, then count strings        |
+//! | 6    | sorted set: count:u32, then score:f64 and member string each |
+//! | 7    | null: nothing                             |
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use super::{Database, Databases, Entry, Hash, List, ListLimits, SaveError, SortedSet, Value};
+
+const MAGIC: &[u8] = b"REDIS-RS";
+const VERSION: u8 = 2;
+
+const OP_SELECT_DB: u8 = 0xFE;
+const OP_EXPIRES_AT: u8 = 0xFC;
+const OP_EOF: u8 = 0xFF;
+
+const TYPE_BYTES: u8 = 0;
+const TYPE_STRING: u8 = 1;
+const TYPE_INTEGER: u8 = 2;
+const TYPE_LIST: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_SET: u8 = 5;
+const TYPE_SORTED_SET: u8 = 6;
+const TYPE_NULL: u8 = 7;
+
+/// A key as stored in a snapshot, with the unix time in milliseconds it
+/// expires at.
+type Record = (Box<[u8]>, Value, Option<u64>);
+
+/// The records of each database, by database index.
+type Snapshot = Vec<(usize, Vec<Record>)>;
+
+impl Databases {
+    /// Writes every database to `path`, replying once the file is in place.
+    pub async fn save(&self, path: &Path) -> Result<(), SaveError> {
+        let _saving = self.start_saving()?;
+        let snapshot = self.snapshot().await;
+        let path = path.to_path_buf();
+
+        tokio::task::spawn_blocking(move || write_file(&path, &snapshot))
+            .await
+            .map_err(io::Error::from)??;
+
+        Ok(())
+    }
+
+    /// Writes every database to `path` in the background. The snapshot is
+    /// taken before returning, so later writes do not end up in the file.
+    pub async fn bgsave(&self, path: PathBuf) -> Result<(), SaveError> {
+        let saving = self.start_saving()?;
+        let snapshot = self.snapshot().await;
+        let delay = self.save_delay();
+
+        tokio::task::spawn_blocking(move || {
+            std::thread::sleep(delay);
+            let keys: usize = snapshot.iter().map(|(_, records)| records.len()).sum();
+
+            match write_file(&path, &snapshot) {
+                Ok(()) => info!(path = ?path, keys, "Background saving finished"),
+                Err(err) => warn!(err = ?err, path = ?path, "Background saving failed"),
+            }
+
+            drop(saving);
+        });
+
+        Ok(())
+    }
+
+    /// Adds the keys saved at `path` to the databases they were saved from,
+    /// skipping those that expired in the meantime, and returns how many
+    /// were loaded. A missing file loads nothing.
+    pub async fn load(&self, path: &Path) -> io::Result<usize> {
+        let snapshot = match File::open(path) {
+            Ok(file) => read(&mut BufReader::new(file), self[0].list_limits)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+
+        if snapshot.iter().any(|(index, _)| *index >= self.len()) {
+            return Err(invalid("snapshot has more databases than are configured"));
+        }
+
+        let mut loaded = 0;
+
+        for (index, records) in snapshot {
+            loaded += self[index].load(records).await;
+        }
+
+        Ok(loaded)
+    }
+
+    /// Marks a save as running until the returned guard is dropped. Only one
+    /// save runs at a time.
+    fn start_saving(&self) -> Result<SavingGuard, SaveError> {
+        if self.saving.swap(true, Ordering::AcqRel) {
+            return Err(SaveError::InProgress);
+        }
+
+        Ok(SavingGuard(Arc::clone(&self.saving)))
+    }
+
+    /// Copies out the records of every database that has keys.
+    async fn snapshot(&self) -> Snapshot {
+        let mut snapshot = Vec::new();
+
+        for (index, database) in self.iter().enumerate() {
+            let records = database.records().await;
+
+            if !records.is_empty() {
+                snapshot.push((index, records));
+            }
+        }
+
+        snapshot
+    }
+}
+
+impl Database {
+    /// Adds `records` to the keyspace, skipping those that expired in the
+    /// meantime, and returns how many were added.
+    async fn load(&self, records: Vec<Record>) -> usize {
+        let now = SystemTime::now();
+        let mut guard = self.map.write().await;
+        let mut loaded = 0;
+
+        for (key, value, expires_at) in records {
+            let duration = match expires_at {
+                Some(at) => match (UNIX_EPOCH + Duration::from_millis(at)).duration_since(now) {
+                    Ok(duration) if !duration.is_zero() => Some(duration),
+                    _ => continue,
+                },
+                None => None,
+            };
+
+            self.insert_entry(&mut guard, key, Entry::new(value, duration));
+            loaded += 1;
+        }
+
+        loaded
+    }
+
+    /// Copies out every live key along with its expiry as unix time.
+    async fn records(&self) -> Vec<Record> {
+        let guard = self.map.read().await;
+        let now = Instant::now();
+        let wall = SystemTime::now();
+
+        guard
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .map(|(key, entry)| {
+                let expires_at = entry.deadline().map(|deadline| {
+                    let remaining = deadline.saturating_duration_since(now);
+                    let at = (wall + remaining)
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default();
+                    at.as_millis() as u64
+                });
+
+                (key.clone(), entry.value().clone(), expires_at)
+            })
+            .collect()
+    }
+}
+
+struct SavingGuard(Arc<AtomicBool>);
+
+impl Drop for SavingGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Writes next to `path` first and renames over it, so a crash mid-save
+/// never leaves a truncated snapshot behind.
+fn write_file(path: &Path, snapshot: &Snapshot) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut out = BufWriter::new(File::create(&tmp)?);
+    write(&mut out, snapshot)?;
+    out.into_inner()
+        .map_err(|err| err.into_error())?
+        .sync_all()?;
+
+    std::fs::rename(&tmp, path)
+}
+
+fn write(out: &mut impl Write, snapshot: &Snapshot) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION])?;
+
+    for (index, records) in snapshot {
+        out.write_all(&[OP_SELECT_DB])?;
+        write_len(out, *index)?;
+
+        for (key, value, expires_at) in records {
+            if let Some(at) = expires_at {
+                out.write_all(&[OP_EXPIRES_AT])?;
+                out.write_all(&at.to_le_bytes())?;
+            }
+
+            out.write_all(&[value_type(value)])?;
+            write_string(out, key)?;
+            write_value(out, value)?;
+        }
+    }
+
+    out.write_all(&[OP_EOF])
+}
+
+fn value_type(value: &Value) -> u8 {
+    match value {
+        Value::Bytes(_) => TYPE_BYTES,
+        Value::String(_) => TYPE_STRING,
+        Value::Integer(_) => TYPE_INTEGER,
+        Value::List(_) => TYPE_LIST,
+        Value::Hash(_) => TYPE_HASH,
+        Value::Set(_) => TYPE_SET,
+        Value::SortedSet(_) => TYPE_SORTED_SET,
+        Value::Null => TYPE_NULL,
+    }
+}
+
+fn write_value(out: &mut impl Write, value: &Value) -> io::Result<()> {
+    match value {
+        Value::Bytes(val) => write_string(out, val),
+        Value::String(val) => write_string(out, val.as_bytes()),
+        Value::Integer(val) => out.write_all(&val.to_le_bytes()),
+        Value::List(list) => {
+            write_len(out, list.len())?;
+            list.iter().try_for_each(|value| write_string(out, value))
+        }
+        Value::Hash(hash) => {
+            write_len(out, hash.len())?;
+            hash.iter().try_for_each(|(field, value)| {
+                write_string(out, field)?;
+                write_string(out, value)
+            })
+        }
+        Value::Set(set) => {
+            write_len(out, set.len())?;
+            set.iter().try_for_each(|member| write_string(out, member))
+        }
+        Value::SortedSet(set) => {
+            write_len(out, set.len())?;
+            set.iter().try_for_each(|(member, score)| {
+                out.write_all(&score.to_le_bytes())?;
+                write_string(out, member)
+            })
+        }
+        Value::Null => Ok(()),
+    }
+}
+
+fn write_len(out: &mut impl Write, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len).map_err(|_| invalid("value too large for a snapshot"))?;
+    out.write_all(&len.to_le_bytes())
+}
+
+fn write_string(out: &mut impl Write, value: &[u8]) -> io::Result<()> {
+    write_len(out, value.len())?;
+    out.write_all(value)
+}
+
+fn read(input: &mut impl Read, limits: ListLimits) -> io::Result<Snapshot> {
+    let mut magic = [0; MAGIC.len()];
+    input.read_exact(&mut magic)?;
+
+    if magic != MAGIC {
+        return Err(invalid("not a snapshot file"));
+    }
+
+    if !(1..=VERSION).contains(&read_u8(input)?) {
+        return Err(invalid("unsupported snapshot version"));
+    }
+
+    let mut snapshot = Vec::new();
+    let mut index = 0;
+    let mut records = Vec::new();
+
+    loop {
+        let mut op = read_u8(input)?;
+        let mut expires_at = None;
+
+        if op == OP_SELECT_DB {
+            snapshot.push((index, std::mem::take(&mut records)));
+            index = read_len(input)?;
+            continue;
+        }
+
+        if op == OP_EXPIRES_AT {
+            expires_at = Some(u64::from_le_bytes(read_array(input)?));
+            op = read_u8(input)?;
+        }
+
+        if op == OP_EOF {
+            snapshot.push((index, records));
+            return Ok(snapshot);
+        }
+
+        let key = read_string(input)?;
+        let value = read_value(input, op, limits)?;
+
+        records.push((key, value, expires_at));
+    }
+}
+
+fn read_value(input: &mut impl Read, kind: u8, limits: ListLimits) -> io::Result<Value> {
+    Ok(match kind {
+        TYPE_BYTES => Value::Bytes(read_string(input)?.into_vec()),
+        TYPE_STRING => {
+            let val = String::from_utf8(read_string(input)?.into_vec())
+                .map_err(|_| invalid("string is not valid UTF-8"))?;
+            Value::String(val.into_boxed_str())
+        }
+        TYPE_INTEGER => Value::Integer(i64::from_le_bytes(read_array(input)?)),
+        TYPE_LIST => {
+            let mut list = List::default();
+
+            for _ in 0..read_len(input)? {
+                list.push_back(read_string(input)?, limits);
+            }
+
+            Value::List(list)
+        }
+        TYPE_HASH => {
+            let len = read_len(input)?;
+            let mut hash = Hash::default();
+
+            for _ in 0..len {
+                let field = read_string(input)?;
+                hash.insert(&field, read_string(input)?);
+            }
+
+            Value::Hash(hash)
+        }
+        TYPE_SET => {
+            let len = read_len(input)?;
+            let mut set = HashSet::new();
+
+            for _ in 0..len {
+                set.insert(read_string(input)?);
+            }
+
+            Value::Set(set)
+        }
+        TYPE_SORTED_SET => {
+            let mut set = SortedSet::default();
+
+            for _ in 0..read_len(input)? {
+                let score = f64::from_le_bytes(read_array(input)?);
+                set.insert(&read_string(input)?, score);
+            }
+
+            Value::SortedSet(set)
+        }
+        TYPE_NULL => Value::Null,
+        _ => return Err(invalid("unknown value type")),
+    })
+}
+
+fn read_u8(input: &mut impl Read) -> io::Result<u8> {
+    Ok(read_array::<1>(input)?[0])
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_len(input: &mut impl Read) -> io::Result<usize> {
+    Ok(u32::from_le_bytes(read_array(input)?) as usize)
+}
+
+fn read_string(input: &mut impl Read) -> io::Result<Box<[u8]>> {
+    let len = read_len(input)?;
+    let mut value = Vec::new();
+
+    // Reads through `take` so a corrupt length cannot allocate up front.
+    input.take(len as u64).read_to_end(&mut value)?;
+
+    if value.len() != len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(value.into_boxed_slice())
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Expire;
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("redis-test-{}-{name}.rdb", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_round_trip() {
+        let path = temp_path("round-trip");
+        let databases = Databases::from(Database::new());
+        let database = &databases[0];
+
+        database.insert(b"bytes", b"\x00\xff" as &[u8], None).await;
+        database.insert(b"string", "value", None).await;
+        database.insert(b"integer", -42i64, None).await;
+        database
+            .insert(b"expiring", "soon", Some(Duration::from_secs(60)))
+            .await;
+        database
+            .insert(b"expired", "gone", Some(Duration::from_millis(1)))
+            .await;
+        database.rpush(b"list", &["a", "b", "c"]).await.unwrap();
+        database.hset(b"hash", &[("field", "value")]).await.unwrap();
+        database.sadd(b"set", &["x", "y"]).await.unwrap();
+        database
+            .zadd(b"zset", &[(1.5, "one"), (-2.0, "two")])
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        databases.save(&path).await.unwrap();
+
+        let loaded = Databases::from(Database::new());
+        assert_eq!(8, loaded.load(&path).await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let loaded = &loaded[0];
+
+        for key in [
+            &b"bytes"[..],
+            b"string",
+            b"integer",
+            b"expiring",
+            b"list",
+            b"hash",
+            b"set",
+            b"zset",
+        ] {
+            assert_eq!(database.get(key).await, loaded.get(key).await);
+        }
+
+        assert_eq!(None, loaded.get(b"expired").await);
+
+        let guard = loaded.map.read().await;
+        let Some(Expire { duration, .. }) = guard
+            .get(b"expiring" as &[u8])
+            .and_then(|entry| entry.expire)
+        else {
+            panic!("expiry was not restored");
+        };
+        assert!(duration > Duration::from_secs(59) && duration <= Duration::from_secs(60));
+        assert!(guard
+            .get(b"string" as &[u8])
+            .is_some_and(|entry| entry.expire.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_bgsave_snapshots_before_returning() {
+        let path = temp_path("bgsave");
+        let databases = Databases::from(Database::new());
+
+        databases[0].insert(b"before", "value", None).await;
+        databases.bgsave(path.clone()).await.unwrap();
+        databases[0].insert(b"after", "value", None).await;
+
+        while databases.saving.load(Ordering::Acquire) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let loaded = Databases::from(Database::new());
+        assert_eq!(1, loaded.load(&path).await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(loaded[0].get(b"before").await.is_some());
+        assert!(loaded[0].get(b"after").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bgsave_waits_for_save_delay() {
+        let path = temp_path("bgsave-delay");
+        let databases = Databases::from(Database::new());
+        databases.set_save_delay(Duration::from_millis(300));
+
+        databases[0].insert(b"key", "value", None).await;
+
+        let start = Instant::now();
+        databases.bgsave(path.clone()).await.unwrap();
+
+        // The keyspace stays usable while the save sleeps.
+        databases[0].insert(b"other", "value", None).await;
+        assert!(databases[0].get(b"key").await.is_some());
+        assert!(start.elapsed() < Duration::from_millis(300));
+        assert!(databases.saving.load(Ordering::Acquire));
+        assert!(!path.exists());
+
+        while databases.saving.load(Ordering::Acquire) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_load_missing_and_corrupt() {
+        let database = Databases::from(Database::new());
+        assert_eq!(0, database.load(&temp_path("missing")).await.unwrap());
+
+        let path = temp_path("corrupt");
+        std::fs::write(&path, b"REDIS-RS\x01\x00\xff\xff\xff\xff").unwrap();
+        let err = database.load(&path).await.unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+        assert!(database[0].map.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_every_database() {
+        let path = temp_path("databases");
+        let databases = Databases::new(Arc::new(Database::new()), 16);
+
+        databases[0].insert(b"zero", "0", None).await;
+        databases[3].insert(b"three", "3", None).await;
+        databases[15].insert(b"fifteen", "15", None).await;
+        databases.save(&path).await.unwrap();
+
+        let loaded = Databases::new(Arc::new(Database::new()), 16);
+        assert_eq!(3, loaded.load(&path).await.unwrap());
+
+        assert!(loaded[0].get(b"zero").await.is_some());
+        assert!(loaded[3].get(b"three").await.is_some());
+        assert!(loaded[15].get(b"fifteen").await.is_some());
+        assert!(loaded[0].get(b"three").await.is_none());
+
+        // Keys of database 15 have nowhere to go with fewer databases.
+        let fewer = Databases::new(Arc::new(Database::new()), 4);
+        let err = fewer.load(&path).await.unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+    }
+
+    #[tokio::test]
+    async fn test_load_version_one_into_first_database() {
+        let path = temp_path("version-one");
+        std::fs::write(&path, b"REDIS-RS\x01\x01\x03\0\0\0key\x05\0\0\0value\xff").unwrap();
+
+        let loaded = Databases::new(Arc::new(Database::new()), 2);
+        assert_eq!(1, loaded.load(&path).await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            Some(Value::String(Box::from("value"))),
+            loaded[0].get(b"key").await
+        );
+        assert!(loaded[1].map.read().await.is_empty());
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::HashSet;
+
+use tokio::sync::RwLockReadGuard;
+use tokio::time::Instant;
+
+use super::collection::Collection;
+use super::{live, Database, Entry, Keyspace, TypeError, Value};
+
+pub(super) type Set = HashSet<Box<[u8]>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOperation {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// Applies `op` across the sets at `keys`, in order. Missing keys count as
+/// empty sets; any other kind of value answers `WRONGTYPE`.
+fn combine(map: &Keyspace, op: SetOperation, keys: &[impl AsRef<[u8]>]) -> Result<Set, TypeError> {
+    let now = Instant::now();
+    let empty = Set::new();
+
+    let sets = keys
+        .iter()
+        .map(|key| match live(map, key.as_ref(), now) {
+            Some(value) => Set::narrow(value).ok_or(TypeError::WrongType),
+            None => Ok(&empty),
+        })
+        .collect::<Result<Vec<&Set>, TypeError>>()?;
+
+    let Some((first, rest)) = sets.split_first() else {
+        return Ok(Set::new());
+    };
+
+    Ok(match op {
+        // Only members of the smallest set can be in every set, so it is
+        // the one walked, looking each member up in the others.
+        SetOperation::Inter => {
+            let smallest = sets
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, set)| set.len())
+                .map_or(0, |(index, _)| index);
+
+            sets[smallest]
+                .iter()
+                .filter(|member| {
+                    sets.iter()
+                        .enumerate()
+                        .all(|(index, set)| index == smallest || set.contains(*member))
+                })
+                .cloned()
+                .collect()
+        }
+        SetOperation::Union => sets.iter().flat_map(|set| set.iter()).cloned().collect(),
+        SetOperation::Diff => first
+            .iter()
+            .filter(|member| !rest.iter().any(|set| set.contains(*member)))
+            .cloned()
+            .collect(),
+    })
+}
+
+impl Collection for Set {
+    fn narrow(value: &Value) -> Option<&Self> {
+        match value {
+            Value::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    fn narrow_mut(value: &mut Value) -> Option<&mut Self> {
+        match value {
+            Value::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Set(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        HashSet::is_empty(self)
+    }
+}
+
+impl Database {
+    /// Adds `members`, returning how many were not already in the set.
+    pub async fn sadd(&self, key: &[u8], members: &[impl AsRef<[u8]>]) -> Result<usize, TypeError> {
+        let added = self
+            .with_set_mut(key, true, |set| {
+                members
+                    .iter()
+                    .filter(|member| set.insert(Box::from(member.as_ref())))
+                    .count()
+            })
+            .await?;
+
+        Ok(added.unwrap_or(0))
+    }
+
+    /// Removes `members`, returning how many were in the set. The key is
+    /// deleted once its last member is gone.
+    pub async fn srem(&self, key: &[u8], members: &[impl AsRef<[u8]>]) -> Result<usize, TypeError> {
+        let removed = self
+            .with_set_mut(key, false, |set| {
+                members
+                    .iter()
+                    .filter(|member| set.remove(member.as_ref()))
+                    .count()
+            })
+            .await?;
+
+        Ok(removed.unwrap_or(0))
+    }
+
+    pub async fn smembers(&self, key: &[u8]) -> Result<Vec<Box<[u8]>>, TypeError> {
+        Ok(self
+            .get_set(key)
+            .await?
+            .map_or_else(Vec::new, |set| set.iter().cloned().collect()))
+    }
+
+    pub async fn sismember(&self, key: &[u8], member: &[u8]) -> Result<bool, TypeError> {
+        Ok(self
+            .get_set(key)
+            .await?
+            .is_some_and(|set| set.contains(member)))
+    }
+
+    /// Whether each of `members` is in the set, in the order given, all
+    /// read under one lock.
+    pub async fn smismember(
+        &self,
+        key: &[u8],
+        members: &[impl AsRef<[u8]>],
+    ) -> Result<Vec<bool>, TypeError> {
+        let set = self.get_set(key).await?;
+
+        Ok(members
+            .iter()
+            .map(|member| {
+                set.as_ref()
+                    .is_some_and(|set| set.contains(member.as_ref()))
+            })
+            .collect())
+    }
+
+    /// How many members the set has, 0 for a missing key.
+    pub async fn scard(&self, key: &[u8]) -> Result<usize, TypeError> {
+        Ok(self.get_set(key).await?.map_or(0, |set| set.len()))
+    }
+
+    /// An alias of [`Database::scard`].
+    pub async fn set_len(&self, key: &[u8]) -> Result<usize, TypeError> {
+        self.scard(key).await
+    }
+
+    /// Returns the members of SINTER, SUNION or SDIFF over `keys`, computed
+    /// under a single read lock.
+    pub async fn combine_sets(
+        &self,
+        op: SetOperation,
+        keys: &[impl AsRef<[u8]>],
+    ) -> Result<Vec<Box<[u8]>>, TypeError> {
+        let guard = self.map.read().await;
+        Ok(combine(&guard, op, keys)?.into_iter().collect())
+    }
+
+    /// Stores the result of `op` over `keys` at `destination`, replacing
+    /// whatever it held, and returns its cardinality. An empty result deletes
+    /// `destination`. The sources are read under the same write lock so the
+    /// result cannot be stale by the time it is stored.
+    pub async fn store_combined_sets(
+        &self,
+        op: SetOperation,
+        destination: &[u8],
+        keys: &[impl AsRef<[u8]>],
+    ) -> Result<usize, TypeError> {
+        let mut guard = self.map.write().await;
+        let set = combine(&guard, op, keys)?;
+        let len = set.len();
+
+        if set.is_empty() {
+            self.remove_entry(&mut guard, destination);
+        } else {
+            self.insert_entry(
+                &mut guard,
+                destination.into(),
+                Entry::new(set.into_value(), None),
+            );
+        }
+
+        Ok(len)
+    }
+
+    pub(super) async fn get_set(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<RwLockReadGuard<'_, Set>>, TypeError> {
+        self.get_collection(key).await
+    }
+
+    pub(super) async fn with_set_mut<T>(
+        &self,
+        key: &[u8],
+        create: bool,
+        f: impl FnOnce(&mut Set) -> T,
+    ) -> Result<Option<T>, TypeError> {
+        self.with_collection_mut(key, create, f).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn members(values: &[&str]) -> Vec<Box<[u8]>> {
+        values
+            .iter()
+            .map(|value| Box::from(value.as_bytes()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_sadd_counts_only_new_members() {
+        let database = Database::new();
+
+        assert_eq!(Ok(2), database.sadd(b"set", &["a", "b", "a"]).await);
+        assert_eq!(Ok(1), database.sadd(b"set", &["b", "c"]).await);
+        assert_eq!(Ok(0), database.sadd(b"set", &["a", "c"]).await);
+        assert_eq!(Ok(3), database.scard(b"set").await);
+
+        let mut all = database.smembers(b"set").await.unwrap();
+        all.sort();
+        assert_eq!(members(&["a", "b", "c"]), all);
+    }
+
+    #[tokio::test]
+    async fn test_sismember_and_missing_key() {
+        let database = Database::new();
+        database.sadd(b"set", &["a"]).await.unwrap();
+
+        assert_eq!(Ok(true), database.sismember(b"set", b"a").await);
+        assert_eq!(Ok(false), database.sismember(b"set", b"b").await);
+        assert_eq!(Ok(false), database.sismember(b"missing", b"a").await);
+        assert_eq!(
+            Ok(vec![true, false, true]),
+            database.smismember(b"set", &["a", "b", "a"]).await
+        );
+        assert_eq!(
+            Ok(vec![false, false]),
+            database.smismember(b"missing", &["a", "b"]).await
+        );
+        assert_eq!(Ok(0), database.scard(b"missing").await);
+        assert_eq!(Ok(vec![]), database.smembers(b"missing").await);
+    }
+
+    #[tokio::test]
+    async fn test_srem_removes_key_with_last_member() {
+        let database = Database::new();
+        database.sadd(b"set", &["a", "b"]).await.unwrap();
+
+        assert_eq!(Ok(1), database.srem(b"set", &["a", "missing"]).await);
+        assert_eq!(Ok(1), database.srem(b"set", &["b"]).await);
+        assert!(database.map.read().await.get(b"set" as &[u8]).is_none());
+        assert_eq!(Ok(0), database.srem(b"set", &["b"]).await);
+
+        // Removing from a missing key leaves no empty set behind.
+        assert!(database.map.read().await.get(b"set" as &[u8]).is_none());
+        assert_eq!(Ok(1), database.rpush(b"set", &[b"a"]).await);
+    }
+
+    async fn database_with_sets() -> Database {
+        let database = Database::new();
+        database.sadd(b"a", &["1", "2", "3"]).await.unwrap();
+        database.sadd(b"b", &["2", "3", "4"]).await.unwrap();
+        database.sadd(b"c", &["5"]).await.unwrap();
+
+        database
+    }
+
+    async fn combined(database: &Database, op: SetOperation, keys: &[&str]) -> Vec<Box<[u8]>> {
+        let mut result = database.combine_sets(op, keys).await.unwrap();
+        result.sort();
+        result
+    }
+
+    #[tokio::test]
+    async fn test_combine_overlapping_sets() {
+        let database = database_with_sets().await;
+
+        assert_eq!(
+            members(&["2", "3"]),
+            combined(&database, SetOperation::Inter, &["a", "b"]).await
+        );
+        assert_eq!(
+            members(&["1", "2", "3", "4"]),
+            combined(&database, SetOperation::Union, &["a", "b"]).await
+        );
+        assert_eq!(
+            members(&["1"]),
+            combined(&database, SetOperation::Diff, &["a", "b"]).await
+        );
+        assert_eq!(
+            members(&["4"]),
+            combined(&database, SetOperation::Diff, &["b", "a"]).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_combine_disjoint_and_missing_sets() {
+        let database = database_with_sets().await;
+
+        assert_eq!(
+            members(&[]),
+            combined(&database, SetOperation::Inter, &["a", "c"]).await
+        );
+        assert_eq!(
+            members(&["1", "2", "3", "5"]),
+            combined(&database, SetOperation::Union, &["a", "c", "missing"]).await
+        );
+        assert_eq!(
+            members(&["1", "2", "3"]),
+            combined(&database, SetOperation::Diff, &["a", "c", "missing"]).await
+        );
+        assert_eq!(
+            members(&[]),
+            combined(&database, SetOperation::Inter, &["a", "missing"]).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_combine_three_sets() {
+        let database = database_with_sets().await;
+        database.sadd(b"d", &["2", "3", "4", "5"]).await.unwrap();
+
+        // Grouping does not matter for intersections and unions.
+        for keys in [["a", "b", "d"], ["d", "a", "b"], ["b", "d", "a"]] {
+            assert_eq!(
+                members(&["2", "3"]),
+                combined(&database, SetOperation::Inter, &keys).await
+            );
+            assert_eq!(
+                members(&["1", "2", "3", "4", "5"]),
+                combined(&database, SetOperation::Union, &keys).await
+            );
+        }
+
+        assert_eq!(
+            members(&[]),
+            combined(&database, SetOperation::Inter, &["a", "missing", "b"]).await
+        );
+        assert_eq!(
+            members(&["1", "2", "3", "4"]),
+            combined(&database, SetOperation::Union, &["a", "missing", "b"]).await
+        );
+        assert_eq!(
+            members(&["1"]),
+            combined(&database, SetOperation::Diff, &["a", "missing", "b"]).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_combined_sets() {
+        let database = database_with_sets().await;
+        database.insert(b"dest", "value", None).await;
+
+        assert_eq!(
+            Ok(4),
+            database
+                .store_combined_sets(SetOperation::Union, b"dest", &["a", "b"])
+                .await
+        );
+        assert_eq!(Ok(4), database.scard(b"dest").await);
+
+        assert_eq!(
+            Ok(2),
+            database
+                .store_combined_sets(SetOperation::Inter, b"a", &["a", "b"])
+                .await
+        );
+        assert_eq!(Ok(2), database.scard(b"a").await);
+
+        assert_eq!(
+            Ok(0),
+            database
+                .store_combined_sets(SetOperation::Inter, b"dest", &["a", "c"])
+                .await
+        );
+        assert!(database.map.read().await.get(b"dest" as &[u8]).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_store_combined_sets_replaces_destination() {
+        let database = database_with_sets().await;
+        database
+            .set(b"dest", "value", Some(Duration::from_secs(1)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Ok(1),
+            database
+                .store_combined_sets(SetOperation::Diff, b"dest", &["a", "b"])
+                .await
+        );
+
+        // The destination is a new key, without the timeout the old one had.
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert_eq!(Ok(members(&["1"])), database.smembers(b"dest").await);
+
+        // A source read as the destination is read before it is replaced.
+        assert_eq!(
+            Ok(1),
+            database
+                .store_combined_sets(SetOperation::Diff, b"b", &["b", "a"])
+                .await
+        );
+        assert_eq!(Ok(members(&["4"])), database.smembers(b"b").await);
+    }
+
+    #[tokio::test]
+    async fn test_set_wrong_type() {
+        let database = Database::new();
+        database.insert(b"key", "value", None).await;
+
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.sadd(b"key", &["a"]).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.srem(b"key", &["a"]).await
+        );
+        assert_eq!(Err(TypeError::WrongType), database.smembers(b"key").await);
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.sismember(b"key", b"a").await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.smismember(b"key", &["a"]).await
+        );
+        assert_eq!(Err(TypeError::WrongType), database.scard(b"key").await);
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database
+                .combine_sets(SetOperation::Union, &["missing", "key"])
+                .await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database
+                .store_combined_sets(SetOperation::Inter, b"dest", &["key"])
+                .await
+        );
+
+        database.sadd(b"set", &["a"]).await.unwrap();
+        assert_eq!(Err(TypeError::WrongType), database.llen(b"set").await);
+        assert_eq!(Err(TypeError::WrongType), database.strlen(b"set").await);
+    }
+
+    #[tokio::test]
+    async fn test_set_len() {
+        let database = Database::new();
+
+        database.sadd(b"set", &["a", "b", "a"]).await.unwrap();
+        database.set(b"string", "value", None).await.unwrap();
+
+        assert_eq!(Ok(2), database.set_len(b"set").await);
+        assert_eq!(Ok(0), database.set_len(b"missing").await);
+        assert_eq!(Err(TypeError::WrongType), database.set_len(b"string").await);
+    }
+}
@@ -0,0 +1,571 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::mem::size_of;
+
+use tokio::sync::RwLockReadGuard;
+
+use super::collection::Collection;
+use super::list::{normalize_range, MultiPopped};
+use super::{Database, TypeError, Value};
+
+pub type ScoredMember = (Box<[u8]>, f64);
+
+/// A score ordered by [`f64::total_cmp`], so it can key the index. NaN is
+/// rejected before it ever reaches a sorted set.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// One end of a score range, as taken by ZRANGEBYSCORE and ZCOUNT. The
+/// infinities are inclusive bounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+impl ScoreBound {
+    fn value(self) -> f64 {
+        match self {
+            ScoreBound::Inclusive(value) | ScoreBound::Exclusive(value) => value,
+        }
+    }
+
+    /// Whether `score` is within this bound used as a minimum.
+    fn admits_from_below(self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(min) => min <= score,
+            ScoreBound::Exclusive(min) => min < score,
+        }
+    }
+
+    /// Whether `score` is within this bound used as a maximum.
+    fn admits_from_above(self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(max) => score <= max,
+            ScoreBound::Exclusive(max) => score < max,
+        }
+    }
+}
+
+/// The end of a sorted set ZMPOP pops from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreEnd {
+    Min,
+    Max,
+}
+
+/// Members with their scores, plus an index ordered by score and then by
+/// member bytes, the order every range command walks.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortedSet {
+    scores: HashMap<Box<[u8]>, f64>,
+    index: BTreeSet<(Score, Box<[u8]>)>,
+}
+
+impl SortedSet {
+    /// Sets the score of `member`, returning whether it was newly added.
+    pub fn insert(&mut self, member: &[u8], score: f64) -> bool {
+        match self.scores.get_mut(member) {
+            Some(old) => {
+                self.index.remove(&(Score(*old), Box::from(member)));
+                self.index.insert((Score(score), Box::from(member)));
+                *old = score;
+                false
+            }
+            None => {
+                self.scores.insert(Box::from(member), score);
+                self.index.insert((Score(score), Box::from(member)));
+                true
+            }
+        }
+    }
+
+    /// Removes and returns the member with the lowest or highest score.
+    pub fn pop(&mut self, end: ScoreEnd) -> Option<ScoredMember> {
+        let (score, member) = match end {
+            ScoreEnd::Min => self.index.pop_first(),
+            ScoreEnd::Max => self.index.pop_last(),
+        }?;
+
+        self.scores.remove(&member);
+        Some((member, score.0))
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Estimated bytes the members take, each stored once in the score map
+    /// and once more in the index.
+    pub fn memory_usage(&self) -> usize {
+        self.scores
+            .keys()
+            .map(|member| 2 * (size_of::<(Box<[u8]>, f64)>() + member.len()))
+            .sum()
+    }
+
+    /// Walks the members in ascending score order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&[u8], f64)> {
+        self.index
+            .iter()
+            .map(|(score, member)| (member.as_ref(), score.0))
+    }
+
+    /// Walks the members scored between `min` and `max` in ascending order,
+    /// seeking straight to `min` in the index.
+    pub fn range_by_score(
+        &self,
+        min: ScoreBound,
+        max: ScoreBound,
+    ) -> impl Iterator<Item = (&[u8], f64)> {
+        self.index
+            .range((Score(min.value()), Box::<[u8]>::default())..)
+            .map(|(score, member)| (member.as_ref(), score.0))
+            .skip_while(move |(_, score)| !min.admits_from_below(*score))
+            .take_while(move |(_, score)| max.admits_from_above(*score))
+    }
+}
+
+/// Formats a score the way Redis replies with it: integral scores have no
+/// fractional part, everything else is the shortest round-trippable form.
+pub fn format_score(score: f64) -> String {
+    if score.is_infinite() {
+        return String::from(if score > 0.0 { "inf" } else { "-inf" });
+    }
+
+    let abs = score.abs();
+
+    if abs != 0.0 && !(1e-5..1e17).contains(&abs) {
+        // Rust spells exponents `1e21`, Redis `1e+21`.
+        let formatted = format!("{score:e}");
+
+        return match formatted.split_once('e') {
+            Some((mantissa, exponent)) if !exponent.starts_with('-') => {
+                format!("{mantissa}e+{exponent}")
+            }
+            _ => formatted,
+        };
+    }
+
+    score.to_string()
+}
+
+impl Collection for SortedSet {
+    fn narrow(value: &Value) -> Option<&Self> {
+        match value {
+            Value::SortedSet(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    fn narrow_mut(value: &mut Value) -> Option<&mut Self> {
+        match value {
+            Value::SortedSet(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::SortedSet(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        SortedSet::is_empty(self)
+    }
+}
+
+impl Database {
+    /// Adds every score/member pair, updating the score of members already
+    /// present, and returns how many members were new.
+    pub async fn zadd(
+        &self,
+        key: &[u8],
+        members: &[(f64, impl AsRef<[u8]>)],
+    ) -> Result<usize, TypeError> {
+        let added = self
+            .with_sorted_set_mut(key, true, |set| {
+                members
+                    .iter()
+                    .filter(|(score, member)| set.insert(member.as_ref(), *score))
+                    .count()
+            })
+            .await?;
+
+        Ok(added.unwrap_or(0))
+    }
+
+    pub async fn zscore(&self, key: &[u8], member: &[u8]) -> Result<Option<f64>, TypeError> {
+        Ok(self
+            .get_sorted_set(key)
+            .await?
+            .and_then(|set| set.score(member)))
+    }
+
+    /// Returns the members ranked `start..=stop` by ascending score, where
+    /// negative ranks count from the highest score.
+    pub async fn zrange(
+        &self,
+        key: &[u8],
+        start: i64,
+        stop: i64,
+    ) -> Result<Vec<ScoredMember>, TypeError> {
+        Ok(match self.get_sorted_set(key).await? {
+            Some(set) => match normalize_range(set.len(), start, stop) {
+                Some((start, stop)) => set
+                    .iter()
+                    .skip(start)
+                    .take(stop - start + 1)
+                    .map(|(member, score)| (Box::from(member), score))
+                    .collect(),
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        })
+    }
+
+    /// Returns the members scored between `min` and `max`, skipping the
+    /// first `offset` and returning at most `count` of the rest when a
+    /// `LIMIT` is given.
+    pub async fn zrangebyscore(
+        &self,
+        key: &[u8],
+        min: ScoreBound,
+        max: ScoreBound,
+        limit: Option<(usize, Option<usize>)>,
+    ) -> Result<Vec<ScoredMember>, TypeError> {
+        let (offset, count) = limit.unwrap_or((0, None));
+
+        Ok(self
+            .get_sorted_set(key)
+            .await?
+            .map_or_else(Vec::new, |set| {
+                set.range_by_score(min, max)
+                    .skip(offset)
+                    .take(count.unwrap_or(usize::MAX))
+                    .map(|(member, score)| (Box::from(member), score))
+                    .collect()
+            }))
+    }
+
+    pub async fn zcount(
+        &self,
+        key: &[u8],
+        min: ScoreBound,
+        max: ScoreBound,
+    ) -> Result<usize, TypeError> {
+        Ok(self
+            .get_sorted_set(key)
+            .await?
+            .map_or(0, |set| set.range_by_score(min, max).count()))
+    }
+
+    pub async fn zcard(&self, key: &[u8]) -> Result<usize, TypeError> {
+        Ok(self.get_sorted_set(key).await?.map_or(0, |set| set.len()))
+    }
+
+    /// Pops up to `count` members from the `end` of the first non-empty
+    /// sorted set among `keys`. `None` if they are all empty.
+    pub async fn zmpop(
+        &self,
+        keys: &[impl AsRef<[u8]>],
+        end: ScoreEnd,
+        count: usize,
+    ) -> Result<Option<MultiPopped<ScoredMember>>, TypeError> {
+        self.with_first_collection_mut(keys, |set: &mut SortedSet| {
+            (0..count).map_while(|_| set.pop(end)).collect()
+        })
+        .await
+    }
+
+    pub(super) async fn get_sorted_set(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<RwLockReadGuard<'_, SortedSet>>, TypeError> {
+        self.get_collection(key).await
+    }
+
+    pub(super) async fn with_sorted_set_mut<T>(
+        &self,
+        key: &[u8],
+        create: bool,
+        f: impl FnOnce(&mut SortedSet) -> T,
+    ) -> Result<Option<T>, TypeError> {
+        self.with_collection_mut(key, create, f).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scored(values: &[(&str, f64)]) -> Vec<ScoredMember> {
+        values
+            .iter()
+            .map(|(member, score)| (Box::from(member.as_bytes()), *score))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_zadd_counts_new_members() {
+        let database = Database::new();
+
+        assert_eq!(
+            Ok(2),
+            database.zadd(b"zset", &[(1.0, "a"), (2.0, "b")]).await
+        );
+        assert_eq!(
+            Ok(1),
+            database.zadd(b"zset", &[(3.0, "a"), (1.5, "c")]).await
+        );
+        assert_eq!(Ok(3), database.zcard(b"zset").await);
+        assert_eq!(Ok(Some(3.0)), database.zscore(b"zset", b"a").await);
+        assert_eq!(Ok(None), database.zscore(b"zset", b"missing").await);
+        assert_eq!(Ok(None), database.zscore(b"missing", b"a").await);
+        assert_eq!(Ok(0), database.zcard(b"missing").await);
+    }
+
+    #[tokio::test]
+    async fn test_zrange_orders_by_score_then_member() {
+        let database = Database::new();
+        database
+            .zadd(b"zset", &[(2.0, "c"), (1.0, "b"), (2.0, "a"), (-1.0, "d")])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Ok(scored(&[("d", -1.0), ("b", 1.0), ("a", 2.0), ("c", 2.0)])),
+            database.zrange(b"zset", 0, -1).await
+        );
+        assert_eq!(
+            Ok(scored(&[("a", 2.0), ("c", 2.0)])),
+            database.zrange(b"zset", -2, 10).await
+        );
+        assert_eq!(Ok(vec![]), database.zrange(b"zset", 3, 1).await);
+        assert_eq!(Ok(vec![]), database.zrange(b"missing", 0, -1).await);
+
+        database.zadd(b"zset", &[(0.0, "c")]).await.unwrap();
+        assert_eq!(
+            Ok(scored(&[("d", -1.0), ("c", 0.0)])),
+            database.zrange(b"zset", 0, 1).await
+        );
+    }
+
+    async fn database_with_scores() -> Database {
+        let database = Database::new();
+        database
+            .zadd(
+                b"zset",
+                &[(1.0, "a"), (1.0, "b"), (2.0, "c"), (3.0, "d"), (4.0, "e")],
+            )
+            .await
+            .unwrap();
+
+        database
+    }
+
+    #[tokio::test]
+    async fn test_zrangebyscore_bounds() {
+        let database = database_with_scores().await;
+        let inf = ScoreBound::Inclusive(f64::INFINITY);
+
+        assert_eq!(
+            Ok(scored(&[("c", 2.0), ("d", 3.0)])),
+            database
+                .zrangebyscore(
+                    b"zset",
+                    ScoreBound::Exclusive(1.0),
+                    ScoreBound::Inclusive(3.0),
+                    None
+                )
+                .await
+        );
+        assert_eq!(
+            Ok(scored(&[("a", 1.0), ("b", 1.0), ("c", 2.0)])),
+            database
+                .zrangebyscore(
+                    b"zset",
+                    ScoreBound::Inclusive(f64::NEG_INFINITY),
+                    ScoreBound::Exclusive(3.0),
+                    None
+                )
+                .await
+        );
+        assert_eq!(
+            Ok(vec![]),
+            database
+                .zrangebyscore(b"zset", ScoreBound::Exclusive(4.0), inf, None)
+                .await
+        );
+        assert_eq!(
+            Ok(vec![]),
+            database
+                .zrangebyscore(
+                    b"zset",
+                    ScoreBound::Inclusive(3.0),
+                    ScoreBound::Inclusive(2.0),
+                    None
+                )
+                .await
+        );
+        assert_eq!(
+            Ok(vec![]),
+            database
+                .zrangebyscore(b"missing", ScoreBound::Inclusive(0.0), inf, None)
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zrangebyscore_limit() {
+        let database = database_with_scores().await;
+        let min = ScoreBound::Inclusive(f64::NEG_INFINITY);
+        let max = ScoreBound::Inclusive(f64::INFINITY);
+
+        assert_eq!(
+            Ok(scored(&[("b", 1.0), ("c", 2.0)])),
+            database
+                .zrangebyscore(b"zset", min, max, Some((1, Some(2))))
+                .await
+        );
+        assert_eq!(
+            Ok(scored(&[("d", 3.0), ("e", 4.0)])),
+            database
+                .zrangebyscore(b"zset", min, max, Some((3, None)))
+                .await
+        );
+        assert_eq!(
+            Ok(vec![]),
+            database
+                .zrangebyscore(b"zset", min, max, Some((10, Some(2))))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zcount() {
+        let database = database_with_scores().await;
+
+        assert_eq!(
+            Ok(5),
+            database
+                .zcount(
+                    b"zset",
+                    ScoreBound::Inclusive(f64::NEG_INFINITY),
+                    ScoreBound::Inclusive(f64::INFINITY)
+                )
+                .await
+        );
+        assert_eq!(
+            Ok(3),
+            database
+                .zcount(
+                    b"zset",
+                    ScoreBound::Exclusive(1.0),
+                    ScoreBound::Inclusive(4.0)
+                )
+                .await
+        );
+        assert_eq!(
+            Ok(0),
+            database
+                .zcount(
+                    b"missing",
+                    ScoreBound::Inclusive(0.0),
+                    ScoreBound::Inclusive(1.0)
+                )
+                .await
+        );
+    }
+
+    #[test]
+    fn test_format_score() {
+        assert_eq!("1", format_score(1.0));
+        assert_eq!("-3", format_score(-3.0));
+        assert_eq!("1.5", format_score(1.5));
+        assert_eq!("0.1", format_score(0.1));
+        assert_eq!("0", format_score(0.0));
+        assert_eq!("inf", format_score(f64::INFINITY));
+        assert_eq!("-inf", format_score(f64::NEG_INFINITY));
+        assert_eq!("1e+21", format_score(1e21));
+        assert_eq!("1.5e-7", format_score(1.5e-7));
+    }
+
+    #[tokio::test]
+    async fn test_zmpop() {
+        let database = Database::new();
+        database
+            .zadd(b"second", &[(1.0, "a"), (3.0, "c"), (2.0, "b")])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Ok(Some((
+                Box::from(b"second" as &[u8]),
+                scored(&[("c", 3.0), ("b", 2.0)])
+            ))),
+            database.zmpop(&["first", "second"], ScoreEnd::Max, 2).await
+        );
+        assert_eq!(
+            Ok(Some((Box::from(b"second" as &[u8]), scored(&[("a", 1.0)])))),
+            database
+                .zmpop(&["first", "second"], ScoreEnd::Min, 10)
+                .await
+        );
+        assert_eq!(Ok(0), database.zcard(b"second").await);
+        assert_eq!(
+            Ok(None),
+            database.zmpop(&["first", "second"], ScoreEnd::Min, 1).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sorted_set_wrong_type() {
+        let database = Database::new();
+        database.insert(b"key", "value", None).await;
+
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.zadd(b"key", &[(1.0, "a")]).await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.zscore(b"key", b"a").await
+        );
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.zrange(b"key", 0, -1).await
+        );
+        assert_eq!(Err(TypeError::WrongType), database.zcard(b"key").await);
+
+        database.zadd(b"zset", &[(1.0, "a")]).await.unwrap();
+        assert_eq!(Err(TypeError::WrongType), database.scard(b"zset").await);
+    }
+}
@@ -0,0 +1,622 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::list::normalize_range;
+use super::{
+    evict_expired, live, parse_integer, Database, Entry, IncrError, StringError, TypeError, Value,
+};
+
+/// Largest string SETRANGE and SETBIT may grow a value to, as in Redis.
+pub(crate) const MAX_STRING_LENGTH: usize = 512 * 1024 * 1024;
+
+/// Views a string value as raw bytes, the way the range and bit commands see
+/// it. Any other kind of value answers `WRONGTYPE`.
+pub(super) fn string_bytes(value: &Value) -> Result<Cow<'_, [u8]>, TypeError> {
+    match value {
+        Value::String(val) => Ok(Cow::Borrowed(val.as_bytes())),
+        Value::Bytes(val) => Ok(Cow::Borrowed(val)),
+        Value::Integer(val) => Ok(Cow::Owned(val.to_string().into_bytes())),
+        Value::Null => Ok(Cow::Borrowed(&[])),
+        _ => Err(TypeError::WrongType),
+    }
+}
+
+/// Which keys SET writes, as its `NX` and `XX` options choose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SetCondition {
+    #[default]
+    Always,
+    /// Only a key that does not exist yet.
+    Missing,
+    /// Only a key that already exists.
+    Exists,
+}
+
+/// How SET writes its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SetOptions {
+    /// How long until the key expires, `None` for it to persist.
+    pub expiration: Option<Duration>,
+    /// Keeps whatever timeout the key already has instead, as `KEEPTTL`.
+    pub keep_ttl: bool,
+    pub condition: SetCondition,
+    /// Returns the string value found at the key, as `GET`.
+    pub get: bool,
+}
+
+impl Database {
+    /// Stores `value` at `key` as `options` direct, unless it is longer
+    /// than the configured maximum value size. Returns whether it was
+    /// written and, when `options.get` is set, the value found at the key,
+    /// in which case a key holding a non-string value is left alone and
+    /// answers `WRONGTYPE`.
+    pub async fn set_with<'a>(
+        &self,
+        key: impl Into<Cow<'a, [u8]>>,
+        value: impl TryInto<Value, Error = &'static str>,
+        options: SetOptions,
+    ) -> Result<(bool, Option<Value>), StringError> {
+        let key = key.into();
+        let value = value.try_into().unwrap();
+        self.check_value_size(&value)?;
+
+        let mut lock = self.map.write().await;
+        let now = Instant::now();
+        let current = lock
+            .get(key.as_ref())
+            .filter(|entry| !entry.is_expired(now));
+
+        if options.get && current.is_some_and(|entry| !entry.value().is_string()) {
+            return Err(TypeError::WrongType.into());
+        }
+
+        let write = match options.condition {
+            SetCondition::Always => true,
+            SetCondition::Missing => current.is_none(),
+            SetCondition::Exists => current.is_some(),
+        };
+
+        if !write {
+            let found = current
+                .filter(|_| options.get)
+                .map(|entry| entry.value().clone());
+            return Ok((false, found));
+        }
+
+        let mut entry = Entry::new(value, options.expiration);
+        if options.keep_ttl {
+            entry.expire = current.and_then(|current| current.expire);
+        }
+
+        let old = self.insert_entry(&mut lock, key.into_owned().into_boxed_slice(), entry);
+        drop(lock);
+
+        match old {
+            Some(old) if options.get && !old.is_expired(now) => Ok((true, Some(old.into_value()))),
+            old => {
+                self.free(old);
+                Ok((true, None))
+            }
+        }
+    }
+
+    /// Appends `value`, creating the key if needed, and returns the new
+    /// length.
+    pub async fn append(&self, key: &[u8], value: &[u8]) -> Result<usize, StringError> {
+        let len = self
+            .with_string_mut(
+                key,
+                true,
+                |len| len + value.len(),
+                |bytes| {
+                    bytes.extend_from_slice(value);
+                    bytes.len()
+                },
+            )
+            .await?;
+
+        Ok(len.unwrap_or(0))
+    }
+
+    pub async fn strlen(&self, key: &[u8]) -> Result<usize, TypeError> {
+        Ok(self.with_string(key, <[u8]>::len).await?.unwrap_or(0))
+    }
+
+    /// Returns the inclusive `start..=end` byte window, where negative
+    /// indices count from the end.
+    pub async fn getrange(&self, key: &[u8], start: i64, end: i64) -> Result<Vec<u8>, TypeError> {
+        let range = self
+            .with_string(key, |bytes| {
+                match normalize_range(bytes.len(), start, end) {
+                    Some((start, end)) => bytes[start..=end].to_vec(),
+                    None => Vec::new(),
+                }
+            })
+            .await?;
+
+        Ok(range.unwrap_or_default())
+    }
+
+    /// Overwrites the value from `offset` on, zero-padding it first if it is
+    /// shorter, and returns the new length. An empty `value` never creates
+    /// the key.
+    pub async fn setrange(
+        &self,
+        key: &[u8],
+        offset: usize,
+        value: &[u8],
+    ) -> Result<usize, StringError> {
+        if value.is_empty() {
+            return Ok(self.strlen(key).await?);
+        }
+
+        let len = self
+            .with_string_mut(
+                key,
+                true,
+                |len| len.max(offset + value.len()),
+                |bytes| {
+                    let end = offset + value.len();
+
+                    if bytes.len() < end {
+                        bytes.resize(end, 0);
+                    }
+
+                    bytes[offset..end].copy_from_slice(value);
+                    bytes.len()
+                },
+            )
+            .await?;
+
+        Ok(len.unwrap_or(0))
+    }
+
+    pub async fn getbit(&self, key: &[u8], offset: usize) -> Result<bool, TypeError> {
+        let bit = self
+            .with_string(key, |bytes| {
+                bytes
+                    .get(offset / 8)
+                    .is_some_and(|byte| byte & bit_mask(offset) != 0)
+            })
+            .await?;
+
+        Ok(bit.unwrap_or(false))
+    }
+
+    /// Sets or clears the bit at `offset`, growing the value as needed, and
+    /// returns the bit's previous state.
+    pub async fn setbit(&self, key: &[u8], offset: usize, bit: bool) -> Result<bool, StringError> {
+        let old = self
+            .with_string_mut(
+                key,
+                true,
+                |len| len.max(offset / 8 + 1),
+                |bytes| {
+                    let idx = offset / 8;
+
+                    if bytes.len() <= idx {
+                        bytes.resize(idx + 1, 0);
+                    }
+
+                    let old = bytes[idx] & bit_mask(offset) != 0;
+
+                    if bit {
+                        bytes[idx] |= bit_mask(offset);
+                    } else {
+                        bytes[idx] &= !bit_mask(offset);
+                    }
+
+                    old
+                },
+            )
+            .await?;
+
+        Ok(old.unwrap_or(false))
+    }
+
+    /// Counts the set bits, optionally only within the inclusive byte range
+    /// `start..=end`.
+    pub async fn bitcount(
+        &self,
+        key: &[u8],
+        range: Option<(i64, i64)>,
+    ) -> Result<usize, TypeError> {
+        let count = self
+            .with_string(key, |bytes| {
+                let bytes = match range {
+                    Some((start, end)) => match normalize_range(bytes.len(), start, end) {
+                        Some((start, end)) => &bytes[start..=end],
+                        None => &[],
+                    },
+                    None => bytes,
+                };
+
+                bytes.iter().map(|byte| byte.count_ones() as usize).sum()
+            })
+            .await?;
+
+        Ok(count.unwrap_or(0))
+    }
+
+    /// Adds `delta` to the integer at `key`, starting from 0 for a missing
+    /// key, and returns the result. Integer values are updated in place;
+    /// strings are parsed once and stored back as integers, so later
+    /// increments take the fast path too. The expiration is kept.
+    pub async fn incr_by(&self, key: &[u8], delta: i64) -> Result<i64, IncrError> {
+        let mut guard = self.map.write().await;
+
+        self.tracked(&mut guard, &[key], |map| {
+            evict_expired(map, key, Instant::now());
+
+            let Some(entry) = map.get_mut(key) else {
+                map.insert(key.into(), Entry::new(Value::Integer(delta), None));
+                return Ok(delta);
+            };
+
+            entry.touch();
+            let current = match entry.value() {
+                Value::Integer(val) => *val,
+                value => parse_integer(&string_bytes(value)?).ok_or(IncrError::NotInteger)?,
+            };
+
+            let result = current.checked_add(delta).ok_or(IncrError::Overflow)?;
+            *entry.value_mut() = Value::Integer(result);
+
+            Ok(result)
+        })
+    }
+
+    /// Fails with [`StringError::TooLarge`] when `value` is a string longer
+    /// than the configured maximum value size.
+    pub(super) fn check_value_size(&self, value: &Value) -> Result<(), StringError> {
+        let len = match value {
+            Value::String(val) => val.len(),
+            Value::Bytes(val) => val.len(),
+            _ => return Ok(()),
+        };
+
+        self.check_len(len)
+    }
+
+    #[inline]
+    fn check_len(&self, len: usize) -> Result<(), StringError> {
+        match self.max_value_size {
+            Some(limit) if len > limit => Err(StringError::TooLarge),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read-locks the keyspace and runs `f` over the string at `key`. Yields
+    /// `Ok(None)` for a missing key.
+    async fn with_string<R>(
+        &self,
+        key: &[u8],
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> Result<Option<R>, TypeError> {
+        let guard = self.map.read().await;
+
+        match live(&guard, key, Instant::now()) {
+            Some(value) => Ok(Some(f(&string_bytes(value)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Write-locks the keyspace and runs `f` over the string at `key`,
+    /// which is stored as raw bytes from then on. The expiration is kept.
+    /// Raw bytes are changed in place, keeping whatever spare capacity they
+    /// grew, so repeated appends are amortized like Redis' sds rather than
+    /// copying the whole value each time. `new_len` gives the length `f`
+    /// leaves a value of the given length at; nothing is changed if that
+    /// exceeds the maximum value size.
+    async fn with_string_mut<R>(
+        &self,
+        key: &[u8],
+        create: bool,
+        new_len: impl FnOnce(usize) -> usize,
+        f: impl FnOnce(&mut Vec<u8>) -> R,
+    ) -> Result<Option<R>, StringError> {
+        let mut guard = self.map.write().await;
+
+        self.tracked(&mut guard, &[key], |map| {
+            evict_expired(map, key, Instant::now());
+
+            let len = match map.get(key) {
+                Some(entry) => string_bytes(entry.value())?.len(),
+                None if create => 0,
+                None => return Ok(None),
+            };
+            self.check_len(new_len(len))?;
+
+            if !map.contains_key(key) {
+                map.insert(key.into(), Entry::new(Value::Bytes(Vec::new()), None));
+            }
+
+            let entry = map
+                .get_mut(key)
+                .expect("the key was just checked or created");
+            entry.touch();
+
+            let value = entry.value_mut();
+            if !matches!(value, Value::Bytes(_)) {
+                *value = Value::Bytes(string_bytes(value)?.into_owned());
+            }
+
+            match value {
+                Value::Bytes(bytes) => Ok(Some(f(bytes))),
+                _ => unreachable!("the value was just stored as bytes"),
+            }
+        })
+    }
+}
+
+/// Bits are addressed from the most significant bit of the first byte.
+#[inline]
+fn bit_mask(offset: usize) -> u8 {
+    0x80 >> (offset % 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_append_and_strlen() {
+        let database = Database::new();
+
+        assert_eq!(Ok(5), database.append(b"key", b"Hello").await);
+        assert_eq!(Ok(11), database.append(b"key", b" World").await);
+        assert_eq!(Ok(11), database.strlen(b"key").await);
+        assert_eq!(Ok(0), database.strlen(b"missing").await);
+
+        database.insert(b"number", 100i64, None).await;
+        assert_eq!(Ok(3), database.strlen(b"number").await);
+        assert_eq!(Ok(4), database.append(b"number", b"0").await);
+        assert_eq!(
+            Some(Value::Bytes(b"1000".to_vec())),
+            database.get(b"number").await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_conditions() {
+        let database = Database::new();
+        let nx = SetOptions {
+            condition: SetCondition::Missing,
+            ..SetOptions::default()
+        };
+        let xx = SetOptions {
+            condition: SetCondition::Exists,
+            ..SetOptions::default()
+        };
+
+        assert_eq!(Ok((false, None)), database.set_with(b"key", "1", xx).await);
+        assert!(database.get(b"key").await.is_none());
+
+        assert_eq!(Ok((true, None)), database.set_with(b"key", "1", nx).await);
+        assert_eq!(Ok((false, None)), database.set_with(b"key", "2", nx).await);
+        assert_eq!(Ok((true, None)), database.set_with(b"key", "3", xx).await);
+
+        let get = SetOptions { get: true, ..nx };
+        assert_eq!(
+            Ok((false, Some(Value::String("3".into())))),
+            database.set_with(b"key", "4", get).await
+        );
+        assert_eq!(Some(Value::String("3".into())), database.get(b"key").await);
+
+        database.rpush(b"list", &["a"]).await.unwrap();
+        assert_eq!(
+            Err(TypeError::WrongType.into()),
+            database
+                .set_with(b"list", "1", SetOptions { get: true, ..xx })
+                .await
+        );
+        assert_eq!(Ok((true, None)), database.set_with(b"list", "1", xx).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_set_keep_ttl() {
+        let database = Database::new();
+        let keep_ttl = SetOptions {
+            keep_ttl: true,
+            ..SetOptions::default()
+        };
+
+        database
+            .set(b"key", "1", Some(Duration::from_secs(10)))
+            .await
+            .unwrap();
+        tokio::time::advance(Duration::from_secs(5)).await;
+        database.set_with(b"key", "2", keep_ttl).await.unwrap();
+
+        tokio::time::advance(Duration::from_secs(4)).await;
+        assert_eq!(Some(Value::String("2".into())), database.get(b"key").await);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(database.get(b"key").await.is_none());
+
+        // A key without a timeout keeps having none.
+        database.set(b"key", "1", None).await.unwrap();
+        database.set_with(b"key", "2", keep_ttl).await.unwrap();
+        tokio::time::advance(Duration::from_secs(3600)).await;
+        assert!(database.get(b"key").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_many_small_appends() {
+        let database = Database::new();
+        database.set(b"key", "ab", None).await.unwrap();
+        assert_eq!(Some("embstr"), database.encoding(b"key").await);
+
+        for count in 1..=100_000 {
+            assert_eq!(Ok(2 + count), database.append(b"key", b"x").await);
+        }
+
+        let mut expected = b"ab".to_vec();
+        expected.resize(100_002, b'x');
+        assert_eq!(Some(Value::Bytes(expected)), database.get(b"key").await);
+
+        // Grown in place, so even a short appended string is raw.
+        database.set(b"short", "ab", None).await.unwrap();
+        database.append(b"short", b"c").await.unwrap();
+        assert_eq!(Some("raw"), database.encoding(b"short").await);
+    }
+
+    #[tokio::test]
+    async fn test_getrange_and_setrange() {
+        let database = Database::new();
+        database.insert(b"key", "Hello World", None).await;
+
+        assert_eq!(Ok(b"Hello".to_vec()), database.getrange(b"key", 0, 4).await);
+        assert_eq!(
+            Ok(b"World".to_vec()),
+            database.getrange(b"key", -5, -1).await
+        );
+        assert_eq!(Ok(vec![]), database.getrange(b"key", 20, 30).await);
+        assert_eq!(Ok(vec![]), database.getrange(b"missing", 0, -1).await);
+
+        assert_eq!(Ok(11), database.setrange(b"key", 6, b"Redis").await);
+        assert_eq!(
+            Ok(b"Hello Redis".to_vec()),
+            database.getrange(b"key", 0, -1).await
+        );
+
+        assert_eq!(Ok(0), database.setrange(b"padded", 2, b"").await);
+        assert!(database.get(b"padded").await.is_none());
+        assert_eq!(Ok(3), database.setrange(b"padded", 2, b"a").await);
+        assert_eq!(
+            Ok(b"\0\0a".to_vec()),
+            database.getrange(b"padded", 0, -1).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incr_stays_integer() {
+        let database = Database::new();
+
+        assert_eq!(Ok(1), database.incr_by(b"counter", 1).await);
+        assert_eq!(Ok(2), database.incr_by(b"counter", 1).await);
+        assert_eq!(Ok(3), database.incr_by(b"counter", 1).await);
+        assert_eq!(Some(Value::Integer(3)), database.get(b"counter").await);
+        assert_eq!(Some("int"), database.encoding(b"counter").await);
+
+        database.insert(b"string", b"10" as &[u8], None).await;
+        assert_eq!(Ok(11), database.incr_by(b"string", 1).await);
+        assert_eq!(Some("int"), database.encoding(b"string").await);
+    }
+
+    #[tokio::test]
+    async fn test_incr_errors() {
+        let database = Database::new();
+        database.insert(b"word", "ten", None).await;
+        database.insert(b"max", i64::MAX, None).await;
+        database.rpush(b"list", &[b"1"]).await.unwrap();
+
+        assert_eq!(
+            Err(IncrError::NotInteger),
+            database.incr_by(b"word", 1).await
+        );
+        assert_eq!(Err(IncrError::Overflow), database.incr_by(b"max", 1).await);
+
+        // Only the canonical form of an integer is one, as in Redis.
+        for value in [&b"+5"[..], b" 5", b"5 ", b"05", b"-0"] {
+            database.insert(b"loose", value, None).await;
+            assert_eq!(
+                Err(IncrError::NotInteger),
+                database.incr_by(b"loose", 1).await
+            );
+        }
+
+        assert_eq!(Some(Value::Integer(i64::MAX)), database.get(b"max").await);
+        assert_eq!(
+            Err(IncrError::Type(TypeError::WrongType)),
+            database.incr_by(b"list", 1).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bits() {
+        let database = Database::new();
+
+        assert_eq!(Ok(false), database.setbit(b"key", 7, true).await);
+        assert_eq!(Ok(true), database.setbit(b"key", 7, true).await);
+        assert_eq!(Ok(true), database.getbit(b"key", 7).await);
+        assert_eq!(Ok(false), database.getbit(b"key", 6).await);
+        assert_eq!(Ok(false), database.getbit(b"key", 100).await);
+        assert_eq!(Ok(false), database.getbit(b"missing", 0).await);
+
+        database.insert(b"foobar", "foobar", None).await;
+        assert_eq!(Ok(26), database.bitcount(b"foobar", None).await);
+        assert_eq!(Ok(4), database.bitcount(b"foobar", Some((0, 0))).await);
+        assert_eq!(Ok(6), database.bitcount(b"foobar", Some((1, 1))).await);
+        assert_eq!(Ok(0), database.bitcount(b"foobar", Some((5, 1))).await);
+        assert_eq!(Ok(0), database.bitcount(b"missing", None).await);
+    }
+
+    #[tokio::test]
+    async fn test_string_commands_wrong_type() {
+        let database = Database::new();
+        database.rpush(b"list", &[b"a"]).await.unwrap();
+
+        let wrong_type = Err(TypeError::WrongType);
+
+        assert_eq!(
+            Err(StringError::Type(TypeError::WrongType)),
+            database.append(b"list", b"a").await
+        );
+        assert_eq!(wrong_type, database.strlen(b"list").await);
+        assert_eq!(
+            Err(TypeError::WrongType),
+            database.getrange(b"list", 0, -1).await
+        );
+        assert_eq!(
+            Err(StringError::Type(TypeError::WrongType)),
+            database.setrange(b"list", 0, b"a").await
+        );
+        assert_eq!(
+            Err(StringError::Type(TypeError::WrongType)),
+            database.setrange(b"list", 0, b"").await
+        );
+        assert_eq!(Err(TypeError::WrongType), database.getbit(b"list", 0).await);
+        assert_eq!(
+            Err(StringError::Type(TypeError::WrongType)),
+            database.setbit(b"list", 0, true).await
+        );
+        assert_eq!(wrong_type, database.bitcount(b"list", None).await);
+
+        assert_eq!(Ok(1), database.llen(b"list").await);
+    }
+
+    #[tokio::test]
+    async fn test_max_value_size() {
+        let database = Database::new().with_max_value_size(4);
+
+        assert_eq!(Ok(()), database.set(b"key", b"abcd" as &[u8], None).await);
+        assert_eq!(
+            Err(StringError::TooLarge),
+            database.set(b"key", b"abcde" as &[u8], None).await
+        );
+        assert_eq!(
+            Some(Value::Bytes(b"abcd".to_vec())),
+            database.get(b"key").await
+        );
+
+        assert_eq!(
+            Err(StringError::TooLarge),
+            database.append(b"key", b"e").await
+        );
+        assert_eq!(
+            Err(StringError::TooLarge),
+            database.setrange(b"key", 4, b"e").await
+        );
+        assert_eq!(Ok(4), database.setrange(b"key", 3, b"e").await);
+        assert_eq!(Ok(b"abce".to_vec()), database.getrange(b"key", 0, -1).await);
+
+        assert_eq!(
+            Err(StringError::TooLarge),
+            database.append(b"new", b"abcde").await
+        );
+        assert!(database.get(b"new").await.is_none());
+        assert_eq!(Ok(2), database.append(b"new", b"ab").await);
+    }
+}
@@ -1,20 +1,114 @@
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::ops::Range;
+
+use super::{parse_integer, Hash, List, SortedSet};
+
+/// Integers Redis keeps as preallocated shared objects instead of giving
+/// every key its own copy.
+pub const SHARED_INTEGERS: Range<i64> = 0..10000;
+
+/// The refcount Redis reports for shared objects, which are never freed.
+pub const SHARED_REFCOUNT: usize = i32::MAX as usize;
+
+/// Strings up to this length fit Redis' embedded string encoding.
+const EMBSTR_SIZE_LIMIT: usize = 44;
+
+/// What a typed read found at a key: nothing, a value of another type, or
+/// the one it was after.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lookup<T> {
+    Missing,
+    WrongType,
+    Found(T),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     String(Box<str>),
-    Bytes(Box<[u8]>),
+    Bytes(Vec<u8>),
     Integer(i64),
+    List(List),
+    Hash(Hash),
+    Set(HashSet<Box<[u8]>>),
+    SortedSet(SortedSet),
     Null,
 }
 
+impl Value {
+    /// Whether this is one of the string encodings that GET and SET work on.
+    pub fn is_string(&self) -> bool {
+        matches!(
+            self,
+            Value::String(_) | Value::Bytes(_) | Value::Integer(_) | Value::Null
+        )
+    }
+
+    /// Integers are stored inline, so every key holding one from
+    /// [`SHARED_INTEGERS`] is equivalent to Redis' shared object and reports
+    /// the same refcount.
+    pub fn refcount(&self) -> usize {
+        match self {
+            Value::Integer(val) if SHARED_INTEGERS.contains(val) => SHARED_REFCOUNT,
+            _ => 1,
+        }
+    }
+
+    /// Estimated bytes the value takes, counting its elements but not the
+    /// spare capacity of hash tables or allocator overhead. Raw bytes count
+    /// the spare capacity appends leave them, as Redis counts it for sds.
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + match self {
+                Value::String(val) => val.len(),
+                Value::Bytes(val) => val.capacity(),
+                Value::Integer(_) | Value::Null => 0,
+                Value::List(list) => list.memory_usage(),
+                Value::Hash(hash) => hash.memory_usage(),
+                Value::Set(set) => set
+                    .iter()
+                    .map(|member| size_of::<Box<[u8]>>() + member.len())
+                    .sum(),
+                Value::SortedSet(set) => set.memory_usage(),
+            }
+    }
+
+    /// The name OBJECT ENCODING reports for the value. Bytes grown by
+    /// APPEND and the like keep spare capacity and report `raw` at any
+    /// length, as in Redis.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "int",
+            Value::String(val) if val.len() <= EMBSTR_SIZE_LIMIT => "embstr",
+            Value::Bytes(val) if val.len() <= EMBSTR_SIZE_LIMIT && val.capacity() == val.len() => {
+                "embstr"
+            }
+            Value::Null => "embstr",
+            Value::String(_) | Value::Bytes(_) => "raw",
+            Value::List(list) => list.encoding(),
+            Value::Hash(_) | Value::Set(_) => "hashtable",
+            Value::SortedSet(_) => "skiplist",
+        }
+    }
+}
+
+/// Stores a string a client sent, as an integer when it is one written the
+/// canonical way, so it shares Redis' `int` encoding.
+fn string_value(val: &[u8]) -> Value {
+    parse_integer(val).map_or_else(|| Value::Bytes(val.to_vec()), Value::Integer)
+}
+
 impl<'a> TryFrom<&crate::Value<'a>> for Value {
     type Error = &'static str;
 
     fn try_from(value: &crate::Value<'a>) -> Result<Self, Self::Error> {
         match value {
             crate::Value::Null => Ok(Value::Null),
-            crate::Value::SimpleString(val) => Ok(Value::String(val.to_string().into_boxed_str())),
+            // Strings are stored as raw bytes so nothing is lost to UTF-8,
+            // unless they are integers Redis would store as such.
+            crate::Value::SimpleString(val) => Ok(string_value(val.as_bytes())),
             crate::Value::Integer(val) => Ok(Value::Integer(*val)),
-            crate::Value::BulkString(val) => Ok(Value::Bytes(val.to_vec().into_boxed_slice())),
+            crate::Value::BulkString(val) => Ok(string_value(val)),
             _ => Err("invalid value"),
         }
     }
@@ -32,7 +126,7 @@ impl TryFrom<&[u8]> for Value {
     type Error = &'static str;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        Ok(Value::Bytes(value.to_vec().into_boxed_slice()))
+        Ok(Value::Bytes(value.to_vec()))
     }
 }
 
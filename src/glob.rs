@@ -0,0 +1,62 @@
+/// Redis-style glob matching: `*`, `?`, `[...]` classes with `^` negation
+/// and ranges, and `\` escapes.
+pub(crate) fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => string.is_empty(),
+        Some((b'*', rest)) => (0..=string.len()).any(|skip| glob_match(rest, &string[skip..])),
+        Some((b'?', rest)) => !string.is_empty() && glob_match(rest, &string[1..]),
+        Some((b'[', rest)) => {
+            let Some((&byte, string)) = string.split_first() else {
+                return false;
+            };
+
+            let (negate, rest) = match rest.split_first() {
+                Some((b'^', rest)) => (true, rest),
+                _ => (false, rest),
+            };
+
+            let mut idx = 0;
+            let mut matched = false;
+
+            while idx < rest.len() && rest[idx] != b']' {
+                if rest[idx] == b'\\' && idx + 1 < rest.len() {
+                    matched |= rest[idx + 1] == byte;
+                    idx += 2;
+                } else if idx + 2 < rest.len() && rest[idx + 1] == b'-' && rest[idx + 2] != b']' {
+                    let (low, high) = (rest[idx].min(rest[idx + 2]), rest[idx].max(rest[idx + 2]));
+                    matched |= (low..=high).contains(&byte);
+                    idx += 3;
+                } else {
+                    matched |= rest[idx] == byte;
+                    idx += 1;
+                }
+            }
+
+            let rest = rest.get(idx + 1..).unwrap_or_default();
+            matched != negate && glob_match(rest, string)
+        }
+        Some((b'\\', [escaped, rest @ ..])) => {
+            string.first() == Some(escaped) && glob_match(rest, &string[1..])
+        }
+        Some((literal, rest)) => string.first() == Some(literal) && glob_match(rest, &string[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"news.*", b"news.sport"));
+        assert!(!glob_match(b"news.*", b"weather"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-c]llo", b"hbllo"));
+        assert!(glob_match(b"h\\*llo", b"h*llo"));
+        assert!(!glob_match(b"h\\*llo", b"hello"));
+    }
+}
@@ -1,13 +1,20 @@
 use std::borrow::Cow;
 use std::future::Future;
+use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 
-use server::Server as InnerRedisServer;
+use server::{Listener, Server as InnerRedisServer};
 pub(crate) use bytes::Buffer;
 
-pub use database::{Database, Value as DatabaseValue};
+pub use database::{
+    Database, Databases, DbIndexError, FieldValue, HrandfieldResult, IncrError, ListLimits,
+    ListPosition, ListSide, Lookup, LposResult, LsetError, MaxMemoryPolicy, MemoryError,
+    MultiPopped, PopResult, Popped, RandomField, SaveError, ScoreBound, ScoreEnd, ScoredMember,
+    SetCondition, SetOperation, SetOptions, StringError, TypeError, Value as DatabaseValue,
+};
 pub use resp::Value;
+pub use server::{AppendFsync, Config, Shutdown};
 
 mod redis_commands {
     include!(concat!(env!("OUT_DIR"), "/commands.rs"));
@@ -18,44 +25,524 @@ pub(crate) use crate::redis_commands::{COMMAND_KEYWORDS, CommandKeywords};
 mod bytes;
 mod macros;
 mod database;
+mod glob;
 
 pub(crate) mod parser;
 mod resp;
 pub(crate) mod server;
 
+/// A field/value argument pair, as taken by HSET.
+pub type Pair<'a> = (Cow<'a, [u8]>, Cow<'a, [u8]>);
+
+/// A username/password argument pair, as taken by HELLO's `AUTH` option.
+pub type Credentials<'a> = (Cow<'a, [u8]>, Cow<'a, [u8]>);
+
+/// A score/member argument pair, as taken by ZADD.
+pub type ScorePair<'a> = (f64, Cow<'a, [u8]>);
+
+/// The `FILTERBY` clause of COMMAND LIST.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandFilter<'a> {
+    Pattern(Cow<'a, [u8]>),
+    AclCat(Cow<'a, str>),
+    Module(Cow<'a, str>),
+}
+
+/// The cursor, `MATCH` pattern and `COUNT` of SCAN and HSCAN.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanOptions<'a> {
+    pub cursor: u64,
+    pub pattern: Option<Cow<'a, [u8]>>,
+    pub count: usize,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command<'a> {
     Ping,
     Command,
+    CommandCount,
+    CommandInfo(Box<[Cow<'a, [u8]>]>),
+    CommandDocs(Box<[Cow<'a, [u8]>]>),
+    CommandList(Option<CommandFilter<'a>>),
     Echo(Cow<'a, str>),
-    Get(Cow<'a, str>),
+    Get(Cow<'a, [u8]>),
     Set {
         key: Cow<'a, [u8]>,
         value: &'a Value<'a>,
-        expiration: Option<tokio::time::Duration>,
+        options: SetOptions,
+    },
+    LPush {
+        key: Cow<'a, [u8]>,
+        values: Box<[Cow<'a, [u8]>]>,
+    },
+    RPush {
+        key: Cow<'a, [u8]>,
+        values: Box<[Cow<'a, [u8]>]>,
+    },
+    LPushX {
+        key: Cow<'a, [u8]>,
+        values: Box<[Cow<'a, [u8]>]>,
+    },
+    RPushX {
+        key: Cow<'a, [u8]>,
+        values: Box<[Cow<'a, [u8]>]>,
+    },
+    LRange {
+        key: Cow<'a, [u8]>,
+        start: i64,
+        stop: i64,
+    },
+    LLen(Cow<'a, [u8]>),
+    LTrim {
+        key: Cow<'a, [u8]>,
+        start: i64,
+        stop: i64,
+    },
+    LPos {
+        key: Cow<'a, [u8]>,
+        element: Cow<'a, [u8]>,
+        rank: i64,
+        count: Option<usize>,
+        maxlen: usize,
+    },
+    LMPop {
+        keys: Box<[Cow<'a, [u8]>]>,
+        side: ListSide,
+        count: usize,
+    },
+    ZMPop {
+        keys: Box<[Cow<'a, [u8]>]>,
+        end: ScoreEnd,
+        count: usize,
+    },
+    LRem {
+        key: Cow<'a, [u8]>,
+        count: i64,
+        element: Cow<'a, [u8]>,
+    },
+    LIndex {
+        key: Cow<'a, [u8]>,
+        index: i64,
+    },
+    BPop {
+        keys: Box<[Cow<'a, [u8]>]>,
+        side: ListSide,
+        timeout: Option<tokio::time::Duration>,
+    },
+    LMove {
+        source: Cow<'a, [u8]>,
+        destination: Cow<'a, [u8]>,
+        from: ListSide,
+        to: ListSide,
+    },
+    BLMove {
+        source: Cow<'a, [u8]>,
+        destination: Cow<'a, [u8]>,
+        from: ListSide,
+        to: ListSide,
+        timeout: Option<tokio::time::Duration>,
+    },
+    LSet {
+        key: Cow<'a, [u8]>,
+        index: i64,
+        element: Cow<'a, [u8]>,
+    },
+    LInsert {
+        key: Cow<'a, [u8]>,
+        position: ListPosition,
+        pivot: Cow<'a, [u8]>,
+        element: Cow<'a, [u8]>,
+    },
+    LPop {
+        key: Cow<'a, [u8]>,
+        count: Option<usize>,
+    },
+    RPop {
+        key: Cow<'a, [u8]>,
+        count: Option<usize>,
+    },
+    Unlink(Box<[Cow<'a, [u8]>]>),
+    Del(Box<[Cow<'a, [u8]>]>),
+    /// FLUSHDB, freeing the keys in the background when `lazy` is set, or
+    /// as the database is configured when it is not given.
+    FlushDb {
+        lazy: Option<bool>,
+    },
+    Info(Box<[Cow<'a, [u8]>]>),
+    Subscribe(Box<[Cow<'a, [u8]>]>),
+    Unsubscribe(Box<[Cow<'a, [u8]>]>),
+    PSubscribe(Box<[Cow<'a, [u8]>]>),
+    PUnsubscribe(Box<[Cow<'a, [u8]>]>),
+    Publish {
+        channel: Cow<'a, [u8]>,
+        message: Cow<'a, [u8]>,
+    },
+    Append {
+        key: Cow<'a, [u8]>,
+        value: Cow<'a, [u8]>,
+    },
+    StrLen(Cow<'a, [u8]>),
+    Incr(Cow<'a, [u8]>),
+    GetRange {
+        key: Cow<'a, [u8]>,
+        start: i64,
+        end: i64,
+    },
+    SetRange {
+        key: Cow<'a, [u8]>,
+        offset: usize,
+        value: Cow<'a, [u8]>,
+    },
+    GetBit {
+        key: Cow<'a, [u8]>,
+        offset: usize,
+    },
+    SetBit {
+        key: Cow<'a, [u8]>,
+        offset: usize,
+        value: bool,
+    },
+    BitCount {
+        key: Cow<'a, [u8]>,
+        range: Option<(i64, i64)>,
+    },
+    Shutdown,
+    Save,
+    BgSave,
+    Select(i64),
+    SwapDb(i64, i64),
+    ClientSetName(Cow<'a, [u8]>),
+    ClientGetName,
+    ClientId,
+    ClientList,
+    Keys(Cow<'a, [u8]>),
+    Scan(ScanOptions<'a>),
+    Auth {
+        username: Option<Cow<'a, [u8]>>,
+        password: Cow<'a, [u8]>,
+    },
+    Hello {
+        protocol: Option<i64>,
+        auth: Option<Credentials<'a>>,
+        name: Option<Cow<'a, [u8]>>,
+    },
+    HSet {
+        key: Cow<'a, [u8]>,
+        pairs: Box<[Pair<'a>]>,
+    },
+    HSetNx {
+        key: Cow<'a, [u8]>,
+        field: Cow<'a, [u8]>,
+        value: Cow<'a, [u8]>,
+    },
+    HGet {
+        key: Cow<'a, [u8]>,
+        field: Cow<'a, [u8]>,
+    },
+    HMGet {
+        key: Cow<'a, [u8]>,
+        fields: Box<[Cow<'a, [u8]>]>,
+    },
+    HGetAll(Cow<'a, [u8]>),
+    HKeys(Cow<'a, [u8]>),
+    HVals(Cow<'a, [u8]>),
+    HDel {
+        key: Cow<'a, [u8]>,
+        fields: Box<[Cow<'a, [u8]>]>,
+    },
+    HExists {
+        key: Cow<'a, [u8]>,
+        field: Cow<'a, [u8]>,
+    },
+    HIncrBy {
+        key: Cow<'a, [u8]>,
+        field: Cow<'a, [u8]>,
+        delta: i64,
+    },
+    HIncrByFloat {
+        key: Cow<'a, [u8]>,
+        field: Cow<'a, [u8]>,
+        delta: f64,
+    },
+    HLen(Cow<'a, [u8]>),
+    HStrLen {
+        key: Cow<'a, [u8]>,
+        field: Cow<'a, [u8]>,
+    },
+    HRandField {
+        key: Cow<'a, [u8]>,
+        count: Option<i64>,
+        with_values: bool,
+    },
+    HScan {
+        key: Cow<'a, [u8]>,
+        options: ScanOptions<'a>,
+        no_values: bool,
+    },
+    SAdd {
+        key: Cow<'a, [u8]>,
+        members: Box<[Cow<'a, [u8]>]>,
+    },
+    SRem {
+        key: Cow<'a, [u8]>,
+        members: Box<[Cow<'a, [u8]>]>,
+    },
+    SMembers(Cow<'a, [u8]>),
+    SIsMember {
+        key: Cow<'a, [u8]>,
+        member: Cow<'a, [u8]>,
+    },
+    SMIsMember {
+        key: Cow<'a, [u8]>,
+        members: Box<[Cow<'a, [u8]>]>,
+    },
+    SCard(Cow<'a, [u8]>),
+    ZAdd {
+        key: Cow<'a, [u8]>,
+        members: Box<[ScorePair<'a>]>,
+    },
+    ZScore {
+        key: Cow<'a, [u8]>,
+        member: Cow<'a, [u8]>,
+    },
+    ZRange {
+        key: Cow<'a, [u8]>,
+        start: i64,
+        stop: i64,
+        with_scores: bool,
+    },
+    ZCard(Cow<'a, [u8]>),
+    ZRangeByScore {
+        key: Cow<'a, [u8]>,
+        min: ScoreBound,
+        max: ScoreBound,
+        with_scores: bool,
+        limit: Option<(usize, Option<usize>)>,
+    },
+    ZCount {
+        key: Cow<'a, [u8]>,
+        min: ScoreBound,
+        max: ScoreBound,
+    },
+    SetAlgebra {
+        op: SetOperation,
+        keys: Box<[Cow<'a, [u8]>]>,
+    },
+    SetAlgebraStore {
+        op: SetOperation,
+        destination: Cow<'a, [u8]>,
+        keys: Box<[Cow<'a, [u8]>]>,
     },
+    DebugNoop,
+    /// DEBUG PROTOCOL, replying with a sample of the named RESP type.
+    DebugProtocol(Cow<'a, str>),
+    DebugSleep(tokio::time::Duration),
+    /// DEBUG SLEEP-AFTER-FORK-SECONDS, delaying every later BGSAVE.
+    DebugSaveDelay(tokio::time::Duration),
+    DebugSetActiveExpire(bool),
+    DebugRefused(Cow<'a, str>),
+    ObjectRefCount(Cow<'a, [u8]>),
+    ObjectEncoding(Cow<'a, [u8]>),
+    /// CONFIG GET, with the patterns of the parameters to read.
+    ConfigGet(Box<[Cow<'a, [u8]>]>),
+    /// CONFIG SET, with each parameter to change and its new value.
+    ConfigSet(Box<[Pair<'a>]>),
+}
+
+impl Command<'_> {
+    /// Whether the command may change the keyspace, and so has to be logged
+    /// to the append-only file.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Command::Set { .. }
+                | Command::LPush { .. }
+                | Command::RPush { .. }
+                | Command::LPushX { .. }
+                | Command::RPushX { .. }
+                | Command::LTrim { .. }
+                | Command::LMPop { .. }
+                | Command::ZMPop { .. }
+                | Command::LRem { .. }
+                | Command::BPop { .. }
+                | Command::LMove { .. }
+                | Command::BLMove { .. }
+                | Command::LSet { .. }
+                | Command::LInsert { .. }
+                | Command::LPop { .. }
+                | Command::RPop { .. }
+                | Command::Unlink(_)
+                | Command::Del(_)
+                | Command::FlushDb { .. }
+                | Command::SwapDb(..)
+                | Command::Append { .. }
+                | Command::Incr(_)
+                | Command::SetRange { .. }
+                | Command::SetBit { .. }
+                | Command::HSet { .. }
+                | Command::HSetNx { .. }
+                | Command::HIncrBy { .. }
+                | Command::HIncrByFloat { .. }
+                | Command::HDel { .. }
+                | Command::SAdd { .. }
+                | Command::SRem { .. }
+                | Command::ZAdd { .. }
+                | Command::SetAlgebraStore { .. }
+        )
+    }
+
+    /// Whether the command may grow the keyspace, and so has to make room
+    /// first once it is over `maxmemory`. Commands that only remove are
+    /// let through, so memory can still be freed.
+    pub fn uses_memory(&self) -> bool {
+        matches!(
+            self,
+            Command::Set { .. }
+                | Command::LPush { .. }
+                | Command::RPush { .. }
+                | Command::LPushX { .. }
+                | Command::RPushX { .. }
+                | Command::LMove { .. }
+                | Command::BLMove { .. }
+                | Command::LSet { .. }
+                | Command::LInsert { .. }
+                | Command::Append { .. }
+                | Command::Incr(_)
+                | Command::SetRange { .. }
+                | Command::SetBit { .. }
+                | Command::HSet { .. }
+                | Command::HSetNx { .. }
+                | Command::HIncrBy { .. }
+                | Command::HIncrByFloat { .. }
+                | Command::SAdd { .. }
+                | Command::ZAdd { .. }
+                | Command::SetAlgebraStore { .. }
+        )
+    }
 }
 
 pub trait Server {
     fn run(&self) -> Pin<Box<dyn Future<Output = Result<(), std::io::Error>> + '_>>;
+
+    /// Stops accepting clients and awaits the registered shutdown steps.
+    fn shutdown(&self) -> Pin<Box<dyn Future<Output = ()> + '_>>;
+
+    /// The TCP addresses the server listens on, with any port 0 resolved.
+    fn local_addrs(&self) -> Result<Vec<SocketAddr>, std::io::Error>;
 }
 
-struct RedisServer(InnerRedisServer, Arc<Database>);
+struct RedisServer<L>(InnerRedisServer<L>, Arc<Databases>);
 
-impl Server for RedisServer {
+impl<L: Listener> Server for RedisServer<L> {
     fn run(&self) -> Pin<Box<dyn Future<Output = Result<(), std::io::Error>> + '_>> {
         Box::pin(self.0.start(Arc::clone(&self.1)))
     }
+
+    fn shutdown(&self) -> Pin<Box<dyn Future<Output = ()> + '_>> {
+        Box::pin(self.0.shutdown())
+    }
+
+    fn local_addrs(&self) -> Result<Vec<SocketAddr>, std::io::Error> {
+        self.0.local_addrs()
+    }
+}
+
+/// Makes `db` the first of the configured number of databases, applies the
+/// configured `maxmemory`, and repopulates them by replaying the append-only
+/// file when it is enabled, or else from the snapshot, if one is configured.
+async fn restore(config: &Config, db: Arc<Database>) -> Result<Arc<Databases>, std::io::Error> {
+    let databases = Databases::new(db, config.databases);
+
+    if config.maxmemory != 0 {
+        databases
+            .set_max_memory(config.maxmemory, config.maxmemory_policy)
+            .await;
+    }
+
+    if let Some(path) = &config.aof_path {
+        let replayed = server::replay_aof(path, &databases).await?;
+        tracing::info!(path = ?path, commands = replayed, "Replayed append-only file");
+    } else if let Some(path) = &config.rdb_path {
+        let loaded = databases.load(path).await?;
+        tracing::info!(path = ?path, keys = loaded, "Loaded snapshot");
+    }
+
+    Ok(Arc::new(databases))
 }
 
+/// Listens on `port` on every IPv4 interface.
 pub async fn start_server(
     port: u16,
     connection_limit: usize,
+    config: Config,
     db: Arc<Database>,
 ) -> Result<Box<dyn Server>, std::io::Error> {
+    start_server_on(
+        &[SocketAddr::from(([0, 0, 0, 0], port))],
+        connection_limit,
+        config,
+        db,
+    )
+    .await
+}
+
+/// Listens on every address in `addrs`, all served by the same accept loop.
+pub async fn start_server_on(
+    addrs: &[SocketAddr],
+    connection_limit: usize,
+    config: Config,
+    db: Arc<Database>,
+) -> Result<Box<dyn Server>, std::io::Error> {
+    let databases = restore(&config, db).await?;
+
+    let server = Box::new(RedisServer(
+        InnerRedisServer::new(addrs, connection_limit, config)
+            .await?
+            .save_on_shutdown(&databases),
+        databases,
+    ));
+
+    Ok(server)
+}
+
+/// Like [`start_server_on`], but every client has to complete a TLS
+/// handshake using `tls` before its first command is read.
+#[cfg(feature = "tls")]
+pub async fn start_server_tls(
+    addrs: &[SocketAddr],
+    tls: Arc<tokio_rustls::rustls::ServerConfig>,
+    connection_limit: usize,
+    config: Config,
+    db: Arc<Database>,
+) -> Result<Box<dyn Server>, std::io::Error> {
+    let databases = restore(&config, db).await?;
+
+    let server = Box::new(RedisServer(
+        InnerRedisServer::new(addrs, connection_limit, config)
+            .await?
+            .with_tls(tls)
+            .save_on_shutdown(&databases),
+        databases,
+    ));
+
+    Ok(server)
+}
+
+/// Listens on a Unix socket at `path`, which is removed again on shutdown.
+#[cfg(unix)]
+pub async fn start_server_unix(
+    path: impl AsRef<std::path::Path>,
+    connection_limit: usize,
+    config: Config,
+    db: Arc<Database>,
+) -> Result<Box<dyn Server>, std::io::Error> {
+    let databases = restore(&config, db).await?;
+
     let server = Box::new(RedisServer(
-        InnerRedisServer::new(port, connection_limit).await?,
-        db
+        InnerRedisServer::bind_unix(path.as_ref(), connection_limit, config)
+            .await?
+            .save_on_shutdown(&databases),
+        databases,
     ));
 
     Ok(server)
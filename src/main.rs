@@ -1,9 +1,10 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 use tracing::{error, info};
 
 use tracing_subscriber::{filter::EnvFilter, fmt::layer as fmt_layer, prelude::*, registry};
 
-use redis_starter_rust::{start_server, Database};
+use redis_starter_rust::{start_server, Config, Database};
 
 #[tokio::main]
 async fn main() {
@@ -23,16 +24,18 @@ async fn main() {
     registry().with(env_filter).with(stdout_layer).init();
 
     let database = Arc::new(Database::new());
-    let server = start_server(6379, 1024, Arc::clone(&database)).await;
+    let config = Config {
+        rdb_path: Some(PathBuf::from("dump.rdb")),
+        ..Config::default()
+    };
+
+    let server = start_server(6379, 1024, config, Arc::clone(&database)).await;
 
     match server {
         Ok(server) => {
             tokio::select! {
                 _ = tokio::signal::ctrl_c() => {
                     info!("Receiving CTRL+C... Exiting...");
-                    drop(server);
-                    drop(database);
-                    return;
                 },
                 result = server.run() => {
                      if let Err(err) = result {
@@ -40,6 +43,10 @@ async fn main() {
                     }
                 }
             }
+
+            server.shutdown().await;
+            drop(server);
+            drop(database);
         }
 
         Err(err) => {
@@ -1,15 +1,22 @@
 mod error;
 mod values;
 
-use bytes::BytesMut;
+use std::borrow::Cow;
+
 use tokio::time::Duration;
 use tracing::{error, instrument};
+use uncased::UncasedStr;
 
 pub use values::Error as ValueError;
 
-use crate::redis_commands::{SetParams, SET_PARAMS};
-use crate::resp::parse as parse_input;
-use crate::{Command, CommandKeywords, Value, COMMAND_KEYWORDS};
+use crate::database::MAX_STRING_LENGTH;
+use crate::redis_commands::{ObjectParams, SetParams, DEBUG_NOOPS, OBJECT_PARAMS, SET_PARAMS};
+use crate::resp::parse_frame;
+use crate::server::command_spec;
+use crate::{
+    Command, CommandFilter, CommandKeywords, ListPosition, ListSide, Pair, ScanOptions, ScoreEnd,
+    ScorePair, SetCondition, SetOperation, SetOptions, Value, COMMAND_KEYWORDS,
+};
 use values::Values;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -29,8 +36,44 @@ pub enum Error {
     #[error("command does not exist")]
     NotExists,
 
-    #[error("Command argument does not exist")]
-    InvalidCommandArgument,
+    #[error("ERR wrong number of arguments for '{0}' command")]
+    WrongArity(&'static str),
+
+    #[error("ERR offset is out of range")]
+    OffsetOutOfRange,
+
+    #[error("ERR bit is not an integer or out of range")]
+    BitOutOfRange,
+
+    #[error("ERR timeout is not a float or out of range")]
+    InvalidTimeout,
+
+    #[error("ERR timeout is negative")]
+    NegativeTimeout,
+
+    #[error("ERR count should be greater than 0")]
+    InvalidCount,
+
+    #[error("ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list")]
+    ZeroRank,
+
+    #[error("ERR COUNT can't be negative")]
+    NegativeCount,
+
+    #[error("ERR MAXLEN can't be negative")]
+    NegativeMaxlen,
+
+    #[error("ERR invalid cursor")]
+    InvalidCursor,
+
+    #[error("ERR syntax error")]
+    Syntax,
+
+    #[error("ERR invalid expire time in '{0}' command")]
+    InvalidExpireTime(&'static str),
+
+    #[error("ERR value is out of range")]
+    ValueOutOfRange,
 
     // #[error("Invalid arguments given to the command: {0}")]
     // InvalidArguments(&'static str),
@@ -41,58 +84,886 @@ pub enum Error {
     Value(#[from] ValueError),
 }
 
+impl Error {
+    /// Whether the input ended before a whole request arrived, so the caller
+    /// should wait for more bytes rather than reject what it has.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self, Error::Parse(super::resp::Error::Incomplete))
+    }
+}
+
 impl<'a> Parser<'a> {
-    pub fn parse(input: &'a BytesMut) -> Result<Self, Error> {
-        let values = match parse_input(input)? {
-            Value::Array(val) => Values::new(val),
+    /// Parses the first request in `input`, returning the parser together
+    /// with the number of bytes consumed so the caller can advance past it.
+    pub fn parse_frame(input: &'a [u8]) -> Result<(Self, usize), Error> {
+        let (values, consumed) = match parse_frame(input)? {
+            (Value::Array(val), consumed) => (Values::new(val), consumed),
             _ => return Err(Error::InvalidInput),
         };
 
-        Ok(Self { ast: values })
+        Ok((Self { ast: values }, consumed))
+    }
+
+    /// The lowercase name of the requested command, if it is one this server
+    /// knows. No arguments are consumed.
+    pub fn name(&self) -> Option<&'static str> {
+        let name = match self.ast.first()? {
+            Value::SimpleString(name) => name.as_ref(),
+            Value::BulkString(name) => std::str::from_utf8(name).ok()?,
+            _ => return None,
+        };
+
+        COMMAND_KEYWORDS
+            .get_key(UncasedStr::new(name))
+            .map(|name| name.as_str())
     }
 
     #[instrument]
     pub fn command(&mut self) -> Result<Command, Error> {
-        let command = COMMAND_KEYWORDS
-            .get(self.ast.get_uncased_string()?)
+        let (name, command) = COMMAND_KEYWORDS
+            .get_entry(self.ast.get_uncased_string()?)
             .ok_or(Error::NotExists)?;
 
+        // Checked up front, so a missing argument is never mistaken for a
+        // malformed one and extra arguments are not silently ignored.
+        if let Some(spec) = command_spec(name.as_str().as_bytes()) {
+            if !spec.accepts(self.ast.remaining() + 1) {
+                return Err(Error::WrongArity(spec.name));
+            }
+        }
+
         match command {
             CommandKeywords::Ping => Ok(Command::Ping),
-            CommandKeywords::Command => Ok(Command::Command),
+            CommandKeywords::Shutdown => Ok(Command::Shutdown),
+            CommandKeywords::Save => Ok(Command::Save),
+            CommandKeywords::Bgsave => Ok(Command::BgSave),
+            CommandKeywords::Select => Ok(Command::Select(self.ast.get_number()?)),
+            CommandKeywords::Swapdb => Ok(Command::SwapDb(
+                self.ast.get_number()?,
+                self.ast.get_number()?,
+            )),
+            CommandKeywords::Client => match self.ast.get_uncased_string()? {
+                subcommand if subcommand == "setname" => {
+                    Ok(Command::ClientSetName(self.ast.get_bytes()?))
+                }
+                subcommand if subcommand == "getname" => Ok(Command::ClientGetName),
+                subcommand if subcommand == "id" => Ok(Command::ClientId),
+                subcommand if subcommand == "list" => Ok(Command::ClientList),
+                _ => Err(Error::Syntax),
+            },
+            CommandKeywords::Keys => Ok(Command::Keys(self.ast.get_bytes()?)),
+            CommandKeywords::Scan => Ok(Command::Scan(self.scan_options(false)?.0)),
+            CommandKeywords::Auth => match self.ast.remaining() {
+                1 => Ok(Command::Auth {
+                    username: None,
+                    password: self.ast.get_bytes()?,
+                }),
+                2 => Ok(Command::Auth {
+                    username: Some(self.ast.get_bytes()?),
+                    password: self.ast.get_bytes()?,
+                }),
+                _ => Err(Error::Syntax),
+            },
+            CommandKeywords::Hello => self.hello(),
+            CommandKeywords::Command => {
+                if self.ast.remaining() == 0 {
+                    return Ok(Command::Command);
+                }
+
+                match self.ast.get_uncased_string()? {
+                    subcommand if subcommand == "list" => {
+                        Ok(Command::CommandList(self.command_filter()?))
+                    }
+                    subcommand if subcommand == "count" => Ok(Command::CommandCount),
+                    subcommand if subcommand == "info" => {
+                        Ok(Command::CommandInfo(self.optional_rest()?))
+                    }
+                    subcommand if subcommand == "docs" => {
+                        Ok(Command::CommandDocs(self.optional_rest()?))
+                    }
+                    _ => Err(Error::Syntax),
+                }
+            }
             CommandKeywords::Echo => Ok(Command::Echo(self.ast.get_string()?)),
-            CommandKeywords::Get => Ok(Command::Get(self.ast.get_string()?)),
+            CommandKeywords::Get => Ok(Command::Get(self.ast.get_bytes()?)),
             CommandKeywords::Set => {
                 let key = self.ast.get_bytes()?;
-                let value = self.ast.next()?;
+                let value = self.ast.get_scalar()?;
 
-                let expiration_ms = match self.ast.get_uncased_string() {
-                    Ok(val) => {
-                        let param = SET_PARAMS.get(val).ok_or(Error::InvalidCommandArgument)?;
+                let mut options = SetOptions::default();
+                // Which of EX, PX and KEEPTTL was given; repeating one is
+                // fine, but mixing them is not.
+                let mut ttl = None;
 
-                        Some(match param {
-                            SetParams::EX => Duration::from_secs(self.ast.get_number()? as u64),
-                            SetParams::PX => Duration::from_millis(self.ast.get_number()? as u64),
-                        })
-                    }
+                while self.ast.remaining() > 0 {
+                    let param = *SET_PARAMS
+                        .get(self.ast.get_uncased_string()?)
+                        .ok_or(Error::Syntax)?;
 
-                    Err(ValueError::OutOfBounds) => None,
-                    Err(err) => return Err(Error::Value(err)),
-                };
+                    match param {
+                        SetParams::EX | SetParams::PX | SetParams::KeepTtl
+                            if ttl.is_some_and(|ttl| ttl != param) =>
+                        {
+                            return Err(Error::Syntax)
+                        }
+                        SetParams::NX if options.condition == SetCondition::Exists => {
+                            return Err(Error::Syntax)
+                        }
+                        SetParams::XX if options.condition == SetCondition::Missing => {
+                            return Err(Error::Syntax)
+                        }
+                        SetParams::EX => {
+                            ttl = Some(param);
+                            options.expiration = Some(self.set_expire_time(1000)?);
+                        }
+                        SetParams::PX => {
+                            ttl = Some(param);
+                            options.expiration = Some(self.set_expire_time(1)?);
+                        }
+                        SetParams::KeepTtl => {
+                            ttl = Some(param);
+                            options.keep_ttl = true;
+                        }
+                        SetParams::NX => options.condition = SetCondition::Missing,
+                        SetParams::XX => options.condition = SetCondition::Exists,
+                        SetParams::Get => options.get = true,
+                    }
+                }
 
                 Ok(Command::Set {
                     key,
                     value,
-                    expiration: expiration_ms,
+                    options,
+                })
+            }
+            // GETSET is the deprecated spelling of `SET key value GET`.
+            CommandKeywords::Getset => Ok(Command::Set {
+                key: self.ast.get_bytes()?,
+                value: self.ast.get_scalar()?,
+                options: SetOptions {
+                    get: true,
+                    ..SetOptions::default()
+                },
+            }),
+            CommandKeywords::Lpush => Ok(Command::LPush {
+                key: self.ast.get_bytes()?,
+                values: self.ast.get_rest_bytes()?,
+            }),
+            CommandKeywords::Rpush => Ok(Command::RPush {
+                key: self.ast.get_bytes()?,
+                values: self.ast.get_rest_bytes()?,
+            }),
+            CommandKeywords::Lpushx => Ok(Command::LPushX {
+                key: self.ast.get_bytes()?,
+                values: self.ast.get_rest_bytes()?,
+            }),
+            CommandKeywords::Rpushx => Ok(Command::RPushX {
+                key: self.ast.get_bytes()?,
+                values: self.ast.get_rest_bytes()?,
+            }),
+            CommandKeywords::Lrange => Ok(Command::LRange {
+                key: self.ast.get_bytes()?,
+                start: self.ast.get_number()?,
+                stop: self.ast.get_number()?,
+            }),
+            CommandKeywords::Llen => Ok(Command::LLen(self.ast.get_bytes()?)),
+            CommandKeywords::Ltrim => Ok(Command::LTrim {
+                key: self.ast.get_bytes()?,
+                start: self.ast.get_number()?,
+                stop: self.ast.get_number()?,
+            }),
+            CommandKeywords::Lpos => {
+                let key = self.ast.get_bytes()?;
+                let element = self.ast.get_bytes()?;
+
+                let mut rank = 1;
+                let mut count = None;
+                let mut maxlen = 0;
+
+                while self.ast.remaining() > 0 {
+                    match self.ast.get_uncased_string()? {
+                        option if option == "rank" => {
+                            rank = match self.ast.get_number()? {
+                                0 => return Err(Error::ZeroRank),
+                                rank => rank,
+                            }
+                        }
+                        option if option == "count" => {
+                            count = Some(
+                                usize::try_from(self.ast.get_number()?)
+                                    .map_err(|_| Error::NegativeCount)?,
+                            )
+                        }
+                        option if option == "maxlen" => {
+                            maxlen = usize::try_from(self.ast.get_number()?)
+                                .map_err(|_| Error::NegativeMaxlen)?
+                        }
+                        _ => return Err(Error::Syntax),
+                    }
+                }
+
+                Ok(Command::LPos {
+                    key,
+                    element,
+                    rank,
+                    count,
+                    maxlen,
+                })
+            }
+            CommandKeywords::Lmpop => Ok(Command::LMPop {
+                keys: self.ast.get_counted_bytes()?,
+                side: self.list_side()?,
+                count: self.mpop_count()?,
+            }),
+            CommandKeywords::Zmpop => Ok(Command::ZMPop {
+                keys: self.ast.get_counted_bytes()?,
+                end: match self.ast.get_uncased_string()? {
+                    end if end == "min" => ScoreEnd::Min,
+                    end if end == "max" => ScoreEnd::Max,
+                    _ => return Err(Error::Syntax),
+                },
+                count: self.mpop_count()?,
+            }),
+            CommandKeywords::Lrem => Ok(Command::LRem {
+                key: self.ast.get_bytes()?,
+                count: self.ast.get_number()?,
+                element: self.ast.get_bytes()?,
+            }),
+            CommandKeywords::Lindex => Ok(Command::LIndex {
+                key: self.ast.get_bytes()?,
+                index: self.ast.get_number()?,
+            }),
+            CommandKeywords::Lset => Ok(Command::LSet {
+                key: self.ast.get_bytes()?,
+                index: self.ast.get_number()?,
+                element: self.ast.get_bytes()?,
+            }),
+            CommandKeywords::Linsert => {
+                let key = self.ast.get_bytes()?;
+
+                let position = match self.ast.get_uncased_string()? {
+                    position if position == "before" => ListPosition::Before,
+                    position if position == "after" => ListPosition::After,
+                    _ => return Err(Error::Syntax),
+                };
+
+                Ok(Command::LInsert {
+                    key,
+                    position,
+                    pivot: self.ast.get_bytes()?,
+                    element: self.ast.get_bytes()?,
+                })
+            }
+            CommandKeywords::Lmove => Ok(Command::LMove {
+                source: self.ast.get_bytes()?,
+                destination: self.ast.get_bytes()?,
+                from: self.list_side()?,
+                to: self.list_side()?,
+            }),
+            CommandKeywords::Blpop => self.blocking_pop("blpop", ListSide::Left),
+            CommandKeywords::Brpop => self.blocking_pop("brpop", ListSide::Right),
+            // RPOPLPUSH is the legacy spelling of `LMOVE source destination RIGHT LEFT`.
+            CommandKeywords::Rpoplpush => Ok(Command::LMove {
+                source: self.ast.get_bytes()?,
+                destination: self.ast.get_bytes()?,
+                from: ListSide::Right,
+                to: ListSide::Left,
+            }),
+            CommandKeywords::Blmove => Ok(Command::BLMove {
+                source: self.ast.get_bytes()?,
+                destination: self.ast.get_bytes()?,
+                from: self.list_side()?,
+                to: self.list_side()?,
+                timeout: self.timeout()?,
+            }),
+            CommandKeywords::Brpoplpush => Ok(Command::BLMove {
+                source: self.ast.get_bytes()?,
+                destination: self.ast.get_bytes()?,
+                from: ListSide::Right,
+                to: ListSide::Left,
+                timeout: self.timeout()?,
+            }),
+            CommandKeywords::Unlink => Ok(Command::Unlink(self.ast.get_rest_bytes()?)),
+            CommandKeywords::Del => Ok(Command::Del(self.ast.get_rest_bytes()?)),
+            CommandKeywords::Flushdb => Ok(Command::FlushDb {
+                lazy: self.flush_mode()?,
+            }),
+            CommandKeywords::Config => match self.ast.get_uncased_string()? {
+                subcommand if subcommand == "get" => match self.ast.remaining() {
+                    0 => Err(Error::WrongArity("config|get")),
+                    _ => Ok(Command::ConfigGet(self.ast.get_rest_bytes()?)),
+                },
+                subcommand if subcommand == "set" => {
+                    Ok(Command::ConfigSet(self.pairs("config|set")?))
+                }
+                _ => Err(Error::Syntax),
+            },
+            CommandKeywords::Info => Ok(Command::Info(self.optional_rest()?)),
+            CommandKeywords::Subscribe => Ok(Command::Subscribe(self.ast.get_rest_bytes()?)),
+            CommandKeywords::Unsubscribe => Ok(Command::Unsubscribe(self.optional_rest()?)),
+            CommandKeywords::Psubscribe => Ok(Command::PSubscribe(self.ast.get_rest_bytes()?)),
+            CommandKeywords::Punsubscribe => Ok(Command::PUnsubscribe(self.optional_rest()?)),
+            CommandKeywords::Publish => Ok(Command::Publish {
+                channel: self.ast.get_bytes()?,
+                message: self.ast.get_bytes()?,
+            }),
+            CommandKeywords::Hset => Ok(Command::HSet {
+                key: self.ast.get_bytes()?,
+                pairs: self.pairs("hset")?,
+            }),
+            CommandKeywords::Hsetnx => Ok(Command::HSetNx {
+                key: self.ast.get_bytes()?,
+                field: self.ast.get_bytes()?,
+                value: self.ast.get_bytes()?,
+            }),
+            CommandKeywords::Hget => Ok(Command::HGet {
+                key: self.ast.get_bytes()?,
+                field: self.ast.get_bytes()?,
+            }),
+            CommandKeywords::Hmget => Ok(Command::HMGet {
+                key: self.ast.get_bytes()?,
+                fields: self.ast.get_rest_bytes()?,
+            }),
+            CommandKeywords::Hgetall => Ok(Command::HGetAll(self.ast.get_bytes()?)),
+            CommandKeywords::Hkeys => Ok(Command::HKeys(self.ast.get_bytes()?)),
+            CommandKeywords::Hvals => Ok(Command::HVals(self.ast.get_bytes()?)),
+            CommandKeywords::Hdel => Ok(Command::HDel {
+                key: self.ast.get_bytes()?,
+                fields: self.ast.get_rest_bytes()?,
+            }),
+            CommandKeywords::Hexists => Ok(Command::HExists {
+                key: self.ast.get_bytes()?,
+                field: self.ast.get_bytes()?,
+            }),
+            CommandKeywords::Hincrby => Ok(Command::HIncrBy {
+                key: self.ast.get_bytes()?,
+                field: self.ast.get_bytes()?,
+                delta: self.ast.get_number()?,
+            }),
+            CommandKeywords::Hincrbyfloat => Ok(Command::HIncrByFloat {
+                key: self.ast.get_bytes()?,
+                field: self.ast.get_bytes()?,
+                delta: self.ast.get_float()?,
+            }),
+            CommandKeywords::Hlen => Ok(Command::HLen(self.ast.get_bytes()?)),
+            CommandKeywords::Hstrlen => Ok(Command::HStrLen {
+                key: self.ast.get_bytes()?,
+                field: self.ast.get_bytes()?,
+            }),
+            CommandKeywords::Hscan => {
+                let key = self.ast.get_bytes()?;
+                let (options, no_values) = self.scan_options(true)?;
+
+                Ok(Command::HScan {
+                    key,
+                    options,
+                    no_values,
+                })
+            }
+            CommandKeywords::Hrandfield => {
+                let key = self.ast.get_bytes()?;
+
+                let count = match self.ast.remaining() {
+                    0 => None,
+                    _ => Some(self.ast.get_number()?),
+                };
+
+                let with_values = match self.ast.remaining() {
+                    0 => false,
+                    1 if self.ast.get_uncased_string()? == "withvalues" => true,
+                    _ => return Err(Error::Syntax),
+                };
+
+                // As in Redis, so the fields and values of a negative count
+                // can always be counted.
+                let limit = if with_values {
+                    -(i64::MAX / 2)
+                } else {
+                    -i64::MAX
+                };
+                if count.is_some_and(|count| count < limit) {
+                    return Err(Error::ValueOutOfRange);
+                }
+
+                Ok(Command::HRandField {
+                    key,
+                    count,
+                    with_values,
+                })
+            }
+            CommandKeywords::Sadd => Ok(Command::SAdd {
+                key: self.ast.get_bytes()?,
+                members: self.ast.get_rest_bytes()?,
+            }),
+            CommandKeywords::Srem => Ok(Command::SRem {
+                key: self.ast.get_bytes()?,
+                members: self.ast.get_rest_bytes()?,
+            }),
+            CommandKeywords::Smembers => Ok(Command::SMembers(self.ast.get_bytes()?)),
+            CommandKeywords::Sismember => Ok(Command::SIsMember {
+                key: self.ast.get_bytes()?,
+                member: self.ast.get_bytes()?,
+            }),
+            CommandKeywords::Smismember => Ok(Command::SMIsMember {
+                key: self.ast.get_bytes()?,
+                members: self.ast.get_rest_bytes()?,
+            }),
+            CommandKeywords::Scard => Ok(Command::SCard(self.ast.get_bytes()?)),
+            CommandKeywords::Zadd => Ok(Command::ZAdd {
+                key: self.ast.get_bytes()?,
+                members: self.score_pairs("zadd")?,
+            }),
+            CommandKeywords::Zscore => Ok(Command::ZScore {
+                key: self.ast.get_bytes()?,
+                member: self.ast.get_bytes()?,
+            }),
+            CommandKeywords::Zrange => {
+                let key = self.ast.get_bytes()?;
+                let start = self.ast.get_number()?;
+                let stop = self.ast.get_number()?;
+
+                let with_scores = match self.ast.remaining() {
+                    0 => false,
+                    _ if self.ast.get_uncased_string()? == "withscores" => true,
+                    _ => return Err(Error::Syntax),
+                };
+
+                if self.ast.remaining() > 0 {
+                    return Err(Error::Syntax);
+                }
+
+                Ok(Command::ZRange {
+                    key,
+                    start,
+                    stop,
+                    with_scores,
+                })
+            }
+            CommandKeywords::Zcard => Ok(Command::ZCard(self.ast.get_bytes()?)),
+            CommandKeywords::Zrangebyscore => {
+                let key = self.ast.get_bytes()?;
+                let min = self.ast.get_score_bound()?;
+                let max = self.ast.get_score_bound()?;
+
+                let mut with_scores = false;
+                let mut limit = None;
+
+                while self.ast.remaining() > 0 {
+                    match self.ast.get_uncased_string()? {
+                        param if param == "withscores" => with_scores = true,
+                        param if param == "limit" => {
+                            let offset = self.ast.get_unsigned()?;
+                            // A negative count means no limit, as in Redis.
+                            let count = usize::try_from(self.ast.get_number()?).ok();
+
+                            limit = Some((offset, count));
+                        }
+                        _ => return Err(Error::Syntax),
+                    }
+                }
+
+                Ok(Command::ZRangeByScore {
+                    key,
+                    min,
+                    max,
+                    with_scores,
+                    limit,
                 })
             }
+            CommandKeywords::Zcount => Ok(Command::ZCount {
+                key: self.ast.get_bytes()?,
+                min: self.ast.get_score_bound()?,
+                max: self.ast.get_score_bound()?,
+            }),
+            CommandKeywords::Sinter => self.set_algebra(SetOperation::Inter),
+            CommandKeywords::Sunion => self.set_algebra(SetOperation::Union),
+            CommandKeywords::Sdiff => self.set_algebra(SetOperation::Diff),
+            CommandKeywords::Sinterstore => self.set_algebra_store(SetOperation::Inter),
+            CommandKeywords::Sunionstore => self.set_algebra_store(SetOperation::Union),
+            CommandKeywords::Sdiffstore => self.set_algebra_store(SetOperation::Diff),
+            CommandKeywords::Debug => self.debug(),
+            CommandKeywords::Object => {
+                let param = OBJECT_PARAMS
+                    .get(self.ast.get_uncased_string()?)
+                    .ok_or(Error::Syntax)?;
+
+                match param {
+                    ObjectParams::Refcount => Ok(Command::ObjectRefCount(self.ast.get_bytes()?)),
+                    ObjectParams::Encoding => Ok(Command::ObjectEncoding(self.ast.get_bytes()?)),
+                }
+            }
+            CommandKeywords::Append => Ok(Command::Append {
+                key: self.ast.get_bytes()?,
+                value: self.ast.get_bytes()?,
+            }),
+            CommandKeywords::Strlen => Ok(Command::StrLen(self.ast.get_bytes()?)),
+            CommandKeywords::Incr => Ok(Command::Incr(self.ast.get_bytes()?)),
+            CommandKeywords::Getrange => Ok(Command::GetRange {
+                key: self.ast.get_bytes()?,
+                start: self.ast.get_number()?,
+                end: self.ast.get_number()?,
+            }),
+            CommandKeywords::Setrange => {
+                let key = self.ast.get_bytes()?;
+                let offset =
+                    usize::try_from(self.ast.get_number()?).map_err(|_| Error::OffsetOutOfRange)?;
+                let value = self.ast.get_bytes()?;
+
+                if offset + value.len() > MAX_STRING_LENGTH {
+                    return Err(Error::OffsetOutOfRange);
+                }
+
+                Ok(Command::SetRange { key, offset, value })
+            }
+            CommandKeywords::Getbit => Ok(Command::GetBit {
+                key: self.ast.get_bytes()?,
+                offset: self.bit_offset()?,
+            }),
+            CommandKeywords::Setbit => Ok(Command::SetBit {
+                key: self.ast.get_bytes()?,
+                offset: self.bit_offset()?,
+                value: match self.ast.get_number() {
+                    Ok(0) => false,
+                    Ok(1) => true,
+                    _ => return Err(Error::BitOutOfRange),
+                },
+            }),
+            CommandKeywords::Bitcount => {
+                let key = self.ast.get_bytes()?;
+
+                let range = match self.ast.remaining() {
+                    0 => None,
+                    2 => Some((self.ast.get_number()?, self.ast.get_number()?)),
+                    _ => return Err(Error::WrongArity("bitcount")),
+                };
+
+                Ok(Command::BitCount { key, range })
+            }
+            CommandKeywords::Lpop => Ok(Command::LPop {
+                key: self.ast.get_bytes()?,
+                count: self.optional_count()?,
+            }),
+            CommandKeywords::Rpop => Ok(Command::RPop {
+                key: self.ast.get_bytes()?,
+                count: self.optional_count()?,
+            }),
+        }
+    }
+
+    /// Parses `[protover [AUTH username password] [SETNAME clientname]]`.
+    #[inline]
+    fn hello(&self) -> Result<Command, Error> {
+        if self.ast.remaining() == 0 {
+            return Ok(Command::Hello {
+                protocol: None,
+                auth: None,
+                name: None,
+            });
+        }
+
+        let protocol = self.ast.get_number()?;
+        let mut auth = None;
+        let mut name = None;
+
+        while self.ast.remaining() > 0 {
+            match self.ast.get_uncased_string()? {
+                option if option == "auth" && self.ast.remaining() >= 2 => {
+                    auth = Some((self.ast.get_bytes()?, self.ast.get_bytes()?))
+                }
+                option if option == "setname" && self.ast.remaining() >= 1 => {
+                    name = Some(self.ast.get_bytes()?)
+                }
+                _ => return Err(Error::Syntax),
+            }
+        }
+
+        Ok(Command::Hello {
+            protocol: Some(protocol),
+            auth,
+            name,
+        })
+    }
+
+    /// The time after SET's EX or PX, counted in units of `unit_millis`,
+    /// which has to be there, positive and no more than `i64::MAX` millis.
+    #[inline]
+    fn set_expire_time(&self, unit_millis: i64) -> Result<Duration, Error> {
+        if self.ast.remaining() == 0 {
+            return Err(Error::Syntax);
+        }
+
+        match self.ast.get_number()? {
+            time if time > 0 => time
+                .checked_mul(unit_millis)
+                .map(|millis| Duration::from_millis(millis as u64))
+                .ok_or(Error::InvalidExpireTime("set")),
+            _ => Err(Error::InvalidExpireTime("set")),
+        }
+    }
+
+    /// Consumes the remaining arguments as field/value pairs, rejecting an
+    /// empty or odd-length tail.
+    #[inline]
+    fn pairs(&self, command: &'static str) -> Result<Box<[Pair]>, Error> {
+        let remaining = self.ast.remaining();
+
+        if remaining == 0 || remaining % 2 != 0 {
+            return Err(Error::WrongArity(command));
+        }
+
+        (0..remaining / 2)
+            .map(|_| Ok((self.ast.get_bytes()?, self.ast.get_bytes()?)))
+            .collect()
+    }
+
+    #[inline]
+    fn set_algebra(&self, op: SetOperation) -> Result<Command, Error> {
+        Ok(Command::SetAlgebra {
+            op,
+            keys: self.ast.get_rest_bytes()?,
+        })
+    }
+
+    #[inline]
+    fn set_algebra_store(&self, op: SetOperation) -> Result<Command, Error> {
+        Ok(Command::SetAlgebraStore {
+            op,
+            destination: self.ast.get_bytes()?,
+            keys: self.ast.get_rest_bytes()?,
+        })
+    }
+
+    #[inline]
+    fn bit_offset(&self) -> Result<usize, Error> {
+        match usize::try_from(self.ast.get_number()?) {
+            Ok(offset) if offset / 8 < MAX_STRING_LENGTH => Ok(offset),
+            _ => Err(Error::OffsetOutOfRange),
+        }
+    }
+
+    /// Consumes the remaining arguments as score/member pairs, rejecting an
+    /// empty or odd-length tail.
+    #[inline]
+    fn score_pairs(&self, command: &'static str) -> Result<Box<[ScorePair]>, Error> {
+        let remaining = self.ast.remaining();
+
+        if remaining == 0 || remaining % 2 != 0 {
+            return Err(Error::WrongArity(command));
+        }
+
+        (0..remaining / 2)
+            .map(|_| Ok((self.ast.get_float()?, self.ast.get_bytes()?)))
+            .collect()
+    }
+
+    /// Parses `key [key ...] timeout`, where the timeout is in seconds and
+    /// zero means waiting forever.
+    #[inline]
+    fn blocking_pop(&self, command: &'static str, side: ListSide) -> Result<Command, Error> {
+        let remaining = self.ast.remaining();
+
+        if remaining < 2 {
+            return Err(Error::WrongArity(command));
+        }
+
+        let keys = (0..remaining - 1)
+            .map(|_| Ok(self.ast.get_bytes()?))
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Command::BPop {
+            keys,
+            side,
+            timeout: self.timeout()?,
+        })
+    }
+
+    /// DEBUG's subcommands. Those test tooling sends that have no effect here
+    /// are accepted and ignored, along with their arguments.
+    fn debug(&self) -> Result<Command, Error> {
+        let subcommand = self.ast.get_string()?;
+
+        if self.ast.remaining() == 1 {
+            match subcommand.to_ascii_lowercase().as_str() {
+                "protocol" => return Ok(Command::DebugProtocol(self.ast.get_string()?)),
+                "sleep" => {
+                    let seconds = self.ast.get_float().map_err(|_| Error::InvalidTimeout)?;
+                    let duration =
+                        Duration::try_from_secs_f64(seconds).map_err(|_| Error::InvalidTimeout)?;
+
+                    return Ok(Command::DebugSleep(duration));
+                }
+                "sleep-after-fork-seconds" => {
+                    let seconds = self.ast.get_float().map_err(|_| Error::InvalidTimeout)?;
+                    let delay =
+                        Duration::try_from_secs_f64(seconds).map_err(|_| Error::InvalidTimeout)?;
+
+                    return Ok(Command::DebugSaveDelay(delay));
+                }
+                "set-active-expire" => {
+                    return Ok(Command::DebugSetActiveExpire(self.ast.get_number()? != 0))
+                }
+                _ => {}
+            }
+        }
+
+        // Arguments of allowed subcommands are ignored along with them.
+        while self.ast.remaining() > 0 {
+            self.ast.next()?;
+        }
+
+        if DEBUG_NOOPS.contains(UncasedStr::new(&subcommand)) {
+            Ok(Command::DebugNoop)
+        } else {
+            Ok(Command::DebugRefused(subcommand))
+        }
+    }
+
+    /// FLUSHDB's optional ASYNC or SYNC, as whether to free lazily.
+    #[inline]
+    fn flush_mode(&self) -> Result<Option<bool>, Error> {
+        let lazy = match self.ast.remaining() {
+            0 => return Ok(None),
+            1 => self.ast.get_uncased_string()?,
+            _ => return Err(Error::Syntax),
+        };
+
+        if lazy == "async" {
+            Ok(Some(true))
+        } else if lazy == "sync" {
+            Ok(Some(false))
+        } else {
+            Err(Error::Syntax)
+        }
+    }
+
+    /// Blocking timeout in (fractional) seconds, where 0 blocks forever.
+    #[inline]
+    fn timeout(&self) -> Result<Option<Duration>, Error> {
+        let timeout = self.ast.get_float().map_err(|_| Error::InvalidTimeout)?;
+
+        if timeout < 0.0 {
+            return Err(Error::NegativeTimeout);
+        }
+
+        match Duration::try_from_secs_f64(timeout) {
+            Ok(timeout) if timeout.is_zero() => Ok(None),
+            Ok(timeout) => Ok(Some(timeout)),
+            Err(_) => Err(Error::InvalidTimeout),
         }
     }
+
+    /// The optional trailing `COUNT count` of LMPOP and ZMPOP, 1 if absent.
+    #[inline]
+    fn mpop_count(&self) -> Result<usize, Error> {
+        let count = match self.ast.remaining() {
+            0 => return Ok(1),
+            _ if self.ast.get_uncased_string()? == "count" => self.ast.get_number()?,
+            _ => return Err(Error::Syntax),
+        };
+
+        if self.ast.remaining() > 0 {
+            return Err(Error::Syntax);
+        }
+
+        match count {
+            1.. => Ok(count as usize),
+            _ => Err(Error::InvalidCount),
+        }
+    }
+
+    /// The optional `FILTERBY` clause of COMMAND LIST.
+    #[inline]
+    fn command_filter(&self) -> Result<Option<CommandFilter>, Error> {
+        if self.ast.remaining() == 0 {
+            return Ok(None);
+        }
+
+        if self.ast.get_uncased_string()? != "filterby" {
+            return Err(Error::Syntax);
+        }
+
+        let filter = match self.ast.get_uncased_string()? {
+            filter if filter == "pattern" => CommandFilter::Pattern(self.ast.get_bytes()?),
+            filter if filter == "aclcat" => CommandFilter::AclCat(self.ast.get_string()?),
+            filter if filter == "module" => CommandFilter::Module(self.ast.get_string()?),
+            _ => return Err(Error::Syntax),
+        };
+
+        if self.ast.remaining() > 0 {
+            return Err(Error::Syntax);
+        }
+
+        Ok(Some(filter))
+    }
+
+    /// The cursor and options of SCAN, along with whether `NOVALUES` was
+    /// given, which only HSCAN takes.
+    fn scan_options(&self, takes_novalues: bool) -> Result<(ScanOptions, bool), Error> {
+        let cursor = self
+            .ast
+            .get_string()?
+            .parse()
+            .map_err(|_| Error::InvalidCursor)?;
+
+        let mut pattern = None;
+        let mut count = 10;
+        let mut no_values = false;
+
+        while self.ast.remaining() > 0 {
+            match self.ast.get_uncased_string()? {
+                option if option == "match" => pattern = Some(self.ast.get_bytes()?),
+                option if option == "count" => {
+                    count = match self.ast.get_number()? {
+                        count if count < 1 => return Err(Error::InvalidCount),
+                        count => count as usize,
+                    }
+                }
+                option if takes_novalues && option == "novalues" => no_values = true,
+                _ => return Err(Error::Syntax),
+            }
+        }
+
+        let options = ScanOptions {
+            cursor,
+            pattern,
+            count,
+        };
+
+        Ok((options, no_values))
+    }
+
+    #[inline]
+    fn list_side(&self) -> Result<ListSide, Error> {
+        match self.ast.get_uncased_string()? {
+            side if side == "left" => Ok(ListSide::Left),
+            side if side == "right" => Ok(ListSide::Right),
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    /// Consumes every remaining argument as bytes, which may be none at all.
+    #[inline]
+    fn optional_rest(&self) -> Result<Box<[Cow<[u8]>]>, Error> {
+        (0..self.ast.remaining())
+            .map(|_| Ok(self.ast.get_bytes()?))
+            .collect()
+    }
+
+    #[inline]
+    fn optional_count(&self) -> Result<Option<usize>, Error> {
+        if self.ast.remaining() == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(self.ast.get_unsigned()?))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{array_box, simple_string, Command};
+    use std::borrow::Cow;
+
+    use crate::{array_box, bulk_string, simple_string, Command, ScoreBound};
 
     use super::*;
 
@@ -107,4 +978,345 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), Command::Ping);
     }
+
+    #[test]
+    fn test_parse_echo_arity() {
+        let mut parser = Parser {
+            ast: Values::new(array_box![bulk_string!(b"ECHO")]),
+        };
+        assert!(matches!(parser.command(), Err(Error::WrongArity("echo"))));
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"echo"),
+                bulk_string!(b"a"),
+                bulk_string!(b"b")
+            ]),
+        };
+        assert!(matches!(parser.command(), Err(Error::WrongArity("echo"))));
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![bulk_string!(b"ECHO"), bulk_string!(b"a")]),
+        };
+        assert_eq!(parser.command().unwrap(), Command::Echo(Cow::Borrowed("a")));
+    }
+
+    #[test]
+    fn test_parse_push_command() {
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"LPUSH"),
+                bulk_string!(b"list"),
+                bulk_string!(b"a"),
+                bulk_string!(b"b")
+            ]),
+        };
+
+        assert_eq!(
+            parser.command().unwrap(),
+            Command::LPush {
+                key: Cow::Borrowed(b"list"),
+                values: Box::from([Cow::Borrowed(b"a" as &[u8]), Cow::Borrowed(b"b")]),
+            }
+        );
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![bulk_string!(b"RPUSH"), bulk_string!(b"list")]),
+        };
+
+        assert!(matches!(parser.command(), Err(Error::WrongArity("rpush"))));
+    }
+
+    #[test]
+    fn test_parse_linsert_position_is_case_insensitive() {
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"LINSERT"),
+                bulk_string!(b"list"),
+                bulk_string!(b"bEfOrE"),
+                bulk_string!(b"pivot"),
+                bulk_string!(b"element")
+            ]),
+        };
+
+        assert_eq!(
+            parser.command().unwrap(),
+            Command::LInsert {
+                key: Cow::Borrowed(b"list"),
+                position: ListPosition::Before,
+                pivot: Cow::Borrowed(b"pivot"),
+                element: Cow::Borrowed(b"element"),
+            }
+        );
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"LINSERT"),
+                bulk_string!(b"list"),
+                bulk_string!(b"middle"),
+                bulk_string!(b"pivot"),
+                bulk_string!(b"element")
+            ]),
+        };
+
+        assert!(matches!(parser.command(), Err(Error::Syntax)));
+    }
+
+    #[test]
+    fn test_parse_zrangebyscore_bounds_and_options() {
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"ZRANGEBYSCORE"),
+                bulk_string!(b"zset"),
+                bulk_string!(b"(1"),
+                bulk_string!(b"+inf"),
+                bulk_string!(b"LIMIT"),
+                bulk_string!(b"1"),
+                bulk_string!(b"-1"),
+                bulk_string!(b"withscores")
+            ]),
+        };
+
+        assert_eq!(
+            parser.command().unwrap(),
+            Command::ZRangeByScore {
+                key: Cow::Borrowed(b"zset"),
+                min: ScoreBound::Exclusive(1.0),
+                max: ScoreBound::Inclusive(f64::INFINITY),
+                with_scores: true,
+                limit: Some((1, None)),
+            }
+        );
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"ZCOUNT"),
+                bulk_string!(b"zset"),
+                bulk_string!(b"-inf"),
+                bulk_string!(b"(nan")
+            ]),
+        };
+
+        assert!(matches!(
+            parser.command(),
+            Err(Error::Value(ValueError::InvalidFloat))
+        ));
+    }
+
+    #[test]
+    fn test_parse_set_options() {
+        let parse = |args: &[&'static [u8]]| {
+            let values: Box<[Value]> = [&b"SET"[..], b"key", b"value"]
+                .iter()
+                .chain(args)
+                .map(|arg| bulk_string!(*arg))
+                .collect();
+            let mut parser = Parser {
+                ast: Values::new(values),
+            };
+
+            match parser.command()? {
+                Command::Set { options, .. } => Ok(options),
+                command => panic!("parsed as {command:?}"),
+            }
+        };
+
+        assert_eq!(
+            parse(&[b"nx", b"GET", b"px", b"1500"]).unwrap(),
+            SetOptions {
+                expiration: Some(Duration::from_millis(1500)),
+                keep_ttl: false,
+                condition: SetCondition::Missing,
+                get: true,
+            }
+        );
+        assert!(parse(&[b"EX", b"10", b"EX", b"20", b"XX", b"XX"]).is_ok());
+
+        for args in [
+            &[&b"EX"[..]][..],
+            &[b"FOO"],
+            &[b"EX", b"10", b"FOO"],
+            &[b"EX", b"10", b"PX", b"10"],
+            &[b"KEEPTTL", b"EX", b"10"],
+            &[b"NX", b"XX"],
+        ] {
+            assert!(matches!(parse(args), Err(Error::Syntax)), "{args:?}");
+        }
+
+        assert!(matches!(
+            parse(&[b"EX", b"0"]),
+            Err(Error::InvalidExpireTime("set"))
+        ));
+        for args in [
+            &[&b"EX"[..], b"-5"][..],
+            &[b"PX", b"-5"],
+            &[b"EX", b"9223372036854775807"],
+        ] {
+            assert!(
+                matches!(parse(args), Err(Error::InvalidExpireTime("set"))),
+                "{args:?}"
+            );
+        }
+        assert_eq!(
+            Some(Duration::from_millis(9223372036854775807)),
+            parse(&[b"PX", b"9223372036854775807"]).unwrap().expiration
+        );
+    }
+
+    #[test]
+    fn test_parse_set_rejects_array_value() {
+        for name in [&b"SET"[..], b"GETSET"] {
+            let mut parser = Parser {
+                ast: Values::new(array_box![
+                    bulk_string!(name),
+                    bulk_string!(b"key"),
+                    Value::Array(array_box![bulk_string!(b"a"), bulk_string!(b"b")])
+                ]),
+            };
+
+            assert!(matches!(
+                parser.command(),
+                Err(Error::Value(ValueError::InvalidType(_)))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_hset_rejects_odd_pairs() {
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"HSET"),
+                bulk_string!(b"hash"),
+                bulk_string!(b"field"),
+                bulk_string!(b"value"),
+                bulk_string!(b"dangling")
+            ]),
+        };
+
+        assert!(matches!(parser.command(), Err(Error::WrongArity("hset"))));
+    }
+
+    #[test]
+    fn test_parse_hset_pairs() {
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"HSET"),
+                bulk_string!(b"hash"),
+                bulk_string!(b"a"),
+                bulk_string!(b"1"),
+                bulk_string!(b"b"),
+                bulk_string!(b"2")
+            ]),
+        };
+
+        assert_eq!(
+            parser.command().unwrap(),
+            Command::HSet {
+                key: Cow::Borrowed(b"hash"),
+                pairs: Box::from([
+                    (Cow::Borrowed(b"a" as &[u8]), Cow::Borrowed(b"1" as &[u8])),
+                    (Cow::Borrowed(b"b"), Cow::Borrowed(b"2")),
+                ]),
+            }
+        );
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![bulk_string!(b"HSET"), bulk_string!(b"hash")]),
+        };
+
+        assert!(matches!(parser.command(), Err(Error::WrongArity("hset"))));
+    }
+
+    #[test]
+    fn test_parse_hmget_keeps_field_order() {
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"HMGET"),
+                bulk_string!(b"hash"),
+                bulk_string!(b"b"),
+                bulk_string!(b"a"),
+                bulk_string!(b"b")
+            ]),
+        };
+
+        assert_eq!(
+            parser.command().unwrap(),
+            Command::HMGet {
+                key: Cow::Borrowed(b"hash"),
+                fields: Box::from([
+                    Cow::Borrowed(b"b" as &[u8]),
+                    Cow::Borrowed(b"a"),
+                    Cow::Borrowed(b"b"),
+                ]),
+            }
+        );
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![bulk_string!(b"HMGET"), bulk_string!(b"hash")]),
+        };
+
+        assert!(parser.command().is_err());
+    }
+
+    #[test]
+    fn test_parse_command_list() {
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"COMMAND"),
+                bulk_string!(b"list"),
+                bulk_string!(b"FILTERBY"),
+                bulk_string!(b"pattern"),
+                bulk_string!(b"get*")
+            ]),
+        };
+
+        assert_eq!(
+            parser.command().unwrap(),
+            Command::CommandList(Some(CommandFilter::Pattern(Cow::Borrowed(b"get*"))))
+        );
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![bulk_string!(b"COMMAND"), bulk_string!(b"LIST")]),
+        };
+        assert_eq!(parser.command().unwrap(), Command::CommandList(None));
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"COMMAND"),
+                bulk_string!(b"LIST"),
+                bulk_string!(b"FILTERBY"),
+                bulk_string!(b"NAME"),
+                bulk_string!(b"get")
+            ]),
+        };
+        assert!(matches!(parser.command(), Err(Error::Syntax)));
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![bulk_string!(b"COMMAND"), bulk_string!(b"DOCS")]),
+        };
+        assert_eq!(
+            parser.command().unwrap(),
+            Command::CommandDocs(Box::new([]))
+        );
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"COMMAND"),
+                bulk_string!(b"INFO"),
+                bulk_string!(b"get")
+            ]),
+        };
+        assert_eq!(
+            parser.command().unwrap(),
+            Command::CommandInfo(Box::new([Cow::Borrowed(b"get")]))
+        );
+
+        let mut parser = Parser {
+            ast: Values::new(array_box![
+                bulk_string!(b"COMMAND"),
+                bulk_string!(b"GETKEYS")
+            ]),
+        };
+        assert!(matches!(parser.command(), Err(Error::Syntax)));
+    }
 }
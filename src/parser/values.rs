@@ -2,14 +2,14 @@ use std::borrow::Cow;
 use std::cell::Cell;
 use tracing::{error, instrument};
 use uncased::UncasedStr;
-use crate::Value;
+use crate::{ScoreBound, Value};
 
 #[derive(thiserror::Error, Debug, Clone)]
 pub enum Error {
     #[error("Invalid Type: {0}")]
     InvalidType(&'static str),
 
-    #[error("Invalid number")]
+    #[error("ERR value is not an integer or out of range")]
     InvalidNumber,
 
     #[error("Invalid UTF8 Input: {0}")]
@@ -17,6 +17,18 @@ pub enum Error {
 
     #[error("Not enough arguments")]
     OutOfBounds,
+
+    #[error("ERR value is out of range, must be positive")]
+    Negative,
+
+    #[error("ERR value is not a valid float")]
+    InvalidFloat,
+
+    #[error("ERR numkeys should be greater than 0")]
+    NoKeys,
+
+    #[error("ERR Number of keys can't be greater than number of args")]
+    TooManyKeys,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -60,6 +72,56 @@ impl<'a> Values<'a> {
         Ok(arg)
     }
 
+    #[inline]
+    #[instrument]
+    pub(crate) fn get_unsigned(&self) -> Result<usize, Error> {
+        usize::try_from(self.get_number()?).map_err(|_| Error::Negative)
+    }
+
+    /// Parses the next argument as a score, accepting `inf`, `+inf` and
+    /// `-inf` but never NaN.
+    #[inline]
+    #[instrument]
+    pub(crate) fn get_float(&self) -> Result<f64, Error> {
+        let arg = match self.next()? {
+            Value::SimpleString(command) => command.parse::<f64>().ok(),
+            Value::BulkString(command) => std::str::from_utf8(command)?.parse::<f64>().ok(),
+            Value::Integer(i) => Some(*i as f64),
+            _ => None,
+        };
+
+        arg.filter(|arg| !arg.is_nan()).ok_or(Error::InvalidFloat)
+    }
+
+    /// Parses the next argument as a score range bound, where a leading `(`
+    /// makes it exclusive.
+    #[inline]
+    #[instrument]
+    pub(crate) fn get_score_bound(&self) -> Result<ScoreBound, Error> {
+        let arg = match self.next()? {
+            Value::SimpleString(command) => command.as_bytes(),
+            Value::BulkString(command) => command,
+            _ => return Err(Error::InvalidFloat),
+        };
+
+        let (exclusive, arg) = match arg.strip_prefix(b"(") {
+            Some(arg) => (true, arg),
+            None => (false, arg),
+        };
+
+        let value = std::str::from_utf8(arg)?
+            .parse::<f64>()
+            .ok()
+            .filter(|value| !value.is_nan())
+            .ok_or(Error::InvalidFloat)?;
+
+        Ok(if exclusive {
+            ScoreBound::Exclusive(value)
+        } else {
+            ScoreBound::Inclusive(value)
+        })
+    }
+
     #[inline]
     #[instrument]
     pub(crate) fn get_array(&self) -> Result<&[Value], Error> {
@@ -101,6 +163,54 @@ impl<'a> Values<'a> {
         }
     }
 
+    /// Consumes the next argument as a value to store, which has to be a
+    /// scalar rather than an array or any other aggregate.
+    #[inline]
+    #[instrument]
+    pub(crate) fn get_scalar(&self) -> Result<&Value, Error> {
+        match self.next()? {
+            value @ (Value::Null
+            | Value::SimpleString(_)
+            | Value::BulkString(_)
+            | Value::Integer(_)) => Ok(value),
+            value => {
+                error!(ty = value.value_type(), "value to store must be a scalar");
+                Err(Error::InvalidType("value to store must be a scalar"))
+            }
+        }
+    }
+
+    /// Consumes every remaining argument as bytes, requiring at least one.
+    #[inline]
+    #[instrument]
+    pub(crate) fn get_rest_bytes(&self) -> Result<Box<[Cow<'_, [u8]>]>, Error> {
+        let mut values = Vec::with_capacity(self.remaining().max(1));
+        values.push(self.get_bytes()?);
+
+        while self.remaining() > 0 {
+            values.push(self.get_bytes()?);
+        }
+
+        Ok(values.into_boxed_slice())
+    }
+
+    /// Consumes a `numkeys` count followed by exactly that many arguments
+    /// as bytes, the shape of LMPOP and ZMPOP keys.
+    #[inline]
+    #[instrument]
+    pub(crate) fn get_counted_bytes(&self) -> Result<Box<[Cow<'_, [u8]>]>, Error> {
+        let count = match self.get_number()? {
+            count if count <= 0 => return Err(Error::NoKeys),
+            count => count as usize,
+        };
+
+        if count > self.remaining() {
+            return Err(Error::TooManyKeys);
+        }
+
+        (0..count).map(|_| self.get_bytes()).collect()
+    }
+
     #[inline]
     #[instrument]
     pub(crate) fn get_uncased_string(&self) -> Result<&UncasedStr, Error> {
@@ -125,6 +235,17 @@ impl<'a> Values<'a> {
         self.check_bounds()?;
         Ok(&self.values[self.idx.get() as usize])
     }
+
+    /// The first argument, regardless of how many have been consumed.
+    #[inline]
+    pub(crate) fn first(&self) -> Option<&Value> {
+        self.values.first()
+    }
+
+    #[inline]
+    pub(crate) fn remaining(&self) -> usize {
+        (self.values.len() as isize - self.idx.get() - 1).max(0) as usize
+    }
 }
 
 impl<'a> Values<'a> {
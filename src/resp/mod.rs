@@ -3,9 +3,9 @@ mod value;
 mod parse;
 
 pub use value::Value;
-pub use parse::parse;
+pub use parse::parse_frame;
 
 #[allow(unused_imports)]
-pub use parse::{Error, OutOfRangeType};
+pub use parse::{parse, Error, OutOfRangeType};
 
-pub(crate) use value::{OK, PONG};
+pub(crate) use value::{OK, QUEUED};
@@ -2,7 +2,7 @@ use std::borrow::Cow;
 use std::str::Utf8Error;
 
 use nom::branch::alt;
-use nom::bytes::streaming::take_until;
+use nom::bytes::streaming::{take, take_until};
 use nom::character::streaming::{char, i64 as i64_parser, line_ending};
 use nom::combinator::{all_consuming, map, map_res};
 use nom::error::{context, ParseError};
@@ -56,7 +56,7 @@ fn parse_simple<'a>(
 #[inline]
 fn parse_simple_string(input: &[u8]) -> RespResult {
     parse_simple('+', |val| {
-        Ok(if val.len() == 0 {
+        Ok(if val.is_empty() {
             Value::SimpleString(EMTPY_STR)
         } else {
             Value::SimpleString(Cow::Borrowed(std::str::from_utf8(val)?))
@@ -90,10 +90,13 @@ fn parse_bulk_string(input: &[u8]) -> RespResult {
         return map(line_ending, |_| Value::BulkString(EMTPY_BYTES)).parse(rest);
     }
 
-    map_res(terminated(take_until("\r"), line_ending), |val: &[u8]| {
-        Ok::<Value, Utf8Error>(Value::BulkString(Cow::Borrowed(val)))
-    })
-        .parse(rest)
+    // The declared length is authoritative, so the payload may hold any
+    // bytes, CR and LF included.
+    map(
+        terminated(take(result as usize), line_ending),
+        |val: &[u8]| Value::BulkString(Cow::Borrowed(val)),
+    )
+    .parse(rest)
 }
 
 #[instrument]
@@ -182,6 +185,22 @@ pub fn parse(input: &[u8]) -> Result<Value, Error> {
     }
 }
 
+/// Parses a single RESP frame from the start of `input`, returning the value
+/// together with the number of bytes it occupied. Unlike [`parse`], trailing
+/// bytes are allowed so pipelined requests can be consumed one by one.
+#[inline]
+#[instrument]
+pub fn parse_frame(input: &[u8]) -> Result<(Value, usize), Error> {
+    match parse_any(input) {
+        Ok((rest, redis_type)) => Ok((redis_type, input.len() - rest.len())),
+        Err(NomParseError::Incomplete(_)) => Err(Error::Incomplete),
+        Err(err) => Err(Error::Parse(nom::error::VerboseError::from_error_kind(
+            err.to_string(),
+            nom::error::ErrorKind::Fail,
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -272,10 +291,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_binary_bulk_string() {
+        let input = b"$5\r\n\xff\r\n\x00\xfe\r\n";
+        let result = parse(input);
+        assert_eq!(
+            result,
+            Ok(Value::BulkString(cow_bytes!(b"\xff\r\n\x00\xfe")))
+        );
+
+        let input = b"$3\r\n\xff\x00\xfe";
+        let result = parse(input);
+        assert_eq!(result, Err(Error::Incomplete));
+    }
+
+    #[test]
+    fn test_parse_frame_pipelined() {
+        let input = b"+PING\r\n+PING\r\n:12";
+
+        let (value, consumed) = parse_frame(input).unwrap();
+        assert_eq!(value, Value::SimpleString(cow_str!("PING")));
+        assert_eq!(consumed, 7);
+
+        let (value, consumed) = parse_frame(&input[7..]).unwrap();
+        assert_eq!(value, Value::SimpleString(cow_str!("PING")));
+        assert_eq!(consumed, 7);
+
+        assert_eq!(parse_frame(&input[14..]), Err(Error::Incomplete));
+    }
+
     #[test]
     fn test_not_enough_data() {
         let input = b":123";
         let result = parse(input);
         assert_eq!(result, Err(Error::Incomplete));
     }
+
+    #[test]
+    fn test_parse_frame_every_prefix_is_incomplete() {
+        let input: &[u8] = b"*6\r\n$3\r\nSET\r\n:-12\r\n+OK\r\n-ERR no\r\n$-1\r\n*-1\r\n";
+
+        for end in 0..input.len() {
+            assert_eq!(
+                parse_frame(&input[..end]),
+                Err(Error::Incomplete),
+                "prefix {:?}",
+                String::from_utf8_lossy(&input[..end])
+            );
+        }
+
+        assert!(parse_frame(input).is_ok());
+    }
 }
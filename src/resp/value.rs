@@ -5,6 +5,8 @@ use std::fmt::{Debug, Formatter};
 use tracing::instrument;
 use serde::Serializer;
 
+use crate::database::format_score;
+
 #[derive(Clone, PartialEq)]
 pub enum Value<'a> {
     Null,
@@ -14,10 +16,64 @@ pub enum Value<'a> {
     Integer(i64),
     BulkString(Cow<'a, [u8]>),
     Array(Box<[Value<'a>]>),
+    /// The types below are RESP3's. Clients that did not switch to RESP3
+    /// get them as [`Value::into_resp2`] spells them instead.
+    Map(Box<[(Value<'a>, Value<'a>)]>),
+    Set(Box<[Value<'a>]>),
+    Double(f64),
+    /// An integer of any size, in decimal digits.
+    BigNumber(Cow<'a, str>),
+    Boolean(bool),
+    /// A string along with the three letter `format` it is written in.
+    Verbatim {
+        format: &'static str,
+        text: Cow<'a, [u8]>,
+    },
+    /// Attributes about the reply that follows them.
+    Attribute(Box<[(Value<'a>, Value<'a>)]>),
+    /// An out-of-band frame, sent apart from any reply.
+    Push(Box<[Value<'a>]>),
+}
+
+/// How a double is spelled, in RESP3 as well as the RESP2 bulk string.
+fn double_repr(val: f64) -> String {
+    if val.is_nan() {
+        return String::from("nan");
+    }
+
+    format_score(val)
+}
+
+/// Writes the `prefix` and `len` every aggregate frame starts with.
+fn write_header(output: &mut Vec<u8>, prefix: u8, len: usize) {
+    let mut buf = itoa::Buffer::new();
+    let fmt = buf.format(len);
+    output.reserve(fmt.len() + 3);
+
+    output.push(prefix);
+    output.extend_from_slice(fmt.as_bytes());
+    output.extend_from_slice(b"\r\n");
+}
+
+fn serialize_items(output: &mut Vec<u8>, prefix: u8, items: Box<[Value]>) {
+    write_header(output, prefix, items.len());
+
+    for value in items.into_vec() {
+        value.serialize(output);
+    }
+}
+
+fn serialize_pairs(output: &mut Vec<u8>, prefix: u8, pairs: Box<[(Value, Value)]>) {
+    write_header(output, prefix, pairs.len());
+
+    for (key, value) in pairs.into_vec() {
+        key.serialize(output);
+        value.serialize(output);
+    }
 }
 
 pub(crate) const OK: &[u8] = b"+OK\r\n";
-pub(crate) const PONG: &[u8] = b"+PONG\r\n";
+pub(crate) const QUEUED: &[u8] = b"+QUEUED\r\n";
 
 impl<'a> Debug for Value<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -54,6 +110,42 @@ impl<'a> Debug for Value<'a> {
 
                 f.write_str("]")
             }
+            Value::Map(pairs) | Value::Attribute(pairs) => {
+                f.write_str(if matches!(self, Value::Map(_)) {
+                    "MAP{"
+                } else {
+                    "ATTRIBUTE{"
+                })?;
+
+                for (key, value) in pairs.iter() {
+                    key.fmt(f)?;
+                    f.write_str(": ")?;
+                    value.fmt(f)?;
+                    f.write_str(", ")?;
+                }
+
+                f.write_str("}")
+            }
+            Value::Set(items) | Value::Push(items) => {
+                f.write_str(if matches!(self, Value::Set(_)) {
+                    "SET["
+                } else {
+                    "PUSH["
+                })?;
+
+                for item in items.iter() {
+                    item.fmt(f)?;
+                    f.write_str(", ")?;
+                }
+
+                f.write_str("]")
+            }
+            Value::Double(val) => write!(f, "DOUBLE({})", double_repr(*val)),
+            Value::BigNumber(val) => write!(f, "BIG NUMBER({val})"),
+            Value::Boolean(val) => write!(f, "BOOLEAN({val})"),
+            Value::Verbatim { format, text } => {
+                write!(f, "VERBATIM({format}:{})", String::from_utf8_lossy(text))
+            }
         }
     }
 }
@@ -75,6 +167,81 @@ impl<'a> Value<'a> {
             Value::Integer(_) => "integer",
             Value::BulkString(_) => "bulk_string",
             Value::Array(_) => "array",
+            Value::Map(_) => "map",
+            Value::Set(_) => "set",
+            Value::Double(_) => "double",
+            Value::BigNumber(_) => "big_number",
+            Value::Boolean(_) => "boolean",
+            Value::Verbatim { .. } => "verbatim_string",
+            Value::Attribute(_) => "attribute",
+            Value::Push(_) => "push",
+        }
+    }
+
+    /// The value as RESP2 spells it, for clients that did not switch to
+    /// RESP3. Maps and attributes become flat arrays of their pairs, sets and
+    /// pushes arrays, booleans the integers 1 and 0, and the other types bulk
+    /// strings.
+    pub fn into_resp2(self) -> Self {
+        match self {
+            Value::Array(items) | Value::Set(items) | Value::Push(items) => Value::Array(
+                items
+                    .into_vec()
+                    .into_iter()
+                    .map(Value::into_resp2)
+                    .collect(),
+            ),
+            Value::Map(pairs) | Value::Attribute(pairs) => Value::Array(
+                pairs
+                    .into_vec()
+                    .into_iter()
+                    .flat_map(|(key, value)| [key.into_resp2(), value.into_resp2()])
+                    .collect(),
+            ),
+            Value::Double(val) => Value::BulkString(Cow::Owned(double_repr(val).into_bytes())),
+            Value::BigNumber(Cow::Borrowed(val)) => {
+                Value::BulkString(Cow::Borrowed(val.as_bytes()))
+            }
+            Value::BigNumber(Cow::Owned(val)) => Value::BulkString(Cow::Owned(val.into_bytes())),
+            Value::Boolean(val) => Value::Integer(val as i64),
+            Value::Verbatim { text, .. } => Value::BulkString(text),
+            value => value,
+        }
+    }
+
+    /// How many bytes `serialize` writes for this value, so the output can be
+    /// reserved once instead of growing element by element.
+    pub fn serialized_len(&self) -> usize {
+        fn digits(value: usize) -> usize {
+            value.checked_ilog10().unwrap_or(0) as usize + 1
+        }
+
+        match self {
+            Value::Null | Value::NullArray => 5,
+            Value::SimpleString(val) | Value::Error(val) => val.len() + 3,
+            Value::Integer(val) => itoa::Buffer::new().format(*val).len() + 3,
+            Value::BulkString(val) => val.len() + digits(val.len()) + 5,
+            Value::Array(array) => {
+                digits(array.len()) + 3 + array.iter().map(Value::serialized_len).sum::<usize>()
+            }
+            Value::Set(items) | Value::Push(items) => {
+                digits(items.len()) + 3 + items.iter().map(Value::serialized_len).sum::<usize>()
+            }
+            Value::Map(pairs) | Value::Attribute(pairs) => {
+                digits(pairs.len())
+                    + 3
+                    + pairs
+                        .iter()
+                        .map(|(key, value)| key.serialized_len() + value.serialized_len())
+                        .sum::<usize>()
+            }
+            Value::Double(val) => double_repr(*val).len() + 3,
+            Value::BigNumber(val) => val.len() + 3,
+            Value::Boolean(_) => 4,
+            Value::Verbatim { format, text } => {
+                let len = format.len() + 1 + text.len();
+                len + digits(len) + 5
+            }
         }
     }
 
@@ -127,6 +294,30 @@ impl<'a> Value<'a> {
                     .drain(..)
                     .for_each(|value| value.serialize(output));
             }
+            Value::Map(pairs) => serialize_pairs(output, b'%', pairs),
+            Value::Attribute(pairs) => serialize_pairs(output, b'|', pairs),
+            Value::Set(items) => serialize_items(output, b'~', items),
+            Value::Push(items) => serialize_items(output, b'>', items),
+            Value::Double(val) => {
+                output.push(b',');
+                output.extend_from_slice(double_repr(val).as_bytes());
+                output.extend_from_slice(b"\r\n");
+            }
+            Value::BigNumber(val) => {
+                output.push(b'(');
+                output.extend_from_slice(val.as_bytes());
+                output.extend_from_slice(b"\r\n");
+            }
+            Value::Boolean(val) => {
+                output.extend_from_slice(if val { b"#t\r\n" } else { b"#f\r\n" })
+            }
+            Value::Verbatim { format, text } => {
+                write_header(output, b'=', format.len() + 1 + text.len());
+                output.extend_from_slice(format.as_bytes());
+                output.push(b':');
+                output.extend_from_slice(&text);
+                output.extend_from_slice(b"\r\n");
+            }
         }
     }
 }
@@ -147,8 +338,10 @@ mod tests {
             error!("SOME ERROR")
         );
 
+        let len = value.serialized_len();
         let mut output = Vec::new();
         value.serialize(&mut output);
+        assert_eq!(len, output.len());
         let output = String::from_utf8(output).unwrap();
 
         assert_eq!(
@@ -156,4 +349,95 @@ mod tests {
             "*6\r\n$-1\r\n*-1\r\n:100\r\n$11\r\nHello World\r\n+Hello World\r\n-SOME ERROR\r\n"
         );
     }
+
+    #[test]
+    fn test_serialized_len_matches_output() {
+        let values = [
+            integer!(-1234),
+            integer!(0),
+            bulk_string!(b""),
+            Value::Array(Box::new([])),
+            array!(
+                bulk_string!(&[b'x'; 100][..]),
+                array!(integer!(i64::MIN), bulk_string!(b"\0\r\n")),
+                null!()
+            ),
+        ];
+
+        for value in values {
+            let len = value.serialized_len();
+            let mut output = Vec::new();
+            value.serialize(&mut output);
+            assert_eq!(len, output.len());
+        }
+    }
+
+    #[test]
+    fn test_serialize_map() {
+        let value = Value::Map(Box::new([
+            (bulk_string!(b"proto"), integer!(3)),
+            (bulk_string!(b"modules"), Value::Array(Box::new([]))),
+        ]));
+
+        let len = value.serialized_len();
+        let mut output = Vec::new();
+        value.serialize(&mut output);
+        assert_eq!(len, output.len());
+
+        assert_eq!(
+            output,
+            b"%2\r\n$5\r\nproto\r\n:3\r\n$7\r\nmodules\r\n*0\r\n"
+        );
+    }
+
+    #[test]
+    fn test_serialize_resp3_types() {
+        let value = Value::Set(Box::new([
+            Value::Double(1.5),
+            Value::Double(f64::NEG_INFINITY),
+            Value::BigNumber(Cow::Borrowed("-12345678901234567890")),
+            Value::Boolean(true),
+            Value::Verbatim {
+                format: "txt",
+                text: Cow::Borrowed(b"a\nb"),
+            },
+            Value::Attribute(Box::new([(bulk_string!(b"ttl"), integer!(5))])),
+            Value::Push(Box::new([bulk_string!(b"message")])),
+        ]));
+
+        let len = value.serialized_len();
+        let mut output = Vec::new();
+        value.serialize(&mut output);
+        assert_eq!(len, output.len());
+
+        assert_eq!(
+            output,
+            b"~7\r\n,1.5\r\n,-inf\r\n(-12345678901234567890\r\n#t\r\n=7\r\ntxt:a\nb\r\n\
+              |1\r\n$3\r\nttl\r\n:5\r\n>1\r\n$7\r\nmessage\r\n"
+        );
+    }
+
+    #[test]
+    fn test_into_resp2() {
+        let value = Value::Map(Box::new([
+            (integer!(1), Value::Boolean(false)),
+            (bulk_string!(b"double"), Value::Double(1.5)),
+            (
+                bulk_string!(b"set"),
+                Value::Set(Box::new([Value::BigNumber(Cow::Borrowed("10"))])),
+            ),
+        ]));
+
+        assert_eq!(
+            array!(
+                integer!(1),
+                integer!(0),
+                bulk_string!(b"double"),
+                bulk_string!(b"1.5"),
+                bulk_string!(b"set"),
+                array!(bulk_string!(b"10"))
+            ),
+            value.into_resp2()
+        );
+    }
 }
@@ -0,0 +1,459 @@
+use std::borrow::Cow;
+use std::fs::OpenOptions;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::warn;
+
+use super::metrics::Metrics;
+use super::pubsub::PubSub;
+use super::reply::CommandReply;
+use super::session::Session;
+use super::{AppendFsync, Config, Shutdown};
+use crate::parser::Parser;
+use crate::{Command, Databases, ListSide, Value};
+
+/// The append-only file: every write command that succeeded, in the RESP
+/// form the client sent it, so replaying the file rebuilds the keyspace.
+///
+/// A write runs while holding the file, through [`Aof::lock`], so writes
+/// are logged in the order they ran. Blocking commands cannot hold it while
+/// they wait: once served, they are logged as the pop or move they made,
+/// like Redis propagates them, which lands after the push that served them
+/// but possibly after writes other connections made since. A SELECT is
+/// logged ahead of any command that ran against a different database than
+/// the one before it.
+#[derive(Debug)]
+pub(crate) struct Aof {
+    writer: Mutex<Writer>,
+    fsync: AppendFsync,
+}
+
+/// Exclusive access to the append-only file, holding off every other
+/// append until dropped.
+pub(crate) struct AofGuard<'a> {
+    writer: MutexGuard<'a, Writer>,
+    fsync: AppendFsync,
+}
+
+#[derive(Debug)]
+struct Writer {
+    file: BufWriter<File>,
+    /// The database the last logged command ran against. Unknown until the
+    /// first append, as the file may end with any database selected.
+    db: Option<usize>,
+}
+
+impl Aof {
+    /// Opens `path` for appending, creating it if needed. Unless every append
+    /// is synced, the buffer is written out once a second on a background
+    /// task that stops when the file is dropped.
+    pub fn open(path: &Path, fsync: AppendFsync) -> io::Result<Arc<Self>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        let aof = Arc::new(Self {
+            writer: Mutex::new(Writer {
+                file: BufWriter::new(File::from_std(file)),
+                db: None,
+            }),
+            fsync,
+        });
+
+        if fsync != AppendFsync::Always {
+            tokio::spawn(flush_every_second(Arc::downgrade(&aof)));
+        }
+
+        Ok(aof)
+    }
+
+    /// Waits for every other append to finish and holds off new ones until
+    /// the guard is dropped, so a command run meanwhile is logged in the
+    /// order it ran.
+    pub async fn lock(&self) -> AofGuard<'_> {
+        AofGuard {
+            writer: self.writer.lock().await,
+            fsync: self.fsync,
+        }
+    }
+
+    /// Logs `frame`, a command that ran against database `db`.
+    pub async fn append(&self, db: usize, frame: &[u8]) -> io::Result<()> {
+        self.lock().await.append(db, frame).await
+    }
+
+    /// Writes out everything appended so far, syncing it to disk unless the
+    /// policy leaves that to the OS.
+    pub async fn flush(&self) -> io::Result<()> {
+        let mut writer = self.writer.lock().await;
+        writer.file.flush().await?;
+
+        if self.fsync != AppendFsync::No {
+            writer.file.get_ref().sync_data().await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl AofGuard<'_> {
+    /// Logs `frame`, a command that ran against database `db`.
+    pub async fn append(&mut self, db: usize, frame: &[u8]) -> io::Result<()> {
+        let writer = &mut *self.writer;
+
+        if writer.db != Some(db) {
+            writer.file.write_all(&select_frame(db)).await?;
+            writer.db = Some(db);
+        }
+
+        writer.file.write_all(frame).await?;
+
+        if self.fsync == AppendFsync::Always {
+            writer.file.flush().await?;
+            writer.file.get_ref().sync_data().await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What a blocking command is logged as once served: the pop or move it
+/// made, which replays without blocking.
+#[derive(Debug)]
+pub(crate) enum Unblocked {
+    /// BLPOP/BRPOP: a pop from the end it blocked on, of the key that
+    /// served it.
+    Pop(ListSide),
+    /// BLMOVE/BRPOPLPUSH: the equivalent LMOVE.
+    Move(Vec<u8>),
+}
+
+impl Unblocked {
+    /// `None` unless `command` is a blocking one.
+    pub fn of(command: &Command) -> Option<Self> {
+        match command {
+            Command::BPop { side, .. } => Some(Unblocked::Pop(*side)),
+            Command::BLMove {
+                source,
+                destination,
+                from,
+                to,
+                ..
+            } => Some(Unblocked::Move(frame(&[
+                b"LMOVE",
+                source,
+                destination,
+                side_name(*from),
+                side_name(*to),
+            ]))),
+            _ => None,
+        }
+    }
+
+    /// The frame to log for a command served with `reply`, `None` if it
+    /// timed out or failed.
+    pub fn frame(self, reply: &CommandReply) -> Option<Vec<u8>> {
+        match (self, reply) {
+            (Unblocked::Pop(side), CommandReply::Value(Value::Array(popped))) => {
+                let Some(Value::BulkString(key)) = popped.first() else {
+                    return None;
+                };
+
+                let name: &[u8] = match side {
+                    ListSide::Left => b"LPOP",
+                    ListSide::Right => b"RPOP",
+                };
+
+                Some(frame(&[name, key]))
+            }
+            (Unblocked::Move(frame), CommandReply::Value(Value::BulkString(_))) => Some(frame),
+            _ => None,
+        }
+    }
+}
+
+fn side_name(side: ListSide) -> &'static [u8] {
+    match side {
+        ListSide::Left => b"LEFT",
+        ListSide::Right => b"RIGHT",
+    }
+}
+
+fn frame(args: &[&[u8]]) -> Vec<u8> {
+    let mut output = Vec::new();
+
+    Value::Array(
+        args.iter()
+            .map(|arg| Value::BulkString(Cow::Borrowed(*arg)))
+            .collect(),
+    )
+    .serialize(&mut output);
+
+    output
+}
+
+fn select_frame(db: usize) -> Vec<u8> {
+    frame(&[b"SELECT", db.to_string().as_bytes()])
+}
+
+async fn flush_every_second(aof: Weak<Aof>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+
+    loop {
+        interval.tick().await;
+
+        let Some(aof) = aof.upgrade() else {
+            return;
+        };
+
+        if let Err(err) = aof.flush().await {
+            warn!(err = ?err, "Failed to flush the append-only file");
+        }
+    }
+}
+
+/// Runs every command in the append-only file at `path` against
+/// `databases` through the normal command path and returns how many were
+/// run, SELECTs included. A missing file replays nothing, and a command cut
+/// off by a crash mid-write is ignored.
+pub(crate) async fn replay(path: &Path, databases: &Databases) -> io::Result<usize> {
+    let input = match tokio::fs::read(path).await {
+        Ok(input) => input,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+
+    let mut session = Session::new(
+        Config::default().into(),
+        Shutdown::new(Duration::ZERO).into(),
+        PubSub::default().into(),
+        Metrics::default().into(),
+    );
+
+    let mut rest = input.as_slice();
+    let mut replayed = 0;
+
+    while !rest.is_empty() {
+        let (mut parser, consumed) = match Parser::parse_frame(rest) {
+            Ok(frame) => frame,
+            Err(err) if err.is_incomplete() => {
+                warn!(
+                    bytes = rest.len(),
+                    "Ignoring a truncated command at the end of the append-only file"
+                );
+                break;
+            }
+            Err(err) => return Err(io::Error::new(ErrorKind::InvalidData, err)),
+        };
+
+        let command = parser
+            .command()
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+
+        session.execute(command, databases).await;
+
+        rest = &rest[consumed..];
+        replayed += 1;
+    }
+
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncReadExt};
+
+    use super::super::handler::Handler;
+    use super::*;
+    use crate::bytes::Pool;
+    use crate::Database;
+
+    fn command(args: &[&str]) -> Vec<u8> {
+        let values = args
+            .iter()
+            .map(|arg| Value::BulkString(arg.as_bytes().into()))
+            .collect();
+
+        let mut output = Vec::new();
+        Value::Array(values).serialize(&mut output);
+        output
+    }
+
+    #[tokio::test]
+    async fn test_replay_rebuilds_state() {
+        let path = std::env::temp_dir().join(format!("redis-test-{}.aof", std::process::id()));
+        let database = Databases::from(Database::new());
+
+        let (mut client, server) = duplex(64 * 1024);
+        let mut handler = Handler::new(
+            server,
+            Pool::default().into(),
+            Pool::default().into(),
+            Config::default().into(),
+            Shutdown::new(Duration::ZERO).into(),
+            PubSub::default().into(),
+            Metrics::default().into(),
+        )
+        .with_aof(Aof::open(&path, AppendFsync::Always).unwrap());
+
+        let input = [
+            command(&["SET", "string", "value"]),
+            command(&["SET", "counter", "1"]),
+            command(&["INCR", "counter"]),
+            command(&["RPUSH", "list", "a", "b", "c"]),
+            command(&["LPOP", "list"]),
+            // Neither of these changes anything, so they are not logged.
+            command(&["INCR", "list"]),
+            command(&["GET", "string"]),
+            command(&["SADD", "set", "x", "y"]),
+            command(&["UNLINK", "counter"]),
+        ]
+        .concat();
+
+        client.write_all(&input).await.unwrap();
+        client.shutdown().await.unwrap();
+        handler.run(&database).await.unwrap();
+        drop(handler);
+        client.read_to_end(&mut Vec::new()).await.unwrap();
+
+        // The seven logged writes, after the SELECT the first one needs.
+        let replayed = Databases::from(Database::new());
+        assert_eq!(8, replay(&path, &replayed).await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        for key in [&b"string"[..], b"counter", b"list", b"set"] {
+            assert_eq!(database[0].get(key).await, replayed[0].get(key).await);
+        }
+        assert_eq!(
+            Ok(vec![Box::from(b"b" as &[u8]), Box::from(b"c" as &[u8])]),
+            replayed[0].lrange(b"list", 0, -1).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_logs_served_blocking_commands_as_pops() {
+        let path =
+            std::env::temp_dir().join(format!("redis-test-{}-blocking.aof", std::process::id()));
+        let database = Arc::new(Databases::from(Database::new()));
+        let aof = Aof::open(&path, AppendFsync::Always).unwrap();
+
+        let connect = || {
+            let (client, server) = duplex(64 * 1024);
+            let mut handler = Handler::new(
+                server,
+                Pool::default().into(),
+                Pool::default().into(),
+                Config::default().into(),
+                Shutdown::new(Duration::ZERO).into(),
+                PubSub::default().into(),
+                Metrics::default().into(),
+            )
+            .with_aof(Arc::clone(&aof));
+
+            let database = Arc::clone(&database);
+            let task = tokio::spawn(async move { handler.run(&database).await });
+            (client, task)
+        };
+
+        let (mut popper, popping) = connect();
+        let (mut mover, moving) = connect();
+        popper
+            .write_all(&command(&["BLPOP", "jobs", "0"]))
+            .await
+            .unwrap();
+        mover
+            .write_all(&command(&["BLMOVE", "jobs", "done", "RIGHT", "LEFT", "0"]))
+            .await
+            .unwrap();
+
+        // Both clients block before the push that serves them.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let (mut pusher, pushing) = connect();
+        pusher
+            .write_all(&command(&["RPUSH", "jobs", "a", "b"]))
+            .await
+            .unwrap();
+
+        for (client, task) in [(popper, popping), (mover, moving), (pusher, pushing)] {
+            let mut client = client;
+            client.shutdown().await.unwrap();
+            client.read_to_end(&mut Vec::new()).await.unwrap();
+            task.await.unwrap().unwrap();
+        }
+        drop(aof);
+
+        let file = std::fs::read(&path).unwrap();
+        assert!(!file.windows(5).any(|window| window == b"BLPOP"));
+        assert!(!file.windows(6).any(|window| window == b"BLMOVE"));
+
+        // Replaying a blocking command would wait forever on an empty list.
+        let replayed = Databases::from(Database::new());
+        tokio::time::timeout(Duration::from_secs(5), replay(&path, &replayed))
+            .await
+            .unwrap()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for key in [&b"jobs"[..], b"done"] {
+            assert_eq!(
+                database[0].lrange(key, 0, -1).await,
+                replayed[0].lrange(key, 0, -1).await
+            );
+        }
+        assert_eq!(Ok(1), replayed[0].llen(b"done").await);
+        assert_eq!(None, replayed[0].get(b"jobs").await);
+    }
+
+    #[tokio::test]
+    async fn test_replay_follows_selected_databases() {
+        let path =
+            std::env::temp_dir().join(format!("redis-test-{}-select.aof", std::process::id()));
+        let aof = Aof::open(&path, AppendFsync::Always).unwrap();
+
+        aof.append(1, &command(&["SET", "one", "1"])).await.unwrap();
+        aof.append(1, &command(&["SET", "uno", "1"])).await.unwrap();
+        aof.append(0, &command(&["SET", "zero", "0"]))
+            .await
+            .unwrap();
+        aof.append(2, &command(&["SWAPDB", "1", "2"]))
+            .await
+            .unwrap();
+        drop(aof);
+
+        let file = std::fs::read(&path).unwrap();
+        let selects = file.windows(6).filter(|window| window == b"SELECT").count();
+        assert_eq!(3, selects);
+
+        let replayed = Databases::new(Arc::new(Database::new()), 3);
+        assert_eq!(7, replay(&path, &replayed).await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(replayed[0].get(b"zero").await.is_some());
+        assert!(replayed[1].get(b"one").await.is_none());
+        assert!(replayed[2].get(b"one").await.is_some());
+        assert!(replayed[2].get(b"uno").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_replay_ignores_truncated_tail() {
+        let path =
+            std::env::temp_dir().join(format!("redis-test-{}-truncated.aof", std::process::id()));
+
+        let mut input = command(&["SET", "key", "value"]);
+        input.extend_from_slice(&command(&["SET", "other", "value"])[..10]);
+        std::fs::write(&path, input).unwrap();
+
+        let database = Databases::from(Database::new());
+        assert_eq!(1, replay(&path, &database).await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(database[0].get(b"key").await.is_some());
+        assert!(database[0].get(b"other").await.is_none());
+        assert_eq!(0, replay(&path, &database).await.unwrap());
+    }
+}
@@ -0,0 +1,87 @@
+use super::Config;
+
+/// The only user there is, as in Redis without ACLs.
+const DEFAULT_USER: &[u8] = b"default";
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum AuthError {
+    #[error("NOAUTH Authentication required.")]
+    Required,
+
+    #[error("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time")]
+    HelloRequired,
+
+    #[error("WRONGPASS invalid username-password pair or user is disabled.")]
+    WrongPass,
+
+    #[error("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?")]
+    NoPassword,
+}
+
+/// Checks the credentials of AUTH or HELLO against `requirepass`. Without
+/// a password configured the default user takes any password, but AUTH
+/// with a password alone is refused, like in Redis.
+pub(super) fn authenticate(
+    config: &Config,
+    username: Option<&[u8]>,
+    password: &[u8],
+) -> Result<(), AuthError> {
+    if username.is_some_and(|username| username != DEFAULT_USER) {
+        return Err(AuthError::WrongPass);
+    }
+
+    match (&config.requirepass, username) {
+        (Some(required), _) if constant_time_eq(required.as_bytes(), password) => Ok(()),
+        (Some(_), _) => Err(AuthError::WrongPass),
+        (None, Some(_)) => Ok(()),
+        (None, None) => Err(AuthError::NoPassword),
+    }
+}
+
+/// Compares every byte rather than stopping at the first difference, so the
+/// time taken does not tell how much of `given` was right. Only whether the
+/// lengths match can be told apart.
+fn constant_time_eq(expected: &[u8], given: &[u8]) -> bool {
+    let diff = expected
+        .iter()
+        .zip(given)
+        .fold(0, |diff, (expected, given)| diff | (expected ^ given));
+
+    expected.len() == given.len() && std::hint::black_box(diff) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authenticate() {
+        let config = Config {
+            requirepass: Some("secret".into()),
+            ..Config::default()
+        };
+
+        assert_eq!(Ok(()), authenticate(&config, None, b"secret"));
+        assert_eq!(Ok(()), authenticate(&config, Some(b"default"), b"secret"));
+        assert_eq!(
+            Err(AuthError::WrongPass),
+            authenticate(&config, Some(b"default"), b"wrong")
+        );
+        assert_eq!(
+            Err(AuthError::WrongPass),
+            authenticate(&config, Some(b"admin"), b"secret")
+        );
+        assert_eq!(
+            Err(AuthError::WrongPass),
+            authenticate(&config, None, b"secrets")
+        );
+        assert_eq!(Err(AuthError::WrongPass), authenticate(&config, None, b""));
+
+        let config = Config::default();
+        assert_eq!(Ok(()), authenticate(&config, Some(b"default"), b"any"));
+        assert_eq!(
+            Err(AuthError::NoPassword),
+            authenticate(&config, None, b"any")
+        );
+    }
+}
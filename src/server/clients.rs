@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ClientNameError {
+    #[error("ERR Client names cannot contain spaces, newlines or special characters.")]
+    Invalid,
+}
+
+#[derive(Debug)]
+struct ClientInfo {
+    addr: String,
+    name: Option<Box<[u8]>>,
+    connected: Instant,
+}
+
+/// Every open connection, as CLIENT LIST reports them. Ids are handed out
+/// in the order clients connect, starting from 1, and are never reused.
+#[derive(Debug, Default)]
+pub struct Clients {
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<u64, ClientInfo>>,
+}
+
+impl Clients {
+    /// Adds a connection from `addr`, which stays listed until the returned
+    /// [`Client`] is dropped.
+    pub fn register(self: &Arc<Self>, addr: String) -> Client {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+
+        self.connections.lock().unwrap().insert(
+            id,
+            ClientInfo {
+                addr,
+                name: None,
+                connected: Instant::now(),
+            },
+        );
+
+        Client {
+            id,
+            clients: Arc::clone(self),
+        }
+    }
+
+    /// A line per connection, ordered by id, in the format of CLIENT LIST.
+    pub fn list(&self) -> String {
+        let connections = self.connections.lock().unwrap();
+
+        let mut ids: Vec<_> = connections.keys().copied().collect();
+        ids.sort_unstable();
+
+        ids.into_iter().fold(String::new(), |mut output, id| {
+            let info = &connections[&id];
+            let name = info.name.as_deref().unwrap_or_default();
+
+            let _ = writeln!(
+                output,
+                "id={id} addr={} name={} age={}",
+                info.addr,
+                String::from_utf8_lossy(name),
+                info.connected.elapsed().as_secs()
+            );
+
+            output
+        })
+    }
+}
+
+/// A connection's entry in [`Clients`], removed once it is dropped.
+#[derive(Debug)]
+pub struct Client {
+    id: u64,
+    clients: Arc<Clients>,
+}
+
+impl Client {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn name(&self) -> Option<Box<[u8]>> {
+        self.with_info(|info| info.name.clone())
+    }
+
+    /// Names the connection, or clears its name when `name` is empty. Like
+    /// Redis, names are limited to printable characters without spaces.
+    pub fn set_name(&self, name: &[u8]) -> Result<(), ClientNameError> {
+        if !name.iter().all(|&byte| byte.is_ascii_graphic()) {
+            return Err(ClientNameError::Invalid);
+        }
+
+        self.with_info(|info| info.name = (!name.is_empty()).then(|| Box::from(name)));
+        Ok(())
+    }
+
+    /// Every connection of the registry this client is in.
+    pub fn clients(&self) -> &Clients {
+        &self.clients
+    }
+
+    fn with_info<T>(&self, f: impl FnOnce(&mut ClientInfo) -> T) -> T {
+        let mut connections = self.clients.connections.lock().unwrap();
+        f(connections.get_mut(&self.id).expect("client is registered"))
+    }
+}
+
+/// A client alone in a registry of its own, for sessions served outside of
+/// a [`Server`](super::tcp::Server).
+impl Default for Client {
+    fn default() -> Self {
+        Arc::new(Clients::default()).register(String::new())
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.clients.connections.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_increase() {
+        let clients = Arc::new(Clients::default());
+
+        let first = clients.register("127.0.0.1:1000".into());
+        let second = clients.register("127.0.0.1:1001".into());
+        drop(first);
+        let third = clients.register("127.0.0.1:1002".into());
+
+        assert_eq!(2, second.id());
+        assert_eq!(3, third.id());
+    }
+
+    #[test]
+    fn test_set_name() {
+        let client = Client::default();
+        assert_eq!(None, client.name());
+
+        assert_eq!(Ok(()), client.set_name(b"worker"));
+        assert_eq!(Some(Box::from(&b"worker"[..])), client.name());
+
+        assert_eq!(Err(ClientNameError::Invalid), client.set_name(b"two words"));
+        assert_eq!(Some(Box::from(&b"worker"[..])), client.name());
+
+        assert_eq!(Ok(()), client.set_name(b""));
+        assert_eq!(None, client.name());
+    }
+
+    #[test]
+    fn test_list_drops_closed_connections() {
+        let clients = Arc::new(Clients::default());
+
+        let first = clients.register("127.0.0.1:1000".into());
+        let second = clients.register("127.0.0.1:1001".into());
+        second.set_name(b"worker").unwrap();
+
+        assert_eq!(
+            "id=1 addr=127.0.0.1:1000 name= age=0\n\
+             id=2 addr=127.0.0.1:1001 name=worker age=0\n",
+            clients.list()
+        );
+
+        drop(first);
+        assert_eq!(
+            "id=2 addr=127.0.0.1:1001 name=worker age=0\n",
+            clients.list()
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use std::borrow::Cow;
+
+use crate::glob::glob_match;
+use crate::resp::Value;
+use crate::{bulk_string, CommandFilter};
+
+/// A command the server knows, as COMMAND reports it.
+pub(crate) struct CommandSpec {
+    pub(crate) name: &'static str,
+    /// The number of arguments, the name included. A negative arity is the
+    /// least number of arguments a command with optional ones takes.
+    pub(crate) arity: i64,
+    /// Where the keys are in the arguments: the first, the last, counted
+    /// from the end when negative, and the step between them. All zero for
+    /// commands without keys, or whose keys move with a `numkeys` argument.
+    pub(crate) keys: (i64, i64, i64),
+    /// The ACL categories the command belongs to, as in Redis.
+    pub(crate) categories: &'static [&'static str],
+}
+
+impl CommandSpec {
+    fn matches(&self, filter: &CommandFilter) -> bool {
+        match filter {
+            CommandFilter::Pattern(pattern) => glob_match(pattern, self.name.as_bytes()),
+            CommandFilter::AclCat(category) => self
+                .categories
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(category)),
+            // Every command is built in, none comes from a module.
+            CommandFilter::Module(_) => false,
+        }
+    }
+
+    /// Whether a request of `args` arguments, the name included, has the
+    /// number of arguments the command takes.
+    pub(crate) fn accepts(&self, args: usize) -> bool {
+        let args = args as i64;
+
+        if self.arity < 0 {
+            args >= -self.arity
+        } else {
+            args == self.arity
+        }
+    }
+
+    /// The command flags that follow from its categories.
+    fn flags(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.categories
+            .iter()
+            .filter_map(|category| match *category {
+                "write" => Some("write"),
+                "read" => Some("readonly"),
+                "admin" => Some("admin"),
+                "pubsub" => Some("pubsub"),
+                "fast" => Some("fast"),
+                "blocking" => Some("blocking"),
+                _ => None,
+            })
+    }
+
+    /// The command's entry in the reply to COMMAND INFO: its name, arity,
+    /// flags, key positions and ACL categories.
+    pub(crate) fn info(&self) -> Value<'static> {
+        let (first, last, step) = self.keys;
+
+        let flags: Box<[Value]> = self
+            .flags()
+            .map(|flag| Value::SimpleString(Cow::Borrowed(flag)))
+            .collect();
+
+        let categories: Box<[Value]> = self
+            .categories
+            .iter()
+            .map(|category| Value::SimpleString(Cow::Owned(format!("@{category}"))))
+            .collect();
+
+        Value::Array(Box::new([
+            bulk_string!(self.name.as_bytes()),
+            Value::Integer(self.arity),
+            Value::Array(flags),
+            Value::Integer(first),
+            Value::Integer(last),
+            Value::Integer(step),
+            Value::Array(categories),
+        ]))
+    }
+}
+
+macro_rules! spec {
+    ($name:literal, $arity:literal, [$($category:literal),* $(,)?]) => {
+        spec!($name, $arity, (0, 0, 0), [$($category),*])
+    };
+    ($name:literal, $arity:literal, ($first:literal, $last:literal, $step:literal), [$($category:literal),* $(,)?]) => {
+        CommandSpec {
+            name: $name,
+            arity: $arity,
+            keys: ($first, $last, $step),
+            categories: &[$($category),*],
+        }
+    };
+}
+
+/// Every command the parser accepts, in the order they were added.
+pub(crate) static COMMAND_TABLE: &[CommandSpec] = &[
+    spec!("ping", -1, ["fast", "connection"]),
+    spec!("echo", 2, ["fast", "connection"]),
+    spec!("command", -1, ["slow", "connection"]),
+    spec!("get", 2, (1, 1, 1), ["read", "string", "fast"]),
+    spec!("set", -3, (1, 1, 1), ["write", "string", "slow"]),
+    spec!("lpush", -3, (1, 1, 1), ["write", "list", "fast"]),
+    spec!("rpush", -3, (1, 1, 1), ["write", "list", "fast"]),
+    spec!("lrange", 4, (1, 1, 1), ["read", "list", "slow"]),
+    spec!("llen", 2, (1, 1, 1), ["read", "list", "fast"]),
+    spec!("unlink", -2, (1, -1, 1), ["keyspace", "write", "fast"]),
+    spec!("lpop", -2, (1, 1, 1), ["write", "list", "fast"]),
+    spec!("rpop", -2, (1, 1, 1), ["write", "list", "fast"]),
+    spec!("getset", 3, (1, 1, 1), ["write", "string", "fast"]),
+    spec!("shutdown", -1, ["admin", "slow", "dangerous"]),
+    spec!("hset", -4, (1, 1, 1), ["write", "hash", "fast"]),
+    spec!("hget", 3, (1, 1, 1), ["read", "hash", "fast"]),
+    spec!("hgetall", 2, (1, 1, 1), ["read", "hash", "slow"]),
+    spec!("hkeys", 2, (1, 1, 1), ["read", "hash", "slow"]),
+    spec!("hvals", 2, (1, 1, 1), ["read", "hash", "slow"]),
+    spec!("hdel", -3, (1, 1, 1), ["write", "hash", "fast"]),
+    spec!("hexists", 3, (1, 1, 1), ["read", "hash", "fast"]),
+    spec!("hincrby", 4, (1, 1, 1), ["write", "hash", "fast"]),
+    spec!("hincrbyfloat", 4, (1, 1, 1), ["write", "hash", "fast"]),
+    spec!("lpushx", -3, (1, 1, 1), ["write", "list", "fast"]),
+    spec!("rpushx", -3, (1, 1, 1), ["write", "list", "fast"]),
+    spec!("append", 3, (1, 1, 1), ["write", "string", "fast"]),
+    spec!("strlen", 2, (1, 1, 1), ["read", "string", "fast"]),
+    spec!("getrange", 4, (1, 1, 1), ["read", "string", "slow"]),
+    spec!("setrange", 4, (1, 1, 1), ["write", "string", "slow"]),
+    spec!("getbit", 3, (1, 1, 1), ["read", "bitmap", "fast"]),
+    spec!("setbit", 4, (1, 1, 1), ["write", "bitmap", "slow"]),
+    spec!("bitcount", -2, (1, 1, 1), ["read", "bitmap", "slow"]),
+    spec!("sadd", -3, (1, 1, 1), ["write", "set", "fast"]),
+    spec!("srem", -3, (1, 1, 1), ["write", "set", "fast"]),
+    spec!("smembers", 2, (1, 1, 1), ["read", "set", "slow"]),
+    spec!("sismember", 3, (1, 1, 1), ["read", "set", "fast"]),
+    spec!("scard", 2, (1, 1, 1), ["read", "set", "fast"]),
+    spec!("object", -2, ["keyspace", "read", "slow"]),
+    spec!("sinter", -2, (1, -1, 1), ["read", "set", "slow"]),
+    spec!("sunion", -2, (1, -1, 1), ["read", "set", "slow"]),
+    spec!("sdiff", -2, (1, -1, 1), ["read", "set", "slow"]),
+    spec!("sinterstore", -3, (1, -1, 1), ["write", "set", "slow"]),
+    spec!("sunionstore", -3, (1, -1, 1), ["write", "set", "slow"]),
+    spec!("sdiffstore", -3, (1, -1, 1), ["write", "set", "slow"]),
+    spec!("linsert", 5, (1, 1, 1), ["write", "list", "slow"]),
+    spec!("incr", 2, (1, 1, 1), ["write", "string", "fast"]),
+    spec!("zadd", -4, (1, 1, 1), ["write", "sortedset", "fast"]),
+    spec!("zscore", 3, (1, 1, 1), ["read", "sortedset", "fast"]),
+    spec!("zrange", -4, (1, 1, 1), ["read", "sortedset", "slow"]),
+    spec!("zcard", 2, (1, 1, 1), ["read", "sortedset", "fast"]),
+    spec!(
+        "zrangebyscore",
+        -4,
+        (1, 1, 1),
+        ["read", "sortedset", "slow"]
+    ),
+    spec!("zcount", 4, (1, 1, 1), ["read", "sortedset", "fast"]),
+    spec!("debug", -2, ["admin", "slow", "dangerous"]),
+    spec!("lindex", 3, (1, 1, 1), ["read", "list", "slow"]),
+    spec!("lrem", 4, (1, 1, 1), ["write", "list", "slow"]),
+    spec!("lset", 4, (1, 1, 1), ["write", "list", "slow"]),
+    spec!("lmove", 5, (1, 2, 1), ["write", "list", "slow"]),
+    spec!("rpoplpush", 3, (1, 2, 1), ["write", "list", "slow"]),
+    spec!("info", -1, ["slow", "dangerous"]),
+    spec!(
+        "blpop",
+        -3,
+        (1, -2, 1),
+        ["write", "list", "slow", "blocking"]
+    ),
+    spec!(
+        "brpop",
+        -3,
+        (1, -2, 1),
+        ["write", "list", "slow", "blocking"]
+    ),
+    spec!("subscribe", -2, ["pubsub", "slow"]),
+    spec!("unsubscribe", -1, ["pubsub", "slow"]),
+    spec!("psubscribe", -2, ["pubsub", "slow"]),
+    spec!("punsubscribe", -1, ["pubsub", "slow"]),
+    spec!("publish", 3, ["pubsub", "fast"]),
+    spec!(
+        "blmove",
+        6,
+        (1, 2, 1),
+        ["write", "list", "slow", "blocking"]
+    ),
+    spec!(
+        "brpoplpush",
+        4,
+        (1, 2, 1),
+        ["write", "list", "slow", "blocking"]
+    ),
+    spec!("ltrim", 4, (1, 1, 1), ["write", "list", "slow"]),
+    spec!("lmpop", -4, ["write", "list", "slow"]),
+    spec!("zmpop", -4, ["write", "sortedset", "slow"]),
+    spec!("lpos", -3, (1, 1, 1), ["read", "list", "slow"]),
+    spec!("save", 1, ["admin", "slow", "dangerous"]),
+    spec!("bgsave", -1, ["admin", "slow", "dangerous"]),
+    spec!("select", 2, ["fast", "connection"]),
+    spec!("swapdb", 3, ["keyspace", "write", "fast", "dangerous"]),
+    spec!("hmget", -3, (1, 1, 1), ["read", "hash", "fast"]),
+    spec!("hsetnx", 4, (1, 1, 1), ["write", "hash", "fast"]),
+    spec!("hlen", 2, (1, 1, 1), ["read", "hash", "fast"]),
+    spec!("hstrlen", 3, (1, 1, 1), ["read", "hash", "fast"]),
+    spec!("client", -2, ["slow", "connection"]),
+    spec!("keys", 2, ["keyspace", "read", "slow", "dangerous"]),
+    spec!("scan", -2, ["keyspace", "read", "slow"]),
+    spec!("hrandfield", -2, (1, 1, 1), ["read", "hash", "slow"]),
+    spec!("hscan", -3, (1, 1, 1), ["read", "hash", "slow"]),
+    spec!("smismember", -3, (1, 1, 1), ["read", "set", "fast"]),
+    spec!("auth", -2, ["fast", "connection"]),
+    spec!("hello", -1, ["fast", "connection"]),
+    spec!("del", -2, (1, -1, 1), ["keyspace", "write", "slow"]),
+    spec!("flushdb", -1, ["keyspace", "write", "slow", "dangerous"]),
+    spec!("config", -2, ["admin", "slow", "dangerous"]),
+];
+
+/// The spec of the command called `name`, in any case.
+pub(crate) fn command_spec(name: &[u8]) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE
+        .iter()
+        .find(|spec| spec.name.as_bytes().eq_ignore_ascii_case(name))
+}
+
+/// Names of the commands COMMAND LIST reports, narrowed down by `filter`.
+pub(crate) fn command_names(filter: Option<&CommandFilter>) -> Vec<&'static str> {
+    COMMAND_TABLE
+        .iter()
+        .filter(|spec| filter.map_or(true, |filter| spec.matches(filter)))
+        .map(|spec| spec.name)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use uncased::UncasedStr;
+
+    use super::*;
+    use crate::COMMAND_KEYWORDS;
+
+    #[test]
+    fn test_table_covers_every_keyword() {
+        assert_eq!(COMMAND_KEYWORDS.len(), COMMAND_TABLE.len());
+
+        for spec in COMMAND_TABLE {
+            assert!(
+                COMMAND_KEYWORDS.contains_key(UncasedStr::new(spec.name)),
+                "{} is not a command",
+                spec.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_specs_are_consistent() {
+        for spec in COMMAND_TABLE {
+            assert_ne!(0, spec.arity, "{} has no arity", spec.name);
+
+            let (first, last, step) = spec.keys;
+            if first == 0 {
+                assert_eq!(
+                    (0, 0),
+                    (last, step),
+                    "{} has keys without a first",
+                    spec.name
+                );
+            } else {
+                assert!(step > 0, "{} has keys without a step", spec.name);
+            }
+        }
+
+        assert_eq!((1, -1, 1), command_spec(b"UNLINK").unwrap().keys);
+        assert!(command_spec(b"nosuch").is_none());
+    }
+
+    #[test]
+    fn test_command_names_filters() {
+        assert_eq!(COMMAND_TABLE.len(), command_names(None).len());
+
+        let mut names = command_names(Some(&CommandFilter::Pattern(Cow::Borrowed(b"get*"))));
+        names.sort_unstable();
+        assert_eq!(vec!["get", "getbit", "getrange", "getset"], names);
+
+        let names = command_names(Some(&CommandFilter::AclCat(Cow::Borrowed("BLOCKING"))));
+        assert_eq!(vec!["blpop", "brpop", "blmove", "brpoplpush"], names);
+
+        assert!(command_names(Some(&CommandFilter::AclCat(Cow::Borrowed("nope")))).is_empty());
+        assert!(command_names(Some(&CommandFilter::Module(Cow::Borrowed("json")))).is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::glob::glob_match;
+use crate::MaxMemoryPolicy;
+
+const DEFAULT_MAX_PIPELINE_DEPTH: usize = 1024;
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(5);
+const DEFAULT_DATABASES: usize = 16;
+const DEFAULT_BUFFER_POOL_HIGH_WATER: usize = 64;
+
+/// The parameters CONFIG GET and SET know: those the databases share, which
+/// can change while the server runs.
+const PARAMETERS: [&str; 2] = ["maxmemory", "maxmemory-policy"];
+
+/// When writes to the append-only file are synced to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AppendFsync {
+    /// After every write command, so none is ever lost.
+    Always,
+    /// Once a second, losing at most the last second of writes on a crash.
+    #[default]
+    EverySec,
+    /// Never explicitly; the buffer is handed to the OS once a second.
+    No,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Maximum number of pipelined commands handled from a single read
+    /// before the replies are flushed and more input is accepted.
+    pub max_pipeline_depth: usize,
+
+    /// Time budget for the shutdown steps once a shutdown is triggered.
+    pub shutdown_timeout: Duration,
+
+    /// How long open connections get to finish their current commands once
+    /// a shutdown is triggered before they are aborted. It is one of the
+    /// shutdown steps, so it counts against `shutdown_timeout`.
+    pub grace_period: Duration,
+
+    /// How long a connection may go without sending anything before it is
+    /// closed. Zero disables the timeout.
+    pub idle_timeout: Duration,
+
+    /// Where SAVE and BGSAVE write the snapshot that is loaded again on
+    /// startup. `None` disables persistence.
+    pub rdb_path: Option<PathBuf>,
+
+    /// The append-only file every write command is logged to and replayed
+    /// from on startup, instead of loading the snapshot. `None` disables it.
+    pub aof_path: Option<PathBuf>,
+
+    pub aof_fsync: AppendFsync,
+
+    /// How many numbered databases there are for SELECT to switch between.
+    pub databases: usize,
+
+    /// How many idle connection buffers are kept for reuse once a burst of
+    /// connections has passed. Those beyond it are freed after a period
+    /// without new connections.
+    pub buffer_pool_high_water: usize,
+
+    /// The password of the `default` user, which connections have to give
+    /// with AUTH or HELLO before running anything else. `None` lets every
+    /// connection in.
+    pub requirepass: Option<String>,
+
+    /// Estimated bytes the keys of every database together may take before
+    /// `maxmemory_policy` makes room. Zero keeps whatever limit the database
+    /// was built with, and CONFIG SET can change it later.
+    pub maxmemory: usize,
+
+    pub maxmemory_policy: MaxMemoryPolicy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_pipeline_depth: DEFAULT_MAX_PIPELINE_DEPTH,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            grace_period: DEFAULT_GRACE_PERIOD,
+            idle_timeout: Duration::ZERO,
+            rdb_path: None,
+            aof_path: None,
+            aof_fsync: AppendFsync::default(),
+            databases: DEFAULT_DATABASES,
+            buffer_pool_high_water: DEFAULT_BUFFER_POOL_HIGH_WATER,
+            requirepass: None,
+            maxmemory: 0,
+            maxmemory_policy: MaxMemoryPolicy::default(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error("ERR Unknown option or number of arguments for CONFIG SET - '{0}'")]
+    UnknownOption(String),
+
+    #[error("ERR CONFIG SET failed (possibly related to argument '{name}') - {reason}")]
+    InvalidValue { name: &'static str, reason: String },
+}
+
+/// The names of the parameters matching `pattern`, as CONFIG GET takes it.
+pub(super) fn matching_parameters(pattern: &[u8]) -> impl Iterator<Item = &'static str> + '_ {
+    PARAMETERS
+        .into_iter()
+        .filter(move |name| glob_match(&pattern.to_ascii_lowercase(), name.as_bytes()))
+}
+
+/// The parameter called `name`, in any case.
+pub(super) fn parameter(name: &[u8]) -> Result<&'static str, ConfigError> {
+    PARAMETERS
+        .into_iter()
+        .find(|known| known.as_bytes().eq_ignore_ascii_case(name))
+        .ok_or_else(|| ConfigError::UnknownOption(String::from_utf8_lossy(name).into_owned()))
+}
+
+/// Reads a memory size the way Redis does: a number of bytes, optionally
+/// followed by `k`, `m` or `g` for powers of 1000, or `kb`, `mb` or `gb` for
+/// powers of 1024, in any case.
+pub(super) fn parse_memory(value: &[u8]) -> Option<usize> {
+    let value = std::str::from_utf8(value).ok()?.to_ascii_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+
+    let multiplier: usize = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(Some(0), parse_memory(b"0"));
+        assert_eq!(Some(100), parse_memory(b"100"));
+        assert_eq!(Some(100), parse_memory(b"100b"));
+        assert_eq!(Some(2000), parse_memory(b"2k"));
+        assert_eq!(Some(2048), parse_memory(b"2KB"));
+        assert_eq!(Some(3 * 1024 * 1024), parse_memory(b"3mb"));
+        assert_eq!(Some(1_000_000_000), parse_memory(b"1G"));
+
+        assert_eq!(None, parse_memory(b""));
+        assert_eq!(None, parse_memory(b"mb"));
+        assert_eq!(None, parse_memory(b"-1"));
+        assert_eq!(None, parse_memory(b"10tb"));
+        assert_eq!(None, parse_memory(b"1 mb"));
+        assert_eq!(None, parse_memory(b"99999999999999999999"));
+    }
+
+    #[test]
+    fn test_parameters() {
+        assert_eq!(
+            vec!["maxmemory", "maxmemory-policy"],
+            matching_parameters(b"MAXMEMORY*").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["maxmemory-policy"],
+            matching_parameters(b"*policy").collect::<Vec<_>>()
+        );
+        assert_eq!(0, matching_parameters(b"port").count());
+
+        assert_eq!(Ok("maxmemory"), parameter(b"MaxMemory"));
+        assert_eq!(
+            Err(ConfigError::UnknownOption("port".into())),
+            parameter(b"port")
+        );
+    }
+}
@@ -1,33 +1,45 @@
-use crate::database::Value as DatabaseValue;
-use crate::resp::{Value, OK, PONG};
-use bytes::BytesMut;
+use crate::resp::{Value, OK, QUEUED};
+use bytes::{Buf, BytesMut};
 use nom::AsBytes;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
 use std::borrow::Cow;
 use std::io::{Error as IoError, ErrorKind, Result as IoResult};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
 
+use super::aof::{Aof, Unblocked};
+use super::auth::AuthError;
+use super::clients::Client;
+use super::metrics::Metrics;
+use super::pubsub::{subscription_frame, Message, PubSub, Subscription};
+use super::reply::CommandReply;
+use super::session::Session;
+use super::{Config, Shutdown};
+use crate::bytes::Pool;
 use crate::parser::{Error as ParserError, Parser};
-use crate::resp::Error as RespError;
-use crate::{Buffer, Command, Database};
+use crate::{Buffer, Command, Databases};
+
+/// Bytes of a sampled reply gathered before they are written out.
+const SAMPLE_BATCH_SIZE: usize = 16 * 1024;
 
 #[derive(Debug)]
 pub struct Handler<W> {
     stream: BufWriter<W>,
-    buf_pool: Arc<sharded_slab::Pool<Buffer>>,
-    vec_pool: Arc<sharded_slab::Pool<Vec<u8>>>,
-}
-
-#[derive(thiserror::Error, Debug)]
-enum ClientError {
-    #[error("key does not exist")]
-    KeyNotExists,
+    buf_pool: Arc<Pool<Buffer>>,
+    vec_pool: Arc<Pool<Vec<u8>>>,
+    session: Session,
+    aof: Option<Arc<Aof>>,
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error(transparent)]
-    IoError(#[from] IoError),
+    Io(#[from] IoError),
 
     #[error("Check Again")]
     Again,
@@ -36,139 +48,1163 @@ pub enum Error {
 impl<W: AsyncRead + AsyncWrite + Unpin> Handler<W> {
     pub fn new(
         stream: W,
-        buf_pool: Arc<sharded_slab::Pool<Buffer>>,
-        vec_pool: Arc<sharded_slab::Pool<Vec<u8>>>,
+        buf_pool: Arc<Pool<Buffer>>,
+        vec_pool: Arc<Pool<Vec<u8>>>,
+        config: Arc<Config>,
+        shutdown: Arc<Shutdown>,
+        pubsub: Arc<PubSub>,
+        metrics: Arc<Metrics>,
     ) -> Self {
         Self {
             stream: BufWriter::new(stream),
             buf_pool,
             vec_pool,
+            session: Session::new(config, shutdown, pubsub, metrics),
+            aof: None,
         }
     }
 
+    /// Lists the connection as `client` in its server's registry.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.session.client = client;
+        self
+    }
+
+    /// Logs every write command that succeeds to `aof`.
+    pub fn with_aof(mut self, aof: Arc<Aof>) -> Self {
+        self.aof = Some(aof);
+        self
+    }
+
     async fn write(&mut self, output: impl AsRef<[u8]>) -> IoResult<()> {
-        self.stream.write_all(output.as_ref()).await?;
-        self.stream.flush().await?;
-        Ok(())
+        self.stream.write_all(output.as_ref()).await
+    }
+
+    async fn write_value(&mut self, value: Value<'_>) -> IoResult<()> {
+        if let Value::Error(message) = &value {
+            self.session.metrics.record_error(message);
+        }
+
+        let value = if self.session.protocol == 3 {
+            value
+        } else {
+            value.into_resp2()
+        };
+
+        let mut output = self.vec_pool.acquire();
+
+        output.reserve(value.serialized_len());
+        value.serialize(&mut output);
+        self.write(output.as_bytes()).await
     }
 
     async fn write_error(&mut self, err: &(dyn std::error::Error + Send + Sync)) -> IoResult<()> {
-        let mut output = Arc::clone(&self.vec_pool)
-            .create_owned()
-            .ok_or_else(|| std::io::Error::new(ErrorKind::Other, "Failed to acquire vec_pool"))?;
+        self.write_value(err.into()).await
+    }
+
+    /// Replies to an (P)UNSUBSCRIBE of `names`, or of everything the
+    /// connection is subscribed to when none are given.
+    async fn write_unsubscribed(
+        &mut self,
+        kind: &'static str,
+        names: &[Cow<'_, [u8]>],
+        subscribed: fn(&Subscription) -> Vec<Box<[u8]>>,
+        unsubscribe: fn(&mut Subscription, &[u8]),
+    ) -> IoResult<()> {
+        let names = match names {
+            [] => subscribed(&self.session.subscription),
+            names => names.iter().map(|name| Box::from(name.as_ref())).collect(),
+        };
+
+        if names.is_empty() {
+            let count = self.session.subscription.count();
+            return self
+                .write_value(subscription_frame(kind, None, count))
+                .await;
+        }
 
-        let val: Value = err.into();
-        val.serialize(&mut output);
-        self.write(&output as &[u8]).await?;
+        for name in names {
+            unsubscribe(&mut self.session.subscription, &name);
+
+            let count = self.session.subscription.count();
+            self.write_value(subscription_frame(kind, Some(&name), count))
+                .await?;
+        }
 
         Ok(())
     }
 
-    async fn handle_command<'b>(&mut self, command: Command<'b>, map: &Database) -> IoResult<()> {
-        match command {
-            Command::Ping => self.write(PONG).await?,
-            Command::Echo(val) => {
-                let mut output = Arc::clone(&self.vec_pool)
-                    .create_owned()
-                    .ok_or_else(|| IoError::new(ErrorKind::Other, "Failed to acquire vec_pool"))?;
-
-                Value::SimpleString(val).serialize(&mut output);
-                self.write(&output as &[u8]).await?;
+    async fn write_reply(&mut self, reply: CommandReply<'_>) -> IoResult<()> {
+        match reply {
+            CommandReply::Ok => self.write(OK).await,
+            CommandReply::Value(value) => self.write_value(value).await,
+            CommandReply::Error(message) => self.write_value(Value::Error(message)).await,
+            CommandReply::Queued => self.write(QUEUED).await,
+            CommandReply::NoReply => Ok(()),
+            CommandReply::Frames(frames) => {
+                for frame in frames {
+                    self.write_value(frame).await?;
+                }
+
+                Ok(())
             }
-            Command::Command => self.write(OK).await?,
-            Command::Get(key) => {
-                match map.get_by_string(&key).await {
-                    Some(value) => {
-                        let mut output =
-                            Arc::clone(&self.vec_pool).create_owned().ok_or_else(|| {
-                                IoError::new(ErrorKind::Other, "Failed to acquire vec_pool")
-                            })?;
-
-                        match value {
-                            DatabaseValue::String(val) => {
-                                Value::SimpleString(Cow::Owned(val.into_string()))
-                                    .serialize(&mut output);
-                            }
-                            DatabaseValue::Bytes(val) => {
-                                Value::BulkString(Cow::Owned(val.into_vec()))
-                                    .serialize(&mut output);
-                            }
-                            DatabaseValue::Integer(val) => {
-                                Value::Integer(val).serialize(&mut output);
-                            }
-                            DatabaseValue::Null => {
-                                Value::Null.serialize(&mut output);
-                            }
-                        }
+            CommandReply::Sample { choices, count } => self.write_sample(choices, count).await,
+        }
+    }
 
-                        self.write(output.as_bytes()).await?;
-                    }
-                    None => {
-                        let mut output =
-                            Arc::clone(&self.vec_pool).create_owned().ok_or_else(|| {
-                                IoError::new(ErrorKind::Other, "Failed to acquire vec_pool")
-                            })?;
+    /// Writes a [`CommandReply::Sample`], picking a batch of choices at a
+    /// time and handing each batch to the socket before picking more.
+    async fn write_sample(&mut self, choices: Vec<Vec<Value<'_>>>, count: u64) -> IoResult<()> {
+        let nested = self.session.protocol == 3;
+        let width = choices.first().map_or(1, Vec::len) as u64;
+        let len = if nested || width == 1 {
+            count
+        } else {
+            count.saturating_mul(width)
+        };
 
-                        Value::Null.serialize(&mut output);
+        // Each choice is serialized once and copied for every pick of it.
+        let choices: Vec<Vec<u8>> = choices
+            .into_iter()
+            .map(|values| {
+                let mut output = Vec::new();
 
-                        self.write(output.as_bytes()).await?;
+                if nested && values.len() > 1 {
+                    Value::Array(values.into()).serialize(&mut output);
+                } else {
+                    for value in values {
+                        value.serialize(&mut output);
                     }
+                }
+
+                output
+            })
+            .collect();
+
+        let mut output = self.vec_pool.acquire();
+        output.extend_from_slice(format!("*{len}\r\n").as_bytes());
+
+        let mut rng = StdRng::from_entropy();
+
+        for _ in 0..count {
+            let Some(choice) = choices.choose(&mut rng) else {
+                break;
+            };
+            output.extend_from_slice(choice);
+
+            if output.len() >= SAMPLE_BATCH_SIZE {
+                self.write(output.as_bytes()).await?;
+                output.clear();
+            }
+        }
+
+        self.write(output.as_bytes()).await
+    }
+
+    /// Runs `command`, parsed from the request `frame`, and writes its reply.
+    async fn handle_command<'b>(
+        &mut self,
+        command: Command<'b>,
+        frame: &[u8],
+        databases: &Databases,
+    ) -> IoResult<()> {
+        // Checked here rather than in the session, so the pub/sub commands
+        // are covered and replaying the append-only file is not.
+        if !self.session.authenticated
+            && !matches!(command, Command::Auth { .. } | Command::Hello { .. })
+        {
+            return self.write_error(&AuthError::Required).await;
+        }
+
+        match command {
+            Command::Subscribe(channels) => {
+                for channel in channels.iter() {
+                    self.session.subscription.subscribe(channel);
+
+                    let count = self.session.subscription.count();
+                    self.write_value(subscription_frame("subscribe", Some(channel), count))
+                        .await?
+                }
+            }
+            Command::PSubscribe(patterns) => {
+                for pattern in patterns.iter() {
+                    self.session.subscription.psubscribe(pattern);
+
+                    let count = self.session.subscription.count();
+                    self.write_value(subscription_frame("psubscribe", Some(pattern), count))
+                        .await?
+                }
+            }
+            Command::Unsubscribe(channels) => {
+                self.write_unsubscribed(
+                    "unsubscribe",
+                    &channels,
+                    Subscription::channels,
+                    Subscription::unsubscribe,
+                )
+                .await?
+            }
+            Command::PUnsubscribe(patterns) => {
+                self.write_unsubscribed(
+                    "punsubscribe",
+                    &patterns,
+                    Subscription::patterns,
+                    Subscription::punsubscribe,
+                )
+                .await?
+            }
+            command => {
+                let blocking = matches!(command, Command::BPop { .. } | Command::BLMove { .. });
+
+                // Replies to earlier pipelined commands must not wait on a
+                // blocking one.
+                if blocking {
+                    self.stream.flush().await?;
+                }
+
+                let write = command.is_write();
+                let db = self.session.db;
+                let unblocked = Unblocked::of(&command);
+
+                // A write runs holding the append-only file, so the clients
+                // it serves from blocking are logged after it. A blocking
+                // command cannot hold it while it waits.
+                let mut guard = match &self.aof {
+                    Some(aof) if write && !blocking => Some(aof.lock().await),
+                    _ => None,
                 };
+
+                let reply = self.session.execute(command, databases).await;
+
+                if let Some(aof) = self.aof.as_ref().filter(|_| write) {
+                    let logged = match (&reply, unblocked) {
+                        (CommandReply::Error(_), _) => None,
+                        (reply, Some(unblocked)) => unblocked.frame(reply).map(Cow::Owned),
+                        (_, None) => Some(Cow::Borrowed(frame)),
+                    };
+
+                    if let Some(logged) = logged {
+                        let appended = match guard.as_mut() {
+                            Some(guard) => guard.append(db, &logged).await,
+                            None => aof.append(db, &logged).await,
+                        };
+
+                        if let Err(err) = appended {
+                            warn!(err = ?err, "Failed to append to the append-only file");
+                        }
+                    }
+                }
+
+                drop(guard);
+                self.write_reply(reply).await?
             }
-            Command::Set {
-                key,
-                value,
-                expiration,
-            } => {
-                map.insert(key, value, expiration).await;
-                self.write(OK).await?
+        }
+
+        Ok(())
+    }
+
+    /// Writes the frames of a published message to a subscribed connection.
+    async fn deliver(&mut self, message: Result<Message, RecvError>) -> IoResult<()> {
+        let message = match message {
+            Ok(message) => message,
+            Err(err) => {
+                warn!(err = ?err, "Dropped published messages");
+                return Ok(());
             }
         };
 
-        Ok(())
+        let mut output = Vec::new();
+
+        for frame in self.session.subscription.frames(&message) {
+            frame.serialize(&mut output);
+        }
+
+        if output.is_empty() {
+            return Ok(());
+        }
+
+        self.write(output).await?;
+        self.stream.flush().await
     }
 
-    async fn handle(&mut self, map: &Database, mut reader: &mut BytesMut) -> Result<(), Error> {
-        self.stream.read_buf(&mut reader).await?;
+    /// Runs every complete request buffered in `reader`.
+    async fn handle(&mut self, databases: &Databases, reader: &mut BytesMut) -> Result<(), Error> {
+        let mut pending = 0;
 
-        let mut parser = Parser::parse(reader);
+        loop {
+            let consumed = match Parser::parse_frame(reader) {
+                Ok((mut parser, consumed)) => {
+                    let name = parser.name();
 
-        let command = match parser {
-            Ok(ref mut parser) => parser.command(),
-            Err(ParserError::Parse(RespError::Incomplete)) => return Err(Error::Again),
-            Err(err) => {
-                self.write_error(&err).await?;
-                return Err(Error::IoError(IoError::new(ErrorKind::InvalidInput, err)));
+                    match parser.command() {
+                        Ok(command) => {
+                            let start = Instant::now();
+                            self.handle_command(command, &reader[..consumed], databases)
+                                .await?;
+
+                            if let Some(name) = name {
+                                self.session.metrics.record(name, start.elapsed());
+                            }
+                        }
+                        // Only a request that is not a command at all leaves
+                        // the connection out of step; bad arguments to one
+                        // are answered like any other error.
+                        Err(err @ (ParserError::Parse(_) | ParserError::InvalidInput)) => {
+                            self.write_error(&err).await?;
+                            self.stream.flush().await?;
+                            return Err(Error::Io(IoError::new(ErrorKind::InvalidInput, err)));
+                        }
+                        Err(err) => self.write_error(&err).await?,
+                    }
+
+                    consumed
+                }
+                Err(err) if err.is_incomplete() => break,
+                Err(err) => {
+                    self.write_error(&err).await?;
+                    self.stream.flush().await?;
+                    return Err(Error::Io(IoError::new(ErrorKind::InvalidInput, err)));
+                }
+            };
+
+            reader.advance(consumed);
+            pending += 1;
+
+            // Replies are buffered until the pipeline depth is reached; the
+            // rest of the batch is only parsed once the client has them.
+            if pending == self.session.config.max_pipeline_depth {
+                self.stream.flush().await?;
+                pending = 0;
             }
-        };
+        }
 
-        match command {
-            Ok(command) => self.handle_command(command, map).await?,
-            Err(err @ ParserError::NotExists) => {
-                self.write_error(&err).await?;
-                return Err(Error::Again);
+        if pending > 0 {
+            self.stream.flush().await?;
+        }
+
+        if reader.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Again)
+        }
+    }
+
+    /// Serves the connection until it closes or the server shuts down.
+    ///
+    /// Commands run one at a time, in the order they arrive, and each has
+    /// finished with the keyspace before the next one starts, so a command
+    /// always observes the writes made by the connection's earlier ones.
+    pub async fn run(&mut self, databases: &Databases) -> Result<(), Error> {
+        let mut reader = self.buf_pool.acquire();
+
+        let shutdown = Arc::clone(&self.session.shutdown);
+
+        loop {
+            let subscribed = self.session.subscription.is_active();
+
+            // Like Redis, subscribers are never timed out for being quiet.
+            let idle_timeout = Some(self.session.config.idle_timeout)
+                .filter(|timeout| !timeout.is_zero() && !subscribed);
+
+            // Requests are only ever cut off between reads, so a shutdown
+            // lets the commands already received finish before closing.
+            // Subscribers stay until Pub/Sub is closed, to receive every
+            // message published before it.
+            let read = tokio::select! {
+                _ = shutdown.triggered(), if !subscribed => return Ok(()),
+                _ = idle(idle_timeout) => {
+                    info!("Closing idle connection");
+                    return Ok(());
+                }
+                read = self.stream.read_buf(&mut reader.0) => read?,
+                message = next_message(self.session.subscription.receiver()) => {
+                    if let Err(RecvError::Closed) = message {
+                        return Ok(());
+                    }
+
+                    self.deliver(message).await?;
+                    continue;
+                }
+            };
+
+            if read == 0 {
+                return Ok(());
             }
-            Err(err) => {
-                self.write_error(&err).await?;
-                return Err(Error::IoError(IoError::new(ErrorKind::InvalidInput, err)));
+
+            match self.handle(databases, &mut reader.0).await {
+                Ok(()) | Err(Error::Again) => continue,
+                Err(err) => return Err(err),
             }
         }
+    }
+}
 
-        Ok(())
+/// The next published message for a subscribed connection. Never resolves
+/// for a connection without subscriptions.
+async fn next_message(
+    receiver: Option<&mut broadcast::Receiver<Message>>,
+) -> Result<Message, RecvError> {
+    match receiver {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Resolves once a connection has been idle for `timeout`, or never when
+/// there is none.
+async fn idle(timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => tokio::time::sleep(timeout).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll};
+
+    use tokio::io::{duplex, DuplexStream, ReadBuf};
+
+    use super::*;
+    use crate::Database;
+
+    struct FlushCounter {
+        inner: DuplexStream,
+        flushes: Arc<AtomicUsize>,
     }
 
-    pub async fn run(&mut self, map: &Database) -> Result<(), Error> {
-        let mut reader = Arc::clone(&self.buf_pool)
-            .create_owned()
-            .ok_or_else(|| IoError::new(ErrorKind::Other, "Failed to buf_pool acquire pool"))?;
+    impl AsyncRead for FlushCounter {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<IoResult<()>> {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for FlushCounter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<IoResult<usize>> {
+            Pin::new(&mut self.inner).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+            let result = Pin::new(&mut self.inner).poll_flush(cx);
 
-        while let Err(err) = self.handle(map, &mut reader.0).await {
-            match err {
-                Error::IoError(io) => return Err(Error::IoError(io)),
-                Error::Again => continue,
+            if result.is_ready() {
+                self.flushes.fetch_add(1, Ordering::SeqCst);
             }
+
+            result
         }
 
-        Ok(())
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    fn handler<W: AsyncRead + AsyncWrite + Unpin>(stream: W, config: Config) -> Handler<W> {
+        Handler::new(
+            stream,
+            Pool::default().into(),
+            Pool::default().into(),
+            config.into(),
+            Shutdown::new(Duration::from_secs(1)).into(),
+            PubSub::default().into(),
+            Metrics::default().into(),
+        )
+    }
+
+    fn command(args: &[&[u8]]) -> Vec<u8> {
+        let values: Box<[Value]> = args
+            .iter()
+            .map(|arg| Value::BulkString(Cow::Borrowed(*arg)))
+            .collect();
+
+        let mut output = Vec::new();
+        Value::Array(values).serialize(&mut output);
+        output
+    }
+
+    /// Sends `input` over a fresh connection and returns everything the
+    /// handler wrote back before the client hung up.
+    async fn execute(database: &Databases, input: &[u8]) -> Vec<u8> {
+        execute_with(database, Config::default(), input).await
+    }
+
+    async fn execute_with(database: &Databases, config: Config, input: &[u8]) -> Vec<u8> {
+        let (mut client, server) = duplex(64 * 1024);
+        let mut handler = handler(server, config);
+
+        client.write_all(input).await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let _ = handler.run(database).await;
+        drop(handler);
+
+        let mut output = Vec::new();
+        client.read_to_end(&mut output).await.unwrap();
+        output
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_depth_flushes_in_batches() {
+        let database = Databases::from(Database::new());
+        let flushes = Arc::new(AtomicUsize::new(0));
+        let (mut client, server) = duplex(64 * 1024);
+
+        let mut handler = handler(
+            FlushCounter {
+                inner: server,
+                flushes: Arc::clone(&flushes),
+            },
+            Config {
+                max_pipeline_depth: 4,
+                ..Config::default()
+            },
+        );
+
+        client
+            .write_all(&b"*1\r\n$4\r\nPING\r\n".repeat(10))
+            .await
+            .unwrap();
+        client.shutdown().await.unwrap();
+
+        handler.run(&database).await.unwrap();
+        drop(handler);
+
+        let mut output = Vec::new();
+        client.read_to_end(&mut output).await.unwrap();
+
+        assert_eq!(output, b"+PONG\r\n".repeat(10));
+        assert_eq!(flushes.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_partial_frame_waits_for_more_input() {
+        let database = Databases::from(Database::new());
+        let (mut client, server) = duplex(64 * 1024);
+        let mut handler = handler(server, Config::default());
+
+        let task = tokio::spawn(async move { handler.run(&database).await });
+
+        client.write_all(b"*1\r\n$4\r\nPI").await.unwrap();
+        client.write_all(b"NG\r\n").await.unwrap();
+
+        let mut output = [0u8; 7];
+        client.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"+PONG\r\n");
+
+        drop(client);
+        assert!(task.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_partial_array_header_keeps_connection_open() {
+        let database = Databases::from(Database::new());
+        let (mut client, server) = duplex(64 * 1024);
+        let mut handler = handler(server, Config::default());
+
+        let task = tokio::spawn(async move { handler.run(&database).await });
+
+        client.write_all(b"*2\r").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!task.is_finished());
+
+        client
+            .write_all(b"\n$4\r\nECHO\r\n$2\r\nhi\r\n")
+            .await
+            .unwrap();
+
+        let mut output = [0u8; 5];
+        client.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"+hi\r\n");
+
+        drop(client);
+        assert!(task.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_hrandfield_negative_count_samples() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSET", b"hash", b"a", b"1"]);
+        input.extend(command(&[b"HRANDFIELD", b"hash", b"-2", b"WITHVALUES"]));
+        input.extend(command(&[b"HRANDFIELD", b"hash", b"-1"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":1\r\n*4\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\na\r\n$1\r\n1\r\n*1\r\n$1\r\na\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hrandfield_huge_negative_count_streams() {
+        let database = Arc::new(Databases::from(Database::new()));
+        database[0].hset(b"hash", &[("a", "1")]).await.unwrap();
+
+        let (mut client, server) = duplex(64 * 1024);
+        let mut handler = handler(server, Config::default());
+        let task = {
+            let database = Arc::clone(&database);
+            tokio::spawn(async move { handler.run(&database).await })
+        };
+
+        client
+            .write_all(&command(&[b"HRANDFIELD", b"hash", b"-10000000000"]))
+            .await
+            .unwrap();
+
+        let mut output = vec![0; 64 * 1024];
+        client.read_exact(&mut output).await.unwrap();
+        assert!(output.starts_with(b"*10000000000\r\n$1\r\na\r\n$1\r\na\r\n"));
+
+        // Hanging up stops the reply instead of leaving it to be built.
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_hrandfield_negative_count_with_values_in_resp3() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSET", b"hash", b"a", b"1"]);
+        input.extend(command(&[b"HELLO", b"3"]));
+        input.extend(command(&[b"HRANDFIELD", b"hash", b"-2", b"WITHVALUES"]));
+
+        let output = execute(&database, &input).await;
+        assert!(output.ends_with(b"*2\r\n*2\r\n$1\r\na\r\n$1\r\n1\r\n*2\r\n$1\r\na\r\n$1\r\n1\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_hello_auth() {
+        let database = Databases::from(Database::new());
+        let config = Config {
+            requirepass: Some("secret".into()),
+            ..Config::default()
+        };
+
+        let mut input = command(&[b"PING"]);
+        input.extend(command(&[b"SUBSCRIBE", b"news"]));
+        input.extend(command(&[b"HELLO", b"3"]));
+        input.extend(command(&[b"HELLO", b"3", b"AUTH", b"default", b"wrong"]));
+        input.extend(command(&[
+            b"HELLO", b"3", b"AUTH", b"default", b"secret", b"SETNAME", b"worker",
+        ]));
+        input.extend(command(&[b"CLIENT", b"GETNAME"]));
+
+        assert_eq!(
+            String::from_utf8(execute_with(&database, config, &input).await).unwrap(),
+            format!(
+                "-NOAUTH Authentication required.\r\n\
+                 -NOAUTH Authentication required.\r\n\
+                 -NOAUTH HELLO must be called with the client already authenticated, \
+                 otherwise the HELLO <proto> AUTH <user> <pass> option can be used to \
+                 authenticate the client and select the RESP protocol version at the same time\r\n\
+                 -WRONGPASS invalid username-password pair or user is disabled.\r\n\
+                 %7\r\n$6\r\nserver\r\n$5\r\nredis\r\n$7\r\nversion\r\n${}\r\n{}\r\n\
+                 $5\r\nproto\r\n:3\r\n$2\r\nid\r\n:1\r\n$4\r\nmode\r\n$10\r\nstandalone\r\n\
+                 $4\r\nrole\r\n$6\r\nmaster\r\n$7\r\nmodules\r\n*0\r\n\
+                 $6\r\nworker\r\n",
+                env!("CARGO_PKG_VERSION").len(),
+                env!("CARGO_PKG_VERSION"),
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auth_and_resp2_hello() {
+        let database = Databases::from(Database::new());
+        let config = Config {
+            requirepass: Some("secret".into()),
+            ..Config::default()
+        };
+
+        let mut input = command(&[b"AUTH", b"wrong"]);
+        input.extend(command(&[b"AUTH", b"secret"]));
+        input.extend(command(&[b"PING"]));
+        input.extend(command(&[b"HELLO", b"4"]));
+
+        assert_eq!(
+            execute_with(&database, config, &input).await,
+            b"-WRONGPASS invalid username-password pair or user is disabled.\r\n\
+              +OK\r\n+PONG\r\n-NOPROTO unsupported protocol version\r\n"
+        );
+
+        let mut input = command(&[b"AUTH", b"any"]);
+        input.extend(command(&[b"HELLO"]));
+
+        let output = execute(&database, &input).await;
+        let expected: &[u8] = b"-ERR AUTH <password> called without any password configured \
+            for the default user. Are you sure your configuration is correct?\r\n\
+            *14\r\n$6\r\nserver\r\n$5\r\nredis\r\n";
+        assert!(output.starts_with(expected));
+        assert!(output.ends_with(
+            b"$5\r\nproto\r\n:2\r\n$2\r\nid\r\n:1\r\n$4\r\nmode\r\n\
+            $10\r\nstandalone\r\n$4\r\nrole\r\n$6\r\nmaster\r\n$7\r\nmodules\r\n*0\r\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_arity_keeps_connection() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"ECHO"]);
+        input.extend(command(&[b"ECHO", b"a", b"b"]));
+        input.extend(command(&[b"GET", b"key", b"extra"]));
+        input.extend(command(&[b"ECHO", b"a"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"-ERR wrong number of arguments for 'echo' command\r\n\
+              -ERR wrong number of arguments for 'echo' command\r\n\
+              -ERR wrong number of arguments for 'get' command\r\n\
+              +a\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bad_lpos_arguments_keep_connection() {
+        let database = Databases::from(Database::new());
+
+        let mut input = Vec::new();
+        for args in [
+            &[&b"RANK"[..], b"0"][..],
+            &[b"COUNT", b"-1"],
+            &[b"MAXLEN", b"-1"],
+            &[b"NOPE", b"1"],
+        ] {
+            let mut lpos = vec![&b"LPOS"[..], b"list", b"x"];
+            lpos.extend(args);
+            input.extend(command(&lpos));
+            input.extend(command(&[b"PING"]));
+        }
+
+        assert_eq!(
+            String::from_utf8(execute(&database, &input).await).unwrap(),
+            "-ERR RANK can't be zero: use 1 to start from the first match, 2 from the second \
+             ... or use negative to start from the end of the list\r\n+PONG\r\n\
+             -ERR COUNT can't be negative\r\n+PONG\r\n\
+             -ERR MAXLEN can't be negative\r\n+PONG\r\n\
+             -ERR syntax error\r\n+PONG\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bad_blocking_timeout_keeps_connection() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"BLPOP", b"list", b"abc"]);
+        input.extend(command(&[b"PING"]));
+        input.extend(command(&[b"BLPOP", b"list", b"-1"]));
+        input.extend(command(&[b"PING"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"-ERR timeout is not a float or out of range\r\n+PONG\r\n\
+              -ERR timeout is negative\r\n+PONG\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bad_counts_and_integers_keep_connection() {
+        let database = Databases::from(Database::new());
+
+        let mut input = Vec::new();
+        for args in [
+            &[&b"LMPOP"[..], b"1", b"list", b"LEFT", b"COUNT", b"0"][..],
+            &[b"LMPOP", b"0", b"list", b"LEFT"],
+            &[b"LMPOP", b"3", b"list", b"LEFT"],
+            &[b"LRANGE", b"list", b"a", b"b"],
+            &[b"HINCRBY", b"hash", b"field", b"x"],
+            &[b"LPOS", b"list", b"x", b"RANK", b"x"],
+        ] {
+            input.extend(command(args));
+            input.extend(command(&[b"PING"]));
+        }
+
+        assert_eq!(
+            String::from_utf8(execute(&database, &input).await).unwrap(),
+            "-ERR count should be greater than 0\r\n+PONG\r\n\
+             -ERR numkeys should be greater than 0\r\n+PONG\r\n\
+             -ERR Number of keys can't be greater than number of args\r\n+PONG\r\n\
+             -ERR value is not an integer or out of range\r\n+PONG\r\n\
+             -ERR value is not an integer or out of range\r\n+PONG\r\n\
+             -ERR value is not an integer or out of range\r\n+PONG\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bad_offsets_keep_connection() {
+        let database = Databases::from(Database::new());
+
+        let mut input = Vec::new();
+        for args in [
+            &[&b"SETRANGE"[..], b"key", b"-1", b"x"][..],
+            &[b"SETRANGE", b"key", b"536870912", b"x"],
+            &[b"SETBIT", b"key", b"0", b"2"],
+            &[b"GETBIT", b"key", b"-1"],
+        ] {
+            input.extend(command(args));
+            input.extend(command(&[b"PING"]));
+        }
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"-ERR offset is out of range\r\n+PONG\r\n\
+              -ERR offset is out of range\r\n+PONG\r\n\
+              -ERR bit is not an integer or out of range\r\n+PONG\r\n\
+              -ERR offset is out of range\r\n+PONG\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bad_cursor_keeps_connection() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SCAN", b"abc"]);
+        input.extend(command(&[b"PING"]));
+        input.extend(command(&[b"HSCAN", b"hash", b"abc"]));
+        input.extend(command(&[b"PING"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"-ERR invalid cursor\r\n+PONG\r\n-ERR invalid cursor\r\n+PONG\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bad_keywords_reply_syntax_error() {
+        let database = Databases::from(Database::new());
+
+        let mut input = Vec::new();
+        for args in [
+            &[&b"LMOVE"[..], b"a", b"b", b"UP", b"LEFT"][..],
+            &[b"BLMOVE", b"a", b"b", b"LEFT", b"DOWN", b"0"],
+            &[b"LINSERT", b"list", b"BESIDE", b"a", b"b"],
+        ] {
+            input.extend(command(args));
+            input.extend(command(&[b"PING"]));
+        }
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"-ERR syntax error\r\n+PONG\r\n-ERR syntax error\r\n+PONG\r\n\
+              -ERR syntax error\r\n+PONG\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bgsave_after_fork_delay_keeps_serving() {
+        let database = Databases::from(Database::new());
+        let path = std::env::temp_dir().join(format!(
+            "redis-test-{}-handler-bgsave-delay.rdb",
+            std::process::id()
+        ));
+        let config = Config {
+            rdb_path: Some(path.clone()),
+            ..Config::default()
+        };
+
+        let mut input = command(&[b"DEBUG", b"SLEEP-AFTER-FORK-SECONDS", b"0.5"]);
+        input.extend(command(&[b"BGSAVE"]));
+        input.extend(command(&[b"BGSAVE"]));
+        input.extend(command(&[b"SET", b"key", b"value"]));
+        input.extend(command(&[b"GET", b"key"]));
+
+        let start = tokio::time::Instant::now();
+        assert_eq!(
+            execute_with(&database, config, &input).await,
+            b"+OK\r\n+Background saving started\r\n\
+              -ERR Background save already in progress\r\n+OK\r\n$5\r\nvalue\r\n"
+        );
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(!path.exists());
+
+        while !path.exists() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(500));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pubsub_delivers_messages() {
+        let database = Arc::new(Databases::from(Database::new()));
+        let pubsub = Arc::new(PubSub::default());
+
+        let connect = |pubsub: &Arc<PubSub>| {
+            let (client, server) = duplex(64 * 1024);
+            let mut handler = Handler::new(
+                server,
+                Pool::default().into(),
+                Pool::default().into(),
+                Config::default().into(),
+                Shutdown::new(Duration::from_secs(1)).into(),
+                Arc::clone(pubsub),
+                Metrics::default().into(),
+            );
+
+            let database = Arc::clone(&database);
+            tokio::spawn(async move { handler.run(&database).await.unwrap() });
+            client
+        };
+
+        let mut subscriber = connect(&pubsub);
+        let mut publisher = connect(&pubsub);
+
+        let mut input = command(&[b"SUBSCRIBE", b"news"]);
+        input.extend(command(&[b"PSUBSCRIBE", b"n*"]));
+        input.extend(command(&[b"GET", b"key"]));
+        input.extend(command(&[b"PING"]));
+        subscriber.write_all(&input).await.unwrap();
+
+        let expected: &[u8] = b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n\
+            *3\r\n$10\r\npsubscribe\r\n$2\r\nn*\r\n:2\r\n\
+            -ERR only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING are allowed in this context\r\n\
+            *2\r\n$4\r\npong\r\n$0\r\n\r\n";
+        let mut output = vec![0; expected.len()];
+        subscriber.read_exact(&mut output).await.unwrap();
+        assert_eq!(output, expected);
+
+        publisher
+            .write_all(&command(&[b"PUBLISH", b"news", b"hello"]))
+            .await
+            .unwrap();
+
+        let mut output = [0; 4];
+        publisher.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b":2\r\n");
+
+        let expected: &[u8] = b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n\
+            *4\r\n$8\r\npmessage\r\n$2\r\nn*\r\n$4\r\nnews\r\n$5\r\nhello\r\n";
+        let mut output = vec![0; expected.len()];
+        subscriber.read_exact(&mut output).await.unwrap();
+        assert_eq!(output, expected);
+
+        let mut input = command(&[b"UNSUBSCRIBE"]);
+        input.extend(command(&[b"PUNSUBSCRIBE", b"n*"]));
+        input.extend(command(&[b"PUNSUBSCRIBE"]));
+        input.extend(command(&[b"PING"]));
+        subscriber.write_all(&input).await.unwrap();
+
+        let expected: &[u8] = b"*3\r\n$11\r\nunsubscribe\r\n$4\r\nnews\r\n:1\r\n\
+            *3\r\n$12\r\npunsubscribe\r\n$2\r\nn*\r\n:0\r\n\
+            *3\r\n$12\r\npunsubscribe\r\n$-1\r\n:0\r\n+PONG\r\n";
+        let mut output = vec![0; expected.len()];
+        subscriber.read_exact(&mut output).await.unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_idle_connection() {
+        let database = Databases::from(Database::new());
+        let shutdown = Arc::new(Shutdown::new(Duration::from_secs(1)));
+        let (mut client, server) = duplex(64 * 1024);
+
+        let mut handler = Handler::new(
+            server,
+            Pool::default().into(),
+            Pool::default().into(),
+            Config::default().into(),
+            Arc::clone(&shutdown),
+            PubSub::default().into(),
+            Metrics::default().into(),
+        );
+
+        let task = tokio::spawn(async move { handler.run(&database).await });
+
+        client.write_all(&command(&[b"PING"])).await.unwrap();
+        let mut output = [0u8; 7];
+        client.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"+PONG\r\n");
+
+        client.write_all(b"*1\r\n$4\r\nPI").await.unwrap();
+        shutdown.trigger();
+
+        task.await.unwrap().unwrap();
+
+        let mut output = Vec::new();
+        client.read_to_end(&mut output).await.unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_info_commandstats() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SET", b"key", b"value"]);
+        input.extend(command(&[b"GET", b"key"]));
+        input.extend(command(&[b"get", b"key"]));
+        input.extend(command(&[b"GET", b"missing"]));
+        input.extend(command(&[b"INFO", b"COMMANDSTATS"]));
+
+        let output = execute(&database, &input).await;
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("+OK\r\n$5\r\nvalue\r\n$5\r\nvalue\r\n$-1\r\n$"));
+        assert!(output.contains("# Commandstats\r\n"));
+        assert!(output.contains("\r\ncmdstat_get:calls=3,usec="));
+        assert!(output.contains("\r\ncmdstat_set:calls=1,usec="));
+        assert!(!output.contains("cmdstat_info"));
+    }
+
+    #[tokio::test]
+    async fn test_blocking_pop_replies() {
+        let database = Arc::new(Databases::from(Database::new()));
+
+        let (mut blocked, server) = duplex(64 * 1024);
+        let mut handler = handler(server, Config::default());
+
+        let cl = Arc::clone(&database);
+        tokio::spawn(async move { handler.run(&cl).await });
+
+        let mut input = command(&[b"BLPOP", b"list", b"0.01"]);
+        input.extend(command(&[b"BRPOP", b"list", b"0"]));
+        blocked.write_all(&input).await.unwrap();
+
+        let mut output = [0u8; 5];
+        blocked.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"*-1\r\n");
+
+        let mut input = command(&[b"RPUSH", b"list", b"a", b"b"]);
+        input.extend(command(&[b"BLPOP", b"list", b"1"]));
+        input.extend(command(&[b"BLPOP", b"list", b"-1"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n*2\r\n$4\r\nlist\r\n$1\r\na\r\n-ERR timeout is negative\r\n"
+        );
+
+        let expected: &[u8] = b"*2\r\n$4\r\nlist\r\n$1\r\nb\r\n";
+        let mut output = vec![0u8; expected.len()];
+        blocked.read_exact(&mut output).await.unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_reads_observe_own_writes() {
+        let database = Arc::new(Databases::from(Database::new()));
+
+        let (mut client, server) = duplex(64 * 1024);
+        let mut handler = handler(server, Config::default());
+
+        let cl = Arc::clone(&database);
+        tokio::spawn(async move { handler.run(&cl).await });
+
+        // Another connection contending for the keyspace lock in between
+        // must not let a read overtake the write before it.
+        let cl = Arc::clone(&database);
+        let noise = tokio::spawn(async move {
+            for _ in 0..1000 {
+                execute(&cl, &command(&[b"SET", b"other", b"noise"])).await;
+            }
+        });
+
+        for i in 0..5000 {
+            let value = i.to_string();
+            let mut input = command(&[b"SET", b"key", value.as_bytes()]);
+            input.extend(command(&[b"GET", b"key"]));
+            client.write_all(&input).await.unwrap();
+
+            let expected = format!("+OK\r\n${}\r\n{value}\r\n", value.len());
+            let mut output = vec![0u8; expected.len()];
+            client.read_exact(&mut output).await.unwrap();
+            assert_eq!(expected.as_bytes(), output, "stale read at {i}");
+        }
+
+        noise.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_info_errorstats() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SET", b"key", b"value"]);
+        input.extend(command(&[b"LLEN", b"key"]));
+        input.extend(command(&[b"LPUSH", b"key", b"a"]));
+        input.extend(command(&[b"LSET", b"missing", b"0", b"a"]));
+        input.extend(command(&[b"INFO", b"errorstats"]));
+
+        let output = execute(&database, &input).await;
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("# Errorstats\r\n"));
+        assert!(output.contains("\r\nerrorstat_WRONGTYPE:count=2\r\n"));
+        assert!(output.contains("\r\nerrorstat_ERR:count=1\r\n"));
+        assert!(!output.contains("# Commandstats"));
+    }
+
+    #[tokio::test]
+    async fn test_blocking_lmove_pipeline() {
+        let database = Arc::new(Databases::from(Database::new()));
+
+        let (mut consumer, server) = duplex(64 * 1024);
+        let mut handler = handler(server, Config::default());
+
+        let cl = Arc::clone(&database);
+        tokio::spawn(async move { handler.run(&cl).await });
+
+        let mut input = command(&[b"BLMOVE", b"jobs", b"done", b"LEFT", b"RIGHT", b"0"]);
+        input.extend(command(&[b"BRPOPLPUSH", b"jobs", b"done", b"0"]));
+        consumer.write_all(&input).await.unwrap();
+
+        assert_eq!(
+            execute(&database, &command(&[b"RPUSH", b"jobs", b"a", b"b"])).await,
+            b":2\r\n"
+        );
+
+        let expected: &[u8] = b"$1\r\na\r\n$1\r\nb\r\n";
+        let mut output = vec![0u8; expected.len()];
+        consumer.read_exact(&mut output).await.unwrap();
+        assert_eq!(output, expected);
+
+        assert_eq!(
+            execute(&database, &command(&[b"LRANGE", b"done", b"0", b"-1"])).await,
+            b"*2\r\n$1\r\nb\r\n$1\r\na\r\n"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout_closes_connection() {
+        let database = Databases::from(Database::new());
+        let (mut client, server) = duplex(64 * 1024);
+
+        let config = Config {
+            idle_timeout: Duration::from_millis(100),
+            ..Config::default()
+        };
+        let mut handler = handler(server, config);
+
+        let start = tokio::time::Instant::now();
+        let task = tokio::spawn(async move { handler.run(&database).await });
+
+        // Each command resets the timeout.
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(80)).await;
+            client.write_all(&command(&[b"PING"])).await.unwrap();
+
+            let mut output = [0u8; 7];
+            client.read_exact(&mut output).await.unwrap();
+            assert_eq!(&output, b"+PONG\r\n");
+        }
+
+        task.await.unwrap().unwrap();
+        assert_eq!(Duration::from_millis(340), start.elapsed());
+
+        let mut output = Vec::new();
+        client.read_to_end(&mut output).await.unwrap();
+        assert!(output.is_empty());
     }
 }
@@ -0,0 +1,47 @@
+use std::fmt::Debug;
+use std::io;
+use std::net::SocketAddr;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+/// A socket the server accepts clients from.
+pub(crate) trait Listener: Send + Sync + 'static {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+    type Addr: Debug;
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(Self::Stream, Self::Addr)>>;
+
+    /// The address a TCP listener is bound to; `None` for other sockets.
+    fn tcp_addr(&self) -> Option<io::Result<SocketAddr>>;
+}
+
+impl Listener for TcpListener {
+    type Stream = tokio::net::TcpStream;
+    type Addr = SocketAddr;
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(Self::Stream, Self::Addr)>> {
+        TcpListener::poll_accept(self, cx)
+    }
+
+    fn tcp_addr(&self) -> Option<io::Result<SocketAddr>> {
+        Some(self.local_addr())
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Stream = tokio::net::UnixStream;
+    type Addr = tokio::net::unix::SocketAddr;
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(Self::Stream, Self::Addr)>> {
+        UnixListener::poll_accept(self, cx)
+    }
+
+    fn tcp_addr(&self) -> Option<io::Result<SocketAddr>> {
+        None
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct CommandStats {
+    calls: u64,
+    usec: u64,
+}
+
+/// Server-wide counters shared by every connection.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    commands: Mutex<HashMap<&'static str, CommandStats>>,
+    errors: Mutex<HashMap<Box<str>, u64>>,
+    evicted_keys: AtomicU64,
+}
+
+impl Metrics {
+    /// Records one call of the command `name` that took `elapsed`.
+    pub fn record(&self, name: &'static str, elapsed: Duration) {
+        let mut commands = self.commands.lock().unwrap();
+        let stats = commands.entry(name).or_default();
+
+        stats.calls += 1;
+        stats.usec += elapsed.as_micros() as u64;
+    }
+
+    /// Counts an error reply under its prefix, the leading upper case word
+    /// such as `ERR` or `WRONGTYPE`. Replies without one count as `ERR`.
+    pub fn record_error(&self, message: &str) {
+        let prefix = message
+            .split(' ')
+            .next()
+            .filter(|prefix| {
+                !prefix.is_empty() && prefix.bytes().all(|byte| byte.is_ascii_uppercase())
+            })
+            .unwrap_or("ERR");
+
+        let mut errors = self.errors.lock().unwrap();
+
+        match errors.get_mut(prefix) {
+            Some(count) => *count += 1,
+            None => {
+                errors.insert(Box::from(prefix), 1);
+            }
+        }
+    }
+
+    /// Counts a key evicted to stay within `maxmemory`.
+    pub fn record_eviction(&self) {
+        self.evicted_keys.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The `# Stats` section of INFO.
+    pub fn stats(&self) -> String {
+        format!(
+            "# Stats\r\nevicted_keys:{}\r\n",
+            self.evicted_keys.load(Ordering::Relaxed)
+        )
+    }
+
+    /// The `# Commandstats` section of INFO, one line per command called so
+    /// far, sorted by name.
+    pub fn commandstats(&self) -> String {
+        let commands = self.commands.lock().unwrap();
+
+        let mut names: Vec<_> = commands.keys().copied().collect();
+        names.sort_unstable();
+
+        let mut output = String::from("# Commandstats\r\n");
+
+        for name in names {
+            let stats = commands[name];
+            let _ = write!(
+                output,
+                "cmdstat_{name}:calls={},usec={},usec_per_call={:.2}\r\n",
+                stats.calls,
+                stats.usec,
+                stats.usec as f64 / stats.calls as f64,
+            );
+        }
+
+        output
+    }
+
+    /// The `# Errorstats` section of INFO, one line per error prefix replied
+    /// so far, sorted by prefix.
+    pub fn errorstats(&self) -> String {
+        let errors = self.errors.lock().unwrap();
+
+        let mut prefixes: Vec<_> = errors.iter().collect();
+        prefixes.sort_unstable();
+
+        let mut output = String::from("# Errorstats\r\n");
+
+        for (prefix, count) in prefixes {
+            let _ = write!(output, "errorstat_{prefix}:count={count}\r\n");
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commandstats_format() {
+        let metrics = Metrics::default();
+
+        metrics.record("set", Duration::from_micros(5));
+        metrics.record("get", Duration::from_micros(3));
+        metrics.record("get", Duration::from_micros(4));
+
+        assert_eq!(
+            metrics.commandstats(),
+            "# Commandstats\r\n\
+             cmdstat_get:calls=2,usec=7,usec_per_call=3.50\r\n\
+             cmdstat_set:calls=1,usec=5,usec_per_call=5.00\r\n"
+        );
+    }
+
+    #[test]
+    fn test_stats_format() {
+        let metrics = Metrics::default();
+        assert_eq!(metrics.stats(), "# Stats\r\nevicted_keys:0\r\n");
+
+        metrics.record_eviction();
+        metrics.record_eviction();
+        assert_eq!(metrics.stats(), "# Stats\r\nevicted_keys:2\r\n");
+    }
+
+    #[test]
+    fn test_errorstats_format() {
+        let metrics = Metrics::default();
+
+        metrics.record_error("WRONGTYPE Operation against a key holding the wrong kind of value");
+        metrics.record_error("ERR index out of range");
+        metrics.record_error("command does not exist");
+
+        assert_eq!(
+            metrics.errorstats(),
+            "# Errorstats\r\n\
+             errorstat_ERR:count=2\r\n\
+             errorstat_WRONGTYPE:count=1\r\n"
+        );
+    }
+}
@@ -1,4 +1,20 @@
+mod aof;
+mod auth;
+mod clients;
+mod commands;
+mod config;
 mod handler;
+mod listener;
+mod metrics;
+mod pubsub;
+mod reply;
+mod session;
+mod shutdown;
 mod tcp;
 
-pub(crate) use tcp::Server;
\ No newline at end of file
+pub(crate) use aof::replay as replay_aof;
+pub(crate) use commands::command_spec;
+pub use config::{AppendFsync, Config};
+pub(crate) use listener::Listener;
+pub use shutdown::Shutdown;
+pub(crate) use tcp::Server;
@@ -0,0 +1,363 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::broadcast;
+
+use crate::glob::glob_match;
+use crate::{bulk_string, Value};
+
+const DEFAULT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    channel: Arc<[u8]>,
+    payload: Arc<[u8]>,
+}
+
+/// How many connections subscribe to each channel and pattern, so PUBLISH
+/// can report its receivers without asking every connection.
+#[derive(Debug, Default)]
+struct Registry {
+    channels: HashMap<Box<[u8]>, usize>,
+    patterns: HashMap<Box<[u8]>, usize>,
+}
+
+impl Registry {
+    fn add(counts: &mut HashMap<Box<[u8]>, usize>, name: &[u8]) {
+        *counts.entry(Box::from(name)).or_default() += 1;
+    }
+
+    fn remove(counts: &mut HashMap<Box<[u8]>, usize>, name: &[u8]) {
+        if let Some(count) = counts.get_mut(name) {
+            *count -= 1;
+
+            if *count == 0 {
+                counts.remove(name);
+            }
+        }
+    }
+}
+
+/// Fans published messages out to every subscribed connection. Each
+/// connection filters the shared stream against its own subscriptions.
+#[derive(Debug)]
+pub struct PubSub {
+    /// `None` once closed.
+    sender: Mutex<Option<broadcast::Sender<Message>>>,
+    registry: Mutex<Registry>,
+}
+
+impl Default for PubSub {
+    fn default() -> Self {
+        Self {
+            sender: Mutex::new(Some(broadcast::channel(DEFAULT_CAPACITY).0)),
+            registry: Mutex::default(),
+        }
+    }
+}
+
+impl PubSub {
+    /// Delivers `payload` to the subscribers of `channel` and returns how
+    /// many subscriptions received it, pattern subscriptions included.
+    /// Nothing is delivered once closed.
+    pub fn publish(&self, channel: &[u8], payload: &[u8]) -> usize {
+        let sender = self.sender.lock().unwrap();
+
+        let Some(sender) = sender.as_ref() else {
+            return 0;
+        };
+
+        let registry = self.registry.lock().unwrap();
+
+        let receivers = registry.channels.get(channel).copied().unwrap_or(0)
+            + registry
+                .patterns
+                .iter()
+                .filter(|(pattern, _)| glob_match(pattern, channel))
+                .map(|(_, count)| count)
+                .sum::<usize>();
+
+        if receivers > 0 {
+            let _ = sender.send(Message {
+                channel: Arc::from(channel),
+                payload: Arc::from(payload),
+            });
+        }
+
+        receivers
+    }
+
+    /// Publishes a keyspace event like Redis: `event` to the subscribers of
+    /// `__keyspace@<db>__:<key>`, and `key` to those of
+    /// `__keyevent@<db>__:<event>`.
+    pub fn notify_keyspace_event(&self, event: &str, db: usize, key: &[u8]) {
+        let mut channel = format!("__keyspace@{db}__:").into_bytes();
+        channel.extend_from_slice(key);
+        self.publish(&channel, event.as_bytes());
+
+        self.publish(format!("__keyevent@{db}__:{event}").as_bytes(), key);
+    }
+
+    /// Stops delivering messages. Subscribers still receive the ones
+    /// published before, after which their receivers report the channel
+    /// closed.
+    pub fn close(&self) {
+        self.sender.lock().unwrap().take();
+    }
+}
+
+/// The channels and patterns one connection is subscribed to. Dropping it
+/// releases them from the shared registry.
+#[derive(Debug)]
+pub struct Subscription {
+    pubsub: Arc<PubSub>,
+    channels: HashSet<Box<[u8]>>,
+    patterns: HashSet<Box<[u8]>>,
+    receiver: Option<broadcast::Receiver<Message>>,
+}
+
+impl Subscription {
+    pub fn new(pubsub: Arc<PubSub>) -> Self {
+        Self {
+            pubsub,
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            receiver: None,
+        }
+    }
+
+    /// Total subscriptions, the count every (un)subscribe reply carries.
+    pub fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    pub fn receiver(&mut self) -> Option<&mut broadcast::Receiver<Message>> {
+        self.receiver.as_mut()
+    }
+
+    pub fn channels(&self) -> Vec<Box<[u8]>> {
+        self.channels.iter().cloned().collect()
+    }
+
+    pub fn patterns(&self) -> Vec<Box<[u8]>> {
+        self.patterns.iter().cloned().collect()
+    }
+
+    pub fn subscribe(&mut self, channel: &[u8]) {
+        if self.channels.insert(Box::from(channel)) {
+            Registry::add(&mut self.pubsub.registry.lock().unwrap().channels, channel);
+        }
+
+        self.listen();
+    }
+
+    pub fn unsubscribe(&mut self, channel: &[u8]) {
+        if self.channels.remove(channel) {
+            Registry::remove(&mut self.pubsub.registry.lock().unwrap().channels, channel);
+        }
+
+        self.listen();
+    }
+
+    pub fn psubscribe(&mut self, pattern: &[u8]) {
+        if self.patterns.insert(Box::from(pattern)) {
+            Registry::add(&mut self.pubsub.registry.lock().unwrap().patterns, pattern);
+        }
+
+        self.listen();
+    }
+
+    pub fn punsubscribe(&mut self, pattern: &[u8]) {
+        if self.patterns.remove(pattern) {
+            Registry::remove(&mut self.pubsub.registry.lock().unwrap().patterns, pattern);
+        }
+
+        self.listen();
+    }
+
+    /// Frames `message` once for a matching channel subscription and once
+    /// per matching pattern, the way Redis delivers it.
+    pub fn frames<'a>(&'a self, message: &'a Message) -> Vec<Value<'a>> {
+        let channel = self
+            .channels
+            .contains(message.channel.as_ref())
+            .then(|| message_frame(&message.channel, &message.payload));
+
+        let patterns = self
+            .patterns
+            .iter()
+            .filter(|pattern| glob_match(pattern, &message.channel))
+            .map(|pattern| pmessage_frame(pattern, &message.channel, &message.payload));
+
+        channel.into_iter().chain(patterns).collect()
+    }
+
+    /// Only holds a receiver while subscribed, so idle connections do not
+    /// buffer every published message. None is handed out once closed.
+    fn listen(&mut self) {
+        match (self.count(), &self.receiver) {
+            (0, Some(_)) => self.receiver = None,
+            (1.., None) => {
+                self.receiver = self
+                    .pubsub
+                    .sender
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map(broadcast::Sender::subscribe);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut registry = self.pubsub.registry.lock().unwrap();
+
+        for channel in &self.channels {
+            Registry::remove(&mut registry.channels, channel);
+        }
+
+        for pattern in &self.patterns {
+            Registry::remove(&mut registry.patterns, pattern);
+        }
+    }
+}
+
+/// `["message", channel, payload]`, the RESP2 shape of a delivered message.
+pub fn message_frame<'a>(channel: &'a [u8], payload: &'a [u8]) -> Value<'a> {
+    Value::Array(Box::new([
+        bulk_string!(b"message"),
+        bulk_string!(channel),
+        bulk_string!(payload),
+    ]))
+}
+
+/// `["pmessage", pattern, channel, payload]`, for pattern subscriptions.
+pub fn pmessage_frame<'a>(pattern: &'a [u8], channel: &'a [u8], payload: &'a [u8]) -> Value<'a> {
+    Value::Array(Box::new([
+        bulk_string!(b"pmessage"),
+        bulk_string!(pattern),
+        bulk_string!(channel),
+        bulk_string!(payload),
+    ]))
+}
+
+/// `[kind, name, count]`, the reply to each (un)subscribed channel or
+/// pattern. `name` is nil when unsubscribing with nothing subscribed.
+pub fn subscription_frame<'a>(
+    kind: &'static str,
+    name: Option<&'a [u8]>,
+    count: usize,
+) -> Value<'a> {
+    Value::Array(Box::new([
+        bulk_string!(kind.as_bytes()),
+        name.map_or(Value::Null, |name| bulk_string!(name)),
+        Value::Integer(count as i64),
+    ]))
+}
+
+/// `["pong", ""]`, the reply to PING while subscribed.
+pub fn pong_frame() -> Value<'static> {
+    Value::Array(Box::new([bulk_string!(b"pong"), bulk_string!(b"")]))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast::error::TryRecvError;
+
+    use super::*;
+
+    fn serialize(value: Value) -> Vec<u8> {
+        let mut output = Vec::new();
+        value.serialize(&mut output);
+        output
+    }
+
+    #[test]
+    fn test_message_framing() {
+        assert_eq!(
+            serialize(message_frame(b"news", b"hello")),
+            b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n"
+        );
+    }
+
+    #[test]
+    fn test_pmessage_framing() {
+        assert_eq!(
+            serialize(pmessage_frame(b"n*", b"news", b"hello")),
+            b"*4\r\n$8\r\npmessage\r\n$2\r\nn*\r\n$4\r\nnews\r\n$5\r\nhello\r\n"
+        );
+    }
+
+    #[test]
+    fn test_subscription_framing() {
+        assert_eq!(
+            serialize(subscription_frame("subscribe", Some(b"news"), 1)),
+            b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n"
+        );
+        assert_eq!(
+            serialize(subscription_frame("unsubscribe", None, 0)),
+            b"*3\r\n$11\r\nunsubscribe\r\n$-1\r\n:0\r\n"
+        );
+    }
+
+    #[test]
+    fn test_publish_counts_subscriptions() {
+        let pubsub = Arc::new(PubSub::default());
+        let mut first = Subscription::new(Arc::clone(&pubsub));
+        let mut second = Subscription::new(Arc::clone(&pubsub));
+
+        first.subscribe(b"news");
+        second.subscribe(b"news");
+        second.psubscribe(b"n*");
+
+        assert_eq!(3, pubsub.publish(b"news", b"hello"));
+        assert_eq!(1, pubsub.publish(b"nothing", b"hello"));
+
+        let message = first.receiver().unwrap().try_recv().unwrap();
+        assert_eq!(
+            vec![message_frame(b"news", b"hello")],
+            first.frames(&message)
+        );
+        assert_eq!(
+            vec![
+                message_frame(b"news", b"hello"),
+                pmessage_frame(b"n*", b"news", b"hello")
+            ],
+            second.frames(&message)
+        );
+
+        drop(second);
+        assert_eq!(1, pubsub.publish(b"news", b"hello"));
+
+        first.unsubscribe(b"news");
+        assert!(!first.is_active());
+        assert_eq!(0, pubsub.publish(b"news", b"hello"));
+    }
+    #[test]
+    fn test_close_delivers_published_messages_first() {
+        let pubsub = Arc::new(PubSub::default());
+        let mut subscription = Subscription::new(Arc::clone(&pubsub));
+
+        subscription.subscribe(b"news");
+        assert_eq!(1, pubsub.publish(b"news", b"hello"));
+
+        pubsub.close();
+        assert_eq!(0, pubsub.publish(b"news", b"late"));
+
+        let receiver = subscription.receiver().unwrap();
+        let message = receiver.try_recv().unwrap();
+        assert_eq!(b"hello".as_slice(), &*message.payload);
+        assert_eq!(Err(TryRecvError::Closed), receiver.try_recv().map(|_| ()));
+
+        let mut late = Subscription::new(Arc::clone(&pubsub));
+        late.subscribe(b"news");
+        assert!(!late.is_active());
+    }
+}
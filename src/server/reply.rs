@@ -0,0 +1,47 @@
+use std::borrow::Cow;
+use std::error::Error;
+
+use crate::resp::Value;
+
+/// What running a command produced for its client, before anything is
+/// written. Turning it into bytes is left to the connection.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandReply<'a> {
+    /// The `+OK` status reply.
+    Ok,
+    Value(Value<'a>),
+    Error(Cow<'a, str>),
+    /// The command was queued in a transaction rather than run. Nothing
+    /// replies with it until MULTI is supported.
+    #[allow(dead_code)]
+    Queued,
+    /// Nothing is sent back, as with a successful SHUTDOWN.
+    NoReply,
+    /// Several frames sent back to back, as when a push frame precedes the
+    /// reply.
+    Frames(Vec<Value<'a>>),
+    /// An array of `count` picks, with repetition, among `choices`, as
+    /// HRANDFIELD with a negative count replies. A choice of several values,
+    /// such as a field with its value, is nested in RESP3 and flattened in
+    /// RESP2. The picks are made as the reply is written, so a huge count
+    /// never has to fit in memory.
+    Sample {
+        choices: Vec<Vec<Value<'a>>>,
+        count: u64,
+    },
+}
+
+impl CommandReply<'_> {
+    pub fn error(err: &(dyn Error + Send + Sync)) -> Self {
+        CommandReply::Error(Cow::Owned(err.to_string()))
+    }
+}
+
+impl<'a> From<Value<'a>> for CommandReply<'a> {
+    fn from(value: Value<'a>) -> Self {
+        match value {
+            Value::Error(message) => CommandReply::Error(message),
+            value => CommandReply::Value(value),
+        }
+    }
+}
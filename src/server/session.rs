@@ -0,0 +1,2442 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use super::auth::{authenticate, AuthError};
+use super::clients::Client;
+use super::commands::{command_names, command_spec, CommandSpec, COMMAND_TABLE};
+use super::config::{matching_parameters, parameter, parse_memory, ConfigError};
+use super::metrics::Metrics;
+use super::pubsub::{pong_frame, PubSub, Subscription};
+use super::reply::CommandReply;
+use super::{Config, Shutdown};
+use crate::database::{format_float, format_score, Value as DatabaseValue};
+use crate::resp::Value;
+use crate::{
+    bulk_string, Command, Credentials, Databases, FieldValue, HrandfieldResult, Lookup, LposResult,
+    MaxMemoryPolicy, Pair, PopResult, ScoredMember, TypeError, COMMAND_KEYWORDS,
+};
+
+const PERSISTENCE_DISABLED: Value<'static> = Value::Error(Cow::Borrowed(
+    "ERR persistence is disabled, no snapshot path is configured",
+));
+
+/// The double DEBUG PROTOCOL replies with, which Redis' tests expect as is.
+#[allow(clippy::approx_constant)]
+const SAMPLE_DOUBLE: f64 = 3.141;
+
+/// The reply GET gives for a stored string, or `None` for any other type.
+fn string_value(value: &DatabaseValue) -> Option<Value<'static>> {
+    Some(match value {
+        DatabaseValue::String(val) => Value::BulkString(Cow::Owned(val.as_bytes().to_vec())),
+        DatabaseValue::Bytes(val) => Value::BulkString(Cow::Owned(val.clone())),
+        DatabaseValue::Integer(val) => Value::BulkString(Cow::Owned(val.to_string().into_bytes())),
+        DatabaseValue::Null => Value::Null,
+        _ => return None,
+    })
+}
+
+fn string_reply(value: Option<DatabaseValue>) -> Result<Value<'static>, TypeError> {
+    match value {
+        Some(value) => string_value(&value).ok_or(TypeError::WrongType),
+        None => Ok(Value::Null),
+    }
+}
+
+fn list_reply(values: Vec<Box<[u8]>>) -> Value<'static> {
+    let values: Box<[Value]> = values
+        .into_iter()
+        .map(|value| Value::BulkString(Cow::Owned(value.into_vec())))
+        .collect();
+
+    Value::Array(values)
+}
+
+/// Fields with their values as a map, which RESP2 clients get as one flat
+/// array.
+fn map_reply(pairs: Vec<FieldValue>) -> Value<'static> {
+    let pairs: Box<[(Value, Value)]> = pairs
+        .into_iter()
+        .map(|(field, value)| (bulk_reply(field), bulk_reply(value)))
+        .collect();
+
+    Value::Map(pairs)
+}
+
+fn pairs_reply(pairs: Vec<FieldValue>) -> Value<'static> {
+    let values: Box<[Value]> = pairs
+        .into_iter()
+        .flat_map(|(field, value)| [field, value])
+        .map(|value| Value::BulkString(Cow::Owned(value.into_vec())))
+        .collect();
+
+    Value::Array(values)
+}
+
+/// The members, each followed by its score when `with_scores` is set, as one
+/// flat array.
+fn scored_reply(members: Vec<ScoredMember>, with_scores: bool) -> Value<'static> {
+    let values: Box<[Value]> = members
+        .into_iter()
+        .flat_map(|(member, score)| {
+            let score = with_scores.then(|| format_score(score).into_bytes());
+            [Some(member.into_vec()), score]
+        })
+        .flatten()
+        .map(|value| Value::BulkString(Cow::Owned(value)))
+        .collect();
+
+    Value::Array(values)
+}
+
+fn pop_reply(result: PopResult) -> Value<'static> {
+    match result {
+        PopResult::Single(Some(value)) => bulk_reply(value),
+        PopResult::Single(None) => Value::Null,
+        PopResult::Multi(Some(values)) => list_reply(values),
+        PopResult::Multi(None) => Value::NullArray,
+    }
+}
+
+/// The cursor to continue from, followed by what was found, as SCAN and
+/// HSCAN reply.
+fn scan_reply(next: u64, found: Value<'static>) -> Value<'static> {
+    Value::Array(Box::new([
+        Value::BulkString(Cow::Owned(next.to_string().into_bytes())),
+        found,
+    ]))
+}
+
+fn bulk_reply(value: Box<[u8]>) -> Value<'static> {
+    Value::BulkString(Cow::Owned(value.into_vec()))
+}
+
+/// CONFIG GET: each known parameter matching one of `patterns`, with its
+/// value.
+fn config_get(patterns: &[Cow<'_, [u8]>], databases: &Databases) -> Value<'static> {
+    let (bytes, policy) = databases[0].max_memory();
+
+    let mut names: Vec<_> = patterns
+        .iter()
+        .flat_map(|pattern| matching_parameters(pattern))
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    let pairs = names
+        .into_iter()
+        .map(|name| {
+            let value = match name {
+                "maxmemory" => bytes.to_string(),
+                _ => policy.name().to_string(),
+            };
+
+            (
+                Value::BulkString(Cow::Borrowed(name.as_bytes())),
+                Value::BulkString(Cow::Owned(value.into_bytes())),
+            )
+        })
+        .collect();
+
+    Value::Map(pairs)
+}
+
+/// CONFIG SET: every value is checked before anything changes, so one bad
+/// value leaves all the parameters as they were.
+async fn config_set(pairs: &[Pair<'_>], databases: &Databases) -> Result<(), ConfigError> {
+    let (mut bytes, mut policy) = databases[0].max_memory();
+
+    for (name, value) in pairs {
+        match parameter(name)? {
+            "maxmemory" => {
+                bytes = parse_memory(value).ok_or_else(|| ConfigError::InvalidValue {
+                    name: "maxmemory",
+                    reason: "argument must be a memory value".into(),
+                })?
+            }
+            name => {
+                policy = std::str::from_utf8(value)
+                    .ok()
+                    .and_then(MaxMemoryPolicy::from_name)
+                    .ok_or_else(|| ConfigError::InvalidValue {
+                        name,
+                        reason: format!(
+                            "argument(s) must be one of the following: {}",
+                            MaxMemoryPolicy::names()
+                        ),
+                    })?
+            }
+        }
+    }
+
+    databases.set_max_memory(bytes, policy).await;
+    Ok(())
+}
+
+/// Everything a command can act on besides the keyspace: the server's
+/// shared state, the connection's subscriptions and its selected database.
+/// Commands run against it without touching the socket, which is left to
+/// the [`Handler`](super::handler::Handler).
+#[derive(Debug)]
+pub struct Session {
+    pub(super) config: Arc<Config>,
+    pub(super) shutdown: Arc<Shutdown>,
+    pub(super) pubsub: Arc<PubSub>,
+    pub(super) subscription: Subscription,
+    pub(super) metrics: Arc<Metrics>,
+    /// The index of the database the connection's commands run against.
+    pub(super) db: usize,
+    pub(super) client: Client,
+    /// Whether the connection may run commands, which without a
+    /// `requirepass` it always may.
+    pub(super) authenticated: bool,
+    /// The RESP version HELLO switched to. Replies are built in their RESP3
+    /// shape and `Handler::write_value` turns them back into RESP2 unless
+    /// it is 3.
+    pub(super) protocol: i64,
+}
+
+impl Session {
+    pub fn new(
+        config: Arc<Config>,
+        shutdown: Arc<Shutdown>,
+        pubsub: Arc<PubSub>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self {
+            authenticated: config.requirepass.is_none(),
+            config,
+            shutdown,
+            subscription: Subscription::new(Arc::clone(&pubsub)),
+            pubsub,
+            metrics,
+            db: 0,
+            client: Client::default(),
+            protocol: 2,
+        }
+    }
+
+    /// Runs HELLO: checks the protocol version, authenticates with the
+    /// `AUTH` credentials and names the connection, in that order, before
+    /// switching protocols. Nothing changes if any step fails.
+    fn hello<'b>(
+        &mut self,
+        protocol: Option<i64>,
+        auth: Option<Credentials<'_>>,
+        name: Option<Cow<'_, [u8]>>,
+    ) -> CommandReply<'b> {
+        if protocol.is_some_and(|protocol| !(2..=3).contains(&protocol)) {
+            return CommandReply::Error(Cow::Borrowed("NOPROTO unsupported protocol version"));
+        }
+
+        if let Some((username, password)) = auth {
+            if let Err(err) = authenticate(&self.config, Some(&username), &password) {
+                return CommandReply::error(&err);
+            }
+
+            self.authenticated = true;
+        }
+
+        if !self.authenticated {
+            return CommandReply::error(&AuthError::HelloRequired);
+        }
+
+        if let Some(name) = name {
+            if let Err(err) = self.client.set_name(&name) {
+                return CommandReply::error(&err);
+            }
+        }
+
+        self.protocol = protocol.unwrap_or(self.protocol);
+
+        let pairs = [
+            ("server", bulk_string!(b"redis")),
+            (
+                "version",
+                bulk_string!(env!("CARGO_PKG_VERSION").as_bytes()),
+            ),
+            ("proto", Value::Integer(self.protocol)),
+            ("id", Value::Integer(self.client.id() as i64)),
+            ("mode", bulk_string!(b"standalone")),
+            ("role", bulk_string!(b"master")),
+            ("modules", Value::Array(Box::new([]))),
+        ]
+        .map(|(field, value)| (bulk_string!(field.as_bytes()), value));
+
+        Value::Map(Box::new(pairs)).into()
+    }
+
+    /// Runs DEBUG PROTOCOL, replying with the sample of the RESP type `name`
+    /// that Redis replies with, so clients can check they decode it.
+    fn debug_protocol(&self, name: &str) -> CommandReply<'static> {
+        let numbers = || (0..3).map(Value::Integer);
+
+        let value = match name.to_ascii_lowercase().as_str() {
+            "string" => bulk_string!(b"Hello World"),
+            "integer" => Value::Integer(12345),
+            "double" => Value::Double(SAMPLE_DOUBLE),
+            "bignum" => Value::BigNumber(Cow::Borrowed("1234567999999999999999999999999999999")),
+            "null" => Value::Null,
+            "array" => Value::Array(numbers().collect()),
+            "set" => Value::Set(numbers().collect()),
+            "map" => Value::Map(
+                numbers()
+                    .zip([false, true, false].map(Value::Boolean))
+                    .collect(),
+            ),
+            "verbatim" => Value::Verbatim {
+                format: "txt",
+                text: Cow::Borrowed(b"This is a verbatim\nstring"),
+            },
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            "err" => Value::Error(Cow::Borrowed("ERR This is an error reply")),
+            // Attributes and pushes are not replies of their own, so a reply
+            // follows them. RESP2 has neither.
+            "attrib" => {
+                let reply = bulk_string!(b"Some real reply following the attribute");
+
+                if self.protocol == 2 {
+                    return reply.into();
+                }
+
+                let attribute = (
+                    bulk_string!(b"key-popularity"),
+                    Value::Array(Box::new([bulk_string!(b"key:123"), Value::Integer(90)])),
+                );
+                return CommandReply::Frames(vec![Value::Attribute(Box::new([attribute])), reply]);
+            }
+            "push" => {
+                if self.protocol == 2 {
+                    return CommandReply::Error(Cow::Borrowed(
+                        "ERR RESP2 is not supported by this command",
+                    ));
+                }
+
+                return CommandReply::Frames(vec![
+                    Value::Push(Box::new([
+                        bulk_string!(b"server-cpu-usage"),
+                        Value::Integer(42),
+                    ])),
+                    bulk_string!(b"Some real reply following the push reply"),
+                ]);
+            }
+            _ => {
+                return CommandReply::Error(Cow::Borrowed(
+                    "ERR Wrong protocol type name. Please use one of the following: \
+                     string|integer|double|bignum|null|array|set|map|attrib|push|verbatim|true|false|err",
+                ))
+            }
+        };
+
+        value.into()
+    }
+
+    /// Runs `command` against the selected database of `databases` and the
+    /// connection's state. The pub/sub subscription commands, which answer
+    /// with a frame per channel, are left to the connection.
+    pub async fn execute<'b>(
+        &mut self,
+        command: Command<'b>,
+        databases: &Databases,
+    ) -> CommandReply<'b> {
+        // A subscribed RESP2 connection only carries pub/sub traffic.
+        if self.subscription.is_active() {
+            return match command {
+                Command::Ping => pong_frame().into(),
+                _ => CommandReply::Error(Cow::Borrowed(
+                    "ERR only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING are allowed in this context",
+                )),
+            };
+        }
+
+        if command.uses_memory() {
+            let reclaimed = databases
+                .reclaim_memory(|db, key| {
+                    self.metrics.record_eviction();
+                    self.pubsub.notify_keyspace_event("evicted", db, key);
+                })
+                .await;
+
+            if let Err(err) = reclaimed {
+                return CommandReply::error(&err);
+            }
+        }
+
+        let map = &databases[self.db];
+
+        match command {
+            Command::Ping => Value::SimpleString(Cow::Borrowed("PONG")).into(),
+            Command::Echo(val) => Value::SimpleString(val).into(),
+            Command::CommandInfo(names) if !names.is_empty() => {
+                let specs: Box<[Value]> = names
+                    .iter()
+                    .map(|name| command_spec(name).map_or(Value::NullArray, CommandSpec::info))
+                    .collect();
+
+                Value::Array(specs).into()
+            }
+            Command::Command | Command::CommandInfo(_) => {
+                let specs: Box<[Value]> = COMMAND_TABLE.iter().map(CommandSpec::info).collect();
+                Value::Array(specs).into()
+            }
+            Command::CommandCount => Value::Integer(COMMAND_KEYWORDS.len() as i64).into(),
+            Command::CommandDocs(names) => {
+                let specs: Vec<&CommandSpec> = if names.is_empty() {
+                    COMMAND_TABLE.iter().collect()
+                } else {
+                    names.iter().filter_map(|name| command_spec(name)).collect()
+                };
+
+                // No documentation is kept, so every command has an empty
+                // set of doc fields.
+                let docs: Box<[Value]> = specs
+                    .into_iter()
+                    .flat_map(|spec| {
+                        [
+                            bulk_string!(spec.name.as_bytes()),
+                            Value::Array(Box::new([])),
+                        ]
+                    })
+                    .collect();
+
+                Value::Array(docs).into()
+            }
+            Command::CommandList(filter) => {
+                let names: Box<[Value]> = command_names(filter.as_ref())
+                    .into_iter()
+                    .map(|name| Value::BulkString(Cow::Borrowed(name.as_bytes())))
+                    .collect();
+
+                CommandReply::Value(Value::Array(names))
+            }
+            Command::Get(key) => match map.lookup(&key, string_value).await {
+                Lookup::Found(value) => value.into(),
+                Lookup::Missing => Value::Null.into(),
+                Lookup::WrongType => CommandReply::error(&TypeError::WrongType),
+            },
+            Command::Set {
+                key,
+                value,
+                options,
+            } => match map
+                .set_with(key, value, options)
+                .await
+                .and_then(|(written, found)| Ok((written, string_reply(found)?)))
+            {
+                Ok((_, found)) if options.get => found.into(),
+                Ok((true, _)) => CommandReply::Ok,
+                Ok((false, _)) => Value::Null.into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LPush { key, values } => match map.lpush(&key, &values).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::RPush { key, values } => match map.rpush(&key, &values).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LPushX { key, values } => match map.lpushx(&key, &values).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::RPushX { key, values } => match map.rpushx(&key, &values).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LRange { key, start, stop } => match map.lrange(&key, start, stop).await {
+                Ok(values) => list_reply(values).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LLen(key) => match map.llen(&key).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LTrim { key, start, stop } => match map.ltrim(&key, start, stop).await {
+                Ok(()) => CommandReply::Ok,
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LPos {
+                key,
+                element,
+                rank,
+                count,
+                maxlen,
+            } => match map.lpos(&key, &element, rank, count, maxlen).await {
+                Ok(LposResult::Single(Some(idx))) => Value::Integer(idx as i64).into(),
+                Ok(LposResult::Single(None)) => Value::Null.into(),
+                Ok(LposResult::Multi(indices)) => {
+                    let indices = indices
+                        .into_iter()
+                        .map(|idx| Value::Integer(idx as i64))
+                        .collect();
+
+                    Value::Array(indices).into()
+                }
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LMPop { keys, side, count } => match map.lmpop(&keys, side, count).await {
+                Ok(Some((key, elements))) => {
+                    let elements = elements
+                        .into_iter()
+                        .map(|element| Value::BulkString(Cow::Owned(element.into_vec())))
+                        .collect();
+
+                    Value::Array(Box::new([
+                        Value::BulkString(Cow::Owned(key.into_vec())),
+                        Value::Array(elements),
+                    ]))
+                    .into()
+                }
+                Ok(None) => Value::NullArray.into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::ZMPop { keys, end, count } => match map.zmpop(&keys, end, count).await {
+                Ok(Some((key, members))) => {
+                    let members = members
+                        .into_iter()
+                        .map(|(member, score)| {
+                            Value::Array(Box::new([
+                                Value::BulkString(Cow::Owned(member.into_vec())),
+                                Value::BulkString(Cow::Owned(format_score(score).into_bytes())),
+                            ]))
+                        })
+                        .collect();
+
+                    Value::Array(Box::new([
+                        Value::BulkString(Cow::Owned(key.into_vec())),
+                        Value::Array(members),
+                    ]))
+                    .into()
+                }
+                Ok(None) => Value::NullArray.into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LRem {
+                key,
+                count,
+                element,
+            } => match map.lrem(&key, count, &element).await {
+                Ok(removed) => Value::Integer(removed as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LIndex { key, index } => match map.lindex(&key, index).await {
+                Ok(Some(value)) => bulk_reply(value).into(),
+                Ok(None) => Value::Null.into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::BPop {
+                keys,
+                side,
+                timeout,
+            } => match map.blocking_pop(&keys, side, timeout).await {
+                Ok(Some((key, value))) => Value::Array(Box::new([
+                    Value::BulkString(Cow::Owned(key.into_vec())),
+                    Value::BulkString(Cow::Owned(value.into_vec())),
+                ]))
+                .into(),
+                Ok(None) => Value::NullArray.into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LMove {
+                source,
+                destination,
+                from,
+                to,
+            } => match map.lmove(&source, &destination, from, to).await {
+                Ok(Some(value)) => bulk_reply(value).into(),
+                Ok(None) => Value::Null.into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::BLMove {
+                source,
+                destination,
+                from,
+                to,
+                timeout,
+            } => match map
+                .blocking_lmove(&source, &destination, from, to, timeout)
+                .await
+            {
+                Ok(Some(value)) => bulk_reply(value).into(),
+                Ok(None) => Value::Null.into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LSet {
+                key,
+                index,
+                element,
+            } => match map.lset(&key, index, &element).await {
+                Ok(()) => CommandReply::Ok,
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LInsert {
+                key,
+                position,
+                pivot,
+                element,
+            } => match map.linsert(&key, position, &pivot, &element).await {
+                Ok(len) => Value::Integer(len).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::LPop { key, count } => match map.lpop(&key, count).await {
+                Ok(result) => pop_reply(result).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::RPop { key, count } => match map.rpop(&key, count).await {
+                Ok(result) => pop_reply(result).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            // Like Redis, a successful SHUTDOWN has no reply: the connection
+            // is closed once the server has finished shutting down.
+            Command::Shutdown => {
+                self.shutdown.trigger();
+                CommandReply::NoReply
+            }
+            Command::Save => match &self.config.rdb_path {
+                Some(path) => match databases.save(path).await {
+                    Ok(()) => CommandReply::Ok,
+                    Err(err) => CommandReply::error(&err),
+                },
+                None => PERSISTENCE_DISABLED.into(),
+            },
+            Command::BgSave => match &self.config.rdb_path {
+                Some(path) => match databases.bgsave(path.clone()).await {
+                    Ok(()) => {
+                        Value::SimpleString(Cow::Borrowed("Background saving started")).into()
+                    }
+                    Err(err) => CommandReply::error(&err),
+                },
+                None => PERSISTENCE_DISABLED.into(),
+            },
+            Command::Select(index) => match databases.checked_index(index) {
+                Ok(index) => {
+                    self.db = index;
+                    CommandReply::Ok
+                }
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::SwapDb(a, b) => match databases.swap(a, b).await {
+                Ok(()) => CommandReply::Ok,
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::ClientSetName(name) => match self.client.set_name(&name) {
+                Ok(()) => CommandReply::Ok,
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::ClientGetName => match self.client.name() {
+                Some(name) => bulk_reply(name).into(),
+                None => Value::Null.into(),
+            },
+            Command::ClientId => Value::Integer(self.client.id() as i64).into(),
+            Command::Auth { username, password } => {
+                match authenticate(&self.config, username.as_deref(), &password) {
+                    Ok(()) => {
+                        self.authenticated = true;
+                        CommandReply::Ok
+                    }
+                    Err(err) => CommandReply::error(&err),
+                }
+            }
+            Command::Hello {
+                protocol,
+                auth,
+                name,
+            } => self.hello(protocol, auth, name),
+            Command::ClientList => {
+                let list = self.client.clients().list();
+                Value::BulkString(Cow::Owned(list.into_bytes())).into()
+            }
+            Command::Keys(pattern) => list_reply(map.keys(&pattern).await).into(),
+            Command::Scan(options) => {
+                let pattern = options.pattern.as_deref();
+                let (next, keys) = map.scan(options.cursor, pattern, options.count).await;
+
+                scan_reply(next, list_reply(keys)).into()
+            }
+            Command::HScan {
+                key,
+                options,
+                no_values,
+            } => {
+                let pattern = options.pattern.as_deref();
+
+                match map
+                    .hscan(&key, options.cursor, pattern, options.count)
+                    .await
+                {
+                    Ok((next, pairs)) if no_values => {
+                        let fields = pairs.into_iter().map(|(field, _)| field).collect();
+                        scan_reply(next, list_reply(fields)).into()
+                    }
+                    Ok((next, pairs)) => scan_reply(next, pairs_reply(pairs)).into(),
+                    Err(err) => CommandReply::error(&err),
+                }
+            }
+            Command::HSet { key, pairs } => match map.hset(&key, &pairs).await {
+                Ok(created) => Value::Integer(created as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HSetNx { key, field, value } => match map.hsetnx(&key, &field, &value).await {
+                Ok(set) => Value::Integer(set as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HGet { key, field } => match map.hget(&key, &field).await {
+                Ok(Some(value)) => bulk_reply(value).into(),
+                Ok(None) => Value::Null.into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HGetAll(key) => match map.hgetall(&key).await {
+                Ok(pairs) => map_reply(pairs).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HKeys(key) => match map.hkeys(&key).await {
+                Ok(fields) => list_reply(fields).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HVals(key) => match map.hvals(&key).await {
+                Ok(values) => list_reply(values).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HMGet { key, fields } => match map.hmget(&key, &fields).await {
+                Ok(values) => {
+                    let values: Box<[Value]> = values
+                        .into_iter()
+                        .map(|value| value.map_or(Value::Null, bulk_reply))
+                        .collect();
+
+                    Value::Array(values).into()
+                }
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HDel { key, fields } => match map.hdel(&key, &fields).await {
+                Ok(removed) => Value::Integer(removed as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HExists { key, field } => match map.hexists(&key, &field).await {
+                Ok(exists) => Value::Integer(exists as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HLen(key) => match map.hlen(&key).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HRandField {
+                key,
+                count,
+                with_values,
+            } => match map.hrandfield(&key, count, with_values).await {
+                Ok(HrandfieldResult::Single(field)) => field.map_or(Value::Null, bulk_reply).into(),
+                Ok(HrandfieldResult::Distinct(fields)) if with_values => map_reply(
+                    fields
+                        .into_iter()
+                        .filter_map(|(field, value)| Some((field, value?)))
+                        .collect(),
+                )
+                .into(),
+                Ok(HrandfieldResult::Distinct(fields)) => {
+                    list_reply(fields.into_iter().map(|(field, _)| field).collect()).into()
+                }
+                Ok(HrandfieldResult::Repeated { choices, .. }) if choices.is_empty() => {
+                    Value::Array(Box::new([])).into()
+                }
+                // Fields may repeat, as many times as asked, so they are
+                // picked while the reply is written instead of all at once.
+                Ok(HrandfieldResult::Repeated { choices, count }) => CommandReply::Sample {
+                    choices: choices
+                        .into_iter()
+                        .map(|(field, value)| {
+                            let mut pick = vec![bulk_reply(field)];
+                            pick.extend(value.map(bulk_reply));
+                            pick
+                        })
+                        .collect(),
+                    count,
+                },
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HStrLen { key, field } => match map.hstrlen(&key, &field).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::HIncrBy { key, field, delta } => {
+                match map.hincrby(&key, &field, delta).await {
+                    Ok(val) => Value::Integer(val).into(),
+                    Err(err) => CommandReply::error(&err),
+                }
+            }
+            Command::HIncrByFloat { key, field, delta } => {
+                match map.hincrbyfloat(&key, &field, delta).await {
+                    Ok(val) => Value::BulkString(Cow::Owned(format_float(val).into_bytes())).into(),
+                    Err(err) => CommandReply::error(&err),
+                }
+            }
+            Command::SAdd { key, members } => match map.sadd(&key, &members).await {
+                Ok(added) => Value::Integer(added as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::SRem { key, members } => match map.srem(&key, &members).await {
+                Ok(removed) => Value::Integer(removed as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::SMembers(key) => match map.smembers(&key).await {
+                Ok(members) => list_reply(members).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::SIsMember { key, member } => match map.sismember(&key, &member).await {
+                Ok(found) => Value::Integer(found as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::SMIsMember { key, members } => match map.smismember(&key, &members).await {
+                Ok(found) => Value::Array(
+                    found
+                        .into_iter()
+                        .map(|found| Value::Integer(found as i64))
+                        .collect(),
+                )
+                .into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::SCard(key) => match map.scard(&key).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::ZAdd { key, members } => match map.zadd(&key, &members).await {
+                Ok(added) => Value::Integer(added as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::ZScore { key, member } => match map.zscore(&key, &member).await {
+                Ok(Some(score)) => {
+                    Value::BulkString(Cow::Owned(format_score(score).into_bytes())).into()
+                }
+                Ok(None) => Value::Null.into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::ZRange {
+                key,
+                start,
+                stop,
+                with_scores,
+            } => match map.zrange(&key, start, stop).await {
+                Ok(members) => scored_reply(members, with_scores).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::ZRangeByScore {
+                key,
+                min,
+                max,
+                with_scores,
+                limit,
+            } => match map.zrangebyscore(&key, min, max, limit).await {
+                Ok(members) => scored_reply(members, with_scores).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::ZCount { key, min, max } => match map.zcount(&key, min, max).await {
+                Ok(count) => Value::Integer(count as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::ZCard(key) => match map.zcard(&key).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::SetAlgebra { op, keys } => match map.combine_sets(op, &keys).await {
+                Ok(members) => list_reply(members).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::SetAlgebraStore {
+                op,
+                destination,
+                keys,
+            } => match map.store_combined_sets(op, &destination, &keys).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::DebugNoop => CommandReply::Ok,
+            Command::DebugProtocol(name) => self.debug_protocol(&name),
+            // Only this connection waits, unlike in Redis where the whole
+            // server does.
+            Command::DebugSleep(duration) => {
+                tokio::time::sleep(duration).await;
+                CommandReply::Ok
+            }
+            Command::DebugSaveDelay(delay) => {
+                databases.set_save_delay(delay);
+                CommandReply::Ok
+            }
+            Command::DebugSetActiveExpire(enabled) => {
+                map.set_active_expire(enabled);
+                CommandReply::Ok
+            }
+            Command::DebugRefused(subcommand) => {
+                let err = format!("ERR DEBUG {} is not allowed", subcommand.to_uppercase());
+                Value::Error(Cow::Owned(err)).into()
+            }
+            Command::ObjectRefCount(key) => match map.refcount(&key).await {
+                Some(refcount) => Value::Integer(refcount as i64).into(),
+                None => Value::Null.into(),
+            },
+            Command::ObjectEncoding(key) => match map.encoding(&key).await {
+                Some(encoding) => Value::BulkString(Cow::Borrowed(encoding.as_bytes())).into(),
+                None => Value::Null.into(),
+            },
+            Command::Append { key, value } => match map.append(&key, &value).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::StrLen(key) => match map.strlen(&key).await {
+                Ok(len) => Value::Integer(len as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::Incr(key) => match map.incr_by(&key, 1).await {
+                Ok(val) => Value::Integer(val).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::GetRange { key, start, end } => match map.getrange(&key, start, end).await {
+                Ok(range) => Value::BulkString(Cow::Owned(range)).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::SetRange { key, offset, value } => {
+                match map.setrange(&key, offset, &value).await {
+                    Ok(len) => Value::Integer(len as i64).into(),
+                    Err(err) => CommandReply::error(&err),
+                }
+            }
+            Command::GetBit { key, offset } => match map.getbit(&key, offset).await {
+                Ok(bit) => Value::Integer(bit as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::SetBit { key, offset, value } => match map.setbit(&key, offset, value).await {
+                Ok(old) => Value::Integer(old as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::BitCount { key, range } => match map.bitcount(&key, range).await {
+                Ok(count) => Value::Integer(count as i64).into(),
+                Err(err) => CommandReply::error(&err),
+            },
+            Command::Info(sections) => {
+                let all = sections.is_empty()
+                    || sections.iter().any(|section| {
+                        section.eq_ignore_ascii_case(b"all")
+                            || section.eq_ignore_ascii_case(b"everything")
+                    });
+
+                let wants = |name: &str| {
+                    all || sections
+                        .iter()
+                        .any(|section| section.eq_ignore_ascii_case(name.as_bytes()))
+                };
+
+                let mut info = Vec::new();
+
+                if wants("stats") {
+                    info.push(self.metrics.stats());
+                }
+
+                if wants("commandstats") {
+                    info.push(self.metrics.commandstats());
+                }
+
+                if wants("errorstats") {
+                    info.push(self.metrics.errorstats());
+                }
+
+                let info = info.join("\r\n");
+
+                Value::BulkString(Cow::Owned(info.into_bytes())).into()
+            }
+            Command::Subscribe(_)
+            | Command::PSubscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::PUnsubscribe(_) => unreachable!("answered by handle_command"),
+            Command::Publish { channel, message } => {
+                let receivers = self.pubsub.publish(&channel, &message);
+                Value::Integer(receivers as i64).into()
+            }
+            Command::Unlink(keys) => {
+                let count = map.unlink(&keys).await;
+                Value::Integer(count as i64).into()
+            }
+            Command::Del(keys) => {
+                let count = map.del(&keys).await;
+                Value::Integer(count as i64).into()
+            }
+            Command::FlushDb { lazy } => {
+                map.flush(lazy).await;
+                CommandReply::Ok
+            }
+            Command::ConfigGet(patterns) => config_get(&patterns, databases).into(),
+            Command::ConfigSet(pairs) => match config_set(&pairs, databases).await {
+                Ok(()) => CommandReply::Ok,
+                Err(err) => CommandReply::error(&err),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::parser::Parser;
+    use crate::resp::{OK, QUEUED};
+    use crate::server::pubsub::{message_frame, Subscription};
+    use crate::Database;
+    use crate::MaxMemoryPolicy;
+    use crate::SetOptions;
+
+    fn session() -> Session {
+        session_with(Config::default())
+    }
+
+    fn session_with(config: Config) -> Session {
+        Session::new(
+            config.into(),
+            Shutdown::new(Duration::from_secs(1)).into(),
+            PubSub::default().into(),
+            Metrics::default().into(),
+        )
+    }
+
+    fn command(args: &[&[u8]]) -> Vec<u8> {
+        let values: Box<[Value]> = args
+            .iter()
+            .map(|arg| Value::BulkString(Cow::Borrowed(*arg)))
+            .collect();
+
+        let mut output = Vec::new();
+        Value::Array(values).serialize(&mut output);
+        output
+    }
+
+    /// Runs the requests in `input` through a fresh session, returning their
+    /// replies serialized as the connection would write them.
+    async fn execute(databases: &Databases, input: &[u8]) -> Vec<u8> {
+        execute_with(databases, Config::default(), input).await
+    }
+
+    async fn execute_with(databases: &Databases, config: Config, input: &[u8]) -> Vec<u8> {
+        let mut session = session_with(config);
+        let mut input = input;
+        let mut output = Vec::new();
+
+        while !input.is_empty() {
+            let (mut parser, consumed) = Parser::parse_frame(input).unwrap();
+            let reply = match parser.command() {
+                Ok(command) => session.execute(command, databases).await,
+                Err(err) => CommandReply::error(&err),
+            };
+
+            let frames = match reply {
+                CommandReply::Ok => {
+                    output.extend_from_slice(OK);
+                    Vec::new()
+                }
+                CommandReply::Queued => {
+                    output.extend_from_slice(QUEUED);
+                    Vec::new()
+                }
+                CommandReply::Value(value) => vec![value],
+                CommandReply::Error(message) => vec![Value::Error(message)],
+                CommandReply::NoReply => Vec::new(),
+                CommandReply::Frames(frames) => frames,
+                CommandReply::Sample { .. } => unreachable!("samples are written by the handler"),
+            };
+
+            for frame in frames {
+                let frame = if session.protocol == 3 {
+                    frame
+                } else {
+                    frame.into_resp2()
+                };
+
+                frame.serialize(&mut output);
+            }
+
+            input = &input[consumed..];
+        }
+
+        output
+    }
+
+    #[tokio::test]
+    async fn test_execute_set_then_get() {
+        let database = Databases::from(Database::new());
+        let mut session = session();
+        let value = crate::Value::BulkString(Cow::Borrowed(b"value"));
+
+        let reply = session
+            .execute(
+                Command::Set {
+                    key: Cow::Borrowed(b"key"),
+                    value: &value,
+                    options: SetOptions::default(),
+                },
+                &database,
+            )
+            .await;
+        assert_eq!(CommandReply::Ok, reply);
+
+        let reply = session
+            .execute(Command::Get(Cow::Borrowed(b"key")), &database)
+            .await;
+        assert_eq!(
+            CommandReply::Value(Value::BulkString(Cow::Borrowed(b"value"))),
+            reply
+        );
+
+        let reply = session
+            .execute(Command::Get(Cow::Borrowed(b"missing")), &database)
+            .await;
+        assert_eq!(CommandReply::Value(Value::Null), reply);
+
+        let reply = session.execute(Command::Ping, &database).await;
+        assert_eq!(
+            CommandReply::Value(Value::SimpleString(Cow::Borrowed("PONG"))),
+            reply
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_returns_replies() {
+        let database = Databases::from(Database::new());
+        let mut session = session();
+
+        let reply = session
+            .execute(
+                Command::RPush {
+                    key: Cow::Borrowed(b"list"),
+                    values: Box::new([Cow::Borrowed(b"a"), Cow::Borrowed(b"b")]),
+                },
+                &database,
+            )
+            .await;
+        assert_eq!(CommandReply::Value(Value::Integer(2)), reply);
+
+        let reply = session
+            .execute(
+                Command::LRange {
+                    key: Cow::Borrowed(b"list"),
+                    start: 0,
+                    stop: -1,
+                },
+                &database,
+            )
+            .await;
+        assert_eq!(
+            CommandReply::Value(Value::Array(Box::new([
+                Value::BulkString(Cow::Borrowed(b"a")),
+                Value::BulkString(Cow::Borrowed(b"b")),
+            ]))),
+            reply
+        );
+
+        let reply = session
+            .execute(Command::Incr(Cow::Borrowed(b"list")), &database)
+            .await;
+        assert_eq!(
+            CommandReply::Error(Cow::Borrowed(
+                "WRONGTYPE Operation against a key holding the wrong kind of value"
+            )),
+            reply
+        );
+
+        let reply = session
+            .execute(
+                Command::LTrim {
+                    key: Cow::Borrowed(b"list"),
+                    start: 0,
+                    stop: 0,
+                },
+                &database,
+            )
+            .await;
+        assert_eq!(CommandReply::Ok, reply);
+
+        let reply = session
+            .execute(Command::Echo(Cow::Borrowed("hello")), &database)
+            .await;
+        assert_eq!(
+            CommandReply::Value(Value::SimpleString(Cow::Borrowed("hello"))),
+            reply
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_shutdown_has_no_reply() {
+        let database = Databases::from(Database::new());
+        let mut session = session();
+
+        let reply = session.execute(Command::Shutdown, &database).await;
+
+        assert_eq!(CommandReply::NoReply, reply);
+        // Resolves straight away once triggered.
+        session.shutdown.triggered().await;
+    }
+
+    #[tokio::test]
+    async fn test_execute_refuses_writes_over_maxmemory() {
+        let database =
+            Databases::from(Database::new().with_max_memory(1, MaxMemoryPolicy::NoEviction));
+        let mut session = session();
+        let push = |key: &'static [u8]| Command::RPush {
+            key: Cow::Borrowed(key),
+            values: Box::new([Cow::Borrowed(b"a")]),
+        };
+
+        let reply = session.execute(push(b"list"), &database).await;
+        assert_eq!(CommandReply::Value(Value::Integer(1)), reply);
+
+        let reply = session.execute(push(b"other"), &database).await;
+        assert_eq!(
+            CommandReply::Error(Cow::Borrowed(
+                "OOM command not allowed when used memory > 'maxmemory'."
+            )),
+            reply
+        );
+
+        // Reads and commands that free memory still run.
+        let reply = session
+            .execute(Command::LLen(Cow::Borrowed(b"list")), &database)
+            .await;
+        assert_eq!(CommandReply::Value(Value::Integer(1)), reply);
+
+        let reply = session
+            .execute(
+                Command::Unlink(Box::new([Cow::Borrowed(b"list")])),
+                &database,
+            )
+            .await;
+        assert_eq!(CommandReply::Value(Value::Integer(1)), reply);
+
+        let reply = session.execute(push(b"other"), &database).await;
+        assert_eq!(CommandReply::Value(Value::Integer(1)), reply);
+    }
+
+    #[tokio::test]
+    async fn test_execute_get_on_list_is_wrong_type() {
+        let database = Databases::from(Database::new());
+        let mut session = session();
+
+        let reply = session
+            .execute(
+                Command::LPush {
+                    key: Cow::Borrowed(b"list"),
+                    values: Box::new([Cow::Borrowed(b"a".as_slice())]),
+                },
+                &database,
+            )
+            .await;
+        assert_eq!(CommandReply::Value(Value::Integer(1)), reply);
+
+        let reply = session
+            .execute(Command::Get(Cow::Borrowed(b"list")), &database)
+            .await;
+        assert_eq!(CommandReply::error(&TypeError::WrongType), reply);
+        assert_eq!(
+            "WRONGTYPE Operation against a key holding the wrong kind of value",
+            TypeError::WrongType.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_config_get_and_set_maxmemory() {
+        let database = Databases::from(Database::new());
+        let mut session = session();
+        let pairs = |pairs: &[(&'static [u8], &'static [u8])]| {
+            pairs
+                .iter()
+                .map(|&(name, value)| (Cow::Borrowed(name), Cow::Borrowed(value)))
+                .collect()
+        };
+        let config_get = || Command::ConfigGet(Box::new([Cow::Borrowed(b"maxmemory*")]));
+        let push = |key: &'static [u8]| Command::RPush {
+            key: Cow::Borrowed(key),
+            values: Box::new([Cow::Borrowed(b"a")]),
+        };
+
+        session.execute(push(b"list"), &database).await;
+
+        let reply = session.execute(config_get(), &database).await;
+        assert_eq!(
+            CommandReply::Value(Value::Map(Box::new([
+                (bulk_string!(b"maxmemory"), bulk_string!(b"0")),
+                (
+                    bulk_string!(b"maxmemory-policy"),
+                    bulk_string!(b"noeviction")
+                ),
+            ]))),
+            reply
+        );
+
+        // A bad value leaves every parameter as it was.
+        let reply = session
+            .execute(
+                Command::ConfigSet(pairs(&[
+                    (b"maxmemory", b"1"),
+                    (b"maxmemory-policy", b"allkeys-lfu"),
+                ])),
+                &database,
+            )
+            .await;
+        assert_eq!(
+            CommandReply::Error(Cow::Borrowed(
+                "ERR CONFIG SET failed (possibly related to argument 'maxmemory-policy') - \
+                 argument(s) must be one of the following: noeviction, allkeys-lru, \
+                 allkeys-random, volatile-ttl"
+            )),
+            reply
+        );
+        assert_eq!((0, MaxMemoryPolicy::NoEviction), database[0].max_memory());
+
+        let reply = session
+            .execute(Command::ConfigSet(pairs(&[(b"port", b"1")])), &database)
+            .await;
+        assert_eq!(
+            CommandReply::Error(Cow::Borrowed(
+                "ERR Unknown option or number of arguments for CONFIG SET - 'port'"
+            )),
+            reply
+        );
+
+        // The key written before there was a limit is counted once one is
+        // set, so it is evicted to make room for the next write.
+        let reply = session
+            .execute(
+                Command::ConfigSet(pairs(&[
+                    (b"MAXMEMORY", b"1"),
+                    (b"maxmemory-policy", b"ALLKEYS-RANDOM"),
+                ])),
+                &database,
+            )
+            .await;
+        assert_eq!(CommandReply::Ok, reply);
+        assert!(database[0].used_memory() > 0);
+
+        let reply = session.execute(push(b"other"), &database).await;
+        assert_eq!(CommandReply::Value(Value::Integer(1)), reply);
+        assert!(database[0].get(b"list").await.is_none());
+
+        let reply = session
+            .execute(
+                Command::ConfigSet(pairs(&[(b"maxmemory", b"0")])),
+                &database,
+            )
+            .await;
+        assert_eq!(CommandReply::Ok, reply);
+        assert_eq!(0, database[0].used_memory());
+
+        let reply = session.execute(config_get(), &database).await;
+        assert_eq!(
+            CommandReply::Value(Value::Map(Box::new([
+                (bulk_string!(b"maxmemory"), bulk_string!(b"0")),
+                (
+                    bulk_string!(b"maxmemory-policy"),
+                    bulk_string!(b"allkeys-random")
+                ),
+            ]))),
+            reply
+        );
+    }
+
+    #[tokio::test]
+    async fn test_eviction_is_counted_and_notified() {
+        let database =
+            Databases::from(Database::new().with_max_memory(1, MaxMemoryPolicy::AllKeysRandom));
+        let mut session = session();
+        let mut subscriber = Subscription::new(Arc::clone(&session.pubsub));
+        subscriber.subscribe(b"__keyevent@0__:evicted");
+        subscriber.subscribe(b"__keyspace@0__:list");
+
+        let push = |key: &'static [u8]| Command::RPush {
+            key: Cow::Borrowed(key),
+            values: Box::new([Cow::Borrowed(b"a")]),
+        };
+
+        session.execute(push(b"list"), &database).await;
+        assert_eq!("# Stats\r\nevicted_keys:0\r\n", session.metrics.stats());
+
+        // Over the limit already, so the only key is evicted to make room.
+        let reply = session.execute(push(b"other"), &database).await;
+        assert_eq!(CommandReply::Value(Value::Integer(1)), reply);
+        assert!(database[0].get(b"list").await.is_none());
+        assert_eq!("# Stats\r\nevicted_keys:1\r\n", session.metrics.stats());
+
+        let receiver = subscriber.receiver().unwrap();
+        let keyspace = receiver.try_recv().unwrap();
+        let keyevent = receiver.try_recv().unwrap();
+
+        assert_eq!(
+            vec![message_frame(b"__keyspace@0__:list", b"evicted")],
+            subscriber.frames(&keyspace)
+        );
+        assert_eq!(
+            vec![message_frame(b"__keyevent@0__:evicted", b"list")],
+            subscriber.frames(&keyevent)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_push_replies_with_length() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"LPUSH", b"list", b"a", b"b", b"c"]);
+        input.extend(command(&[b"RPUSH", b"list", b"d"]));
+        input.extend(command(&[b"LRANGE", b"list", b"0", b"-1"]));
+        input.extend(command(&[b"SET", b"key", b"value"]));
+        input.extend(command(&[b"LPUSH", b"key", b"a"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":3\r\n:4\r\n*4\r\n$1\r\nc\r\n$1\r\nb\r\n$1\r\na\r\n$1\r\nd\r\n+OK\r\n\
+              -WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_getset_matches_set_get() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SET", b"a", b"1", b"PX", b"50"]);
+        input.extend(command(&[b"SET", b"b", b"1", b"PX", b"50"]));
+        input.extend(command(&[b"GETSET", b"a", b"2"]));
+        input.extend(command(&[b"SET", b"b", b"2", b"GET"]));
+        input.extend(command(&[b"GETSET", b"missing", b"1"]));
+        input.extend(command(&[b"RPUSH", b"list", b"1"]));
+        input.extend(command(&[b"GETSET", b"list", b"1"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"+OK\r\n+OK\r\n$1\r\n1\r\n$1\r\n1\r\n$-1\r\n:1\r\n\
+              -WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(60)).await;
+
+        let mut input = command(&[b"GET", b"a"]);
+        input.extend(command(&[b"GET", b"b"]));
+        input.extend(command(&[b"LLEN", b"list"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"$1\r\n2\r\n$1\r\n2\r\n:1\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lrange_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"RPUSH", b"list", b"a", b"b", b"c"]);
+        input.extend(command(&[b"LRANGE", b"list", b"0", b"-1"]));
+        input.extend(command(&[b"LRANGE", b"list", b"-2", b"-2"]));
+        input.extend(command(&[b"LRANGE", b"list", b"5", b"10"]));
+        input.extend(command(&[b"LRANGE", b"missing", b"0", b"-1"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":3\r\n*3\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n*1\r\n$1\r\nb\r\n*0\r\n*0\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hash_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSET", b"hash", b"a", b"1", b"b", b"2"]);
+        input.extend(command(&[b"HSET", b"hash", b"a", b"3"]));
+        input.extend(command(&[b"HGET", b"hash", b"a"]));
+        input.extend(command(&[b"HGET", b"hash", b"missing"]));
+        input.extend(command(&[b"HDEL", b"hash", b"b", b"missing"]));
+        input.extend(command(&[b"HEXISTS", b"hash", b"a"]));
+        input.extend(command(&[b"HEXISTS", b"hash", b"b"]));
+        input.extend(command(&[b"HGETALL", b"hash"]));
+        input.extend(command(&[b"HGETALL", b"missing"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n:0\r\n$1\r\n3\r\n$-1\r\n:1\r\n:1\r\n:0\r\n*2\r\n$1\r\na\r\n$1\r\n3\r\n*0\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hgetall_of_emptied_hash() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSET", b"hash", b"a", b"1", b"b", b"2"]);
+        input.extend(command(&[b"HDEL", b"hash", b"a", b"b"]));
+        input.extend(command(&[b"HGETALL", b"hash"]));
+
+        assert_eq!(execute(&database, &input).await, b":2\r\n:2\r\n*0\r\n");
+        assert!(database[0].get(b"hash").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_hrandfield_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSET", b"hash", b"a", b"1"]);
+        input.extend(command(&[b"HRANDFIELD", b"hash"]));
+        input.extend(command(&[b"HRANDFIELD", b"hash", b"5"]));
+        input.extend(command(&[b"HRANDFIELD", b"missing"]));
+        input.extend(command(&[b"HRANDFIELD", b"missing", b"2"]));
+        input.extend(command(&[
+            b"HRANDFIELD",
+            b"hash",
+            b"1",
+            b"WITHVALUES",
+            b"x",
+        ]));
+        input.extend(command(&[b"HRANDFIELD", b"hash", b"1", b"WITHSCORES"]));
+        input.extend(command(&[
+            b"HRANDFIELD",
+            b"hash",
+            b"-9223372036854775807",
+            b"WITHVALUES",
+        ]));
+        input.extend(command(&[b"HRANDFIELD", b"hash", b"-9223372036854775808"]));
+        input.extend(command(&[
+            b"HRANDFIELD",
+            b"hash",
+            b"9223372036854775807",
+            b"WITHVALUES",
+        ]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":1\r\n$1\r\na\r\n*1\r\n$1\r\na\r\n$-1\r\n*0\r\n\
+              -ERR syntax error\r\n-ERR syntax error\r\n\
+              -ERR value is out of range\r\n-ERR value is out of range\r\n\
+              *2\r\n$1\r\na\r\n$1\r\n1\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hash_maps_in_resp3() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSET", b"hash", b"a", b"1"]);
+        input.extend(command(&[b"HGETALL", b"hash"]));
+        input.extend(command(&[b"HRANDFIELD", b"hash", b"1", b"WITHVALUES"]));
+        input.extend(command(&[b"HELLO", b"3"]));
+        input.extend(command(&[b"HGETALL", b"hash"]));
+        input.extend(command(&[b"HGETALL", b"missing"]));
+        input.extend(command(&[b"HRANDFIELD", b"hash", b"1", b"WITHVALUES"]));
+
+        let output = execute(&database, &input).await;
+        assert!(
+            output.starts_with(b":1\r\n*2\r\n$1\r\na\r\n$1\r\n1\r\n*2\r\n$1\r\na\r\n$1\r\n1\r\n%")
+        );
+        assert!(output.ends_with(b"%1\r\n$1\r\na\r\n$1\r\n1\r\n%0\r\n%1\r\n$1\r\na\r\n$1\r\n1\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_hscan_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSET", b"hash", b"a", b"1", b"b", b"2"]);
+        input.extend(command(&[b"HSCAN", b"hash", b"0", b"MATCH", b"a"]));
+        input.extend(command(&[
+            b"HSCAN",
+            b"hash",
+            b"0",
+            b"MATCH",
+            b"b",
+            b"NOVALUES",
+        ]));
+        input.extend(command(&[b"HSCAN", b"missing", b"0"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n*2\r\n$1\r\n0\r\n*2\r\n$1\r\na\r\n$1\r\n1\r\n\
+              *2\r\n$1\r\n0\r\n*1\r\n$1\r\nb\r\n*2\r\n$1\r\n0\r\n*0\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_select_switches_database() {
+        let databases = Databases::new(Arc::new(Database::new()), 16);
+
+        let mut input = command(&[b"SELECT", b"1"]);
+        input.extend(command(&[b"SET", b"key", b"value"]));
+        input.extend(command(&[b"GET", b"key"]));
+        input.extend(command(&[b"SELECT", b"0"]));
+        input.extend(command(&[b"GET", b"key"]));
+        input.extend(command(&[b"SELECT", b"16"]));
+        input.extend(command(&[b"GET", b"key"]));
+
+        assert_eq!(
+            execute(&databases, &input).await,
+            b"+OK\r\n+OK\r\n$5\r\nvalue\r\n+OK\r\n$-1\r\n-ERR DB index is out of range\r\n$-1\r\n"
+        );
+        assert!(databases[1].get(b"key").await.is_some());
+
+        // A new connection starts out on database 0 again.
+        let mut input = command(&[b"SWAPDB", b"0", b"1"]);
+        input.extend(command(&[b"GET", b"key"]));
+        input.extend(command(&[b"SWAPDB", b"0", b"-1"]));
+
+        assert_eq!(
+            execute(&databases, &input).await,
+            b"+OK\r\n$5\r\nvalue\r\n-ERR DB index is out of range\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_command_list_filters_by_pattern() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"COMMAND", b"LIST", b"FILTERBY", b"PATTERN", b"get*"]);
+        input.extend(command(&[
+            b"COMMAND",
+            b"LIST",
+            b"FILTERBY",
+            b"MODULE",
+            b"json",
+        ]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"*4\r\n$3\r\nget\r\n$6\r\ngetset\r\n$8\r\ngetrange\r\n$6\r\ngetbit\r\n*0\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_command_count_info_and_docs() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"COMMAND", b"COUNT"]);
+        input.extend(command(&[b"COMMAND", b"INFO", b"GET", b"nosuch"]));
+        input.extend(command(&[b"COMMAND", b"DOCS", b"get", b"nosuch"]));
+
+        let expected = [
+            format!(":{}\r\n", crate::COMMAND_KEYWORDS.len()).as_bytes(),
+            b"*2\r\n*7\r\n$3\r\nget\r\n:2\r\n*2\r\n+readonly\r\n+fast\r\n:1\r\n:1\r\n:1\r\n\
+              *3\r\n+@read\r\n+@string\r\n+@fast\r\n*-1\r\n",
+            b"*2\r\n$3\r\nget\r\n*0\r\n",
+        ]
+        .concat();
+
+        assert_eq!(execute(&database, &input).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_bare_command_lists_every_spec() {
+        let database = Databases::from(Database::new());
+
+        let output = execute(&database, &command(&[b"COMMAND"])).await;
+        let expected = format!(
+            "*{}\r\n*7\r\n$4\r\nping\r\n:-1\r\n",
+            crate::COMMAND_KEYWORDS.len()
+        );
+        assert!(output.starts_with(expected.as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn test_hsetnx_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSETNX", b"hash", b"field", b"first"]);
+        input.extend(command(&[b"HSETNX", b"hash", b"field", b"second"]));
+        input.extend(command(&[b"HGET", b"hash", b"field"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":1\r\n:0\r\n$5\r\nfirst\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hlen_and_hstrlen_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSET", b"hash", b"a", b"1", b"b", b"hello"]);
+        input.extend(command(&[b"HLEN", b"hash"]));
+        input.extend(command(&[b"HLEN", b"missing"]));
+        input.extend(command(&[b"HSTRLEN", b"hash", b"b"]));
+        input.extend(command(&[b"HSTRLEN", b"hash", b"missing"]));
+        input.extend(command(&[b"RPUSH", b"list", b"a"]));
+        input.extend(command(&[b"HLEN", b"list"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n:2\r\n:0\r\n:5\r\n:0\r\n:1\r\n\
+              -WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_setname_getname() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"CLIENT", b"GETNAME"]);
+        input.extend(command(&[b"CLIENT", b"SETNAME", b"worker"]));
+        input.extend(command(&[b"client", b"getname"]));
+        input.extend(command(&[b"CLIENT", b"SETNAME", b"two words"]));
+        input.extend(command(&[b"CLIENT", b"ID"]));
+        input.extend(command(&[b"CLIENT", b"LIST"]));
+
+        assert_eq!(
+            String::from_utf8(execute(&database, &input).await).unwrap(),
+            "$-1\r\n+OK\r\n$6\r\nworker\r\n\
+             -ERR Client names cannot contain spaces, newlines or special characters.\r\n\
+             :1\r\n$29\r\nid=1 addr= name=worker age=0\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_debug_protocol() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"DEBUG", b"PROTOCOL", b"double"]);
+        input.extend(command(&[b"DEBUG", b"PROTOCOL", b"map"]));
+        input.extend(command(&[b"DEBUG", b"PROTOCOL", b"push"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"$5\r\n3.141\r\n*6\r\n:0\r\n:0\r\n:1\r\n:1\r\n:2\r\n:0\r\n\
+              -ERR RESP2 is not supported by this command\r\n"
+        );
+
+        let mut input = command(&[b"HELLO", b"3"]);
+        input.extend(command(&[b"DEBUG", b"PROTOCOL", b"double"]));
+        input.extend(command(&[b"DEBUG", b"PROTOCOL", b"map"]));
+        input.extend(command(&[b"DEBUG", b"PROTOCOL", b"push"]));
+        input.extend(command(&[b"DEBUG", b"PROTOCOL", b"nope"]));
+
+        // The HELLO reply is checked elsewhere.
+        let output = execute(&database, &input).await;
+        assert!(output.ends_with(
+            b",3.141\r\n%3\r\n:0\r\n#f\r\n:1\r\n#t\r\n:2\r\n#f\r\n\
+              >2\r\n$16\r\nserver-cpu-usage\r\n:42\r\n\
+              $40\r\nSome real reply following the push reply\r\n\
+              -ERR Wrong protocol type name. Please use one of the following: \
+              string|integer|double|bignum|null|array|set|map|attrib|push|verbatim|true|false|err\r\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_keys_and_scan_match_binary_keys() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SET", b"\xff\xfe", b"1"]);
+        input.extend(command(&[b"KEYS", b"*"]));
+        input.extend(command(&[b"KEYS", b"[\xf0-\xff]?"]));
+        input.extend(command(&[
+            b"SCAN", b"0", b"MATCH", b"\xff*", b"COUNT", b"5",
+        ]));
+        input.extend(command(&[b"SCAN", b"nope"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"+OK\r\n*1\r\n$2\r\n\xff\xfe\r\n*1\r\n$2\r\n\xff\xfe\r\n\
+              *2\r\n$1\r\n0\r\n*1\r\n$2\r\n\xff\xfe\r\n-ERR invalid cursor\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hexists_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSET", b"hash", b"a", b"1"]);
+        input.extend(command(&[b"SET", b"string", b"value"]));
+        input.extend(command(&[b"HEXISTS", b"hash", b"a"]));
+        input.extend(command(&[b"HEXISTS", b"hash", b"b"]));
+        input.extend(command(&[b"HEXISTS", b"missing", b"a"]));
+        input.extend(command(&[b"HEXISTS", b"string", b"a"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":1\r\n+OK\r\n:1\r\n:0\r\n:0\r\n\
+              -WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hmget_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSET", b"hash", b"a", b"1", b"b", b"2"]);
+        input.extend(command(&[b"HMGET", b"hash", b"b", b"missing", b"a"]));
+        input.extend(command(&[b"HMGET", b"missing", b"a", b"b"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n*3\r\n$1\r\n2\r\n$-1\r\n$1\r\n1\r\n*2\r\n$-1\r\n$-1\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hkeys_and_hvals_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HSET", b"hash", b"\0field\r\n", b"\xffvalue"]);
+        input.extend(command(&[b"HKEYS", b"hash"]));
+        input.extend(command(&[b"HVALS", b"hash"]));
+        input.extend(command(&[b"HGETALL", b"hash"]));
+        input.extend(command(&[b"HKEYS", b"missing"]));
+        input.extend(command(&[b"HVALS", b"missing"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            [
+                &b":1\r\n*1\r\n$8\r\n\0field\r\n\r\n*1\r\n$6\r\n\xffvalue\r\n"[..],
+                b"*2\r\n$8\r\n\0field\r\n\r\n$6\r\n\xffvalue\r\n*0\r\n*0\r\n",
+            ]
+            .concat()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hincr_by_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HINCRBY", b"hash", b"n", b"5"]);
+        input.extend(command(&[b"HINCRBY", b"hash", b"n", b"-10"]));
+        input.extend(command(&[b"HINCRBYFLOAT", b"hash", b"f", b"10.50"]));
+        input.extend(command(&[b"HINCRBYFLOAT", b"hash", b"f", b"0.1"]));
+        input.extend(command(&[b"HINCRBYFLOAT", b"hash", b"n", b"1.5"]));
+        input.extend(command(&[b"HINCRBY", b"hash", b"f", b"1"]));
+        input.extend(command(&[b"HINCRBYFLOAT", b"hash", b"f", b"abc"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":5\r\n:-5\r\n$4\r\n10.5\r\n$4\r\n10.6\r\n$4\r\n-3.5\r\n\
+              -ERR hash value is not an integer\r\n\
+              -ERR value is not a valid float\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hset_rejects_odd_pairs() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"HGET", b"missing", b"a"]);
+        input.extend(command(&[b"HSET", b"hash", b"a", b"1", b"b"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"$-1\r\n-ERR wrong number of arguments for 'hset' command\r\n"
+        );
+        assert!(database[0].get(b"hash").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_string_commands_reply_wrong_type() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"RPUSH", b"list", b"a"]);
+        input.extend(command(&[b"APPEND", b"key", b"Hello"]));
+        input.extend(command(&[b"GETRANGE", b"key", b"1", b"-2"]));
+        input.extend(command(&[b"SETBIT", b"key", b"7", b"1"]));
+        input.extend(command(&[b"BITCOUNT", b"key", b"0", b"0"]));
+        input.extend(command(&[b"APPEND", b"list", b"a"]));
+        input.extend(command(&[b"STRLEN", b"list"]));
+        input.extend(command(&[b"GETRANGE", b"list", b"0", b"-1"]));
+        input.extend(command(&[b"SETRANGE", b"list", b"0", b"a"]));
+        input.extend(command(&[b"GETBIT", b"list", b"0"]));
+        input.extend(command(&[b"SETBIT", b"list", b"0", b"1"]));
+        input.extend(command(&[b"BITCOUNT", b"list"]));
+
+        let mut expected = b":1\r\n:5\r\n$3\r\nell\r\n:0\r\n:3\r\n".to_vec();
+        expected.extend(
+            b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n".repeat(7),
+        );
+
+        assert_eq!(execute(&database, &input).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_wrongtype_across_types() {
+        const WRONGTYPE: &[u8] =
+            b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SET", b"string", b"value"]);
+        input.extend(command(&[b"LPUSH", b"list", b"a"]));
+        input.extend(command(&[b"HSET", b"hash", b"a", b"1"]));
+        input.extend(command(&[b"SADD", b"set", b"a"]));
+        input.extend(command(&[b"ZADD", b"zset", b"1", b"a"]));
+        assert_eq!(
+            execute(&database, &input).await,
+            b"+OK\r\n:1\r\n:1\r\n:1\r\n:1\r\n"
+        );
+
+        let mut input = Vec::new();
+        for key in [&b"list"[..], b"hash", b"set", b"zset"] {
+            input.extend(command(&[b"GET", key]));
+            input.extend(command(&[b"APPEND", key, b"a"]));
+        }
+        for write in [
+            &[&b"LPUSH"[..], b"string", b"a"][..],
+            &[b"HSET", b"string", b"a", b"1"],
+            &[b"SADD", b"string", b"a"],
+            &[b"ZADD", b"string", b"1", b"a"],
+            &[b"SADD", b"list", b"a"],
+            &[b"LPUSH", b"hash", b"a"],
+        ] {
+            input.extend(command(write));
+        }
+
+        assert_eq!(execute(&database, &input).await, WRONGTYPE.repeat(14));
+    }
+
+    #[tokio::test]
+    async fn test_set_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SADD", b"set", b"a", b"b", b"a"]);
+        input.extend(command(&[b"SADD", b"set", b"b"]));
+        input.extend(command(&[b"SISMEMBER", b"set", b"a"]));
+        input.extend(command(&[b"SISMEMBER", b"set", b"c"]));
+        input.extend(command(&[b"SREM", b"set", b"a", b"c"]));
+        input.extend(command(&[b"SMEMBERS", b"set"]));
+        input.extend(command(&[b"SCARD", b"set"]));
+        input.extend(command(&[b"SMEMBERS", b"missing"]));
+        input.extend(command(&[b"GET", b"set"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n:0\r\n:1\r\n:0\r\n:1\r\n*1\r\n$1\r\nb\r\n:1\r\n*0\r\n\
+              -WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sismember_binary_members() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SADD", b"set", b"a\r\nb", b"\0\xff"]);
+        input.extend(command(&[b"SISMEMBER", b"set", b"a\r\nb"]));
+        input.extend(command(&[b"SISMEMBER", b"set", b"a"]));
+        input.extend(command(&[
+            b"SMISMEMBER",
+            b"set",
+            b"\0\xff",
+            b"a\r\n",
+            b"a\r\nb",
+        ]));
+        input.extend(command(&[b"SMISMEMBER", b"missing", b"a\r\nb"]));
+        input.extend(command(&[b"SMISMEMBER", b"set"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n:1\r\n:0\r\n*3\r\n:1\r\n:0\r\n:1\r\n*1\r\n:0\r\n\
+              -ERR wrong number of arguments for 'smismember' command\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pop_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"RPUSH", b"list", b"a", b"b", b"c"]);
+        input.extend(command(&[b"LPOP", b"list", b"0"]));
+        input.extend(command(&[b"LPOP", b"list"]));
+        input.extend(command(&[b"RPOP", b"list", b"5"]));
+        input.extend(command(&[b"LLEN", b"list"]));
+        input.extend(command(&[b"LPOP", b"list"]));
+        input.extend(command(&[b"RPOP", b"list", b"2"]));
+        input.extend(command(&[b"LPOP", b"list", b"0"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":3\r\n*0\r\n$1\r\na\r\n*2\r\n$1\r\nc\r\n$1\r\nb\r\n:0\r\n$-1\r\n*-1\r\n*-1\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scard_replies() {
+        let database = Databases::from(Database::new());
+        database[0].sadd(b"set", &["a", "b", "c"]).await.unwrap();
+        database[0].set(b"string", "value", None).await.unwrap();
+
+        let mut input = command(&[b"SCARD", b"set"]);
+        input.extend(command(&[b"SCARD", b"missing"]));
+        input.extend(command(&[b"SCARD", b"string"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":3\r\n:0\r\n\
+              -WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+        assert_eq!(Ok(3), database[0].scard(b"set").await);
+    }
+
+    #[tokio::test]
+    async fn test_set_algebra_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SADD", b"a", b"1", b"2"]);
+        input.extend(command(&[b"SADD", b"b", b"2", b"3"]));
+        input.extend(command(&[b"SINTER", b"a", b"b"]));
+        input.extend(command(&[b"SDIFF", b"a", b"b", b"missing"]));
+        input.extend(command(&[b"SINTER", b"a", b"missing"]));
+        input.extend(command(&[b"SUNIONSTORE", b"dest", b"a", b"b"]));
+        input.extend(command(&[b"SCARD", b"dest"]));
+        input.extend(command(&[b"SDIFFSTORE", b"dest", b"a", b"a"]));
+        input.extend(command(&[b"SCARD", b"dest"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n:2\r\n*1\r\n$1\r\n2\r\n*1\r\n$1\r\n1\r\n*0\r\n:3\r\n:3\r\n:0\r\n:0\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_llen_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"LPUSH", b"list", b"a", b"b"]);
+        input.extend(command(&[b"LLEN", b"list"]));
+        input.extend(command(&[b"LLEN", b"missing"]));
+        input.extend(command(&[b"SET", b"key", b"value"]));
+        input.extend(command(&[b"LLEN", b"key"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n:2\r\n:0\r\n+OK\r\n\
+              -WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incr_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"INCR", b"counter"]);
+        input.extend(command(&[b"INCR", b"counter"]));
+        input.extend(command(&[b"INCR", b"counter"]));
+        input.extend(command(&[b"GET", b"counter"]));
+        input.extend(command(&[b"OBJECT", b"ENCODING", b"counter"]));
+        input.extend(command(&[b"SET", b"word", b"ten"]));
+        input.extend(command(&[b"INCR", b"word"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":1\r\n:2\r\n:3\r\n$1\r\n3\r\n$3\r\nint\r\n+OK\r\n\
+              -ERR value is not an integer or out of range\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_stores_canonical_integers() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SET", b"shared", b"100"]);
+        input.extend(command(&[b"OBJECT", b"REFCOUNT", b"shared"]));
+        input.extend(command(&[b"OBJECT", b"ENCODING", b"shared"]));
+        input.extend(command(&[b"GET", b"shared"]));
+        input.extend(command(&[b"SET", b"large", b"-123456789"]));
+        input.extend(command(&[b"OBJECT", b"REFCOUNT", b"large"]));
+        input.extend(command(&[b"OBJECT", b"ENCODING", b"large"]));
+        input.extend(command(&[b"SET", b"plus", b"+100"]));
+        input.extend(command(&[b"OBJECT", b"ENCODING", b"plus"]));
+        input.extend(command(&[b"SET", b"zeros", b"0100"]));
+        input.extend(command(&[b"OBJECT", b"ENCODING", b"zeros"]));
+        input.extend(command(&[b"SET", b"huge", b"9223372036854775808"]));
+        input.extend(command(&[b"OBJECT", b"ENCODING", b"huge"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"+OK\r\n:2147483647\r\n$3\r\nint\r\n$3\r\n100\r\n\
+              +OK\r\n:1\r\n$3\r\nint\r\n\
+              +OK\r\n$6\r\nembstr\r\n+OK\r\n$6\r\nembstr\r\n+OK\r\n$6\r\nembstr\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sorted_set_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"ZADD", b"zset", b"1", b"a", b"2.5", b"b", b"-inf", b"c"]);
+        input.extend(command(&[b"ZADD", b"zset", b"3", b"a"]));
+        input.extend(command(&[b"ZSCORE", b"zset", b"a"]));
+        input.extend(command(&[b"ZSCORE", b"zset", b"b"]));
+        input.extend(command(&[b"ZSCORE", b"zset", b"missing"]));
+        input.extend(command(&[b"ZRANGE", b"zset", b"0", b"-1"]));
+        input.extend(command(&[b"ZRANGE", b"zset", b"0", b"1", b"withscores"]));
+        input.extend(command(&[b"ZCARD", b"zset"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":3\r\n:0\r\n$1\r\n3\r\n$3\r\n2.5\r\n$-1\r\n\
+              *3\r\n$1\r\nc\r\n$1\r\nb\r\n$1\r\na\r\n\
+              *4\r\n$1\r\nc\r\n$4\r\n-inf\r\n$1\r\nb\r\n$3\r\n2.5\r\n:3\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zrangebyscore_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"ZADD", b"zset", b"1", b"a", b"2", b"b", b"3", b"c"]);
+        input.extend(command(&[
+            b"ZRANGEBYSCORE",
+            b"zset",
+            b"(1",
+            b"+inf",
+            b"WITHSCORES",
+        ]));
+        input.extend(command(&[
+            b"ZRANGEBYSCORE",
+            b"zset",
+            b"-inf",
+            b"+inf",
+            b"LIMIT",
+            b"2",
+            b"5",
+        ]));
+        input.extend(command(&[b"ZCOUNT", b"zset", b"-inf", b"(3"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":3\r\n*4\r\n$1\r\nb\r\n$1\r\n2\r\n$1\r\nc\r\n$1\r\n3\r\n\
+              *1\r\n$1\r\nc\r\n:2\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_debug_allowlist() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"DEBUG", b"jmap"]);
+        input.extend(command(&[b"DEBUG", b"SET-ACTIVE-EXPIRE", b"0"]));
+        input.extend(command(&[b"DEBUG", b"SLEEP-AFTER-FORK-SECONDS", b"1"]));
+        input.extend(command(&[b"DEBUG", b"segfault"]));
+        input.extend(command(&[b"DEBUG", b"PANIC"]));
+        input.extend(command(&[b"PING"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"+OK\r\n+OK\r\n+OK\r\n-ERR DEBUG SEGFAULT is not allowed\r\n\
+              -ERR DEBUG PANIC is not allowed\r\n+PONG\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_del_and_flushdb() {
+        let database = Databases::from(Database::new().with_lazy_free(true));
+
+        let mut input = command(&[b"SET", b"a", b"1"]);
+        input.extend(command(&[b"SET", b"b", b"2"]));
+        input.extend(command(&[b"DEL", b"a", b"missing"]));
+        input.extend(command(&[b"SET", b"a", b"1"]));
+        input.extend(command(&[b"FLUSHDB", b"ASYNC"]));
+        input.extend(command(&[b"GET", b"b"]));
+        input.extend(command(&[b"FLUSHDB", b"NOW"]));
+        input.extend(command(&[b"FLUSHDB"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"+OK\r\n+OK\r\n:1\r\n+OK\r\n+OK\r\n$-1\r\n-ERR syntax error\r\n+OK\r\n"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_debug_sleep() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"DEBUG", b"SLEEP", b"1.5"]);
+        input.extend(command(&[b"DEBUG", b"SLEEP", b"0"]));
+
+        let start = tokio::time::Instant::now();
+        assert_eq!(execute(&database, &input).await, b"+OK\r\n+OK\r\n");
+        assert!(start.elapsed() >= Duration::from_millis(1500));
+    }
+
+    #[tokio::test]
+    async fn test_lset_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"RPUSH", b"list", b"a", b"b"]);
+        input.extend(command(&[b"LSET", b"list", b"-1", b"c"]));
+        input.extend(command(&[b"LRANGE", b"list", b"0", b"-1"]));
+        input.extend(command(&[b"LSET", b"list", b"2", b"d"]));
+        input.extend(command(&[b"LSET", b"missing", b"0", b"d"]));
+        input.extend(command(&[b"SET", b"key", b"value"]));
+        input.extend(command(&[b"LSET", b"key", b"0", b"d"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n+OK\r\n*2\r\n$1\r\na\r\n$1\r\nc\r\n\
+              -ERR index out of range\r\n-ERR no such key\r\n+OK\r\n\
+              -WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_binary_values_round_trip() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SET", b"key", b"\xff\x00\xfe"]);
+        input.extend(command(&[b"GET", b"key"]));
+        input.extend(command(&[b"SET", b"\xff\r\n", b"\r\n"]));
+        input.extend(command(&[b"GET", b"\xff\r\n"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"+OK\r\n$3\r\n\xff\x00\xfe\r\n+OK\r\n$2\r\n\r\n\r\n"
+        );
+        assert_eq!(
+            Some(DatabaseValue::Bytes(b"\xff\x00\xfe".to_vec())),
+            database[0].get(b"key").await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_value_with_nul_and_crlf() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SET", b"key", b"\x00\xff\r\n"]);
+        input.extend(command(&[b"GET", b"key"]));
+        input.extend(command(&[b"SET", b"key", b"\r\n\x00", b"GET"]));
+        input.extend(command(&[b"GETSET", b"key", b"plain"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"+OK\r\n$4\r\n\x00\xff\r\n\r\n$4\r\n\x00\xff\r\n\r\n$3\r\n\r\n\x00\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_linsert_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"RPUSH", b"list", b"a", b"c"]);
+        input.extend(command(&[b"LINSERT", b"list", b"BEFORE", b"c", b"b"]));
+        input.extend(command(&[b"LINSERT", b"list", b"after", b"c", b"d"]));
+        input.extend(command(&[b"LINSERT", b"list", b"BEFORE", b"x", b"y"]));
+        input.extend(command(&[b"LINSERT", b"missing", b"BEFORE", b"a", b"b"]));
+        input.extend(command(&[b"LRANGE", b"list", b"0", b"-1"]));
+        input.extend(command(&[b"SET", b"key", b"value"]));
+        input.extend(command(&[b"LINSERT", b"key", b"BEFORE", b"a", b"b"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n:3\r\n:4\r\n:-1\r\n:0\r\n\
+              *4\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n$1\r\nd\r\n+OK\r\n\
+              -WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lmove_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"RPUSH", b"list", b"a", b"b"]);
+        input.extend(command(&[b"LMOVE", b"list", b"other", b"left", b"RIGHT"]));
+        input.extend(command(&[b"RPOPLPUSH", b"list", b"other"]));
+        input.extend(command(&[b"RPOPLPUSH", b"list", b"other"]));
+        input.extend(command(&[b"LRANGE", b"other", b"0", b"-1"]));
+        input.extend(command(&[b"SET", b"key", b"value"]));
+        input.extend(command(&[b"RPOPLPUSH", b"other", b"key"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n$1\r\na\r\n$1\r\nb\r\n$-1\r\n\
+              *2\r\n$1\r\nb\r\n$1\r\na\r\n+OK\r\n\
+              -WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lrem_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"RPUSH", b"list", b"a", b"b", b"a", b"a"]);
+        input.extend(command(&[b"LREM", b"list", b"-2", b"a"]));
+        input.extend(command(&[b"LRANGE", b"list", b"0", b"-1"]));
+        input.extend(command(&[b"LREM", b"list", b"0", b"x"]));
+        input.extend(command(&[b"LREM", b"missing", b"0", b"a"]));
+        input.extend(command(&[b"SET", b"key", b"value"]));
+        input.extend(command(&[b"LREM", b"key", b"0", b"a"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":4\r\n:2\r\n*2\r\n$1\r\na\r\n$1\r\nb\r\n:0\r\n:0\r\n+OK\r\n\
+              -WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_options_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SET", b"key", b"1", b"XX"]);
+        input.extend(command(&[b"SET", b"key", b"1", b"NX"]));
+        input.extend(command(&[b"SET", b"key", b"2", b"NX", b"GET"]));
+        input.extend(command(&[b"SET", b"key", b"3", b"XX", b"GET"]));
+        input.extend(command(&[b"SET", b"key", b"4", b"EX"]));
+        input.extend(command(&[b"SET", b"key", b"4", b"FOO"]));
+        input.extend(command(&[b"SET", b"key", b"4", b"EX", b"10", b"PX", b"10"]));
+        input.extend(command(&[b"SET", b"key", b"4", b"EX", b"0"]));
+        input.extend(command(&[b"GET", b"key"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b"$-1\r\n+OK\r\n$1\r\n1\r\n$1\r\n1\r\n\
+              -ERR syntax error\r\n-ERR syntax error\r\n-ERR syntax error\r\n\
+              -ERR invalid expire time in 'set' command\r\n$1\r\n3\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_rejects_array_value() {
+        let database = Databases::from(Database::new());
+
+        let input = b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n*2\r\n$1\r\na\r\n$1\r\nb\r\n";
+
+        assert_eq!(
+            execute(&database, input).await,
+            b"-Invalid Type: value to store must be a scalar\r\n"
+        );
+        assert!(database[0].get(b"k").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_blocking_lmove_timeout() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"BRPOPLPUSH", b"jobs", b"done", b"0.01"]);
+        input.extend(command(&[
+            b"BLMOVE", b"jobs", b"done", b"LEFT", b"LEFT", b"0.01",
+        ]));
+
+        assert_eq!(execute(&database, &input).await, b"$-1\r\n$-1\r\n");
+        assert!(database[0].get(b"done").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ltrim_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"RPUSH", b"foo", b"a", b"b", b"c"]);
+        input.extend(command(&[b"LTRIM", b"foo", b"0", b"-1"]));
+        input.extend(command(&[b"LRANGE", b"foo", b"0", b"-1"]));
+        input.extend(command(&[b"LTRIM", b"foo", b"2", b"1"]));
+        input.extend(command(&[b"LLEN", b"foo"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":3\r\n+OK\r\n*3\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n+OK\r\n:0\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lmpop_and_zmpop_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"RPUSH", b"second", b"a", b"b"]);
+        input.extend(command(&[
+            b"LMPOP", b"2", b"first", b"second", b"LEFT", b"COUNT", b"5",
+        ]));
+        input.extend(command(&[b"LMPOP", b"2", b"first", b"second", b"right"]));
+        input.extend(command(&[b"ZADD", b"zset", b"1", b"a", b"2.5", b"b"]));
+        input.extend(command(&[b"ZMPOP", b"1", b"zset", b"MAX"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":2\r\n*2\r\n$6\r\nsecond\r\n*2\r\n$1\r\na\r\n$1\r\nb\r\n*-1\r\n:2\r\n\
+              *2\r\n$4\r\nzset\r\n*1\r\n*2\r\n$1\r\nb\r\n$3\r\n2.5\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lmpop_numkeys() {
+        let database = Databases::from(Database::new());
+
+        let input = command(&[b"LMPOP", b"3", b"first", b"LEFT"]);
+        assert_eq!(
+            execute(&database, &input).await,
+            b"-ERR Number of keys can't be greater than number of args\r\n"
+        );
+
+        let input = command(&[b"LMPOP", b"0", b"first", b"LEFT"]);
+        assert_eq!(
+            execute(&database, &input).await,
+            b"-ERR numkeys should be greater than 0\r\n"
+        );
+
+        let input = command(&[b"LMPOP", b"1", b"first", b"LEFT", b"COUNT", b"0"]);
+        assert_eq!(
+            execute(&database, &input).await,
+            b"-ERR count should be greater than 0\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lpos_replies() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"RPUSH", b"list", b"a", b"b", b"a"]);
+        input.extend(command(&[b"LPOS", b"list", b"a"]));
+        input.extend(command(&[
+            b"LPOS", b"list", b"a", b"RANK", b"-1", b"COUNT", b"0",
+        ]));
+        input.extend(command(&[b"LPOS", b"list", b"c"]));
+        input.extend(command(&[b"LPOS", b"list", b"c", b"count", b"1"]));
+        input.extend(command(&[b"LPOS", b"list", b"a", b"RANK", b"0"]));
+
+        assert_eq!(
+            execute(&database, &input).await,
+            b":3\r\n:0\r\n*2\r\n:2\r\n:0\r\n$-1\r\n*0\r\n\
+              -ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... \
+              or use negative to start from the end of the list\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_commands_reply_wrong_type() {
+        let database = Databases::from(Database::new());
+
+        let mut input = command(&[b"SET", b"key", b"value"]);
+        input.extend(command(&[b"LPUSH", b"key", b"a"]));
+        input.extend(command(&[b"RPUSH", b"key", b"a"]));
+        input.extend(command(&[b"LRANGE", b"key", b"0", b"-1"]));
+        input.extend(command(&[b"LLEN", b"key"]));
+        input.extend(command(&[b"LPOP", b"key"]));
+        input.extend(command(&[b"RPOP", b"key", b"2"]));
+        input.extend(command(&[b"LTRIM", b"key", b"0", b"-1"]));
+        input.extend(command(&[b"LPOS", b"key", b"a"]));
+        input.extend(command(&[b"GET", b"key"]));
+
+        let mut expected = b"+OK\r\n".to_vec();
+        expected.extend(
+            b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n".repeat(8),
+        );
+        expected.extend(b"$5\r\nvalue\r\n");
+
+        assert_eq!(execute(&database, &input).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_save() {
+        let database = Databases::from(Database::new());
+        database[0].insert(b"key", "value", None).await;
+
+        let output = execute(&database, &command(&[b"SAVE"])).await;
+        assert_eq!(
+            output,
+            b"-ERR persistence is disabled, no snapshot path is configured\r\n"
+        );
+
+        let path = std::env::temp_dir().join(format!("redis-test-{}-save.rdb", std::process::id()));
+        let config = Config {
+            rdb_path: Some(path.clone()),
+            ..Config::default()
+        };
+
+        let output = execute_with(&database, config, &command(&[b"SAVE"])).await;
+        assert_eq!(output, b"+OK\r\n");
+
+        let loaded = Databases::from(Database::new());
+        assert_eq!(1, loaded.load(&path).await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            Some("value".try_into().unwrap()),
+            loaded[0].get(b"key").await
+        );
+    }
+}
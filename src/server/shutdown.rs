@@ -0,0 +1,121 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use tokio::sync::watch;
+use tokio::time::{timeout_at, Duration, Instant};
+use tracing::{info, warn};
+
+type Step = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+/// Coordinates a clean shutdown: once triggered the accept loop stops, then
+/// every registered step (flushing persistence, notifying subscribers, ...)
+/// is awaited in registration order within a shared time budget.
+pub struct Shutdown {
+    triggered: watch::Sender<bool>,
+    steps: Mutex<Vec<(&'static str, Step)>>,
+    timeout: Duration,
+}
+
+impl std::fmt::Debug for Shutdown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shutdown")
+            .field("triggered", &*self.triggered.borrow())
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl Shutdown {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            triggered: watch::channel(false).0,
+            steps: Mutex::new(Vec::new()),
+            timeout,
+        }
+    }
+
+    pub fn register<F>(&self, name: &'static str, step: impl FnOnce() -> F + Send + 'static)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.steps
+            .lock()
+            .unwrap()
+            .push((name, Box::new(move || Box::pin(step()))));
+    }
+
+    pub fn trigger(&self) {
+        self.triggered.send_replace(true);
+    }
+
+    /// Resolves once [`Shutdown::trigger`] has been called, including when it
+    /// happened before this future was created.
+    pub async fn triggered(&self) {
+        let mut receiver = self.triggered.subscribe();
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+
+    /// Triggers the shutdown and runs the registered steps. Steps still
+    /// pending when the timeout elapses are abandoned.
+    pub async fn run(&self) {
+        self.trigger();
+
+        let steps = std::mem::take(&mut *self.steps.lock().unwrap());
+        let deadline = Instant::now() + self.timeout;
+
+        for (name, step) in steps {
+            match timeout_at(deadline, step()).await {
+                Ok(()) => info!(step = name, "Shutdown step finished"),
+                Err(_) => warn!(step = name, "Shutdown step timed out"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown_runs_steps_in_order() {
+        let shutdown = Shutdown::new(Duration::from_secs(1));
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let cl = Arc::clone(&log);
+        shutdown.register("flush", || async move {
+            tokio::task::yield_now().await;
+            cl.lock().unwrap().push("flush");
+        });
+
+        let cl = Arc::clone(&log);
+        shutdown.register("save", || async move {
+            cl.lock().unwrap().push("save");
+        });
+
+        shutdown.run().await;
+
+        assert_eq!(vec!["flush", "save"], *log.lock().unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_abandons_steps_after_timeout() {
+        let shutdown = Shutdown::new(Duration::from_secs(5));
+        shutdown.register("stuck", std::future::pending::<()>);
+
+        let start = Instant::now();
+        shutdown.run().await;
+
+        assert_eq!(Duration::from_secs(5), start.elapsed());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_triggered_after_the_fact() {
+        let shutdown = Shutdown::new(Duration::from_secs(1));
+        shutdown.trigger();
+
+        shutdown.triggered().await;
+    }
+}
@@ -1,79 +1,703 @@
+use std::future::poll_fn;
 use std::io::ErrorKind;
-use std::sync::Arc;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
 
-use crate::bytes::Buffer;
-use tokio::io;
+use crate::bytes::{reclaim_every, Buffer, Pool};
+use crate::Databases;
+use tokio::io::{self, AsyncRead, AsyncWrite};
 use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tokio::sync::{OwnedSemaphorePermit, Semaphore};
-use tracing::{error, info, instrument, span, Level};
-use crate::Database;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+#[cfg(feature = "tls")]
+use tokio_rustls::{rustls::ServerConfig, TlsAcceptor};
+use tracing::{error, info, instrument, span, warn, Level};
 
+use super::aof::Aof;
+use super::clients::{Client, Clients};
 use super::handler::Handler;
+use super::listener::Listener;
+use super::metrics::Metrics;
+use super::pubsub::PubSub;
+use super::{Config, Shutdown};
 
-pub(crate) struct Server {
-    listener: TcpListener,
+/// How often the buffer pools are trimmed down to their high-water mark.
+const POOL_RECLAIM_PERIOD: Duration = Duration::from_secs(10);
+
+pub(crate) struct Server<L = TcpListener> {
+    listeners: Box<[L]>,
+    config: Arc<Config>,
+    shutdown: Arc<Shutdown>,
+    pubsub: Arc<PubSub>,
+    metrics: Arc<Metrics>,
+    clients: Arc<Clients>,
+    connections: Arc<Mutex<JoinSet<()>>>,
     connection_limit: Arc<Semaphore>,
-    buf_pool: Arc<sharded_slab::Pool<Buffer>>,
-    vec_pool: Arc<sharded_slab::Pool<Vec<u8>>>,
+    buf_pool: Arc<Pool<Buffer>>,
+    vec_pool: Arc<Pool<Vec<u8>>>,
+    aof: Option<Arc<Aof>>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsAcceptor>,
 }
 
-impl Server {
+impl Server<TcpListener> {
     #[instrument]
     #[inline]
-    pub async fn new(port: u16, connection_limit: usize) -> Result<Self, io::Error> {
-        let listener = TcpListener::bind(("0.0.0.0", port)).await?;
-        listener.set_ttl(60)?;
+    pub async fn new(
+        addrs: &[SocketAddr],
+        connection_limit: usize,
+        config: Config,
+    ) -> Result<Self, io::Error> {
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "no address to bind to",
+            ));
+        }
+
+        let mut listeners = Vec::with_capacity(addrs.len());
+
+        for addr in addrs {
+            let listener = TcpListener::bind(addr).await?;
+
+            if addr.is_ipv4() {
+                listener.set_ttl(60)?;
+            }
+
+            listeners.push(listener);
+        }
+
+        Self::with_listeners(listeners, connection_limit, config)
+    }
+
+    /// Requires every accepted client to complete a TLS handshake first.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, tls: Arc<ServerConfig>) -> Self {
+        self.tls = Some(TlsAcceptor::from(tls));
+        self
+    }
+}
+
+#[cfg(unix)]
+impl Server<UnixListener> {
+    /// Listens on a Unix socket at `path`, replacing a stale socket left
+    /// there by an earlier run. The socket file is removed on shutdown.
+    #[instrument]
+    pub async fn bind_unix(
+        path: &Path,
+        connection_limit: usize,
+        config: Config,
+    ) -> Result<Self, io::Error> {
+        use std::os::unix::fs::FileTypeExt;
+
+        if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        let server = Self::with_listeners(vec![listener], connection_limit, config)?;
+
+        let path = path.to_path_buf();
+        server
+            .shutdown
+            .register("remove unix socket", move || async move {
+                if let Err(err) = std::fs::remove_file(&path) {
+                    warn!(err = ?err, path = ?path, "Failed to remove unix socket");
+                }
+            });
+
+        Ok(server)
+    }
+}
+
+impl<L: Listener> Server<L> {
+    fn with_listeners(
+        listeners: Vec<L>,
+        connection_limit: usize,
+        config: Config,
+    ) -> Result<Self, io::Error> {
+        let aof = match &config.aof_path {
+            Some(path) => Some(Aof::open(path, config.aof_fsync)?),
+            None => None,
+        };
 
-        Ok(Self {
-            listener,
+        let server = Self {
+            listeners: listeners.into_boxed_slice(),
+            shutdown: Shutdown::new(config.shutdown_timeout).into(),
+            config: config.into(),
+            pubsub: PubSub::default().into(),
+            metrics: Metrics::default().into(),
+            clients: Clients::default().into(),
+            connections: Arc::default(),
             connection_limit: Semaphore::new(connection_limit).into(),
-            buf_pool: sharded_slab::Pool::new().into(),
-            vec_pool: sharded_slab::Pool::new().into(),
-        })
+            buf_pool: Pool::default().into(),
+            vec_pool: Pool::default().into(),
+            aof,
+            #[cfg(feature = "tls")]
+            tls: None,
+        };
+
+        // Buffers left idle by a burst of connections are freed again once
+        // it has passed.
+        let keep = server.config.buffer_pool_high_water;
+        tokio::spawn(reclaim_every(
+            Arc::downgrade(&server.buf_pool),
+            keep,
+            POOL_RECLAIM_PERIOD,
+        ));
+        tokio::spawn(reclaim_every(
+            Arc::downgrade(&server.vec_pool),
+            keep,
+            POOL_RECLAIM_PERIOD,
+        ));
+
+        // Subscribers get the messages published so far and close, then the
+        // other connections finish, before anything is persisted.
+        let pubsub = Arc::clone(&server.pubsub);
+        server
+            .shutdown
+            .register("notify subscribers", move || async move {
+                pubsub.close();
+            });
+
+        let connections = Arc::clone(&server.connections);
+        let grace_period = server.config.grace_period;
+        server
+            .shutdown
+            .register("close connections", move || async move {
+                close_connections(&connections, grace_period).await;
+            });
+
+        if let Some(aof) = server.aof.clone() {
+            server
+                .shutdown
+                .register("flush append-only file", move || async move {
+                    if let Err(err) = aof.flush().await {
+                        warn!(err = ?err, "Failed to flush the append-only file");
+                    }
+                });
+        }
+
+        Ok(server)
     }
 
-    async fn accept_client(&self, token: OwnedSemaphorePermit, map: Arc<Database>) -> Result<(), io::Error> {
-        let (client, socket) = self.listener.accept().await?;
-        let span = span!(Level::INFO, "new client", addr = ?socket.ip(), port = socket.port());
+    /// Saves a snapshot of `databases` once every connection has closed on
+    /// shutdown, when a snapshot path is configured.
+    pub fn save_on_shutdown(self, databases: &Arc<Databases>) -> Self {
+        if let Some(path) = self.config.rdb_path.clone() {
+            let databases = Arc::clone(databases);
+
+            self.shutdown.register("save snapshot", move || async move {
+                if let Err(err) = databases.save(&path).await {
+                    warn!(err = ?err, path = ?path, "Failed to save the snapshot");
+                }
+            });
+        }
+
+        self
+    }
+
+    /// Builds a handler for a new connection once its stream is ready, which
+    /// for TLS clients is only after the handshake.
+    fn handler<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        client: Client,
+    ) -> impl FnOnce(S) -> Handler<S> + Send + 'static {
+        let buf_pool = Arc::clone(&self.buf_pool);
+        let vec_pool = Arc::clone(&self.vec_pool);
+        let config = Arc::clone(&self.config);
+        let shutdown = Arc::clone(&self.shutdown);
+        let pubsub = Arc::clone(&self.pubsub);
+        let metrics = Arc::clone(&self.metrics);
+        let aof = self.aof.clone();
+
+        move |stream| {
+            let handler = Handler::new(
+                stream, buf_pool, vec_pool, config, shutdown, pubsub, metrics,
+            )
+            .with_client(client);
+
+            match aof {
+                Some(aof) => handler.with_aof(aof),
+                None => handler,
+            }
+        }
+    }
+
+    async fn accept_client(
+        &self,
+        token: OwnedSemaphorePermit,
+        map: Arc<Databases>,
+    ) -> Result<(), io::Error> {
+        let (client, socket) = self.accept().await?;
+        let span = span!(Level::INFO, "new client", addr = ?socket);
         let _enter = span.enter();
 
-        let mut handler = Handler::new(
-            client,
-            Arc::clone(&self.buf_pool),
-            Arc::clone(&self.vec_pool),
-        );
+        let registered = self.clients.register(format!("{socket:?}"));
+        let mut connections = self.connections.lock().unwrap();
+
+        // Reap connections that already closed so the set only tracks live ones.
+        while connections.try_join_next().is_some() {}
 
-        tokio::spawn(async move {
-            loop {
-                if let Err(err) = handler.run(&map).await {
-                    error!(err = ?err, "Failed to handle client");
-                    drop(handler);
-                    drop(token);
-                    return;
+        #[cfg(feature = "tls")]
+        if let Some(acceptor) = self.tls.clone() {
+            let handler = self.handler(registered);
+
+            // The handshake runs in the connection's task so a slow client
+            // does not hold up accepting others.
+            connections.spawn(async move {
+                match acceptor.accept(client).await {
+                    Ok(stream) => serve(handler(stream), &map).await,
+                    Err(err) => warn!(err = ?err, "TLS handshake failed"),
                 }
-            }
+
+                drop(token);
+            });
+
+            return Ok(());
+        }
+
+        let handler = self.handler(registered)(client);
+
+        connections.spawn(async move {
+            serve(handler, &map).await;
+            drop(token);
         });
 
         Ok(())
     }
 
-    pub async fn start(&self, db:Arc<Database>) -> Result<(), io::Error> {
+    pub async fn start(&self, db: Arc<Databases>) -> Result<(), io::Error> {
         let span = span!(Level::TRACE, "Client Accept Loop");
         let _enter = span.enter();
 
-
         info!("Starting Accept connection loop");
 
         loop {
-            let token = Arc::clone(&self.connection_limit)
-                .acquire_owned()
+            tokio::select! {
+                _ = self.shutdown.triggered() => {
+                    info!("Shutdown triggered, no longer accepting connections");
+                    return Ok(());
+                }
+                result = self.accept_next(Arc::clone(&db)) => result?,
+            }
+        }
+    }
+
+    /// Stops accepting clients and runs the shutdown steps: notifying
+    /// subscribers, closing connections, flushing the append-only file and
+    /// saving the snapshot, each only where it applies.
+    pub async fn shutdown(&self) {
+        self.shutdown.run().await;
+    }
+
+    /// The addresses of the TCP listeners among this server's listeners.
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>, io::Error> {
+        self.listeners.iter().filter_map(L::tcp_addr).collect()
+    }
+
+    /// Accepts the next client from whichever listener has one first.
+    async fn accept(&self) -> Result<(L::Stream, L::Addr), io::Error> {
+        poll_fn(|cx| {
+            for listener in self.listeners.iter() {
+                if let Poll::Ready(result) = listener.poll_accept(cx) {
+                    return Poll::Ready(result);
+                }
+            }
+
+            Poll::Pending
+        })
+        .await
+    }
+
+    async fn accept_next(&self, db: Arc<Databases>) -> Result<(), io::Error> {
+        let token = Arc::clone(&self.connection_limit)
+            .acquire_owned()
+            .await
+            .map_err(|err| io::Error::new(ErrorKind::ConnectionRefused, err))?;
+
+        match self.accept_client(token, db).await {
+            Ok(_) => info!("New Client accepted"),
+            Err(err) => error!(err = ?err, "Failed to accept new client"),
+        };
+
+        Ok(())
+    }
+}
+
+/// Gives open connections the grace period to finish their current commands
+/// and close, and aborts the rest.
+async fn close_connections(connections: &Mutex<JoinSet<()>>, grace_period: Duration) {
+    let mut connections = std::mem::take(&mut *connections.lock().unwrap());
+
+    let drained = timeout(grace_period, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+
+    if drained.is_err() {
+        warn!(
+            remaining = connections.len(),
+            "Grace period elapsed, aborting connections"
+        );
+        connections.shutdown().await;
+    }
+}
+
+async fn serve<S: AsyncRead + AsyncWrite + Unpin>(mut handler: Handler<S>, map: &Databases) {
+    if let Err(err) = handler.run(map).await {
+        error!(err = ?err, "Failed to handle client");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::time::{sleep, Duration, Instant};
+
+    use super::*;
+    use crate::server::AppendFsync;
+    use crate::Database;
+
+    #[tokio::test]
+    async fn test_shutdown_closes_connections_cleanly() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = Arc::new(Server::new(&[addr], 8, Config::default()).await.unwrap());
+        let addr = server.local_addrs().unwrap()[0];
+
+        let cl = Arc::clone(&server);
+        let databases = Arc::new(Databases::from(Database::new()));
+        let accept = tokio::spawn(async move { cl.start(databases).await });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+
+        let mut output = [0u8; 7];
+        client.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"+PONG\r\n");
+
+        // Half a PING, which the handler buffers while waiting for the rest.
+        client.write_all(b"*1\r\n$4\r\nPI").await.unwrap();
+        sleep(Duration::from_millis(50)).await;
+
+        server.shutdown().await;
+        accept.await.unwrap().unwrap();
+
+        let mut output = Vec::new();
+        client.read_to_end(&mut output).await.unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_accepts_on_every_listener() {
+        let addrs = [
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+        ];
+        let server = Arc::new(Server::new(&addrs, 8, Config::default()).await.unwrap());
+
+        let cl = Arc::clone(&server);
+        let databases = Arc::new(Databases::from(Database::new()));
+        tokio::spawn(async move { cl.start(databases).await });
+
+        for addr in server.local_addrs().unwrap() {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+
+            let mut output = [0u8; 7];
+            client.read_exact(&mut output).await.unwrap();
+            assert_eq!(&output, b"+PONG\r\n");
+        }
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_client_list_tracks_connections() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = Arc::new(Server::new(&[addr], 8, Config::default()).await.unwrap());
+        let addr = server.local_addrs().unwrap()[0];
+
+        let cl = Arc::clone(&server);
+        let databases = Arc::new(Databases::from(Database::new()));
+        tokio::spawn(async move { cl.start(databases).await });
+
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        let mut second = TcpStream::connect(addr).await.unwrap();
+
+        for (client, id) in [(&mut first, b":1\r\n"), (&mut second, b":2\r\n")] {
+            client
+                .write_all(b"*2\r\n$6\r\nCLIENT\r\n$2\r\nID\r\n")
                 .await
-                .map_err(|err| io::Error::new(ErrorKind::ConnectionRefused, err))?;
+                .unwrap();
 
-            match self.accept_client(token, Arc::clone(&db)).await {
-                Ok(_) => info!("New Client accepted"),
-                Err(err) => error!(err = ?err, "Failed to accept new client"),
-            };
+            let mut output = [0u8; 4];
+            client.read_exact(&mut output).await.unwrap();
+            assert_eq!(&output, id);
         }
+
+        drop(first);
+        sleep(Duration::from_millis(50)).await;
+
+        let list = server.clients.list();
+        assert!(list.starts_with(&format!(
+            "id=2 addr={} name= age=",
+            second.local_addr().unwrap()
+        )));
+        assert_eq!(1, list.lines().count());
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_releases_connection_slot() {
+        let config = Config {
+            idle_timeout: Duration::from_millis(50),
+            ..Config::default()
+        };
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = Arc::new(Server::new(&[addr], 1, config).await.unwrap());
+        let addr = server.local_addrs().unwrap()[0];
+
+        let cl = Arc::clone(&server);
+        let databases = Arc::new(Databases::from(Database::new()));
+        tokio::spawn(async move { cl.start(databases).await });
+
+        let mut idle = TcpStream::connect(addr).await.unwrap();
+        sleep(Duration::from_millis(10)).await;
+
+        // Only served once the idle client is closed and frees the one slot.
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+
+        let mut output = [0u8; 7];
+        client.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"+PONG\r\n");
+
+        let mut output = Vec::new();
+        idle.read_to_end(&mut output).await.unwrap();
+        assert!(output.is_empty());
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_flushes_pending_aof_write() {
+        let path =
+            std::env::temp_dir().join(format!("redis-test-{}-shutdown.aof", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let config = Config {
+            aof_path: Some(path.clone()),
+            aof_fsync: AppendFsync::EverySec,
+            ..Config::default()
+        };
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = Arc::new(Server::new(&[addr], 8, config).await.unwrap());
+        let addr = server.local_addrs().unwrap()[0];
+
+        let cl = Arc::clone(&server);
+        let databases = Arc::new(Databases::from(Database::new()));
+        tokio::spawn(async move { cl.start(databases).await });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let set = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+        client.write_all(set).await.unwrap();
+
+        let mut output = [0u8; 5];
+        client.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"+OK\r\n");
+
+        // Still buffered until the next once-a-second flush.
+        assert!(std::fs::read(&path).unwrap().is_empty());
+
+        server.shutdown().await;
+
+        let logged = std::fs::read(&path).unwrap();
+        assert!(logged.ends_with(set));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_saves_snapshot() {
+        let path =
+            std::env::temp_dir().join(format!("redis-test-{}-shutdown.rdb", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let config = Config {
+            rdb_path: Some(path.clone()),
+            ..Config::default()
+        };
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let databases = Arc::new(Databases::from(Database::new()));
+        let server = Server::new(&[addr], 8, config)
+            .await
+            .unwrap()
+            .save_on_shutdown(&databases);
+        let server = Arc::new(server);
+        let addr = server.local_addrs().unwrap()[0];
+
+        let cl = Arc::clone(&server);
+        tokio::spawn(async move { cl.start(databases).await });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n")
+            .await
+            .unwrap();
+
+        let mut output = [0u8; 5];
+        client.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"+OK\r\n");
+
+        server.shutdown().await;
+
+        let restored = Databases::from(Database::new());
+        assert_eq!(1, restored.load(&path).await.unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_subscribers() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = Arc::new(Server::new(&[addr], 8, Config::default()).await.unwrap());
+        let addr = server.local_addrs().unwrap()[0];
+
+        let cl = Arc::clone(&server);
+        let databases = Arc::new(Databases::from(Database::new()));
+        tokio::spawn(async move { cl.start(databases).await });
+
+        let mut subscriber = TcpStream::connect(addr).await.unwrap();
+        subscriber
+            .write_all(b"*2\r\n$9\r\nSUBSCRIBE\r\n$4\r\nnews\r\n")
+            .await
+            .unwrap();
+
+        let expected: &[u8] = b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n";
+        let mut output = vec![0u8; expected.len()];
+        subscriber.read_exact(&mut output).await.unwrap();
+        assert_eq!(output, expected);
+
+        let mut publisher = TcpStream::connect(addr).await.unwrap();
+        publisher
+            .write_all(b"*3\r\n$7\r\nPUBLISH\r\n$4\r\nnews\r\n$5\r\nhello\r\n")
+            .await
+            .unwrap();
+
+        let mut output = [0u8; 4];
+        publisher.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b":1\r\n");
+
+        // Well within the grace period, which the subscriber would otherwise
+        // hold up.
+        let start = Instant::now();
+        server.shutdown().await;
+        assert!(start.elapsed() < Config::default().grace_period);
+
+        let mut output = Vec::new();
+        subscriber.read_to_end(&mut output).await.unwrap();
+        assert_eq!(
+            output,
+            b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket() {
+        use tokio::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("redis-test-{}.sock", std::process::id()));
+        let server = Arc::new(
+            Server::bind_unix(&path, 8, Config::default())
+                .await
+                .unwrap(),
+        );
+
+        let cl = Arc::clone(&server);
+        let databases = Arc::new(Databases::from(Database::new()));
+        tokio::spawn(async move { cl.start(databases).await });
+
+        let mut client = UnixStream::connect(&path).await.unwrap();
+        client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+
+        let mut output = [0u8; 7];
+        client.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"+PONG\r\n");
+
+        assert!(server.local_addrs().unwrap().is_empty());
+
+        server.shutdown().await;
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_tls() {
+        use tokio_rustls::rustls::pki_types::{
+            CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName,
+        };
+        use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+        use tokio_rustls::TlsConnector;
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let der = CertificateDer::from(cert.serialize_der().unwrap());
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.serialize_private_key_der()));
+
+        let tls = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![der.clone()], key)
+            .unwrap();
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = Server::new(&[addr], 8, Config::default())
+            .await
+            .unwrap()
+            .with_tls(Arc::new(tls));
+        let server = Arc::new(server);
+        let addr = server.local_addrs().unwrap()[0];
+
+        let cl = Arc::clone(&server);
+        let databases = Arc::new(Databases::from(Database::new()));
+        tokio::spawn(async move { cl.start(databases).await });
+
+        // A plaintext client fails the handshake without taking the server down.
+        let mut plain = TcpStream::connect(addr).await.unwrap();
+        plain.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        let mut output = Vec::new();
+        let _ = plain.read_to_end(&mut output).await;
+        assert!(!output.starts_with(b"+PONG"));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(der).unwrap();
+        let connector = TlsConnector::from(Arc::new(
+            ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        ));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut client = connector
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .unwrap();
+
+        client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+
+        let mut output = [0u8; 7];
+        client.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"+PONG\r\n");
+
+        server.shutdown().await;
     }
 }